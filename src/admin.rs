@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Authentication for the admin routes
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    web, Error,
+};
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+use crate::app::serve_themed_404;
+use crate::theme::Theme;
+
+/// Token that clients must give to use the admin routes
+pub struct AdminToken(pub String);
+
+impl AdminToken {
+    /// Check whether a token given by a client is the admin token.
+    ///
+    /// The comparison takes the same time regardless of where the tokens differ, so that the admin
+    /// token can't be guessed from response times.
+    ///
+    /// # Arguments
+    /// * `token` - The token given by the client
+    fn matches(&self, token: &str) -> bool {
+        self.0.as_bytes().ct_eq(token.as_bytes()).into()
+    }
+}
+
+/// Get the bearer token given in a request's `Authorization` header, if any.
+///
+/// # Arguments
+/// * `req` - The request
+fn get_bearer_token(req: &ServiceRequest) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Middleware to reject requests to the admin routes without the admin token.
+///
+/// The token must be given as a bearer token in the `Authorization` header. If no admin token is
+/// registered as app data, then all requests are rejected. Rejected requests get the same 404
+/// response as invalid URLs, so that the admin routes aren't disclosed.
+pub async fn admin_middleware(
+    token: Option<web::Data<AdminToken>>,
+    theme: Theme,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let authorized = match (token, get_bearer_token(&req)) {
+        (Some(token), Some(given)) => token.matches(given),
+        _ => false,
+    };
+    if !authorized {
+        info!("Unauthorized request to admin route: {}", req.path());
+        return Ok(req
+            .into_response(serve_themed_404(None, theme))
+            .map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::{
+        http::StatusCode,
+        middleware::from_fn,
+        test::{call_service, init_service, TestRequest},
+        App, HttpResponse,
+    };
+    use test_case::test_case;
+
+    #[test_case(Some("secret"), Some("Bearer secret"), StatusCode::OK; "valid token")]
+    #[test_case(Some("secret"), Some("Bearer wrong"), StatusCode::NOT_FOUND; "wrong token")]
+    #[test_case(Some("secret"), Some("secret"), StatusCode::NOT_FOUND; "not bearer")]
+    #[test_case(Some("secret"), None, StatusCode::NOT_FOUND; "missing token")]
+    #[test_case(Some("secret"), Some("Bearer secret2"), StatusCode::NOT_FOUND; "longer token")]
+    #[test_case(None, Some("Bearer secret"), StatusCode::NOT_FOUND; "not configured")]
+    #[actix_web::test]
+    /// Test whether only requests with the admin token are allowed.
+    ///
+    /// # Arguments
+    /// * `token` - The configured admin token, if any
+    /// * `authorization` - The request's `Authorization` header, if any
+    /// * `expected` - The expected response status
+    async fn test_admin_middleware(
+        token: Option<&str>,
+        authorization: Option<&str>,
+        expected: StatusCode,
+    ) {
+        let mut app = App::new();
+        if let Some(token) = token {
+            app = app.app_data(web::Data::new(AdminToken(token.into())));
+        }
+        let app = init_service(
+            app.wrap(from_fn(admin_middleware))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let mut req = TestRequest::default();
+        if let Some(authorization) = authorization {
+            req = req.insert_header((AUTHORIZATION, authorization));
+        }
+        let resp = call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), expected, "Wrong response status");
+    }
+}
@@ -4,32 +4,200 @@
 
 //! The viewer app struct and its methods
 use std::cmp::{max, min};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
 use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
 
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{
+    body::None as NoBody,
+    http::header::{
+        CacheControl, CacheDirective, ContentDisposition, ContentType, DispositionParam,
+        DispositionType, ETag, EntityTag, IfModifiedSince, IfNoneMatch, ACCEPT, LOCATION,
+        RETRY_AFTER, VARY,
+    },
+    rt::{spawn, time::sleep},
+    web::Bytes,
+    HttpResponse,
+};
 use askama::Template;
-use chrono::{Duration, NaiveDate};
-use tracing::{debug, error};
+use async_graphql::Request;
+use chrono::{Datelike, Days, Duration, Months, NaiveDate};
+use futures::{
+    channel::mpsc::unbounded,
+    future::{join_all, select, Either},
+    stream, StreamExt,
+};
+use image::ImageFormat;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, error, info, warn};
 
-use crate::constants::{APP_URL, DISP_DATE_FMT, FIRST_COMIC, LAST_COMIC, REPO_URL, SRC_DATE_FMT};
-use crate::datetime::str_to_date;
-use crate::db::RedisPool;
+use crate::assets::GeneratedFile;
+use crate::cbz::CbzWriter;
+use crate::client::build_client;
+use crate::config::Config;
+use crate::constants::{
+    API_CONCURRENCY, API_MAX_COMICS, APP_ICON_URL, APP_NAME, APP_SHORT_NAME, APP_THEME_COLOUR,
+    APP_URL, ARCHIVE_MONTH_FMT, CBZ_CONTENT_TYPE, CBZ_MONTH_FMT, COMIC_AUTHOR, DISP_DATE_FMT,
+    DISP_DAY_FMT, DISP_MONTH_FMT, DOWNLOAD_PREFIX, FIRST_COMIC, GRID_MONTH_FMT, MANIFEST_MAX_AGE,
+    MAX_STRIP_LEN, NONCE_PLACEHOLDER, PREVIEW_HEIGHT, PREVIEW_WIDTH, REPO_URL, SRC_DATE_FMT,
+    THUMBNAIL_WIDTH, WARM_INTERVAL,
+};
+use crate::csp::CspNonce;
+use crate::datetime::{date_to_number, str_to_date};
+use crate::db::CacheBackend;
 use crate::errors::{AppError, AppResult, MinificationError};
-use crate::scraper::ComicData;
+use crate::favorites::Favorites;
+use crate::graphql::{ComicRange, ComicRequest, ComicRequester, ComicSchema};
+use crate::history::History;
+use crate::i18n::Lang;
+use crate::overlay::Attribution;
+use crate::prefs::ClientPrefs;
+use crate::reporting::report_error;
+use crate::retry::RetryPolicy;
 #[mockall_double::double]
-use crate::scraper::ComicScraper;
-use crate::templates::{ComicTemplate, ErrorTemplate, NotFoundTemplate};
+use crate::scrapers::ComicScraper;
+use crate::scrapers::{
+    get_search_words, normalize_tag, ComicData, ComicImage, ComicSource, ImageInfo,
+    LatestDateScraper,
+};
+use crate::templates::{
+    ArchiveEntry, ArchiveIndexTemplate, ArchiveTemplate, BadRequestTemplate, CalendarDay,
+    ComicListEntry, ComicListTemplate, ComicTemplate, ErrorTemplate, FeedTemplate, GridEntry,
+    GridTemplate, NotFoundTemplate, StripEntry, StripTemplate, TextTemplate,
+    TooManyRequestsTemplate, WeekTemplate,
+};
+use crate::theme::Theme;
+use crate::throttle::Throttle;
 
-pub struct Viewer<T: RedisPool + 'static> {
+/// The end of the available comics past which a requested date was
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutOfRange {
+    /// The date was before the first comic
+    Before,
+    /// The date was after the latest comic
+    After,
+}
+
+impl OutOfRange {
+    /// Get the name of the end, as given in the URL of the comic at that end.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Before => "before",
+            Self::After => "after",
+        }
+    }
+}
+
+pub struct Viewer<T: CacheBackend + 'static> {
+    /// The site of the comics
+    source: Arc<dyn ComicSource>,
     /// The scraper for comics given date
     comic_scraper: ComicScraper<T>,
+    /// The scraper for the date of the latest comic
+    latest_date_scraper: LatestDateScraper<T>,
+    /// The minified CSS to inline into comic pages, if enabled
+    inline_css: Option<String>,
+    /// Whether navigation wraps around at the first and last comics
+    nav_wrap: bool,
+    /// The `robots.txt` for crawlers
+    robots_txt: GeneratedFile,
+    /// The number of days in the feed
+    feed_len: usize,
+    /// The date of the last comic in the feed, or the latest comic if not given
+    feed_end: Option<NaiveDate>,
+    /// The attribution to overlay on served comic images, if enabled
+    attribution: Option<Attribution>,
 }
 
-impl<T: RedisPool + Clone + 'static> Viewer<T> {
+impl<T: CacheBackend + Clone + 'static> Viewer<T> {
     /// Initialize all necessary stuff for the viewer.
-    pub fn new(db: Option<T>, base_url: String, cdx_url: String) -> Self {
-        let comic_scraper = ComicScraper::new(db, base_url, cdx_url);
-        Self { comic_scraper }
+    ///
+    /// # Arguments
+    /// * `db` - The optional cache for comics
+    /// * `source` - The site of the comics
+    /// * `config` - The configuration for the viewer
+    /// * `inline_css` - The minified CSS to inline into comic pages, if enabled
+    /// * `robots_txt` - The `robots.txt` for crawlers
+    /// * `throttle` - The throttle for requests to the comic source, shared with other workers
+    pub fn new(
+        db: Option<T>,
+        source: Arc<dyn ComicSource>,
+        config: &Config,
+        inline_css: Option<String>,
+        robots_txt: GeneratedFile,
+        throttle: Arc<Throttle>,
+    ) -> Self {
+        let http_client = build_client(config, StdDuration::from_secs(config.resp_timeout));
+        let retry = RetryPolicy {
+            max_attempts: config.retry_attempts,
+            backoff: StdDuration::from_millis(config.retry_backoff),
+            jitter: config.retry_jitter,
+        };
+        let comic_scraper = ComicScraper::new(
+            db.clone(),
+            source.clone(),
+            http_client.clone(),
+            retry.clone(),
+            throttle.clone(),
+            config.scrape_lock,
+        );
+        let latest_date_scraper = LatestDateScraper::new(
+            db,
+            source.clone(),
+            http_client,
+            retry,
+            throttle,
+            config.live_latest,
+        );
+        Self {
+            source,
+            comic_scraper,
+            latest_date_scraper,
+            inline_css,
+            nav_wrap: config.nav_wrap,
+            robots_txt,
+            feed_len: config.feed_len,
+            feed_end: config.feed_end,
+            attribution: None,
+        }
+    }
+
+    /// Overlay an attribution on the served comic images.
+    ///
+    /// # Arguments
+    /// * `attribution` - The attribution to overlay, or None to serve the images as they are
+    pub fn with_attribution(mut self, attribution: Option<Attribution>) -> Self {
+        self.attribution = attribution;
+        self
+    }
+
+    /// Serve the `robots.txt` for crawlers.
+    ///
+    /// # Arguments
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `if_modified_since` - The time when the client's cached copy was last modified, if any
+    pub fn serve_robots_txt(
+        &self,
+        if_none_match: Option<&IfNoneMatch>,
+        if_modified_since: Option<&IfModifiedSince>,
+    ) -> HttpResponse {
+        self.robots_txt
+            .serve(ContentType::plaintext(), if_none_match, if_modified_since)
+    }
+
+    /// Get the inclusive range of dates of the available comics.
+    ///
+    /// The range ends at the latest comic, which may be detected from the source.
+    pub async fn get_comic_range(&self) -> AppResult<RangeInclusive<NaiveDate>> {
+        let first_comic = *self.source.date_range()?.start();
+        let last_comic = self.latest_date_scraper.get_latest_date().await?;
+        Ok(first_comic..=last_comic)
     }
 
     /// Get the info about the requested comic.
@@ -41,474 +209,4390 @@ impl<T: RedisPool + Clone + 'static> Viewer<T> {
         }
     }
 
+    /// Serve the requested comic, without handling errors.
+    ///
+    /// The rendered page is cached, and served directly from the cache if possible.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    /// * `clamped` - The end of the available comics past which the client originally requested
+    ///   a date, if it was redirected here
+    /// * `nonce` - The CSP nonce for inline content in the response
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `prefs` - The preferences of the client
+    async fn serve_comic_raw(
+        &self,
+        date: &NaiveDate,
+        clamped: Option<OutOfRange>,
+        nonce: &CspNonce,
+        if_none_match: Option<&IfNoneMatch>,
+        prefs: &ClientPrefs,
+    ) -> AppResult<HttpResponse> {
+        let (first_comic, last_comic) = self.get_comic_range().await?.into_inner();
+
+        // Dates past either end of the comics are redirected to the comic at that end.
+        let out_of_range = if *date < first_comic {
+            Some((first_comic, OutOfRange::Before))
+        } else if *date > last_comic {
+            Some((last_comic, OutOfRange::After))
+        } else {
+            None
+        };
+        if let Some((target, out_of_range)) = out_of_range {
+            let target = target.format(SRC_DATE_FMT);
+            info!("Redirecting out-of-range date {date} to {target}");
+            return Ok(HttpResponse::Found()
+                .append_header((
+                    LOCATION,
+                    format!("/{target}?clamped={}", out_of_range.as_str()),
+                ))
+                .finish());
+        }
+
+        let comic_data = self.get_comic_info(date).await?;
+        let missing = match self.comic_scraper.get_missing_dates().await {
+            Ok(missing) => missing,
+            Err(err) => {
+                // Navigation can still go through missing comics, so simply log the error.
+                error!("Error retrieving missing comics: {err}");
+                Vec::new()
+            }
+        };
+
+        // Links to previous and next comics
+        let mut nav = get_navigation(date, first_comic, last_comic, &missing, self.nav_wrap);
+        // Only show the notice on the comics that out-of-range dates are redirected to.
+        nav.clamped = clamped.filter(|clamped| match clamped {
+            OutOfRange::Before => *date == first_comic,
+            OutOfRange::After => *date == last_comic,
+        });
+        let inline_css = self.inline_css.as_deref();
+        // The ETag is computed from the inputs to the page, since the page itself has a fresh nonce.
+        let etag = get_comic_etag(date, &comic_data, &nav, inline_css, prefs);
+        // The page depends on the cookies for the theme and favorites, and on the language, so
+        // caches must not share it across them.
+        let vary = (VARY, "Accept-Language, Cookie");
+        if etag_matches(if_none_match, &etag) {
+            return Ok(HttpResponse::NotModified()
+                .insert_header(ETag(etag))
+                .insert_header(vary)
+                .finish());
+        }
+
+        // The ETag covers all inputs to the page, so it also identifies the cached page.
+        let page = if let Some(page) = self.comic_scraper.get_cached_page(date, etag.tag()).await {
+            page
+        } else {
+            let page = render_template(
+                date,
+                &comic_data,
+                &nav,
+                inline_css,
+                NONCE_PLACEHOLDER,
+                prefs,
+            )?;
+            self.comic_scraper.cache_page(date, etag.tag(), &page).await;
+            page
+        };
+        // The nonce is only in the inlined CSS, which is in the head before any comic data.
+        let page = if inline_css.is_some() {
+            page.replacen(NONCE_PLACEHOLDER, &nonce.0, 1)
+        } else {
+            page
+        };
+
+        Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .insert_header(ETag(etag))
+            .insert_header(vary)
+            .body(page))
+    }
+
     /// Serve the requested comic.
     ///
-    /// If an error is raised, then a 500 internal server error response is returned.
+    /// If the date is past either end of the available comics, then the client is redirected to
+    /// the comic at that end. If the client's cached copy is still valid, then a 304 not modified
+    /// response is returned. If an error is raised, then a 500 internal server error response is
+    /// returned.
     ///
     /// # Arguments
     /// * `date` - The date of the requested comic
-    pub async fn serve_comic(&self, date: &NaiveDate) -> HttpResponse {
+    /// * `clamped` - The end of the available comics past which the client originally requested
+    ///   a date, if it was redirected here
+    /// * `nonce` - The CSP nonce for inline content in the response
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `prefs` - The preferences of the client
+    pub async fn serve_comic(
+        &self,
+        date: &NaiveDate,
+        clamped: Option<OutOfRange>,
+        nonce: &CspNonce,
+        if_none_match: Option<&IfNoneMatch>,
+        prefs: &ClientPrefs,
+    ) -> HttpResponse {
         match self
-            .get_comic_info(date)
+            .serve_comic_raw(date, clamped, nonce, if_none_match, prefs)
             .await
-            .and_then(|info| serve_template(date, &info))
         {
             Ok(response) => response,
-            Err(AppError::NotFound(..)) => serve_404(Some(date)),
-            Err(err) => serve_500(&err),
+            Err(AppError::NotFound(..)) => serve_themed_404(Some(date), prefs.theme),
+            Err(err) => serve_themed_500(&err, Some(date), prefs.theme),
         }
     }
-}
-
-fn minify_html(mut html: String) -> AppResult<String> {
-    let old_len = html.len();
-    let result = minify_html::in_place_str(html.as_mut_str(), &minify_html::Cfg::new());
-
-    // The in-place minification returns a slice to the minified part, but leaves the rest of
-    // the string as-is. Hence, we get the length of the slice and truncate the string, since
-    // we want to return an owned string.
-    let new_len = match result {
-        Ok(slice) => slice.len(),
-        Err(err) => Err(MinificationError::Html(err))?,
-    };
-    html.truncate(new_len);
 
-    debug!("Minified HTML from {old_len} bytes to {}", html.len());
-    Ok(html)
-}
+    /// Serve the latest comic.
+    ///
+    /// If the client's cached copy is still valid, then a 304 not modified response is returned.
+    /// If an error is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `nonce` - The CSP nonce for inline content in the response
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `prefs` - The preferences of the client
+    pub async fn serve_latest_comic(
+        &self,
+        nonce: &CspNonce,
+        if_none_match: Option<&IfNoneMatch>,
+        prefs: &ClientPrefs,
+    ) -> HttpResponse {
+        match self.latest_date_scraper.get_latest_date().await {
+            Ok(date) => {
+                self.serve_comic(&date, None, nonce, if_none_match, prefs)
+                    .await
+            }
+            Err(err) => serve_themed_500(&err, None, prefs.theme),
+        }
+    }
 
-/// Serve the rendered HTML given scraped data.
-///
-/// # Arguments
-/// * `date` - The date of the comic
-/// * `comic_data` - The scraped comic data
-fn serve_template(date: &NaiveDate, comic_data: &ComicData) -> AppResult<HttpResponse> {
-    let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT)?;
-    let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT)?;
-
-    // Links to previous and next comics
-    let previous_comic = &max(first_comic, *date - Duration::days(1))
-        .format(SRC_DATE_FMT)
-        .to_string();
-    let next_comic = &min(last_comic, *date + Duration::days(1))
-        .format(SRC_DATE_FMT)
-        .to_string();
+    /// Serve the image of the requested comic.
+    ///
+    /// For HEAD requests, only the image's metadata is retrieved, which avoids downloading the
+    /// image if possible. If the comic doesn't exist, then a 404 not found response is returned.
+    /// If any other error is raised, then a 500 internal server error response is returned.
+    ///
+    /// If the client accepts WebP, then the image is transcoded into WebP. Its metadata can't be
+    /// known without transcoding it, so this is also done for HEAD requests. If an attribution is
+    /// overlaid, then the overlaid image is served instead, for both GET and HEAD requests.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    /// * `head` - Whether this is for a HEAD request
+    /// * `webp` - Whether the client accepts WebP images
+    pub async fn serve_comic_image(
+        &self,
+        date: &NaiveDate,
+        head: bool,
+        webp: bool,
+    ) -> HttpResponse {
+        let result = if let Some(attribution) = &self.attribution {
+            self.comic_scraper
+                .get_comic_image_overlaid(date, attribution)
+                .await
+                .map(|image| {
+                    image.map(|image| {
+                        if head {
+                            serve_image_info(ImageInfo::from(&image))
+                        } else {
+                            serve_image(image)
+                        }
+                    })
+                })
+        } else if webp {
+            self.comic_scraper
+                .get_comic_image_webp(date)
+                .await
+                .map(|image| {
+                    image.map(|image| {
+                        if head {
+                            serve_image_info(ImageInfo::from(&image))
+                        } else {
+                            serve_image(image)
+                        }
+                    })
+                })
+        } else if head {
+            self.comic_scraper
+                .get_comic_image_info(date)
+                .await
+                .map(|info| info.map(serve_image_info))
+        } else {
+            self.comic_scraper
+                .get_comic_image(date)
+                .await
+                .map(|image| image.map(serve_image))
+        };
+        match result {
+            Ok(Some(response)) => response,
+            Ok(None) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-    let template = ComicTemplate {
-        data: comic_data,
-        date_disp: &date.format(DISP_DATE_FMT).to_string(),
-        date: &date.format(SRC_DATE_FMT).to_string(),
-        first_comic: FIRST_COMIC,
-        previous_comic,
-        next_comic,
-        disable_left_nav: *date == first_comic,
-        disable_right_nav: *date == last_comic,
-        permalink: &comic_data.permalink,
-        app_url: APP_URL,
-        repo_url: REPO_URL,
-    };
-    debug!("Rendering comic template: {template:?}");
+    /// Serve another resolution of the image of the requested comic.
+    ///
+    /// If an attribution is overlaid, then the overlaid original image is served instead, so that
+    /// every served image has the attribution. If the comic or the variant doesn't exist, then a
+    /// 404 not found response is returned. If any other error is raised, then a 500 internal
+    /// server error response is returned.
+    ///
+    /// The variant's metadata isn't scraped, so it's retrieved (and cached) for HEAD requests too.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    /// * `width` - The width of the requested variant
+    /// * `head` - Whether this is for a HEAD request
+    pub async fn serve_comic_image_variant(
+        &self,
+        date: &NaiveDate,
+        width: i32,
+        head: bool,
+    ) -> HttpResponse {
+        let result = if let Some(attribution) = &self.attribution {
+            self.comic_scraper
+                .get_comic_image_overlaid(date, attribution)
+                .await
+        } else {
+            self.comic_scraper
+                .get_comic_image_variant(date, width)
+                .await
+        };
+        match result {
+            Ok(Some(image)) if head => serve_image_info(ImageInfo::from(&image)),
+            Ok(Some(image)) => serve_image(image),
+            Ok(None) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(minify_html(template.render()?)?))
-}
+    /// Serve the image of the requested comic as a download, named after the comic's date.
+    ///
+    /// If the comic doesn't exist, then a 404 not found response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    pub async fn serve_comic_download(&self, date: &NaiveDate) -> HttpResponse {
+        let result = if let Some(attribution) = &self.attribution {
+            self.comic_scraper
+                .get_comic_image_overlaid(date, attribution)
+                .await
+        } else {
+            self.comic_scraper.get_comic_image(date).await
+        };
+        match result {
+            Ok(Some(image)) => serve_download(date, image),
+            Ok(None) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-/// Load a file from disk
-async fn load_file(path: &Path) -> AppResult<String> {
-    let file = match tokio::fs::read(path).await {
-        Ok(text) => text,
-        Err(err) => return Err(AppError::NotFound(err.to_string())),
-    };
-    Ok(std::str::from_utf8(&file)?.to_string())
-}
+    /// Get the next comic out of the given ones whose image is cached, skipping the others.
+    ///
+    /// # Arguments
+    /// * `dates` - The dates of the comics, in order
+    async fn next_cached_image(
+        &self,
+        dates: impl Iterator<Item = NaiveDate>,
+    ) -> Option<(NaiveDate, ComicImage)> {
+        for date in dates {
+            match self.comic_scraper.get_cached_comic_image(&date).await {
+                Ok(Some(image)) => return Some((date, image)),
+                Ok(None) => debug!("Skipping uncached comic for {date} in archive"),
+                Err(err) => {
+                    report_error(&err, Some(&date));
+                    error!("Couldn't get comic image for {date} in archive: {err}");
+                }
+            }
+        }
+        None
+    }
 
-/// Serve the requested CSS file with minification, without handling errors.
-async fn serve_css_raw(path: &Path) -> AppResult<HttpResponse> {
-    let css = load_file(path).await?;
+    /// Serve a comic book archive (CBZ) of the comics in a month, streamed as they're retrieved.
+    ///
+    /// The images are named after the comics' dates, so that comic book readers show them in
+    /// order. Only comics whose images are already cached are included, since a single request
+    /// shouldn't make a month's worth of requests to the comic source. If none of them are, then a
+    /// 404 not found response is returned.
+    ///
+    /// # Arguments
+    /// * `month` - Any date in the requested month
+    pub async fn serve_cbz(self: Arc<Self>, month: &NaiveDate) -> HttpResponse {
+        let (start, end) = match self.get_comic_range().await {
+            Ok(range) => match get_month_range(month, &range) {
+                Some(month_range) => month_range,
+                None => return serve_404(None),
+            },
+            Err(err) => return serve_500(&err, None),
+        };
 
-    let minified = match minifier::css::minify(&css) {
-        Ok(minified) => minified.to_string(),
-        Err(err) => return Err(MinificationError::Css(err.into()).into()),
-    };
-    debug!(
-        "Minified \"{}\" from {} bytes to {}",
-        path.display(),
-        css.len(),
-        minified.len()
-    );
+        let mut dates = start.iter_days().take_while(move |date| *date <= end);
+        // Look for the first comic before responding, so that a 404 can be served without one.
+        let Some(first) = self.next_cached_image(dates.by_ref()).await else {
+            return serve_404(None);
+        };
+        let state = Some((self, CbzWriter::default(), dates, Some(first)));
+        let chunks = stream::unfold(state, |state| async move {
+            let (viewer, mut writer, mut dates, pending) = state?;
+            let next = match pending {
+                Some(next) => Some(next),
+                None => viewer.next_cached_image(dates.by_ref()).await,
+            };
+            let Some((date, image)) = next else {
+                return Some((writer.finish(), None));
+            };
+            let name = download_filename(&date, &image.content_type);
+            match writer.add_image(&date, &name, &image.bytes) {
+                Ok(chunk) => Some((Ok(chunk), Some((viewer, writer, dates, None)))),
+                Err(err) => Some((Err(err), None)),
+            }
+        });
 
-    Ok(HttpResponse::Ok()
-        .content_type("text/css;charset=utf-8")
-        .body(minified))
-}
+        let filename = format!("{DOWNLOAD_PREFIX}-{}.cbz", start.format(CBZ_MONTH_FMT));
+        HttpResponse::Ok()
+            .content_type(CBZ_CONTENT_TYPE)
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(filename)],
+            })
+            .streaming(chunks)
+    }
 
-/// Serve the requested CSS file with minification.
-///
-/// If an error is raised, then a 500 internal server error response is returned.
-///
-/// # Arguments
-/// * `path` - The path to the CSS file
-pub async fn serve_css(path: &Path) -> HttpResponse {
-    match serve_css_raw(path).await {
-        Ok(resp) => resp,
-        Err(AppError::NotFound(..)) => serve_404(None),
-        Err(err) => serve_500(&err),
+    /// Serve the social-share preview image of the requested comic.
+    ///
+    /// If the comic doesn't exist, then a 404 not found response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    pub async fn serve_comic_preview(&self, date: &NaiveDate) -> HttpResponse {
+        match self.comic_scraper.get_comic_preview(date).await {
+            Ok(Some(image)) => serve_image(image),
+            Ok(None) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
     }
-}
 
-/// Serve the requested JavaScript file with minification, without handling errors.
-async fn serve_js_raw(path: &Path) -> AppResult<HttpResponse> {
-    let js = load_file(path).await?;
+    /// Serve the downscaled thumbnail of the requested comic.
+    ///
+    /// If the comic doesn't exist, then a 404 not found response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    pub async fn serve_comic_thumbnail(&self, date: &NaiveDate) -> HttpResponse {
+        match self.comic_scraper.get_comic_thumbnail(date).await {
+            Ok(Some(image)) => serve_image(image),
+            Ok(None) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-    let minified = minifier::js::minify(&js).to_string();
-    debug!(
-        "Minified \"{}\" from {} bytes to {}",
-        path.display(),
-        js.len(),
-        minified.len()
-    );
+    /// Serve the text-only view of the requested comic, for screen readers and text browsers.
+    ///
+    /// If the comic doesn't exist, then a 404 not found response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    pub async fn serve_comic_text(&self, date: &NaiveDate) -> HttpResponse {
+        match self
+            .get_comic_info(date)
+            .await
+            .and_then(|comic_data| serve_text_template(date, &comic_data))
+        {
+            Ok(response) => response,
+            Err(AppError::NotFound(..)) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-    Ok(HttpResponse::Ok()
-        .content_type("text/javascript;charset=utf-8")
-        .body(minified))
-}
+    /// Serve the oEmbed response for the requested comic, for rich embeds of its page.
+    ///
+    /// The comic's image is given as a photo embed, using the image served by the app. If the
+    /// comic doesn't exist, then a 404 not found response is returned. If any other error is
+    /// raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    /// * `lang` - The language of the comic's fallback title
+    pub async fn serve_oembed(&self, date: &NaiveDate, lang: Lang) -> HttpResponse {
+        match self.get_comic_info(date).await {
+            Ok(comic_data) => serve_oembed_json(date, &comic_data, lang),
+            Err(AppError::NotFound(..)) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
 
-/// Serve the requested JavaScript file with minification.
-///
-/// If an error is raised, then a 500 internal server error response is returned.
-///
-/// # Arguments
-/// * `path` - The path to the JavaScript file
-pub async fn serve_js(path: &Path) -> HttpResponse {
-    match serve_js_raw(path).await {
-        Ok(resp) => resp,
-        Err(AppError::NotFound(..)) => serve_404(None),
-        Err(err) => serve_500(&err),
+    /// Serve the data of the requested comic as JSON, for use by other apps.
+    ///
+    /// If the comic doesn't exist, then a 404 not found response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the requested comic
+    pub async fn serve_comic_data(&self, date: &NaiveDate) -> HttpResponse {
+        let result = async {
+            let range = self.get_comic_range().await?;
+            let comic_data = self.get_comic_info(date).await?;
+            Ok(serve_comic_data_json(date, &comic_data, &range))
+        }
+        .await;
+        match result {
+            Ok(response) => response,
+            Err(AppError::NotFound(..)) => serve_404(Some(date)),
+            Err(err) => serve_500(&err, Some(date)),
+        }
     }
-}
 
-/// Serve a 404 not found response for invalid URLs, without handling errors.
-fn serve_404_raw(date: Option<&NaiveDate>) -> AppResult<HttpResponse> {
-    let date_str = date.map(|date| date.format(SRC_DATE_FMT).to_string());
-    let template = NotFoundTemplate {
-        date: date_str.as_deref(),
-        repo_url: REPO_URL,
-    };
-    debug!("Rendering 404 template: {template:?}");
-    Ok(HttpResponse::NotFound()
-        .content_type(ContentType::html())
-        .body(minify_html(template.render()?)?))
-}
+    /// Serve the data of the comics in an inclusive range of dates as JSON.
+    ///
+    /// Only the first comics in the range are served, up to the limit, and they're retrieved
+    /// concurrently. Each missing comic, or comic that couldn't be retrieved, is marked with an
+    /// error in place of its data. If the range is invalid, then a 400 bad request response is
+    /// returned.
+    ///
+    /// # Arguments
+    /// * `start` - The date of the first comic in the range
+    /// * `end` - The date of the last comic in the range
+    /// * `limit` - The maximum number of comics to serve, which is capped
+    pub async fn serve_comics_data(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        limit: Option<NonZeroUsize>,
+    ) -> HttpResponse {
+        let limit = limit.map_or(API_MAX_COMICS, |limit| limit.get().min(API_MAX_COMICS));
+        // The range is truncated before it's checked, so that it's allowed to be longer.
+        let end = min(*end, *start + Duration::days(limit as i64 - 1));
+        let range = match self.get_comic_range().await {
+            Ok(range) => range,
+            Err(err) => return serve_500(&err, None),
+        };
+        if let Err(err) = check_range(start, &end, &range) {
+            return match err {
+                AppError::BadRequest(msg) => serve_400(&msg),
+                err => serve_500(&err, None),
+            };
+        }
 
-/// Serve a 404 not found response for invalid URLs.
-///
-/// If an error is raised, then a 500 internal server error response is returned.
-///
-/// # Arguments
-/// * `date` - The date of the requested comic, if available. This must be a valid date for
-///            which a comic doesn't exist.
-pub fn serve_404(date: Option<&NaiveDate>) -> HttpResponse {
-    match serve_404_raw(date) {
-        Ok(response) => response,
-        Err(err) => serve_500(&err),
+        let dates = start.iter_days().take_while(|date| *date <= end);
+        let results: Vec<_> = stream::iter(dates)
+            .map(|date| async move { (date, self.comic_scraper.get_comic_data(&date).await) })
+            .buffered(API_CONCURRENCY)
+            .collect()
+            .await;
+
+        let comics: Vec<_> = results
+            .into_iter()
+            .map(|(date, result)| match result {
+                Ok(Some(comic_data)) => comic_data_json(&date, &comic_data, &range),
+                Ok(None) => json!({
+                    "date": date.format(SRC_DATE_FMT).to_string(),
+                    "error": format!("No comic found for {date}"),
+                }),
+                Err(err) => {
+                    report_error(&err, Some(&date));
+                    error!("Couldn't get comic data for {date}: {err}");
+                    json!({
+                        "date": date.format(SRC_DATE_FMT).to_string(),
+                        "error": err.to_string(),
+                    })
+                }
+            })
+            .collect();
+        HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .body(serde_json::Value::from(comics).to_string())
     }
-}
 
-/// Serve a 500 internal server error response.
-///
-/// # Arguments
+    /// Serve the response to a GraphQL request.
+    ///
+    /// The resolvers can't use the comic scraper, since they must be thread-safe, so they request
+    /// comics from here instead, which are retrieved concurrently. Errors are given in the
+    /// response, as per GraphQL conventions.
+    ///
+    /// # Arguments
+    /// * `schema` - The schema of the GraphQL API
+    /// * `request` - The GraphQL request
+    pub async fn serve_graphql(&self, schema: &ComicSchema, request: Request) -> HttpResponse {
+        let range = match self.get_comic_range().await {
+            Ok(range) => range,
+            Err(err) => return serve_500(&err, None),
+        };
+        let (sender, receiver) = unbounded();
+        let request = request.data(ComicRequester(sender)).data(ComicRange(range));
+        let execution = schema.execute(request);
+        let retrievals =
+            receiver.for_each_concurrent(None, |(date, reply): ComicRequest| async move {
+                let result = self.comic_scraper.get_comic_data(&date).await;
+                if let Err(err) = &result {
+                    report_error(err, Some(&date));
+                    error!("Couldn't get comic data for GraphQL: {err}");
+                }
+                // The resolver may have been cancelled, in which case this can be ignored.
+                let _ = reply.send(result.map_err(|err| err.to_string()));
+            });
+
+        let response = match select(pin!(execution), pin!(retrievals)).await {
+            Either::Left((response, _)) => response,
+            // The requests only end when the execution drops the requester, i.e. when it's done.
+            Either::Right(((), execution)) => execution.await,
+        };
+        match serde_json::to_string(&response) {
+            Ok(body) => HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .body(body),
+            Err(err) => serve_500(&err.into(), None),
+        }
+    }
+
+    /// Purge the requested comic from the cache, so that it's scraped again when requested.
+    ///
+    /// If an error is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    pub async fn serve_purge(&self, date: &NaiveDate) -> HttpResponse {
+        match self.comic_scraper.purge(date).await {
+            Ok(()) => HttpResponse::NoContent().finish(),
+            Err(err) => serve_500(&err, Some(date)),
+        }
+    }
+
+    /// Purge everything from the cache.
+    ///
+    /// If an error is raised, then a 500 internal server error response is returned.
+    pub async fn serve_purge_all(&self) -> HttpResponse {
+        match self.comic_scraper.purge_all().await {
+            Ok(()) => HttpResponse::NoContent().finish(),
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Warm up the cache with the comics in a range of dates, by scraping the ones not cached.
+    ///
+    /// The comics are scraped one at a time, with a pause after each scrape, so that the comic
+    /// source isn't overloaded. Failures are logged and skipped.
+    ///
+    /// # Arguments
+    /// * `dates` - The inclusive range of dates of the comics
+    /// * `interval` - The pause after each scrape
+    ///
+    /// # Returns
+    /// The number of comics scraped
+    pub async fn warm_cache(
+        &self,
+        dates: RangeInclusive<NaiveDate>,
+        interval: StdDuration,
+    ) -> usize {
+        info!(
+            "Warming up the cache from {} to {}",
+            dates.start(),
+            dates.end()
+        );
+        let mut scraped = 0;
+        for date in dates
+            .start()
+            .iter_days()
+            .take_while(|date| date <= dates.end())
+        {
+            match self.comic_scraper.get_cached_comic_data(&date).await {
+                Ok(Some(_)) => continue,
+                Ok(None) => {}
+                // Better to scrape it again than skip it, so simply log the error.
+                Err(err) => error!("Error retrieving {date} from cache: {err}"),
+            }
+            match self.comic_scraper.get_comic_data(&date).await {
+                Ok(_) => scraped += 1,
+                Err(err) => error!("Error warming up the cache with {date}: {err}"),
+            }
+            sleep(interval).await;
+        }
+        info!("Finished warming up the cache, with {scraped} comics scraped");
+        scraped
+    }
+
+    /// Get the info about all available comics in the given inclusive range.
+    ///
+    /// Missing comics are skipped.
+    async fn get_strip_info(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> AppResult<Vec<StripEntry>> {
+        check_range(start, end, &self.get_comic_range().await?)?;
+        let dates: Vec<_> = start.iter_days().take_while(|date| date <= end).collect();
+        self.get_comics_info(dates).await
+    }
+
+    /// Get the info about all available comics on the given dates, concurrently.
+    ///
+    /// Missing comics are skipped.
+    ///
+    /// # Arguments
+    /// * `dates` - The dates of the comics, in order
+    async fn get_comics_info(&self, dates: Vec<NaiveDate>) -> AppResult<Vec<StripEntry>> {
+        let results = join_all(
+            dates
+                .iter()
+                .map(|date| self.comic_scraper.get_comic_data(date)),
+        )
+        .await;
+
+        let mut comics = Vec::with_capacity(dates.len());
+        for (date, result) in dates.into_iter().zip(results) {
+            if let Some(data) = result? {
+                comics.push(StripEntry {
+                    date: date.format(SRC_DATE_FMT).to_string(),
+                    date_disp: date.format(DISP_DATE_FMT).to_string(),
+                    data,
+                });
+            }
+        }
+        Ok(comics)
+    }
+
+    /// Serve all available comics in the given inclusive range.
+    ///
+    /// If the range is invalid, then a 400 bad request response is returned. If any other error
+    /// is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `start` - The date of the first comic in the range
+    /// * `end` - The date of the last comic in the range
+    pub async fn serve_strip(&self, start: &NaiveDate, end: &NaiveDate) -> HttpResponse {
+        match self.get_strip_info(start, end).await.and_then(|comics| {
+            let title = format!(
+                "{} to {}",
+                start.format(SRC_DATE_FMT),
+                end.format(SRC_DATE_FMT)
+            );
+            serve_strip_template(&title, &comics, "No comics found in this range")
+        }) {
+            Ok(response) => response,
+            Err(AppError::BadRequest(msg)) => serve_400(&msg),
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve all available comics in the week (from Sunday to Saturday) with the given date.
+    ///
+    /// The comics are retrieved concurrently, and missing comics are skipped. If the week has no
+    /// comics, then a 404 not found response is returned. If any other error is raised, then a
+    /// 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `date` - Any date in the requested week
+    pub async fn serve_week(&self, date: &NaiveDate) -> HttpResponse {
+        match self.serve_week_raw(date).await {
+            Ok(Some(response)) => response,
+            Ok(None) => serve_404(None),
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve all available comics in the week with the given date, without handling errors.
+    ///
+    /// If the week has no comics, then None is returned.
+    ///
+    /// # Arguments
+    /// * `date` - Any date in the requested week
+    async fn serve_week_raw(&self, date: &NaiveDate) -> AppResult<Option<HttpResponse>> {
+        let range = self.get_comic_range().await?;
+        let (first_comic, last_comic) = (*range.start(), *range.end());
+        let weekday = date.weekday().num_days_from_sunday();
+        let Some(week_start) = date.checked_sub_days(Days::new(weekday.into())) else {
+            return Ok(None);
+        };
+
+        let has_comics = |start: NaiveDate| {
+            start <= last_comic
+                && start
+                    .checked_add_days(Days::new(6))
+                    .is_some_and(|end| end >= first_comic)
+        };
+        if !has_comics(week_start) {
+            return Ok(None);
+        }
+        let get_path = |start: Option<NaiveDate>| {
+            start
+                .filter(|start| has_comics(*start))
+                .map(|start| start.format(SRC_DATE_FMT).to_string())
+        };
+        let previous_week = get_path(week_start.checked_sub_days(Days::new(7)));
+        let next_week = get_path(week_start.checked_add_days(Days::new(7)));
+
+        let dates = week_start
+            .iter_days()
+            .take(7)
+            .filter(|date| (first_comic..=last_comic).contains(date))
+            .collect();
+        let comics = self.get_comics_info(dates).await?;
+
+        let title = format!("Week of {}", week_start.format(DISP_DATE_FMT));
+        serve_week_template(
+            &title,
+            &comics,
+            previous_week.as_deref(),
+            next_week.as_deref(),
+        )
+        .map(Some)
+    }
+
+    /// Serve the most recent cached comics with a tag, newest first.
+    ///
+    /// If an error is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `tag` - The tag, with or without the leading "#"
+    pub async fn serve_tag(&self, tag: &str) -> HttpResponse {
+        let tag = normalize_tag(tag);
+        match self
+            .comic_scraper
+            .get_tagged_comics(&tag)
+            .await
+            .and_then(|comics| {
+                serve_strip_template(
+                    &format!("Comics tagged #{tag}"),
+                    &get_strip_entries(comics),
+                    "No comics with this tag have been viewed yet",
+                )
+            }) {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve the most recent cached comics that match a search query, newest first.
+    ///
+    /// If an error is raised, then a 500 internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `query` - The search query
+    pub async fn serve_search(&self, query: &str) -> HttpResponse {
+        let query = query.trim();
+        let empty_msg = if get_search_words(query).is_empty() {
+            "Search for comics by the words in their titles or transcripts"
+        } else {
+            "No viewed comics match this search"
+        };
+        match self
+            .comic_scraper
+            .search_comics(query)
+            .await
+            .and_then(|comics| {
+                serve_strip_template(
+                    &format!("Search results for \"{query}\""),
+                    &get_strip_entries(comics),
+                    empty_msg,
+                )
+            }) {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve the comics on the same day of the year as the given date, across all years.
+    ///
+    /// Missing comics are skipped. If an error is raised, then a 500 internal server error
+    /// response is returned.
+    ///
+    /// # Arguments
+    /// * `today` - The date whose day of the year is used
+    pub async fn serve_on_this_day(&self, today: &NaiveDate) -> HttpResponse {
+        let result = match self.get_comic_range().await {
+            Ok(range) => {
+                self.get_comics_info(get_on_this_day_dates(today, &range))
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match result.and_then(|comics| {
+            serve_strip_template(
+                &format!("On this day: {}", today.format(DISP_DAY_FMT)),
+                &comics,
+                "No comics found on this day",
+            )
+        }) {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve the favorite comics of the client, the most recently marked first.
+    ///
+    /// Missing comics are skipped. If an error is raised, then a 500 internal server error
+    /// response is returned.
+    ///
+    /// # Arguments
+    /// * `favorites` - The favorite comics of the client
+    pub async fn serve_favorites(&self, favorites: &Favorites) -> HttpResponse {
+        let dates = favorites.dates().copied().collect();
+        match self.get_comics_info(dates).await.and_then(|comics| {
+            let entries: Vec<_> = comics
+                .into_iter()
+                .map(|comic| ComicListEntry {
+                    date: comic.date,
+                    date_disp: comic.date_disp,
+                    title: comic.data.title,
+                })
+                .collect();
+            serve_comic_list_template(
+                "Favorite comics",
+                &entries,
+                "Mark comics as favorites with the star on their pages",
+            )
+        }) {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve the comics recently viewed by the client, the most recent first.
+    ///
+    /// The titles of the comics are taken from the cache only, since they were viewed recently,
+    /// and are left out for comics that are no longer cached. If an error is raised, then a 500
+    /// internal server error response is returned.
+    ///
+    /// # Arguments
+    /// * `history` - The history of the client
+    pub async fn serve_history(&self, history: &History) -> HttpResponse {
+        let entries = self
+            .comic_scraper
+            .get_cached_comics_data(history.dates())
+            .await
+            .map(|results| {
+                history
+                    .dates()
+                    .iter()
+                    .zip(results)
+                    .map(|(date, comic_data)| ComicListEntry {
+                        date: date.format(SRC_DATE_FMT).to_string(),
+                        date_disp: date.format(DISP_DATE_FMT).to_string(),
+                        title: comic_data
+                            .map(|comic_data| comic_data.title)
+                            .unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>()
+            });
+        match entries.and_then(|entries| {
+            serve_comic_list_template("Recently viewed comics", &entries, "No comics viewed yet")
+        }) {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+
+    /// Serve an Atom feed of the comics in the feed's range, newest first.
+    ///
+    /// Missing comics are skipped. If an error is raised, then a 500 internal server error
+    /// response is returned.
+    pub async fn serve_feed(&self) -> HttpResponse {
+        let result = match self.get_comic_range().await {
+            Ok(range) => {
+                let dates = get_feed_dates(self.feed_len, self.feed_end, &range);
+                let (start, end) = (dates.start(), dates.end());
+                self.get_strip_info(start, end)
+                    .await
+                    .and_then(|mut comics| {
+                        // Feed readers expect the newest entries first.
+                        comics.reverse();
+                        serve_feed_template(end, &comics)
+                    })
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(response) => response,
+            Err(err) => serve_500(&err, None),
+        }
+    }
+}
+
+/// Get the inclusive range of dates of comics in the feed.
+///
+/// The range is clamped to the available comics, and to the maximum number of comics that can be
+/// fetched at once.
+///
+/// # Arguments
+/// * `len` - The number of days in the feed
+/// * `end` - The date of the last comic in the feed, or the last available comic if not given
+/// * `range` - The inclusive range of dates of the available comics
+pub fn get_feed_dates(
+    len: usize,
+    end: Option<NaiveDate>,
+    range: &RangeInclusive<NaiveDate>,
+) -> RangeInclusive<NaiveDate> {
+    let (first_comic, last_comic) = (*range.start(), *range.end());
+    let clamped_len = len.clamp(1, MAX_STRIP_LEN as usize);
+    if clamped_len != len {
+        warn!("Feed length {len} is not within 1 and {MAX_STRIP_LEN}; using {clamped_len}");
+    }
+    let end = end.unwrap_or(last_comic).clamp(first_comic, last_comic);
+    let start = max(end - Duration::days(clamped_len as i64 - 1), first_comic);
+    start..=end
+}
+
+/// Get the inclusive range of dates of comics for warming up the cache.
+///
+/// The range is clamped to the available comics.
+///
+/// # Arguments
+/// * `from` - The date of the first comic
+/// * `to` - The date of the last comic, or the last available comic if not given
+/// * `range` - The inclusive range of dates of the available comics
+pub fn get_warm_dates(
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+    range: &RangeInclusive<NaiveDate>,
+) -> AppResult<RangeInclusive<NaiveDate>> {
+    let (first_comic, last_comic) = (*range.start(), *range.end());
+
+    let start = max(from, first_comic);
+    let end = min(to.unwrap_or(last_comic), last_comic);
+    if end < start {
+        Err(AppError::BadRequest(format!(
+            "The range ends ({end}) before it starts ({start})"
+        )))
+    } else {
+        Ok(start..=end)
+    }
+}
+
+/// Warm up the cache with the comics in a range of dates in the background.
+///
+/// A 202 accepted response is returned once the warm-up starts. If the range is invalid, then a
+/// 400 bad request response is returned.
+///
+/// # Arguments
+/// * `viewer` - The viewer whose cache to warm up
+/// * `from` - The date of the first comic
+/// * `to` - The date of the last comic, or the last available comic if not given
+pub async fn serve_warm<T: CacheBackend + Clone + 'static>(
+    viewer: Arc<Viewer<T>>,
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+) -> HttpResponse {
+    let range = match viewer.get_comic_range().await {
+        Ok(range) => range,
+        Err(err) => return serve_500(&err, None),
+    };
+    let dates = match get_warm_dates(from, to, &range) {
+        Ok(dates) => dates,
+        Err(err) => return serve_400(&err.to_string()),
+    };
+    let message = format!(
+        "Warming up the cache from {} to {}",
+        dates.start(),
+        dates.end()
+    );
+    spawn(async move {
+        viewer
+            .warm_cache(dates, StdDuration::from_millis(WARM_INTERVAL))
+            .await;
+    });
+    HttpResponse::Accepted()
+        .content_type(ContentType::plaintext())
+        .body(message)
+}
+
+/// Get the entries for a page of multiple comics.
+///
+/// # Arguments
+/// * `comics` - The dates and data of the comics, in order
+fn get_strip_entries(comics: Vec<(NaiveDate, ComicData)>) -> Vec<StripEntry> {
+    comics
+        .into_iter()
+        .map(|(date, data)| StripEntry {
+            date: date.format(SRC_DATE_FMT).to_string(),
+            date_disp: date.format(DISP_DATE_FMT).to_string(),
+            data,
+        })
+        .collect()
+}
+
+/// Get the dates of all comics on the same day of the year as the given date, oldest first.
+///
+/// Years in which the day doesn't exist (such as February 29) are skipped.
+///
+/// # Arguments
+/// * `today` - The date whose day of the year is used
+/// * `range` - The inclusive range of dates of the available comics
+fn get_on_this_day_dates(today: &NaiveDate, range: &RangeInclusive<NaiveDate>) -> Vec<NaiveDate> {
+    (range.start().year()..=range.end().year())
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, today.month(), today.day()))
+        .filter(|date| range.contains(date))
+        .collect()
+}
+
+/// Get the dates of the first and last comics in a month.
+///
+/// If the month has no comics, then None is returned.
+///
+/// # Arguments
+/// * `month` - Any date in the month
+/// * `range` - The inclusive range of dates of the available comics
+fn get_month_range(
+    month: &NaiveDate,
+    range: &RangeInclusive<NaiveDate>,
+) -> Option<(NaiveDate, NaiveDate)> {
+    let start = month.with_day(1);
+    let end = (*month + Months::new(1))
+        .with_day(1)
+        .and_then(|date| date.pred_opt());
+    let (Some(start), Some(end)) = (start, end) else {
+        return None;
+    };
+    let (start, end) = (max(start, *range.start()), min(end, *range.end()));
+    (start <= end).then_some((start, end))
+}
+
+/// Check whether an inclusive range of comics can be shown on a single page.
+///
+/// # Arguments
+/// * `start` - The date of the first comic in the range
+/// * `end` - The date of the last comic in the range
+/// * `range` - The inclusive range of dates of the available comics
+pub fn check_range(
+    start: &NaiveDate,
+    end: &NaiveDate,
+    range: &RangeInclusive<NaiveDate>,
+) -> AppResult<()> {
+    let (first_comic, last_comic) = (range.start(), range.end());
+    if end < start {
+        Err(AppError::BadRequest(format!(
+            "The range ends ({end}) before it starts ({start})"
+        )))
+    } else if (*end - *start).num_days() >= MAX_STRIP_LEN {
+        Err(AppError::BadRequest(format!(
+            "The range can't be longer than {MAX_STRIP_LEN} days"
+        )))
+    } else if start < first_comic || end > last_comic {
+        Err(AppError::BadRequest(format!(
+            "The range must be within {first_comic} and {last_comic}"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn minify_html(mut html: String) -> AppResult<String> {
+    let old_len = html.len();
+    let result = minify_html::in_place_str(html.as_mut_str(), &minify_html::Cfg::new());
+
+    // The in-place minification returns a slice to the minified part, but leaves the rest of
+    // the string as-is. Hence, we get the length of the slice and truncate the string, since
+    // we want to return an owned string.
+    let new_len = match result {
+        Ok(slice) => slice.len(),
+        Err(err) => Err(MinificationError::Html(err))?,
+    };
+    html.truncate(new_len);
+
+    debug!("Minified HTML from {old_len} bytes to {}", html.len());
+    Ok(html)
+}
+
+/// Dates and states for navigating from a comic to its neighbours
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Navigation {
+    /// The date of the first comic
+    first: NaiveDate,
+    /// The date of the previous comic
+    previous: NaiveDate,
+    /// The date of the next comic
+    next: NaiveDate,
+    /// Whether to disable navigation to previous comics
+    disable_left: bool,
+    /// Whether to disable navigation to next comics
+    disable_right: bool,
+    /// The end of the available comics past which the client originally requested a date, if it
+    /// was redirected to this comic
+    clamped: Option<OutOfRange>,
+}
+
+/// Step over the comics known to be missing, starting from the given one.
+///
+/// If a step goes past either end, then it wraps around to the other end if enabled, or else
+/// stops at the end.
+///
+/// # Arguments
+/// * `date` - The date of the comic to start from
+/// * `step` - The number of days to move by in each step
+/// * `first_comic` - The date of the first comic
+/// * `last_comic` - The date of the last comic
+/// * `missing` - The sorted dates of comics known to be missing
+/// * `wrap` - Whether to wrap around at the ends
+fn skip_missing(
+    mut date: NaiveDate,
+    step: Duration,
+    first_comic: NaiveDate,
+    last_comic: NaiveDate,
+    missing: &[NaiveDate],
+    wrap: bool,
+) -> NaiveDate {
+    // Bound the steps, in case every comic is missing.
+    for _ in 0..missing.len() {
+        if missing.binary_search(&date).is_err() {
+            break;
+        }
+        let next = date + step;
+        date = if (first_comic..=last_comic).contains(&next) {
+            next
+        } else if !wrap {
+            break;
+        } else if step > Duration::zero() {
+            first_comic
+        } else {
+            last_comic
+        };
+    }
+    date
+}
+
+/// Get the navigation for a comic.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `first_comic` - The date of the first comic
+/// * `last_comic` - The date of the last comic
+/// * `missing` - The sorted dates of comics known to be missing, which are skipped over
+/// * `wrap` - Whether to wrap around at the ends, instead of disabling navigation
+fn get_navigation(
+    date: &NaiveDate,
+    first_comic: NaiveDate,
+    last_comic: NaiveDate,
+    missing: &[NaiveDate],
+    wrap: bool,
+) -> Navigation {
+    let at_first = *date <= first_comic;
+    let at_last = *date >= last_comic;
+    let skip = |date, step| skip_missing(date, step, first_comic, last_comic, missing, wrap);
+
+    let nav = if wrap {
+        Navigation {
+            first: first_comic,
+            previous: if at_first {
+                last_comic
+            } else {
+                *date - Duration::days(1)
+            },
+            next: if at_last {
+                first_comic
+            } else {
+                *date + Duration::days(1)
+            },
+            disable_left: false,
+            disable_right: false,
+            clamped: None,
+        }
+    } else {
+        Navigation {
+            first: first_comic,
+            previous: max(first_comic, *date - Duration::days(1)),
+            next: min(last_comic, *date + Duration::days(1)),
+            disable_left: at_first,
+            disable_right: at_last,
+            clamped: None,
+        }
+    };
+    Navigation {
+        previous: skip(nav.previous, Duration::days(-1)),
+        next: skip(nav.next, Duration::days(1)),
+        ..nav
+    }
+}
+
+/// Render the minified HTML given scraped data.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The scraped comic data
+/// * `nav` - The navigation from the comic to its neighbours
+/// * `inline_css` - The minified CSS to inline into the page, if any
+/// * `nonce` - The CSP nonce for inline content in the page
+/// * `prefs` - The preferences of the client
+fn render_template(
+    date: &NaiveDate,
+    comic_data: &ComicData,
+    nav: &Navigation,
+    inline_css: Option<&str>,
+    nonce: &str,
+    prefs: &ClientPrefs,
+) -> AppResult<String> {
+    let lang = prefs.lang;
+    let first_comic = &nav.first.format(SRC_DATE_FMT).to_string();
+    let previous_comic = &nav.previous.format(SRC_DATE_FMT).to_string();
+    let next_comic = &nav.next.format(SRC_DATE_FMT).to_string();
+
+    let template = ComicTemplate {
+        data: comic_data,
+        date_disp: &lang.format_date(date),
+        number: date_to_number(date),
+        date: &date.format(SRC_DATE_FMT).to_string(),
+        first_comic,
+        previous_comic,
+        next_comic,
+        disable_left_nav: nav.disable_left,
+        disable_right_nav: nav.disable_right,
+        notice: nav.clamped.map(|clamped| match clamped {
+            OutOfRange::Before => lang.strings().before_first_comic,
+            OutOfRange::After => lang.strings().after_latest_comic,
+        }),
+        permalink: &comic_data.permalink,
+        json_ld: &comic_json_ld(date, comic_data, lang),
+        inline_css,
+        nonce,
+        theme: prefs.theme,
+        favorite: prefs.favorites.contains(date),
+        lang: lang.code(),
+        text: lang.strings(),
+        app_url: APP_URL,
+        repo_url: REPO_URL,
+        preview_width: PREVIEW_WIDTH,
+        preview_height: PREVIEW_HEIGHT,
+    };
+    debug!("Rendering comic template: {template:?}");
+    minify_html(template.render()?)
+}
+
+/// Get the ETag for a comic page.
+///
+/// The ETag is weak, since the page is compressed differently for different clients. It includes
+/// the app's version, so that it changes when the templates do.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The scraped comic data
+/// * `nav` - The navigation from the comic to its neighbours
+/// * `inline_css` - The minified CSS to inline into the page, if any
+/// * `prefs` - The preferences of the client
+fn get_comic_etag(
+    date: &NaiveDate,
+    comic_data: &ComicData,
+    nav: &Navigation,
+    inline_css: Option<&str>,
+    prefs: &ClientPrefs,
+) -> EntityTag {
+    // This hasher is deterministic for a given build of the app, which is all that's needed.
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    date.hash(&mut hasher);
+    comic_data.hash(&mut hasher);
+    nav.hash(&mut hasher);
+    inline_css.hash(&mut hasher);
+    prefs.theme.hash(&mut hasher);
+    prefs.lang.hash(&mut hasher);
+    // Only whether this comic is a favorite affects its page, not the other favorites.
+    prefs.favorites.contains(date).hash(&mut hasher);
+    EntityTag::new_weak(format!("{:016x}", hasher.finish()))
+}
+
+/// Check whether the client's cached copy is still valid.
+///
+/// The client's copy is valid if any of its ETags weakly match the current ETag.
+///
+/// # Arguments
+/// * `if_none_match` - The ETags of the client's cached copies, if any
+/// * `etag` - The current ETag
+pub fn etag_matches(if_none_match: Option<&IfNoneMatch>, etag: &EntityTag) -> bool {
+    match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => false,
+    }
+}
+
+/// Serve the rendered HTML for multiple comics.
+///
+/// # Arguments
+/// * `title` - The title of the page
+/// * `comics` - The comics to show, in order
+/// * `empty_msg` - The message to show if there are no comics
+fn serve_strip_template(
+    title: &str,
+    comics: &[StripEntry],
+    empty_msg: &str,
+) -> AppResult<HttpResponse> {
+    let template = StripTemplate {
+        title,
+        comics,
+        empty_msg,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering strip template: {template:?}");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve the rendered HTML for the comics in a week.
+///
+/// # Arguments
+/// * `title` - The title of the page
+/// * `comics` - The comics in the week, in order
+/// * `previous_week` - The first day of the previous week, if it has comics
+/// * `next_week` - The first day of the next week, if it has comics
+fn serve_week_template(
+    title: &str,
+    comics: &[StripEntry],
+    previous_week: Option<&str>,
+    next_week: Option<&str>,
+) -> AppResult<HttpResponse> {
+    let template = WeekTemplate {
+        title,
+        comics,
+        previous_week,
+        next_week,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering week template: {template:?}");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve the rendered HTML for a list of comics, which links to their pages.
+///
+/// # Arguments
+/// * `title` - The title of the page
+/// * `comics` - The comics to list, in order
+/// * `empty_msg` - The message to show if there are no comics
+fn serve_comic_list_template(
+    title: &str,
+    comics: &[ComicListEntry],
+    empty_msg: &str,
+) -> AppResult<HttpResponse> {
+    let template = ComicListTemplate {
+        title,
+        comics,
+        empty_msg,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering comic list template: {template:?}");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve the rendered HTML for the text-only view of a comic.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The data of the comic
+fn serve_text_template(date: &NaiveDate, comic_data: &ComicData) -> AppResult<HttpResponse> {
+    let template = TextTemplate {
+        date: &date.format(SRC_DATE_FMT).to_string(),
+        date_disp: &date.format(DISP_DATE_FMT).to_string(),
+        title: &comic_data.title,
+        transcript: comic_data.transcript.as_deref(),
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering text template: {template:?}");
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve an Atom feed of comics.
+///
+/// # Arguments
+/// * `updated` - The date of the latest comic in the feed
+/// * `comics` - The comics in the feed, newest first
+fn serve_feed_template(updated: &NaiveDate, comics: &[StripEntry]) -> AppResult<HttpResponse> {
+    let template = FeedTemplate {
+        updated: &updated.format(SRC_DATE_FMT).to_string(),
+        comics,
+        app_url: APP_URL,
+    };
+    debug!("Rendering feed template: {template:?}");
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml;charset=utf-8")
+        .body(template.render()?))
+}
+
+/// Get the weeks of a month for a calendar, starting on Sundays.
+///
+/// # Arguments
+/// * `month_start` - The first day of the month
+/// * `first_comic` - The date of the first comic
+/// * `last_comic` - The date of the last comic
+fn get_calendar_weeks(
+    month_start: &NaiveDate,
+    first_comic: &NaiveDate,
+    last_comic: &NaiveDate,
+) -> Vec<[Option<CalendarDay>; 7]> {
+    let mut weeks = Vec::new();
+    let mut week: [Option<CalendarDay>; 7] = Default::default();
+    for date in month_start
+        .iter_days()
+        .take_while(|date| date.month() == month_start.month())
+    {
+        let weekday = date.weekday().num_days_from_sunday() as usize;
+        week[weekday] = Some(CalendarDay {
+            day: date.day(),
+            date: date.format(SRC_DATE_FMT).to_string(),
+            available: (first_comic..=last_comic).contains(&&date),
+        });
+        if weekday == 6 {
+            weeks.push(std::mem::take(&mut week));
+        }
+    }
+    if week.iter().any(Option::is_some) {
+        weeks.push(week);
+    }
+    weeks
+}
+
+/// A month with comics, and the URL paths of its neighbouring months
+struct MonthPaths {
+    /// The first day of the month
+    start: NaiveDate,
+    /// The URL path of the previous month, if it has comics
+    previous: Option<String>,
+    /// The URL path of the next month, if it has comics
+    next: Option<String>,
+}
+
+/// Get a month, along with the URL paths of its neighbouring months that have comics.
+///
+/// If the month is invalid or has no comics, then None is returned.
+///
+/// # Arguments
+/// * `year` - The year of the month
+/// * `month` - The month, starting from 1
+/// * `fmt` - The date format of the URL paths of months
+/// * `range` - The inclusive range of dates of the available comics
+fn get_month_paths(
+    year: i32,
+    month: u32,
+    fmt: &str,
+    range: &RangeInclusive<NaiveDate>,
+) -> Option<MonthPaths> {
+    let (first_comic, last_comic) = (*range.start(), *range.end());
+    let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+
+    // Months are compared by their first days, so that partial months of comics are included.
+    let first_month = first_comic.with_day(1).unwrap_or(first_comic);
+    let has_comics = |date: &NaiveDate| (first_month..=last_comic).contains(date);
+    if !has_comics(&start) {
+        return None;
+    }
+    let get_path = |date: NaiveDate| has_comics(&date).then(|| date.format(fmt).to_string());
+    Some(MonthPaths {
+        start,
+        previous: start.checked_sub_months(Months::new(1)).and_then(get_path),
+        next: start.checked_add_months(Months::new(1)).and_then(get_path),
+    })
+}
+
+/// Serve a calendar of the comics in a month, without handling errors.
+///
+/// If the month is invalid or has no comics, then None is returned.
+///
+/// # Arguments
+/// * `year` - The year of the month
+/// * `month` - The month, starting from 1
+/// * `range` - The inclusive range of dates of the available comics
+fn serve_archive_month_raw(
+    year: i32,
+    month: u32,
+    range: &RangeInclusive<NaiveDate>,
+) -> AppResult<Option<HttpResponse>> {
+    let Some(month_paths) = get_month_paths(year, month, ARCHIVE_MONTH_FMT, range) else {
+        return Ok(None);
+    };
+
+    let template = ArchiveTemplate {
+        title: &month_paths.start.format(DISP_MONTH_FMT).to_string(),
+        year,
+        weeks: &get_calendar_weeks(&month_paths.start, range.start(), range.end()),
+        previous_month: month_paths.previous.as_deref(),
+        next_month: month_paths.next.as_deref(),
+        grid_month: &month_paths.start.format(GRID_MONTH_FMT).to_string(),
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering archive template: {template:?}");
+
+    Ok(Some(
+        HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(minify_html(template.render()?)?),
+    ))
+}
+
+/// Serve a grid of thumbnails of the comics in a month, without handling errors.
+///
+/// The thumbnails are loaded lazily by clients, so the comics aren't retrieved here. If the month
+/// is invalid or has no comics, then None is returned.
+///
+/// # Arguments
+/// * `year` - The year of the month
+/// * `month` - The month, starting from 1
+/// * `range` - The inclusive range of dates of the available comics
+fn serve_grid_raw(
+    year: i32,
+    month: u32,
+    range: &RangeInclusive<NaiveDate>,
+) -> AppResult<Option<HttpResponse>> {
+    let Some(month_paths) = get_month_paths(year, month, GRID_MONTH_FMT, range) else {
+        return Ok(None);
+    };
+
+    let comics: Vec<_> = month_paths
+        .start
+        .iter_days()
+        .take_while(|date| date.month() == month)
+        .filter(|date| range.contains(date))
+        .map(|date| GridEntry {
+            date: date.format(SRC_DATE_FMT).to_string(),
+            date_disp: date.format(DISP_DATE_FMT).to_string(),
+        })
+        .collect();
+
+    let template = GridTemplate {
+        title: &month_paths.start.format(DISP_MONTH_FMT).to_string(),
+        comics: &comics,
+        archive_month: &month_paths.start.format(ARCHIVE_MONTH_FMT).to_string(),
+        previous_month: month_paths.previous.as_deref(),
+        next_month: month_paths.next.as_deref(),
+        thumbnail_width: THUMBNAIL_WIDTH,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering grid template: {template:?}");
+
+    Ok(Some(
+        HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(minify_html(template.render()?)?),
+    ))
+}
+
+/// Serve a grid of thumbnails of the comics in a month.
+///
+/// If the month is invalid or has no comics, then a 404 not found response is returned. If any
+/// other error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `year` - The year of the month
+/// * `month` - The month, starting from 1
+/// * `range` - The inclusive range of dates of the available comics
+pub fn serve_grid(year: i32, month: u32, range: &RangeInclusive<NaiveDate>) -> HttpResponse {
+    match serve_grid_raw(year, month, range) {
+        Ok(Some(response)) => response,
+        Ok(None) => serve_404(None),
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Serve a calendar of the comics in a month.
+///
+/// If the month is invalid or has no comics, then a 404 not found response is returned. If any
+/// other error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `year` - The year of the month
+/// * `month` - The month, starting from 1
+/// * `range` - The inclusive range of dates of the available comics
+pub fn serve_archive_month(
+    year: i32,
+    month: u32,
+    range: &RangeInclusive<NaiveDate>,
+) -> HttpResponse {
+    match serve_archive_month_raw(year, month, range) {
+        Ok(Some(response)) => response,
+        Ok(None) => serve_404(None),
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Get the entries in the index of the archive.
+///
+/// If a year is given, then these are its months with comics. Otherwise, these are all years with
+/// comics. If the year has no comics, then None is returned.
+///
+/// # Arguments
+/// * `year` - The year whose months are to be listed, if any
+/// * `range` - The inclusive range of dates of the available comics
+fn get_archive_entries(
+    year: Option<i32>,
+    range: &RangeInclusive<NaiveDate>,
+) -> Option<Vec<ArchiveEntry>> {
+    let (first_comic, last_comic) = (range.start(), range.end());
+
+    let Some(year) = year else {
+        let entries = (first_comic.year()..=last_comic.year())
+            .map(|year| ArchiveEntry {
+                path: year.to_string(),
+                label: year.to_string(),
+            })
+            .collect();
+        return Some(entries);
+    };
+    if !(first_comic.year()..=last_comic.year()).contains(&year) {
+        return None;
+    }
+
+    let first_month = if year == first_comic.year() {
+        first_comic.month()
+    } else {
+        1
+    };
+    let last_month = if year == last_comic.year() {
+        last_comic.month()
+    } else {
+        12
+    };
+    let entries = (first_month..=last_month)
+        .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+        .map(|month_start| ArchiveEntry {
+            path: month_start.format(ARCHIVE_MONTH_FMT).to_string(),
+            label: month_start.format("%B").to_string(),
+        })
+        .collect();
+    Some(entries)
+}
+
+/// Serve an index of the archive, without handling errors.
+///
+/// If the year has no comics, then None is returned.
+///
+/// # Arguments
+/// * `year` - The year whose months are to be listed, or None to list all years
+/// * `range` - The inclusive range of dates of the available comics
+fn serve_archive_index_raw(
+    year: Option<i32>,
+    range: &RangeInclusive<NaiveDate>,
+) -> AppResult<Option<HttpResponse>> {
+    let Some(entries) = get_archive_entries(year, range) else {
+        return Ok(None);
+    };
+    let title = match year {
+        Some(year) => format!("Archive of {year}"),
+        None => "Archive".into(),
+    };
+    let template = ArchiveIndexTemplate {
+        title: &title,
+        entries: &entries,
+        is_year: year.is_some(),
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering archive index template: {template:?}");
+
+    Ok(Some(
+        HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(minify_html(template.render()?)?),
+    ))
+}
+
+/// Serve an index of the archive.
+///
+/// If the year has no comics, then a 404 not found response is returned. If any other error is
+/// raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `year` - The year whose months are to be listed, or None to list all years
+/// * `range` - The inclusive range of dates of the available comics
+pub fn serve_archive_index(year: Option<i32>, range: &RangeInclusive<NaiveDate>) -> HttpResponse {
+    match serve_archive_index_raw(year, range) {
+        Ok(Some(response)) => response,
+        Ok(None) => serve_404(None),
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Render every template with dummy data, to catch broken templates before serving requests.
+pub fn check_templates() -> AppResult<()> {
+    let date = str_to_date(FIRST_COMIC, SRC_DATE_FMT)?;
+    let comic_data = ComicData {
+        title: String::new(),
+        img_url: String::new(),
+        img_width: 0,
+        img_height: 0,
+        img_variants: Vec::new(),
+        permalink: String::new(),
+        transcript: None,
+        tags: Vec::new(),
+    };
+    let strip = [StripEntry {
+        date: FIRST_COMIC.into(),
+        date_disp: date.format(DISP_DATE_FMT).to_string(),
+        data: comic_data.clone(),
+    }];
+
+    let nav = get_navigation(&date, date, date, &[], false);
+    for lang in [Lang::English, Lang::German, Lang::French, Lang::Spanish] {
+        let prefs = ClientPrefs {
+            theme: Theme::Dark,
+            lang,
+            ..Default::default()
+        };
+        render_template(&date, &comic_data, &nav, Some(""), "", &prefs)?;
+    }
+    serve_strip_template("", &strip, "")?;
+    serve_week_template("", &strip, Some(FIRST_COMIC), None)?;
+    let list = [ComicListEntry {
+        date: FIRST_COMIC.into(),
+        date_disp: date.format(DISP_DATE_FMT).to_string(),
+        title: String::new(),
+    }];
+    serve_comic_list_template("", &list, "")?;
+    serve_text_template(&date, &comic_data)?;
+    serve_feed_template(&date, &strip)?;
+    let range = date..=date;
+    serve_archive_month_raw(date.year(), date.month(), &range)?;
+    serve_grid_raw(date.year(), date.month(), &range)?;
+    serve_archive_index_raw(None, &range)?;
+    serve_400_raw("")?;
+    serve_404_raw(Some(&date), Theme::Light)?;
+    serve_404_raw(None, Theme::Dark)?;
+    serve_429_raw(1)?;
+    // `serve_500` never fails, so render its template directly.
+    ErrorTemplate {
+        error: "",
+        theme: Theme::Dark,
+        repo_url: REPO_URL,
+    }
+    .render()?;
+
+    debug!("All templates rendered successfully");
+    Ok(())
+}
+
+/// Load a file from disk
+async fn load_file(path: &Path) -> AppResult<String> {
+    let file = match tokio::fs::read(path).await {
+        Ok(text) => text,
+        Err(err) => return Err(AppError::NotFound(err.to_string())),
+    };
+    Ok(std::str::from_utf8(&file)?.to_string())
+}
+
+/// Load the requested CSS file and minify it.
+///
+/// # Arguments
+/// * `path` - The path to the CSS file
+pub async fn load_minified_css(path: &Path) -> AppResult<String> {
+    let css = load_file(path).await?;
+
+    let minified = match minifier::css::minify(&css) {
+        Ok(minified) => minified.to_string(),
+        Err(err) => {
+            // The minifier can choke on valid (but unusual) CSS, which is still servable as-is.
+            warn!(
+                "CSS minification of \"{}\" crashed with error: {err}. Serving it unminified.",
+                path.display()
+            );
+            return Ok(css);
+        }
+    };
+    debug!(
+        "Minified \"{}\" from {} bytes to {}",
+        path.display(),
+        css.len(),
+        minified.len()
+    );
+    Ok(minified)
+}
+
+/// Serve a comic's image.
+///
+/// The format of the image depends on the formats accepted by the client, so caches are told so.
+fn serve_image(image: ComicImage) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(image.content_type)
+        .insert_header((VARY, ACCEPT.as_str()))
+        .body(image.bytes)
+}
+
+/// Get the file name of a comic's image, named after the comic's date.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `content_type` - The MIME type of the image, which decides the extension
+fn download_filename(date: &NaiveDate, content_type: &str) -> String {
+    let filename = format!("{DOWNLOAD_PREFIX}-{}", date.format(SRC_DATE_FMT));
+    let extension = ImageFormat::from_mime_type(content_type)
+        .and_then(|format| format.extensions_str().first());
+    match extension {
+        Some(extension) => format!("{filename}.{extension}"),
+        None => filename,
+    }
+}
+
+/// Serve a comic's image as a download, named after the comic's date.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `image` - The image of the comic
+fn serve_download(date: &NaiveDate, image: ComicImage) -> HttpResponse {
+    let filename = download_filename(date, &image.content_type);
+    HttpResponse::Ok()
+        .content_type(image.content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(image.bytes)
+}
+
+/// Serve the metadata of a comic's image for a HEAD request, without the image itself.
+fn serve_image_info(info: ImageInfo) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    builder
+        .content_type(info.content_type)
+        .insert_header((VARY, ACCEPT.as_str()));
+    if let Some(len) = info.content_length {
+        // The body of a HEAD response is never sent, so an empty body with the image's length
+        // gives the right headers.
+        builder
+            .no_chunking(len)
+            .streaming(stream::empty::<AppResult<Bytes>>())
+    } else {
+        builder.body(NoBody::new())
+    }
+}
+
+/// Render the contents of `robots.txt`.
+///
+/// Comic pages are always allowed, so that they can be indexed. Thus, any path prefix that could
+/// match a comic's URL (such as "/" or "/2000-") is skipped.
+///
+/// # Arguments
+/// * `disallow` - The URL path prefixes that crawlers shouldn't crawl
+pub fn render_robots_txt(disallow: &[String]) -> String {
+    let disallow: Vec<_> = disallow
+        .iter()
+        .filter(|path| {
+            let matches_comics = path
+                .trim_start_matches('/')
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '-' || c == '*');
+            if matches_comics {
+                warn!("Not disallowing \"{path}\" in robots.txt, since it matches comic pages");
+            }
+            !matches_comics
+        })
+        .collect();
+
+    let mut robots_txt = String::from("# Disallow crawling non-content pages\nuser-agent: *\n");
+    if disallow.is_empty() {
+        // An empty rule allows crawling everything.
+        robots_txt.push_str("disallow:\n");
+    }
+    for path in disallow {
+        robots_txt.push_str(&format!("disallow: {path}\n"));
+    }
+    robots_txt
+}
+
+/// Serve the requested JavaScript file with minification, without handling errors.
+async fn serve_js_raw(path: &Path) -> AppResult<HttpResponse> {
+    let js = load_file(path).await?;
+
+    let minified = minifier::js::minify(&js).to_string();
+    debug!(
+        "Minified \"{}\" from {} bytes to {}",
+        path.display(),
+        js.len(),
+        minified.len()
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/javascript;charset=utf-8")
+        .body(minified))
+}
+
+/// Serve the requested JavaScript file with minification.
+///
+/// If an error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `path` - The path to the JavaScript file
+pub async fn serve_js(path: &Path) -> HttpResponse {
+    match serve_js_raw(path).await {
+        Ok(resp) => resp,
+        Err(AppError::NotFound(..)) => serve_404(None),
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Serve the web app manifest, which makes the viewer installable as an app.
+pub fn serve_manifest() -> HttpResponse {
+    let manifest = json!({
+        "name": APP_NAME,
+        "short_name": APP_SHORT_NAME,
+        "description": "A simple comic viewer for Dilbert by Scott Adams.",
+        "start_url": "/",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": APP_THEME_COLOUR,
+        "theme_color": APP_THEME_COLOUR,
+        "icons": [{
+            "src": APP_ICON_URL,
+            "sizes": "any",
+            "type": "image/svg+xml",
+        }],
+    });
+    HttpResponse::Ok()
+        .content_type("application/manifest+json")
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(MANIFEST_MAX_AGE),
+        ]))
+        .body(manifest.to_string())
+}
+
+/// Serve the oEmbed JSON for a comic.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The scraped comic data
+/// * `lang` - The language of the comic's fallback title, for comics without titles
+fn serve_oembed_json(date: &NaiveDate, comic_data: &ComicData, lang: Lang) -> HttpResponse {
+    let date = date.format(SRC_DATE_FMT);
+    let title = if comic_data.title.is_empty() {
+        format!("{} {date}", lang.strings().comic_strip_on)
+    } else {
+        comic_data.title.clone()
+    };
+    let img_url = format!("{APP_URL}{date}/image");
+    let oembed = json!({
+        "version": "1.0",
+        "type": "photo",
+        "title": title,
+        "author_name": COMIC_AUTHOR,
+        "provider_name": APP_NAME,
+        "provider_url": APP_URL,
+        "url": img_url,
+        "width": comic_data.img_width,
+        "height": comic_data.img_height,
+        "thumbnail_url": img_url,
+        "thumbnail_width": comic_data.img_width,
+        "thumbnail_height": comic_data.img_height,
+    });
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(oembed.to_string())
+}
+
+/// Escape JSON for embedding in an HTML `<script>` element.
+///
+/// The characters that could close the element or start an HTML comment are replaced by their
+/// Unicode escapes, which are still parsed as the same JSON. These only occur inside JSON strings,
+/// so the replacements are always valid.
+///
+/// # Arguments
+/// * `json` - The serialized JSON
+fn escape_script_json(json: &str) -> String {
+    json.replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+/// Get the JSON-LD structured data of a comic, for search engines.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The scraped comic data
+/// * `lang` - The language of the comic's fallback title, for comics without titles
+fn comic_json_ld(date: &NaiveDate, comic_data: &ComicData, lang: Lang) -> String {
+    let date = date.format(SRC_DATE_FMT);
+    let title = if comic_data.title.is_empty() {
+        format!("{} {date}", lang.strings().comic_strip_on)
+    } else {
+        comic_data.title.clone()
+    };
+    let json_ld = json!({
+        "@context": "https://schema.org",
+        "@type": "ComicStory",
+        "name": title,
+        "datePublished": date.to_string(),
+        "url": format!("{APP_URL}{date}"),
+        "sameAs": comic_data.permalink,
+        "author": {
+            "@type": "Person",
+            "name": COMIC_AUTHOR,
+        },
+        "keywords": comic_data.tags,
+        "image": {
+            "@type": "ImageObject",
+            "contentUrl": format!("{APP_URL}{date}/image"),
+            "width": comic_data.img_width,
+            "height": comic_data.img_height,
+        },
+    });
+    escape_script_json(&json_ld.to_string())
+}
+
+/// Get the data of a comic as JSON, for use by other apps.
+///
+/// The URLs are to the comic's page and image served by the app. Fields derived from the date are
+/// also included, so that clients needn't re-derive them with the app's conventions.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The data of the comic
+/// * `range` - The inclusive range of dates of the available comics
+fn comic_data_json(
+    date: &NaiveDate,
+    comic_data: &ComicData,
+    range: &RangeInclusive<NaiveDate>,
+) -> serde_json::Value {
+    let number = date_to_number(date);
+    let is_first = date == range.start();
+    let is_last = date == range.end();
+    let day_of_week = date.format("%A").to_string();
+    let date = date.format(SRC_DATE_FMT);
+    json!({
+        "date": date.to_string(),
+        "number": number,
+        "ordinal": number,
+        "day_of_week": day_of_week,
+        "is_first": is_first,
+        "is_last": is_last,
+        "title": comic_data.title,
+        "url": format!("{APP_URL}{date}"),
+        "img_url": format!("{APP_URL}{date}/image"),
+        "img_width": comic_data.img_width,
+        "img_height": comic_data.img_height,
+        "permalink": comic_data.permalink,
+        "transcript": comic_data.transcript,
+        "tags": comic_data.tags,
+    })
+}
+
+/// Serve the data of a comic as JSON.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+/// * `comic_data` - The data of the comic
+/// * `range` - The inclusive range of dates of the available comics
+fn serve_comic_data_json(
+    date: &NaiveDate,
+    comic_data: &ComicData,
+    range: &RangeInclusive<NaiveDate>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(comic_data_json(date, comic_data, range).to_string())
+}
+
+/// Serve a 400 bad request response for invalid requests, without handling errors.
+fn serve_400_raw(message: &str) -> AppResult<HttpResponse> {
+    let template = BadRequestTemplate {
+        message,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering 400 template: {template:?}");
+    Ok(HttpResponse::BadRequest()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve a 400 bad request response for invalid requests.
+///
+/// If an error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `message` - The reason why the request is invalid
+pub fn serve_400(message: &str) -> HttpResponse {
+    match serve_400_raw(message) {
+        Ok(response) => response,
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Serve a 404 not found response for invalid URLs, without handling errors.
+fn serve_404_raw(date: Option<&NaiveDate>, theme: Theme) -> AppResult<HttpResponse> {
+    let date_str = date.map(|date| date.format(SRC_DATE_FMT).to_string());
+    let template = NotFoundTemplate {
+        date: date_str.as_deref(),
+        theme,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering 404 template: {template:?}");
+    Ok(HttpResponse::NotFound()
+        .content_type(ContentType::html())
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve a 404 not found response for invalid URLs, in the given colour theme.
+///
+/// If an error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `date` - The date of the requested comic, if available. This must be a valid date for
+///   which a comic doesn't exist.
+/// * `theme` - The colour theme chosen by the client
+pub fn serve_themed_404(date: Option<&NaiveDate>, theme: Theme) -> HttpResponse {
+    match serve_404_raw(date, theme) {
+        Ok(response) => response,
+        Err(err) => serve_themed_500(&err, date, theme),
+    }
+}
+
+/// Serve a 404 not found response for invalid URLs, in the default colour theme.
+///
+/// # Arguments
+/// * `date` - The date of the requested comic, if available. This must be a valid date for
+///   which a comic doesn't exist.
+pub fn serve_404(date: Option<&NaiveDate>) -> HttpResponse {
+    serve_themed_404(date, Theme::default())
+}
+
+/// Get the value of a "Retry-After" header, in seconds, for the time after which a client can retry.
+///
+/// # Arguments
+/// * `wait` - The time after which the client can retry
+fn retry_after_secs(wait: StdDuration) -> u64 {
+    // Round up, so that clients don't retry too early.
+    wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+}
+
+/// Serve a 429 too many requests response for rate-limited clients, without handling errors.
+fn serve_429_raw(retry_after: u64) -> AppResult<HttpResponse> {
+    let template = TooManyRequestsTemplate {
+        retry_after,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering 429 template: {template:?}");
+    Ok(HttpResponse::TooManyRequests()
+        .content_type(ContentType::html())
+        .insert_header((RETRY_AFTER, retry_after))
+        .body(minify_html(template.render()?)?))
+}
+
+/// Serve a 429 too many requests response for rate-limited clients.
+///
+/// If an error is raised, then a 500 internal server error response is returned.
+///
+/// # Arguments
+/// * `wait` - The time after which the client can retry
+pub fn serve_429(wait: StdDuration) -> HttpResponse {
+    match serve_429_raw(retry_after_secs(wait)) {
+        Ok(response) => response,
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Serve a 500 internal server error response, in the default colour theme.
+///
+/// The error is also reported, if error reporting is enabled.
+///
+/// # Arguments
+/// * `err` - The actual internal server error
+/// * `date` - The date of the requested comic, if any
+pub fn serve_500(err: &AppError, date: Option<&NaiveDate>) -> HttpResponse {
+    serve_themed_500(err, date, Theme::default())
+}
+
+/// Serve a 500 internal server error response, in the given colour theme.
+///
+/// The error is also reported, if error reporting is enabled. If the comic source is unavailable,
+/// then a 503 service unavailable response is served instead, with a "Retry-After" header for when
+/// it's expected to be available again.
+///
+/// # Arguments
 /// * `err` - The actual internal server error
-pub fn serve_500(err: &AppError) -> HttpResponse {
+/// * `date` - The date of the requested comic, if any
+/// * `theme` - The colour theme chosen by the client
+pub fn serve_themed_500(err: &AppError, date: Option<&NaiveDate>, theme: Theme) -> HttpResponse {
+    report_error(err, date);
     let error = &format!("{err}");
-    let mut response = HttpResponse::InternalServerError();
+    let mut response = match err.original() {
+        AppError::SourceUnavailable(_, wait) => {
+            let mut response = HttpResponse::ServiceUnavailable();
+            response.insert_header((RETRY_AFTER, retry_after_secs(*wait)));
+            response
+        }
+        _ => HttpResponse::InternalServerError(),
+    };
+
+    let error_template = ErrorTemplate {
+        error,
+        theme,
+        repo_url: REPO_URL,
+    };
+    debug!("Rendering 500 template: {error_template:?}");
+    match error_template.render() {
+        Ok(webpage) => {
+            // Minification can crash, so if it fails, just serve the original. Since
+            // minification modifies the input, give it a clone.
+            let minified = match minify_html(webpage.clone()) {
+                Ok(html) => html,
+                Err(err) => {
+                    error!("HTML minification crashed with error: {err}");
+                    webpage
+                }
+            };
+            response.content_type(ContentType::html()).body(minified)
+        }
+        Err(err) => {
+            error!("Couldn't render Error 500 HTML: {err}");
+            // An empty Error 500 response is still better than crashing
+            response.finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::read_to_string;
+    use std::io::Cursor;
+
+    use actix_web::{
+        body::{to_bytes, MessageBody},
+        http::{
+            header::{TryIntoHeaderValue, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, ETAG},
+            StatusCode,
+        },
+        web,
+    };
+    use awc::Client;
+    use test_case::test_case;
+    use zip::ZipArchive;
+
+    use crate::constants::LAST_COMIC;
+    use crate::db::mock::MockPool;
+    use crate::graphql::build_schema;
+    use crate::scrapers::{DilbertSource, ImageVariant};
+
+    /// Get the inclusive range of dates of the available comics, when pinned to the last comic.
+    fn get_comic_range() -> RangeInclusive<NaiveDate> {
+        str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap()
+            ..=str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap()
+    }
+
+    /// Get the preferences of a client without favorites.
+    ///
+    /// # Arguments
+    /// * `theme` - The colour theme chosen by the client
+    /// * `lang` - The language of the page
+    fn get_prefs(theme: Theme, lang: Lang) -> ClientPrefs {
+        ClientPrefs {
+            theme,
+            lang,
+            favorites: Favorites::default(),
+        }
+    }
+
+    /// Path to the directory where test HTML files are stored
+    const HTML_TEST_CASE_PATH: &str = "testdata/html";
+
+    // NOTE: This does *NOT* check if the minified HTML is equivalent, only that it's parsable.
+    #[test_case("empty"; "empty HTML")]
+    #[test_case("simple"; "simple HTML")]
+    #[test_case("comic"; "comic HTML")]
+    #[test_case("minimized"; "already minimized HTML")]
+    /// Test whether HTML minification results in a parsable HTML.
+    ///
+    /// # Arguments
+    /// * `file_stem` - The filename stem of the HTML file to be used for testing
+    fn test_minified_html_is_parsable(file_stem: &str) {
+        let path = format!("{HTML_TEST_CASE_PATH}/{file_stem}.html");
+        let html =
+            read_to_string(&path).unwrap_or_else(|_| panic!("Couldn't read test case {}", &path));
+
+        let result = minify_html(html).expect("Error minifying HTML");
+        // Only checks if the minified HTML is actually parsable.
+        tl::parse(&result, tl::ParserOptions::default()).expect("Cannot parse minified HTML");
+    }
+
+    /// Test if an HTTP response is a valid HTML page, and return the page
+    fn test_html_response(resp: HttpResponse) -> String {
+        // Check the "Content-Type" header.
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE),
+            Some(&ContentType::html().try_into_value().unwrap()),
+            "Response content type is not HTML"
+        );
+
+        // Check if response body is valid UTF-8 and the HTML is parsable.
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let body_utf8 = std::str::from_utf8(&body).expect("Response body not UTF-8");
+        tl::parse(body_utf8, tl::ParserOptions::default()).expect("Response body not valid HTML");
+        body_utf8.into()
+    }
+
+    #[test_case(2000, 1, 1, "Test", None; "comic with title")]
+    #[test_case(2000, 1, 1, "", None; "comic without title")]
+    #[test_case(2000, 1, 1, "", Some("Dilbert: Hi."); "comic with transcript")]
+    /// Test rendering of comic page templates.
+    ///
+    /// # Arguments
+    /// * `comic_year` - The year of the comic
+    /// * `comic_month` - The month of the comic
+    /// * `comic_day` - The day of the comic
+    /// * `title` - The title of the comic
+    /// * `transcript` - The transcript of the comic, if any
+    fn test_template_rendering(
+        comic_year: i32,
+        comic_month: u32,
+        comic_day: u32,
+        title: &str,
+        transcript: Option<&str>,
+    ) {
+        let comic_date = NaiveDate::from_ymd_opt(comic_year, comic_month, comic_day)
+            .expect("Invalid test parameters");
+        let comic_data = ComicData {
+            title: title.into(),
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            transcript: transcript.map(String::from),
+            ..Default::default()
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let nav = get_navigation(&comic_date, first_comic, last_comic, &[], false);
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            None,
+            "",
+            &get_prefs(Theme::Light, Lang::English),
+        )
+        .expect("Error generating comic page");
+        assert_eq!(
+            html.contains("Transcript"),
+            transcript.is_some(),
+            "Transcript isn't rendered only when available"
+        );
+    }
+
+    #[test_case(None, None; "no notice")]
+    #[test_case(Some(OutOfRange::Before), Some("before the first comic"); "before")]
+    #[test_case(Some(OutOfRange::After), Some("after the latest comic"); "after")]
+    /// Test whether the notice for out-of-range dates is rendered.
+    ///
+    /// # Arguments
+    /// * `clamped` - The end of the comics past which the date was requested, if any
+    /// * `expected` - Part of the expected notice, if any
+    fn test_template_notice(clamped: Option<OutOfRange>, expected: Option<&str>) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let nav = Navigation {
+            clamped,
+            ..get_navigation(&comic_date, first_comic, last_comic, &[], false)
+        };
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            None,
+            "",
+            &get_prefs(Theme::Light, Lang::English),
+        )
+        .expect("Error generating comic page");
+        assert_eq!(
+            html.contains("alert"),
+            expected.is_some(),
+            "Notice isn't rendered only when needed"
+        );
+        if let Some(expected) = expected {
+            assert!(html.contains(expected), "Wrong notice rendered");
+        }
+    }
+
+    #[test_case((1989, 4, 16), false, (1989, 4, 16), (1989, 4, 17), true, false; "first comic, clamped")]
+    #[test_case((2023, 3, 12), false, (2023, 3, 11), (2023, 3, 12), false, true; "last comic, clamped")]
+    #[test_case((2000, 1, 1), false, (1999, 12, 31), (2000, 1, 2), false, false; "middle comic, clamped")]
+    #[test_case((1989, 4, 16), true, (2023, 3, 12), (1989, 4, 17), false, false; "first comic, wrapped")]
+    #[test_case((2023, 3, 12), true, (2023, 3, 11), (1989, 4, 16), false, false; "last comic, wrapped")]
+    #[test_case((2000, 1, 1), true, (1999, 12, 31), (2000, 1, 2), false, false; "middle comic, wrapped")]
+    /// Test the navigation to the previous and next comics.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - A tuple containing the year, month and day of the comic
+    /// * `wrap` - Whether navigation wraps around at the ends
+    /// * `prev_ymd` - A tuple containing the year, month and day of the expected previous comic
+    /// * `next_ymd` - A tuple containing the year, month and day of the expected next comic
+    /// * `disable_left` - Whether navigation to previous comics is expected to be disabled
+    /// * `disable_right` - Whether navigation to next comics is expected to be disabled
+    fn test_navigation(
+        date_ymd: (i32, u32, u32),
+        wrap: bool,
+        prev_ymd: (i32, u32, u32),
+        next_ymd: (i32, u32, u32),
+        disable_left: bool,
+        disable_right: bool,
+    ) {
+        let to_date = |(year, month, day)| {
+            NaiveDate::from_ymd_opt(year, month, day).expect("Invalid test parameters")
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+
+        let expected = Navigation {
+            first: first_comic,
+            previous: to_date(prev_ymd),
+            next: to_date(next_ymd),
+            disable_left,
+            disable_right,
+            clamped: None,
+        };
+        let result = get_navigation(&to_date(date_ymd), first_comic, last_comic, &[], wrap);
+        assert_eq!(result, expected, "Wrong navigation for comic");
+    }
+
+    #[test_case((2000, 1, 2), false, &[(2000, 1, 1), (2000, 1, 3)], (1999, 12, 31), (2000, 1, 4); "middle comic")]
+    #[test_case((1989, 4, 18), false, &[(1989, 4, 16), (1989, 4, 17)], (1989, 4, 16), (1989, 4, 19); "clamped at first comic")]
+    #[test_case((1989, 4, 16), true, &[(2023, 3, 12)], (2023, 3, 11), (1989, 4, 17); "wrapped to last comic")]
+    #[test_case((2023, 3, 11), true, &[(1989, 4, 16), (2023, 3, 12)], (2023, 3, 10), (1989, 4, 17); "wrapped past last comic")]
+    /// Test whether navigation skips over missing comics.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - A tuple containing the year, month and day of the comic
+    /// * `wrap` - Whether navigation wraps around at the ends
+    /// * `missing_ymd` - Tuples containing the year, month and day of the missing comics, in order
+    /// * `prev_ymd` - A tuple containing the year, month and day of the expected previous comic
+    /// * `next_ymd` - A tuple containing the year, month and day of the expected next comic
+    fn test_navigation_missing(
+        date_ymd: (i32, u32, u32),
+        wrap: bool,
+        missing_ymd: &[(i32, u32, u32)],
+        prev_ymd: (i32, u32, u32),
+        next_ymd: (i32, u32, u32),
+    ) {
+        let to_date = |&(year, month, day): &(i32, u32, u32)| {
+            NaiveDate::from_ymd_opt(year, month, day).expect("Invalid test parameters")
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let missing: Vec<_> = missing_ymd.iter().map(to_date).collect();
+
+        let result = get_navigation(&to_date(&date_ymd), first_comic, last_comic, &missing, wrap);
+        assert_eq!(
+            (result.previous, result.next),
+            (to_date(&prev_ymd), to_date(&next_ymd)),
+            "Navigation doesn't skip missing comics"
+        );
+    }
+
+    #[test]
+    /// Test whether the ETag of a comic page is weak, and changes only with the page's inputs.
+    fn test_comic_etag() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 2).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let nav = get_navigation(&date, first_comic, last_comic, &[], false);
+
+        let etag = get_comic_etag(
+            &date,
+            &comic_data,
+            &nav,
+            None,
+            &get_prefs(Theme::Light, Lang::English),
+        );
+        assert!(etag.weak, "ETag isn't weak");
+        assert_eq!(
+            etag,
+            get_comic_etag(
+                &date,
+                &comic_data,
+                &nav,
+                None,
+                &get_prefs(Theme::Light, Lang::English)
+            ),
+            "ETag isn't deterministic"
+        );
+
+        let missing = [date.succ_opt().unwrap()];
+        let other_nav = get_navigation(&date, first_comic, last_comic, &missing, false);
+        assert_ne!(
+            etag,
+            get_comic_etag(
+                &date,
+                &comic_data,
+                &other_nav,
+                None,
+                &get_prefs(Theme::Light, Lang::English)
+            ),
+            "ETag doesn't change with the navigation"
+        );
+        assert_ne!(
+            etag,
+            get_comic_etag(
+                &date,
+                &comic_data,
+                &nav,
+                Some(""),
+                &get_prefs(Theme::Light, Lang::English)
+            ),
+            "ETag doesn't change with the inlined CSS"
+        );
+        assert_ne!(
+            etag,
+            get_comic_etag(
+                &date,
+                &comic_data,
+                &nav,
+                None,
+                &get_prefs(Theme::Dark, Lang::English)
+            ),
+            "ETag doesn't change with the theme"
+        );
+        assert_ne!(
+            etag,
+            get_comic_etag(
+                &date,
+                &comic_data,
+                &nav,
+                None,
+                &get_prefs(Theme::Light, Lang::German)
+            ),
+            "ETag doesn't change with the language"
+        );
+
+        let mut prefs = get_prefs(Theme::Light, Lang::English);
+        prefs.favorites.toggle(date.pred_opt().unwrap());
+        assert_eq!(
+            etag,
+            get_comic_etag(&date, &comic_data, &nav, None, &prefs),
+            "ETag changes with other favorites"
+        );
+        prefs.favorites.toggle(date);
+        assert_ne!(
+            etag,
+            get_comic_etag(&date, &comic_data, &nav, None, &prefs),
+            "ETag doesn't change with whether the comic is a favorite"
+        );
+    }
+
+    #[test_case(None, false; "unconditional")]
+    #[test_case(Some(IfNoneMatch::Any), true; "any")]
+    #[test_case(Some(IfNoneMatch::Items(vec![EntityTag::new_weak("abc".into())])), true; "weak match")]
+    #[test_case(Some(IfNoneMatch::Items(vec![EntityTag::new_strong("abc".into())])), true; "strong match")]
+    #[test_case(Some(IfNoneMatch::Items(vec![EntityTag::new_weak("xyz".into())])), false; "mismatch")]
+    /// Test whether the client's cached copy is valid only if its ETag weakly matches.
+    ///
+    /// # Arguments
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `expected` - Whether the client's copy is expected to be valid
+    fn test_etag_matches(if_none_match: Option<IfNoneMatch>, expected: bool) {
+        let etag = EntityTag::new_weak("abc".into());
+        assert_eq!(
+            etag_matches(if_none_match.as_ref(), &etag),
+            expected,
+            "Wrong validity of the client's copy"
+        );
+    }
+
+    #[test_case("Dilbert"; "plain title")]
+    #[test_case("</script><script>alert(1)</script>"; "closing script tag")]
+    #[test_case("<!-- A & B -->"; "HTML comment")]
+    /// Test whether the comic page has JSON-LD structured data for the comic.
+    ///
+    /// # Arguments
+    /// * `title` - The title of the comic
+    fn test_template_json_ld(title: &str) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            title: title.into(),
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 900,
+            img_height: 280,
+            permalink: "https://dilbert.com/strip/2000-01-01".into(),
+            tags: vec!["dogbert".into()],
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            None,
+            "",
+            &get_prefs(Theme::Light, Lang::English),
+        )
+        .expect("Error generating comic page");
+
+        // The minified HTML has unquoted attributes.
+        let json_ld = html
+            .split_once("<script type=application/ld+json>")
+            .and_then(|(_, rest)| rest.split_once("</script>"))
+            .map(|(json_ld, _)| json_ld)
+            .expect("Missing JSON-LD");
+        let json_ld: serde_json::Value =
+            serde_json::from_str(json_ld).expect("JSON-LD isn't valid JSON");
+        assert_eq!(
+            json_ld,
+            json!({
+                "@context": "https://schema.org",
+                "@type": "ComicStory",
+                "name": title,
+                "datePublished": "2000-01-01",
+                "url": format!("{APP_URL}2000-01-01"),
+                "sameAs": "https://dilbert.com/strip/2000-01-01",
+                "author": {
+                    "@type": "Person",
+                    "name": COMIC_AUTHOR,
+                },
+                "keywords": ["dogbert"],
+                "image": {
+                    "@type": "ImageObject",
+                    "contentUrl": format!("{APP_URL}2000-01-01/image"),
+                    "width": 900,
+                    "height": 280,
+                },
+            }),
+            "Wrong JSON-LD"
+        );
+    }
+
+    #[test_case(None; "linked CSS")]
+    #[test_case(Some("main{max-width:800px}"); "inline CSS")]
+    /// Test whether the comic page either links or inlines the CSS.
+    ///
+    /// # Arguments
+    /// * `inline_css` - The CSS to inline into the page, if any
+    fn test_template_css_inlining(inline_css: Option<&str>) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let nav = get_navigation(&comic_date, first_comic, last_comic, &[], false);
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            inline_css,
+            "test",
+            &get_prefs(Theme::Light, Lang::English),
+        )
+        .expect("Error generating comic page");
+        let html = html.as_str();
+
+        let dom =
+            tl::parse(html, tl::ParserOptions::default()).expect("Response body not valid HTML");
+        let parser = dom.parser();
+        // The minified HTML has unquoted attributes, which `tl` can't parse for paths.
+        let has_link = html.contains("href=/styles.css");
+        let style = dom
+            .query_selector("style")
+            .expect("Invalid CSS selector")
+            .next()
+            .and_then(|handle| handle.get(parser))
+            .map(|node| node.inner_text(parser).into_owned());
+
+        match inline_css {
+            Some(css) => {
+                assert!(!has_link, "Inlined CSS is still linked");
+                assert_eq!(style.as_deref(), Some(css), "CSS not inlined correctly");
+                assert!(
+                    html.contains("<style nonce=test>"),
+                    "Inline CSS has no nonce"
+                );
+            }
+            None => {
+                assert!(has_link, "CSS is not linked");
+                assert_eq!(style, None, "CSS inlined when it shouldn't be");
+            }
+        }
+    }
+
+    #[test_case(Lang::English, "Saturday January 01, 2000", "Random"; "English")]
+    #[test_case(Lang::German, "Samstag, 1. Januar 2000", "Zufällig"; "German")]
+    /// Test whether the comic page is translated into the given language.
+    ///
+    /// # Arguments
+    /// * `lang` - The language of the page
+    /// * `date_disp` - The expected display of the comic's date
+    /// * `random` - The expected text of the button for a random comic
+    fn test_template_lang(lang: Lang, date_disp: &str, random: &str) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            None,
+            "",
+            &get_prefs(Theme::Light, lang),
+        )
+        .expect("Error generating comic page");
+
+        // The minified HTML has unquoted attributes.
+        assert!(
+            html.contains(&format!("lang={}", lang.code())),
+            "Page has the wrong language"
+        );
+        assert!(html.contains(date_disp), "Date isn't translated");
+        assert!(html.contains(random), "UI strings aren't translated");
+    }
+
+    #[test_case(false; "not favorite")]
+    #[test_case(true; "favorite")]
+    /// Test whether the star shows whether the comic is a favorite.
+    ///
+    /// # Arguments
+    /// * `favorite` - Whether the comic is a favorite of the client
+    fn test_template_favorite(favorite: bool) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let mut prefs = get_prefs(Theme::Light, Lang::English);
+        if favorite {
+            prefs.favorites.toggle(comic_date);
+        }
+        let html = render_template(&comic_date, &comic_data, &nav, None, "", &prefs)
+            .expect("Couldn't render template");
+
+        assert!(
+            html.contains("/favorite/2000-01-01"),
+            "Missing star for the comic"
+        );
+        let label = if favorite {
+            "Remove from favorites"
+        } else {
+            "Add to favorites"
+        };
+        assert!(html.contains(label), "Star doesn't match the favorite");
+    }
+
+    #[test]
+    /// Test whether the comic's sequence number is shown, linking to its numbered URL.
+    fn test_template_number() {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let prefs = get_prefs(Theme::Light, Lang::English);
+        let html = render_template(&comic_date, &comic_data, &nav, None, "", &prefs)
+            .expect("Couldn't render template");
+
+        assert!(html.contains("/n/3913"), "Missing link to the numbered URL");
+        // The minified HTML has the non-breaking space as a character.
+        assert!(html.contains("No.\u{a0}3913"), "Missing comic number");
+    }
+
+    #[test_case(&[]; "no variants")]
+    #[test_case(&[450, 1800]; "variants")]
+    /// Test whether the comic's image lists its other resolutions only if it has any.
+    ///
+    /// # Arguments
+    /// * `widths` - The widths of the other resolutions of the image
+    fn test_template_srcset(widths: &[i32]) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 900,
+            img_height: 1,
+            img_variants: widths
+                .iter()
+                .map(|&width| ImageVariant {
+                    url: REPO_URL.into(),
+                    width,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let prefs = get_prefs(Theme::Light, Lang::English);
+        let html = render_template(&comic_date, &comic_data, &nav, None, "", &prefs)
+            .expect("Couldn't render template");
+
+        assert_eq!(
+            html.contains("srcset"),
+            !widths.is_empty(),
+            "Image resolutions aren't listed only when available"
+        );
+        for width in widths {
+            assert!(
+                html.contains(&format!("/2000-01-01/image/{width} {width}w")),
+                "Missing image resolution: {width}"
+            );
+        }
+    }
+
+    #[test_case(Theme::Light, false; "light")]
+    #[test_case(Theme::Dark, true; "dark")]
+    /// Test whether the dark stylesheet is linked only for the dark theme.
+    ///
+    /// # Arguments
+    /// * `theme` - The colour theme chosen by the client
+    /// * `dark` - Whether the dark stylesheet is expected to be linked
+    fn test_template_theme(theme: Theme, dark: bool) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let nav = get_navigation(&comic_date, comic_date, comic_date, &[], false);
+        let html = render_template(
+            &comic_date,
+            &comic_data,
+            &nav,
+            None,
+            "",
+            &get_prefs(theme, Lang::English),
+        )
+        .expect("Error generating comic page");
+
+        // The minified HTML has unquoted attributes.
+        assert_eq!(
+            html.contains("href=/dark.css"),
+            dark,
+            "Dark stylesheet linked for the wrong theme"
+        );
+        assert!(
+            html.contains(&format!("value={}", theme.toggled().as_str())),
+            "Theme toggle doesn't switch to the other theme"
+        );
+    }
+
+    #[test_case((2000, 1, 1), (2000, 1, 1), true; "single day")]
+    #[test_case((2000, 1, 1), (2000, 1, 31), true; "longest range")]
+    #[test_case((2000, 1, 1), (2000, 2, 1), false; "too long")]
+    #[test_case((2000, 1, 2), (2000, 1, 1), false; "reversed")]
+    #[test_case((1989, 4, 15), (1989, 4, 20), false; "starts too early")]
+    #[test_case((2023, 3, 10), (2023, 3, 13), false; "ends too late")]
+    /// Test the validation of ranges of comics.
+    ///
+    /// # Arguments
+    /// * `start_ymd` - A tuple containing the year, month and day of the start of the range
+    /// * `end_ymd` - A tuple containing the year, month and day of the end of the range
+    /// * `valid` - Whether the range is expected to be valid
+    fn test_check_range(start_ymd: (i32, u32, u32), end_ymd: (i32, u32, u32), valid: bool) {
+        let start = NaiveDate::from_ymd_opt(start_ymd.0, start_ymd.1, start_ymd.2)
+            .expect("Invalid test parameters");
+        let end = NaiveDate::from_ymd_opt(end_ymd.0, end_ymd.1, end_ymd.2)
+            .expect("Invalid test parameters");
+        match check_range(&start, &end, &get_comic_range()) {
+            Ok(()) => assert!(valid, "Invalid range passed the check"),
+            Err(AppError::BadRequest(msg)) => {
+                assert!(!valid, "Valid range failed the check: {msg}")
+            }
+            Err(err) => panic!("Error checking range: {err}"),
+        }
+    }
+
+    #[test_case(10, None, (2023, 3, 3), (2023, 3, 12); "default")]
+    #[test_case(3, Some((2000, 1, 10)), (2000, 1, 8), (2000, 1, 10); "custom end")]
+    #[test_case(10, Some((1989, 4, 18)), (1989, 4, 16), (1989, 4, 18); "fewer than available")]
+    #[test_case(10, Some((2030, 1, 1)), (2023, 3, 3), (2023, 3, 12); "end too late")]
+    #[test_case(100, None, (2023, 2, 10), (2023, 3, 12); "too long")]
+    #[test_case(0, None, (2023, 3, 12), (2023, 3, 12); "empty")]
+    /// Test the calculation of the range of dates in the feed.
+    ///
+    /// # Arguments
+    /// * `len` - The number of days in the feed
+    /// * `end_ymd` - A tuple containing the year, month and day of the feed's end, if any
+    /// * `start_ymd` - A tuple containing the year, month and day of the expected start
+    /// * `expected_end_ymd` - A tuple containing the year, month and day of the expected end
+    fn test_feed_dates(
+        len: usize,
+        end_ymd: Option<(i32, u32, u32)>,
+        start_ymd: (i32, u32, u32),
+        expected_end_ymd: (i32, u32, u32),
+    ) {
+        let end = end_ymd.map(|ymd| {
+            NaiveDate::from_ymd_opt(ymd.0, ymd.1, ymd.2).expect("Invalid test parameters")
+        });
+        let expected_start = NaiveDate::from_ymd_opt(start_ymd.0, start_ymd.1, start_ymd.2)
+            .expect("Invalid test parameters");
+        let expected_end =
+            NaiveDate::from_ymd_opt(expected_end_ymd.0, expected_end_ymd.1, expected_end_ymd.2)
+                .expect("Invalid test parameters");
+
+        let dates = get_feed_dates(len, end, &get_comic_range());
+        assert_eq!(dates, expected_start..=expected_end, "Wrong feed dates");
+    }
+
+    #[test_case("2000-01-01", Some("2000-01-10"), Some(("2000-01-01", "2000-01-10")); "valid")]
+    #[test_case("1980-01-01", Some("1989-04-20"), Some(("1989-04-16", "1989-04-20")); "start too early")]
+    #[test_case("2023-03-01", None, Some(("2023-03-01", "2023-03-12")); "default end")]
+    #[test_case("2000-01-10", Some("2000-01-01"), None; "reversed")]
+    #[test_case("2030-01-01", None, None; "start too late")]
+    /// Test the calculation of the range of dates for warming up the cache.
+    ///
+    /// # Arguments
+    /// * `from` - The range's start, in the format YYYY-MM-DD
+    /// * `to` - The range's end, in the format YYYY-MM-DD, if any
+    /// * `expected` - The expected start and end, in the format YYYY-MM-DD, if the range is valid
+    fn test_warm_dates(from: &str, to: Option<&str>, expected: Option<(&str, &str)>) {
+        let to_date = |date| str_to_date(date, SRC_DATE_FMT).expect("Invalid test parameters");
+        let expected = expected.map(|(start, end)| to_date(start)..=to_date(end));
+
+        match get_warm_dates(to_date(from), to.map(to_date), &get_comic_range()) {
+            Ok(dates) => assert_eq!(Some(dates), expected, "Wrong warm-up dates"),
+            Err(AppError::BadRequest(_)) => assert!(expected.is_none(), "Valid range rejected"),
+            Err(err) => panic!("Error getting warm-up dates: {err}"),
+        }
+    }
+
+    #[test_case(""; "no comics")]
+    #[test_case("Test"; "single comic")]
+    /// Test rendering of the template for multiple comics.
+    ///
+    /// # Arguments
+    /// * `title` - The title of the comic in the page, if any
+    fn test_strip_template_rendering(title: &str) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comics: Vec<_> = if title.is_empty() {
+            Vec::new()
+        } else {
+            vec![StripEntry {
+                date: date.format(SRC_DATE_FMT).to_string(),
+                date_disp: date.format(DISP_DATE_FMT).to_string(),
+                data: ComicData {
+                    title: title.into(),
+                    img_url: REPO_URL.into(), // Any URL should technically work.
+                    img_width: 1,
+                    img_height: 1,
+                    ..Default::default()
+                },
+            }]
+        };
+        let resp =
+            serve_strip_template(FIRST_COMIC, &comics, "").expect("Error generating strip page");
+
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        test_html_response(resp);
+    }
+
+    #[test_case("Test", None; "comic with title")]
+    #[test_case("", Some("Dilbert: Hi."); "comic with transcript")]
+    /// Test rendering of the template for the text-only view of a comic.
+    ///
+    /// # Arguments
+    /// * `title` - The title of the comic
+    /// * `transcript` - The transcript of the comic, if any
+    fn test_text_template_rendering(title: &str, transcript: Option<&str>) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            title: title.into(),
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            transcript: transcript.map(String::from),
+            ..Default::default()
+        };
+        let resp = serve_text_template(&date, &comic_data).expect("Error generating text page");
+
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let html = test_html_response(resp);
+        assert!(!html.contains("<img"), "Text view has an image");
+        assert!(html.contains(title), "Title is missing");
+        assert_eq!(
+            html.contains("No transcript is available"),
+            transcript.is_none(),
+            "Missing transcript isn't noted only when unavailable"
+        );
+        if let Some(transcript) = transcript {
+            assert!(html.contains(transcript), "Transcript is missing");
+        }
+    }
+
+    #[test]
+    /// Test whether the calendar of the first comic's month has the right weeks and days.
+    fn test_calendar_weeks() {
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let month_start = NaiveDate::from_ymd_opt(1989, 4, 1).unwrap();
+        let weeks = get_calendar_weeks(&month_start, &first_comic, &last_comic);
+
+        // April 1989 starts on a Saturday and ends on a Sunday.
+        assert_eq!(weeks.len(), 6, "Wrong number of weeks");
+        assert!(
+            weeks[0][..6].iter().all(Option::is_none),
+            "Days before the month are in the calendar"
+        );
+        assert!(
+            weeks[5][1..].iter().all(Option::is_none),
+            "Days after the month are in the calendar"
+        );
+
+        let days: Vec<_> = weeks.iter().flatten().flatten().collect();
+        assert_eq!(days.len(), 30, "Wrong number of days");
+        for day in days {
+            assert_eq!(
+                day.available,
+                day.day >= 16,
+                "Wrong availability for day {}",
+                day.day
+            );
+        }
+    }
+
+    #[test_case(1989, 4, StatusCode::OK; "first month")]
+    #[test_case(2023, 3, StatusCode::OK; "last month")]
+    #[test_case(1989, 3, StatusCode::NOT_FOUND; "before first month")]
+    #[test_case(2023, 4, StatusCode::NOT_FOUND; "after last month")]
+    #[test_case(2000, 13, StatusCode::NOT_FOUND; "invalid month")]
+    /// Test rendering of the calendar of a month.
+    ///
+    /// # Arguments
+    /// * `year` - The year of the month
+    /// * `month` - The month
+    /// * `expected_status` - The expected HTTP status code
+    fn test_archive_month(year: i32, month: u32, expected_status: StatusCode) {
+        let resp = serve_archive_month(year, month, &get_comic_range());
+        assert_eq!(resp.status(), expected_status, "Wrong response status");
+        if expected_status == StatusCode::OK {
+            test_html_response(resp);
+        }
+    }
+
+    #[test_case(1989, 4, Some(15), None, Some("1989-05"); "first month")]
+    #[test_case(2000, 2, Some(29), Some("2000-01"), Some("2000-03"); "leap month")]
+    #[test_case(2023, 3, Some(12), Some("2023-02"), None; "last month")]
+    #[test_case(1989, 3, None, None, None; "before first month")]
+    #[test_case(2000, 13, None, None, None; "invalid month")]
+    /// Test whether the grid of a month has a thumbnail for every comic, with navigation.
+    ///
+    /// # Arguments
+    /// * `year` - The year of the month
+    /// * `month` - The month
+    /// * `num_comics` - The expected number of thumbnails, or None if the month isn't found
+    /// * `previous` - The expected URL path of the previous month, if any
+    /// * `next` - The expected URL path of the next month, if any
+    fn test_serve_grid(
+        year: i32,
+        month: u32,
+        num_comics: Option<usize>,
+        previous: Option<&str>,
+        next: Option<&str>,
+    ) {
+        let resp = serve_grid(year, month, &get_comic_range());
+        let Some(num_comics) = num_comics else {
+            assert_eq!(
+                resp.status(),
+                StatusCode::NOT_FOUND,
+                "Response is not status NOT FOUND"
+            );
+            return;
+        };
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+
+        let html = test_html_response(resp);
+        assert_eq!(
+            html.matches("/thumbnail").count(),
+            num_comics,
+            "Wrong number of thumbnails"
+        );
+        assert!(
+            html.contains(&format!("/archive/{year:04}/{month:02}")),
+            "Missing link to the calendar"
+        );
+        for (path, label) in [(previous, "previous"), (next, "next")] {
+            if let Some(path) = path {
+                assert!(
+                    html.contains(&format!("/grid/{path}")),
+                    "Missing link to {label} month"
+                );
+            }
+        }
+        assert_eq!(
+            html.matches("/grid/").count(),
+            usize::from(previous.is_some()) + usize::from(next.is_some()),
+            "Wrong links to other months"
+        );
+    }
+
+    #[test_case(None, Some(("1989", "1989")), Some(("2023", "2023")), 35; "all years")]
+    #[test_case(Some(1989), Some(("1989/04", "April")), Some(("1989/12", "December")), 9; "first year")]
+    #[test_case(Some(2023), Some(("2023/01", "January")), Some(("2023/03", "March")), 3; "last year")]
+    #[test_case(Some(2000), Some(("2000/01", "January")), Some(("2000/12", "December")), 12; "middle year")]
+    #[test_case(Some(2024), None, None, 0; "year without comics")]
+    /// Test whether the index of the archive lists the right periods.
+    ///
+    /// # Arguments
+    /// * `year` - The year whose months are to be listed, if any
+    /// * `first` - The expected path and label of the first entry, if any
+    /// * `last` - The expected path and label of the last entry, if any
+    /// * `len` - The expected number of entries
+    fn test_archive_entries(
+        year: Option<i32>,
+        first: Option<(&str, &str)>,
+        last: Option<(&str, &str)>,
+        len: usize,
+    ) {
+        let entries = get_archive_entries(year, &get_comic_range()).unwrap_or_default();
+        let to_tuple = |entry: &ArchiveEntry| (entry.path.clone(), entry.label.clone());
+        let to_owned = |(path, label): (&str, &str)| (path.to_string(), label.to_string());
+        assert_eq!(entries.len(), len, "Wrong number of entries");
+        assert_eq!(
+            entries.first().map(to_tuple),
+            first.map(to_owned),
+            "Wrong first entry"
+        );
+        assert_eq!(
+            entries.last().map(to_tuple),
+            last.map(to_owned),
+            "Wrong last entry"
+        );
+
+        let resp = serve_archive_index(year, &get_comic_range());
+        if first.is_some() {
+            assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+            test_html_response(resp);
+        } else {
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "Response is not 404");
+        }
+    }
+
+    #[test]
+    /// Test rendering of the 400 bad request page template.
+    fn test_400_page() {
+        let resp = serve_400_raw("Invalid").expect("Error generating 400 page");
+        assert_eq!(
+            resp.status(),
+            StatusCode::BAD_REQUEST,
+            "Response is not status BAD REQUEST"
+        );
+        test_html_response(resp);
+    }
+
+    #[test]
+    /// Test whether the startup self-test of templates passes.
+    fn test_check_templates() {
+        check_templates().expect("Template self-test failed");
+    }
+
+    #[actix_web::test]
+    /// Test whether the web app manifest is valid JSON with the app's icon.
+    async fn test_manifest() {
+        let resp = serve_manifest();
+        assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Couldn't read manifest");
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("Manifest isn't valid JSON");
+        assert_eq!(manifest["name"], APP_NAME, "Wrong app name");
+        assert_eq!(manifest["icons"][0]["src"], APP_ICON_URL, "Wrong app icon");
+    }
+
+    #[test_case(&["/random", "/api/"], &["disallow: /random", "disallow: /api/"]; "default")]
+    #[test_case(&[], &["disallow:"]; "allow all")]
+    #[test_case(&["/", "/2000-", "/random"], &["disallow: /random"]; "comic prefixes")]
+    /// Test whether `robots.txt` has the right rules, without disallowing comic pages.
+    ///
+    /// # Arguments
+    /// * `disallow` - The URL path prefixes to disallow
+    /// * `expected` - The rules expected in `robots.txt`
+    fn test_robots_txt(disallow: &[&str], expected: &[&str]) {
+        let disallow: Vec<_> = disallow.iter().map(|path| path.to_string()).collect();
+        let robots_txt = render_robots_txt(&disallow);
+
+        assert!(
+            robots_txt.contains("user-agent: *\n"),
+            "robots.txt doesn't apply to all crawlers"
+        );
+        let rules: Vec<_> = robots_txt
+            .lines()
+            .filter(|line| line.starts_with("disallow:"))
+            .collect();
+        assert_eq!(rules, expected, "Wrong rules in robots.txt");
+    }
+
+    #[test_case(Some((2000, 1, 1)), Theme::Light; "missing comic")]
+    #[test_case(None, Theme::Light; "generic 404")]
+    #[test_case(None, Theme::Dark; "dark theme")]
+    /// Test rendering of the 404 not found page template.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - A tuple containing the year, month and day of the missing comic, if any
+    /// * `theme` - The colour theme chosen by the client
+    fn test_404_page(date_ymd: Option<(i32, u32, u32)>, theme: Theme) {
+        let date = date_ymd.map(|ymd| {
+            NaiveDate::from_ymd_opt(ymd.0, ymd.1, ymd.2).expect("Invalid test parameters")
+        });
+        let resp = serve_404_raw(date.as_ref(), theme).expect("Error generating 404 page");
+
+        assert_eq!(
+            resp.status(),
+            StatusCode::NOT_FOUND,
+            "Response is not status NOT FOUND"
+        );
+        test_html_response(resp);
+    }
+
+    #[test_case(""; "empty error msg")]
+    #[test_case("Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor
+    incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation
+    ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit
+    in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat
+    cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.";
+    "long error msg")]
+    /// Test rendering of the 500 internal server error page template.
+    ///
+    /// # Arguments
+    /// * `error_msg` - The error message to be displayed in the page
+    fn test_500_page(error_msg: &str) {
+        let resp = serve_500(&AppError::Scrape(error_msg.into()), None);
+        assert_eq!(
+            resp.status(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Response is not status INTERNAL SERVER ERROR"
+        );
+        test_html_response(resp);
+    }
+
+    #[test]
+    /// Test whether outages of the comic source are served as 503 with a "Retry-After" header.
+    fn test_503_page() {
+        let err = AppError::SourceUnavailable(String::new(), StdDuration::from_millis(1500));
+        let resp = serve_500(&err, None);
+        assert_eq!(
+            resp.status(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Response is not status SERVICE UNAVAILABLE"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()),
+            Some("2"),
+            "Wrong Retry-After header"
+        );
+        test_html_response(resp);
+    }
+
+    /// Enum for the state of `Viewer::get_comic_info`.
+    #[derive(PartialEq, Eq)]
+    enum GetComicInfoState {
+        /// Comic info.
+        Found,
+        /// Comic info is missing, and no redirection is to be done.
+        MissingComic,
+        /// Crashes with a miscellaneous error.
+        Fail,
+    }
+
+    /// Get a latest date scraper that's pinned to the last comic, and so never makes requests.
+    fn get_pinned_latest_date_scraper() -> LatestDateScraper<MockPool> {
+        LatestDateScraper::new(
+            None,
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+            false,
+        )
+    }
+
+    /// Get a viewer for tests, which has a latest date scraper pinned to the last comic.
+    ///
+    /// # Arguments
+    /// * `comic_scraper` - The (mocked) comic scraper
+    /// * `feed_end` - The date of the last comic in the feed
+    fn get_viewer(comic_scraper: ComicScraper<MockPool>, feed_end: NaiveDate) -> Viewer<MockPool> {
+        Viewer {
+            source: Arc::new(DilbertSource::default()),
+            comic_scraper,
+            latest_date_scraper: get_pinned_latest_date_scraper(),
+            inline_css: None,
+            nav_wrap: false,
+            robots_txt: GeneratedFile::new(String::new()),
+            feed_len: 1,
+            feed_end: Some(feed_end),
+            attribution: None,
+        }
+    }
+
+    /// Get a `Viewer` whose scrapers have been mocked, along with the data it works with.
+    ///
+    /// # Arguments
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    ///
+    /// # Returns
+    /// * The "mocked" viewer
+    /// * The test comic date
+    /// * The test comic data
+    fn get_mock_viewer(state: GetComicInfoState) -> (Viewer<MockPool>, NaiveDate, ComicData) {
+        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData::default();
 
-    let error_template = ErrorTemplate {
-        error,
-        repo_url: REPO_URL,
-    };
-    debug!("Rendering 500 template: {error_template:?}");
-    match error_template.render() {
-        Ok(webpage) => {
-            // Minification can crash, so if it fails, just serve the original. Since
-            // minification modifies the input, give it a clone.
-            let minified = match minify_html(webpage.clone()) {
-                Ok(html) => html,
-                Err(err) => {
-                    error!("HTML minification crashed with error: {err}");
-                    webpage
+        // Set up the mock comic scraper.
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        let expected_comic_data = Some(comic_data.clone());
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(1)
+            .returning(move |date| match state {
+                GetComicInfoState::Found if date == &comic_date => Ok(expected_comic_data.clone()),
+                GetComicInfoState::Fail => Err(AppError::Scrape("Manual error".into())),
+                _ => Ok(None),
+            });
+
+        mock_comic_scraper
+            .expect_get_missing_dates()
+            .returning(|| Ok(Vec::new()));
+        mock_comic_scraper
+            .expect_get_cached_page()
+            .returning(|_, _| None);
+        mock_comic_scraper.expect_cache_page().return_const(());
+
+        let viewer = get_viewer(mock_comic_scraper, comic_date);
+        (viewer, comic_date, comic_data)
+    }
+
+    #[test_case("2000-01-01", "2000-01-05", NonZeroUsize::new(3), &["ok", "missing", "error"]; "limited")]
+    #[test_case("2000-01-01", "2000-01-02", None, &["ok", "missing"]; "unlimited")]
+    #[test_case("2000-01-02", "2000-01-01", None, &[]; "reversed")]
+    #[actix_web::test]
+    /// Test whether the batch API serves the comics in a range up to the limit, marking errors.
+    ///
+    /// # Arguments
+    /// * `start` - The date of the first comic in the range
+    /// * `end` - The date of the last comic in the range
+    /// * `limit` - The maximum number of comics to serve
+    /// * `expected` - The expected state of each served comic, or none if the range is invalid
+    async fn test_serve_comics_data(
+        start: &str,
+        end: &str,
+        limit: Option<NonZeroUsize>,
+        expected: &[&str],
+    ) {
+        let start = str_to_date(start, SRC_DATE_FMT).unwrap();
+        let end = str_to_date(end, SRC_DATE_FMT).unwrap();
+        let missing = NaiveDate::from_ymd_opt(2000, 1, 2).unwrap();
+        let failing = NaiveDate::from_ymd_opt(2000, 1, 3).unwrap();
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(expected.len())
+            .returning(move |date| {
+                if date == &failing {
+                    return Err(AppError::Scrape("Manual error".into()));
                 }
-            };
-            response.content_type(ContentType::html()).body(minified)
+                Ok((date != &missing).then(|| ComicData {
+                    img_width: 1,
+                    img_height: 1,
+                    ..Default::default()
+                }))
+            });
+        let viewer = get_viewer(mock_comic_scraper, start);
+
+        let resp = viewer.serve_comics_data(&start, &end, limit).await;
+        if expected.is_empty() {
+            assert_eq!(
+                resp.status(),
+                StatusCode::BAD_REQUEST,
+                "Invalid range served"
+            );
+            return;
         }
-        Err(err) => {
-            error!("Couldn't render Error 500 HTML: {err}");
-            // An empty Error 500 response is still better than crashing
-            response.finish()
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Couldn't read comics data");
+        let comics: Vec<serde_json::Value> =
+            serde_json::from_slice(&bytes).expect("Comics data isn't a valid JSON array");
+        let states: Vec<_> = comics
+            .iter()
+            .map(|comic| match comic["error"].as_str() {
+                None => "ok",
+                Some(err) if err.starts_with("No comic found") => "missing",
+                Some(_) => "error",
+            })
+            .collect();
+        assert_eq!(states, expected, "Wrong comics served");
+        let first_date = str_to_date(comics[0]["date"].as_str().unwrap(), SRC_DATE_FMT);
+        assert_eq!(first_date.ok(), Some(start), "Comics are out of order");
+    }
+
+    #[test_case("image/gif", "dilbert-2000-01-01.gif"; "gif")]
+    #[test_case("image/jpeg", "dilbert-2000-01-01.jpg"; "jpeg")]
+    #[test_case("application/octet-stream", "dilbert-2000-01-01"; "unknown")]
+    /// Test whether downloaded images are named after their dates, with the right extensions.
+    ///
+    /// # Arguments
+    /// * `content_type` - The MIME type of the image
+    /// * `expected` - The expected file name
+    fn test_serve_download(content_type: &str, expected: &str) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let image = ComicImage {
+            content_type: content_type.into(),
+            bytes: Vec::new(),
+        };
+        let resp = serve_download(&date, image);
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        assert_eq!(
+            resp.headers()
+                .get(CONTENT_DISPOSITION)
+                .and_then(|value| value.to_str().ok()),
+            Some(format!("attachment; filename=\"{expected}\"").as_str()),
+            "Wrong file name"
+        );
+    }
+
+    #[test_case(false; "some cached")]
+    #[test_case(true; "none cached")]
+    #[actix_web::test]
+    /// Test whether the archive of a month has its cached comics in order.
+    ///
+    /// # Arguments
+    /// * `none_cached` - Whether none of the comics in the month are cached
+    async fn test_serve_cbz(none_cached: bool) {
+        // The first comic is in the middle of this month.
+        let month = NaiveDate::from_ymd_opt(1989, 4, 1).unwrap();
+        let missing = NaiveDate::from_ymd_opt(1989, 4, 20).unwrap();
+        let failing = NaiveDate::from_ymd_opt(1989, 4, 25).unwrap();
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_cached_comic_image()
+            .times(15)
+            .returning(move |date| {
+                if date == &failing {
+                    return Err(AppError::Scrape("Manual error".into()));
+                }
+                Ok((!none_cached && date != &missing).then(|| ComicImage {
+                    content_type: "image/gif".into(),
+                    bytes: date.to_string().into_bytes(),
+                }))
+            });
+        // Comics should never be fetched for an archive.
+        mock_comic_scraper.expect_get_comic_image().never();
+        let viewer = get_viewer(mock_comic_scraper, month);
+
+        // The viewer is shared through app data when serving.
+        let resp = web::Data::new(viewer).into_inner().serve_cbz(&month).await;
+        if none_cached {
+            assert_eq!(
+                resp.status(),
+                StatusCode::NOT_FOUND,
+                "Response is not status NOT FOUND"
+            );
+            return;
+        }
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        assert_eq!(
+            resp.headers()
+                .get(CONTENT_DISPOSITION)
+                .and_then(|value| value.to_str().ok()),
+            Some("attachment; filename=\"dilbert-1989-04.cbz\""),
+            "Wrong file name"
+        );
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Could not read response body");
+
+        let archive = ZipArchive::new(Cursor::new(bytes)).expect("Invalid archive");
+        let names: Vec<_> = archive.file_names().collect();
+        let expected: Vec<_> = (16..=30)
+            .filter(|day| ![20, 25].contains(day))
+            .map(|day| format!("dilbert-1989-04-{day}.gif"))
+            .collect();
+        assert_eq!(names, expected, "Wrong comics in archive or out of order");
+    }
+
+    #[actix_web::test]
+    /// Test whether the history lists all viewed comics in order, with titles only if cached.
+    async fn test_serve_history() {
+        let dates: Vec<_> = (1..=2)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+        let cached = dates[1];
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_cached_comics_data()
+            .times(1)
+            .returning(move |dates| {
+                Ok(dates
+                    .iter()
+                    .map(|date| {
+                        (date == &cached).then(|| ComicData {
+                            title: "Cached Title".into(),
+                            ..Default::default()
+                        })
+                    })
+                    .collect())
+            });
+        let viewer = get_viewer(mock_comic_scraper, cached);
+
+        let mut history = History::default();
+        for date in &dates {
+            history.record(*date);
         }
+        let resp = viewer.serve_history(&history).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let html = std::str::from_utf8(&body).expect("Response body not UTF-8");
+
+        let positions: Vec<_> = ["/2000-01-02", "Cached Title", "/2000-01-01"]
+            .iter()
+            .map(|text| html.find(text).expect("Missing comic in history"))
+            .collect();
+        assert!(
+            positions.is_sorted(),
+            "History isn't most recent first: {positions:?}"
+        );
+    }
+
+    #[test_case("2000-01-05", "2000-01-02", 7, true, true; "middle")]
+    #[test_case("1989-04-20", "1989-04-16", 7, false, true; "first")]
+    #[test_case("2023-03-12", "2023-03-12", 1, true, false; "last")]
+    #[actix_web::test]
+    /// Test whether the week has all its comics in order, with navigation to neighbouring weeks.
+    ///
+    /// # Arguments
+    /// * `date` - The requested date
+    /// * `week_start` - The first day of the week
+    /// * `num_comics` - The number of comics expected in the week
+    /// * `has_previous` - Whether the previous week is expected to be linked
+    /// * `has_next` - Whether the next week is expected to be linked
+    async fn test_serve_week(
+        date: &str,
+        week_start: &str,
+        num_comics: usize,
+        has_previous: bool,
+        has_next: bool,
+    ) {
+        let date = str_to_date(date, SRC_DATE_FMT).unwrap();
+        let week_start = str_to_date(week_start, SRC_DATE_FMT).unwrap();
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(num_comics)
+            .returning(|date| {
+                Ok(Some(ComicData {
+                    title: format!("Title {}", date.format(SRC_DATE_FMT)),
+                    ..Default::default()
+                }))
+            });
+        let viewer = get_viewer(mock_comic_scraper, date);
+
+        let resp = viewer.serve_week(&date).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let html = std::str::from_utf8(&body).expect("Response body not UTF-8");
+
+        let positions: Vec<_> = week_start
+            .iter_days()
+            .take(num_comics)
+            .map(|date| {
+                let title = format!("Title {}", date.format(SRC_DATE_FMT));
+                html.find(&title).expect("Missing comic in week")
+            })
+            .collect();
+        assert!(
+            positions.is_sorted(),
+            "Comics aren't in order: {positions:?}"
+        );
+
+        let previous = (week_start - Duration::weeks(1)).format(SRC_DATE_FMT);
+        let next = (week_start + Duration::weeks(1)).format(SRC_DATE_FMT);
+        assert_eq!(
+            html.contains(&format!("/week/{previous}")),
+            has_previous,
+            "Wrong link to previous week"
+        );
+        assert_eq!(
+            html.contains(&format!("/week/{next}")),
+            has_next,
+            "Wrong link to next week"
+        );
+    }
+
+    #[test_case("1989-04-15"; "before first")]
+    #[test_case("2023-03-19"; "after last")]
+    #[actix_web::test]
+    /// Test whether weeks without any comics aren't found.
+    ///
+    /// # Arguments
+    /// * `date` - The requested date
+    async fn test_serve_week_missing(date: &str) {
+        let date = str_to_date(date, SRC_DATE_FMT).unwrap();
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper.expect_get_comic_data().never();
+        let viewer = get_viewer(mock_comic_scraper, date);
+
+        let resp = viewer.serve_week(&date).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::NOT_FOUND,
+            "Response is not status NOT FOUND"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether the feed has only the available comics, newest first.
+    async fn test_serve_feed() {
+        let end = NaiveDate::from_ymd_opt(2000, 1, 5).unwrap();
+        let missing = NaiveDate::from_ymd_opt(2000, 1, 3).unwrap();
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(5)
+            .returning(move |date| {
+                Ok((date != &missing).then(|| ComicData {
+                    img_url: REPO_URL.into(), // Any URL should technically work.
+                    img_width: 1,
+                    img_height: 1,
+                    ..Default::default()
+                }))
+            });
+        let viewer = Viewer {
+            feed_len: 5,
+            ..get_viewer(mock_comic_scraper, end)
+        };
+
+        let resp = viewer.serve_feed().await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let feed = std::str::from_utf8(&body).expect("Response body not UTF-8");
+
+        let entry_dates: Vec<_> = feed
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("<updated>"))
+            .filter_map(|line| line.strip_suffix("T00:00:00Z</updated>"))
+            .collect();
+        // The first date is the feed's own update date.
+        assert_eq!(
+            entry_dates,
+            [
+                "2000-01-05",
+                "2000-01-05",
+                "2000-01-04",
+                "2000-01-02",
+                "2000-01-01"
+            ],
+            "Feed entries are wrong or not newest first"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether warming up the cache scrapes only the comics that aren't cached.
+    async fn test_warm_cache() {
+        let start = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2000, 1, 4).unwrap();
+        let cached = NaiveDate::from_ymd_opt(2000, 1, 2).unwrap();
+        let failing = NaiveDate::from_ymd_opt(2000, 1, 3).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        let cached_data = comic_data.clone();
+        mock_comic_scraper
+            .expect_get_cached_comic_data()
+            .times(4)
+            .returning(move |date| Ok((date == &cached).then(|| cached_data.clone())));
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(3)
+            .returning(move |date| {
+                if date == &failing {
+                    Err(AppError::Scrape("Test error".into()))
+                } else {
+                    Ok(Some(comic_data.clone()))
+                }
+            });
+        let viewer = Viewer {
+            feed_len: 4,
+            ..get_viewer(mock_comic_scraper, end)
+        };
+
+        let scraped = viewer.warm_cache(start..=end, StdDuration::ZERO).await;
+        assert_eq!(scraped, 2, "Wrong number of comics scraped");
+    }
+
+    #[actix_web::test]
+    /// Test whether the page for a tag has the comics with the tag.
+    async fn test_serve_tag() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_tagged_comics()
+            .withf(|tag| tag == "boss")
+            .return_once(move |_| {
+                Ok(vec![(
+                    date,
+                    ComicData {
+                        img_url: REPO_URL.into(), // Any URL should technically work.
+                        img_width: 1,
+                        img_height: 1,
+                        tags: vec!["Boss".into()],
+                        ..Default::default()
+                    },
+                )])
+            });
+        let viewer = get_viewer(mock_comic_scraper, date);
+
+        let resp = viewer.serve_tag("#Boss").await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let html = test_html_response(resp);
+        assert!(html.contains("/2000-01-01"), "Tagged comic isn't shown");
+    }
+
+    #[test_case("", false; "empty query")]
+    #[test_case("consultant", true; "with results")]
+    #[actix_web::test]
+    /// Test whether the search page has the matching comics.
+    ///
+    /// # Arguments
+    /// * `query` - The search query
+    /// * `found` - Whether a comic matches the query
+    async fn test_serve_search(query: &'static str, found: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_search_comics()
+            .withf(move |other| other == query)
+            .return_once(move |_| {
+                let comic_data = ComicData {
+                    img_url: REPO_URL.into(), // Any URL should technically work.
+                    img_width: 1,
+                    img_height: 1,
+                    transcript: Some("The consultant says hi.".into()),
+                    ..Default::default()
+                };
+                Ok(found.then_some((date, comic_data)).into_iter().collect())
+            });
+        let viewer = get_viewer(mock_comic_scraper, date);
+
+        let resp = viewer.serve_search(query).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let html = test_html_response(resp);
+        assert_eq!(
+            html.contains("/2000-01-01"),
+            found,
+            "Search results are wrong"
+        );
+    }
+
+    #[test_case(4, 16, 34; "first comic's day")]
+    #[test_case(1, 1, 34; "new year")]
+    #[test_case(2, 29, 8; "leap day")]
+    /// Test whether "on this day" has the same day of every year within the comics' range.
+    ///
+    /// # Arguments
+    /// * `month` - The month of today's date
+    /// * `day` - The day of today's date
+    /// * `expected_len` - The expected number of dates
+    fn test_on_this_day_dates(month: u32, day: u32, expected_len: usize) {
+        let today = NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        let dates = get_on_this_day_dates(&today, &get_comic_range());
+
+        assert_eq!(dates.len(), expected_len, "Wrong number of dates");
+        assert!(
+            dates.windows(2).all(|pair| pair[0] < pair[1]),
+            "Dates aren't in order"
+        );
+        assert!(
+            dates
+                .iter()
+                .all(|date| date.month() == month && date.day() == day),
+            "Dates are on a different day of the year"
+        );
+    }
+
+    #[test]
+    /// Test whether the ranges of comics follow a latest comic past the pinned last comic.
+    fn test_live_comic_range() {
+        let latest = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let range = *get_comic_range().start()..=latest;
+
+        assert_eq!(
+            get_feed_dates(3, None, &range),
+            NaiveDate::from_ymd_opt(2024, 5, 30).unwrap()..=latest,
+            "Feed doesn't end at the latest comic"
+        );
+        let week_start = NaiveDate::from_ymd_opt(2024, 5, 26).unwrap();
+        assert!(
+            check_range(&week_start, &latest, &range).is_ok(),
+            "Range past the last comic is rejected"
+        );
+        assert_eq!(
+            get_month_range(&latest, &range),
+            Some((latest, latest)),
+            "Wrong range of the latest month"
+        );
+        assert_eq!(
+            get_on_this_day_dates(&latest, &range).last(),
+            Some(&latest),
+            "Missing the latest year on this day"
+        );
+        assert!(
+            get_archive_entries(Some(2024), &range).is_some(),
+            "Missing the latest year in the archive"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether the "on this day" page has only the available comics.
+    async fn test_serve_on_this_day() {
+        let available = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .times(34)
+            .returning(move |date| {
+                Ok((date == &available).then(|| ComicData {
+                    img_url: REPO_URL.into(), // Any URL should technically work.
+                    img_width: 1,
+                    img_height: 1,
+                    ..Default::default()
+                }))
+            });
+        let viewer = get_viewer(mock_comic_scraper, available);
+
+        let resp = viewer
+            .serve_on_this_day(&NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        let html = test_html_response(resp);
+        assert!(html.contains("On this day: January 1"), "Wrong title");
+        assert!(html.contains("/2000-01-01"), "Available comic isn't shown");
+        assert!(!html.contains("/1990-01-01"), "Missing comic is shown");
+    }
+
+    #[test_case(GetComicInfoState::Found; "comic exists")]
+    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[actix_web::test]
+    /// Test the comic info retrieval by the viewer.
+    ///
+    /// # Arguments
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    async fn test_get_comic_info(state: GetComicInfoState) {
+        let is_missing = state == GetComicInfoState::MissingComic;
+        let (viewer, comic_date, comic_data) = get_mock_viewer(state);
+        match viewer.get_comic_info(&comic_date).await {
+            Ok(result_data) => {
+                assert_eq!(result_data, comic_data, "Viewer returned wrong comic data");
+            }
+            Err(AppError::NotFound(..)) if is_missing => {}
+            Err(err) => panic!("Viewer failed to get info: {err}"),
+        };
+    }
+
+    #[test_case(GetComicInfoState::Found; "comic exists")]
+    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[test_case(GetComicInfoState::Fail; "crash")]
+    #[actix_web::test]
+    /// Test the comic info serving.
+    ///
+    /// # Arguments
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    async fn test_serve_comic(state: GetComicInfoState) {
+        let expected_status = match state {
+            GetComicInfoState::Found => StatusCode::OK,
+            GetComicInfoState::MissingComic => StatusCode::NOT_FOUND,
+            GetComicInfoState::Fail => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        let (viewer, comic_date, _) = get_mock_viewer(state);
+        let resp = viewer
+            .serve_comic(
+                &comic_date,
+                None,
+                &CspNonce(String::new()),
+                None,
+                &get_prefs(Theme::Light, Lang::English),
+            )
+            .await;
+        assert_eq!(resp.status(), expected_status);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test_case((1989, 4, 15), "/1989-04-16?clamped=before"; "before first")]
+    #[test_case((2023, 3, 13), "/2023-03-12?clamped=after"; "after last")]
+    #[actix_web::test]
+    /// Test whether out-of-range dates are redirected to the comic at the nearest end.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - The requested date as a tuple of year, month and day
+    /// * `expected` - The expected URL to which the client is redirected
+    async fn test_serve_comic_out_of_range(date_ymd: (i32, u32, u32), expected: &str) {
+        let (year, month, day) = date_ymd;
+        let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        // The comic scraper must not be used, since no comic exists for the date.
+        let viewer = get_viewer(ComicScraper::<MockPool>::default(), date);
 
-    use std::fs::read_to_string;
+        let resp = viewer
+            .serve_comic(
+                &date,
+                None,
+                &CspNonce(String::new()),
+                None,
+                &get_prefs(Theme::Light, Lang::English),
+            )
+            .await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::FOUND,
+            "Response isn't a redirect"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(LOCATION)
+                .and_then(|loc| loc.to_str().ok()),
+            Some(expected),
+            "Redirected to the wrong URL"
+        );
+    }
 
-    use actix_web::{
-        body::MessageBody,
-        http::{
-            header::{TryIntoHeaderValue, CONTENT_TYPE},
-            StatusCode,
-        },
-    };
-    use test_case::test_case;
+    #[test_case(GetComicInfoState::Found; "comic exists")]
+    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[test_case(GetComicInfoState::Fail; "crash")]
+    #[actix_web::test]
+    /// Test whether the oEmbed response embeds the comic's image.
+    ///
+    /// # Arguments
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    async fn test_serve_oembed(state: GetComicInfoState) {
+        let expected_status = match state {
+            GetComicInfoState::Found => StatusCode::OK,
+            GetComicInfoState::MissingComic => StatusCode::NOT_FOUND,
+            GetComicInfoState::Fail => StatusCode::INTERNAL_SERVER_ERROR,
+        };
 
-    use crate::db::mock::MockPool;
+        let (viewer, comic_date, _) = get_mock_viewer(state);
+        let resp = viewer.serve_oembed(&comic_date, Lang::English).await;
+        assert_eq!(resp.status(), expected_status, "Wrong response status");
+        if expected_status != StatusCode::OK {
+            return;
+        }
 
-    /// Path to the directory where test HTML files are stored
-    const HTML_TEST_CASE_PATH: &str = "testdata/html";
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Couldn't read oEmbed response");
+        let oembed: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("oEmbed response isn't valid JSON");
+        assert_eq!(oembed["type"], "photo", "Wrong oEmbed type");
+        assert_eq!(
+            oembed["title"], "Comic Strip on 2000-01-01",
+            "Untitled comic has the wrong title"
+        );
+        assert_eq!(
+            oembed["url"],
+            format!("{APP_URL}2000-01-01/image"),
+            "Wrong image URL"
+        );
+    }
 
-    // NOTE: This does *NOT* check if the minified HTML is equivalent, only that it's parsable.
-    #[test_case("empty"; "empty HTML")]
-    #[test_case("simple"; "simple HTML")]
-    #[test_case("comic"; "comic HTML")]
-    #[test_case("minimized"; "already minimized HTML")]
-    /// Test whether HTML minification results in a parsable HTML.
+    #[test_case(GetComicInfoState::Found; "comic exists")]
+    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[test_case(GetComicInfoState::Fail; "crash")]
+    #[actix_web::test]
+    /// Test whether the comic's data is served as JSON with the app's URLs.
     ///
     /// # Arguments
-    /// * `file_stem` - The filename stem of the HTML file to be used for testing
-    fn test_minified_html_is_parsable(file_stem: &str) {
-        let path = format!("{HTML_TEST_CASE_PATH}/{file_stem}.html");
-        let html =
-            read_to_string(&path).unwrap_or_else(|_| panic!("Couldn't read test case {}", &path));
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    async fn test_serve_comic_data(state: GetComicInfoState) {
+        let expected_status = match state {
+            GetComicInfoState::Found => StatusCode::OK,
+            GetComicInfoState::MissingComic => StatusCode::NOT_FOUND,
+            GetComicInfoState::Fail => StatusCode::INTERNAL_SERVER_ERROR,
+        };
 
-        let result = minify_html(html).expect("Error minifying HTML");
-        // Only checks if the minified HTML is actually parsable.
-        tl::parse(&result, tl::ParserOptions::default()).expect("Cannot parse minified HTML");
-    }
+        let (viewer, comic_date, _) = get_mock_viewer(state);
+        let resp = viewer.serve_comic_data(&comic_date).await;
+        assert_eq!(resp.status(), expected_status, "Wrong response status");
+        if expected_status != StatusCode::OK {
+            return;
+        }
 
-    /// Test if an HTTP response is a valid HTML page
-    fn test_html_response(resp: HttpResponse) {
-        // Check the "Content-Type" header.
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Couldn't read comic data");
+        let data: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("Comic data isn't valid JSON");
+        assert_eq!(data["date"], "2000-01-01", "Wrong date");
+        assert_eq!(data["number"], 3913, "Wrong number");
         assert_eq!(
-            resp.headers().get(CONTENT_TYPE),
-            Some(&ContentType::html().try_into_value().unwrap()),
-            "Response content type is not HTML"
+            data["img_url"],
+            format!("{APP_URL}2000-01-01/image"),
+            "Wrong image URL"
         );
-
-        // Check if response body is valid UTF-8 and the HTML is parsable.
-        let body = resp
-            .into_body()
-            .try_into_bytes()
-            .expect("Could not read response body");
-        let body_utf8 = std::str::from_utf8(&body).expect("Response body not UTF-8");
-        tl::parse(body_utf8, tl::ParserOptions::default()).expect("Response body not valid HTML");
     }
 
-    #[test_case(2000, 1, 1, "Test"; "comic with title")]
-    #[test_case(2000, 1, 1, ""; "comic without title")]
-    /// Test rendering of comic page templates.
+    #[test_case(FIRST_COMIC, "Sunday", 1, true, false; "first comic")]
+    #[test_case("2000-01-01", "Saturday", 3913, false, false; "middle comic")]
+    #[test_case(LAST_COMIC, "Sunday", 12384, false, true; "last comic")]
+    /// Test whether the fields derived from a comic's date are included in its JSON data.
     ///
     /// # Arguments
-    /// * `comic_year` - The year of the comic
-    /// * `comic_month` - The month of the comic
-    /// * `comic_day` - The day of the comic
-    /// * `title` - The title of the comic
-    fn test_template_rendering(comic_year: i32, comic_month: u32, comic_day: u32, title: &str) {
-        let comic_date = NaiveDate::from_ymd_opt(comic_year, comic_month, comic_day)
-            .expect("Invalid test parameters");
+    /// * `date` - The date of the comic
+    /// * `day_of_week` - The expected day of the week
+    /// * `ordinal` - The expected sequence number of the comic
+    /// * `is_first` - Whether the comic is expected to be the first one
+    /// * `is_last` - Whether the comic is expected to be the last one
+    fn test_comic_data_json_derived(
+        date: &str,
+        day_of_week: &str,
+        ordinal: u32,
+        is_first: bool,
+        is_last: bool,
+    ) {
+        let date = str_to_date(date, SRC_DATE_FMT).expect("Invalid test parameters");
+        let range = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap()
+            ..=str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
         let comic_data = ComicData {
-            title: title.into(),
             img_url: REPO_URL.into(), // Any URL should technically work.
             img_width: 1,
             img_height: 1,
-            permalink: String::new(),
+            ..Default::default()
         };
-        let resp = serve_template(&comic_date, &comic_data).expect("Error generating comic page");
 
-        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
-        test_html_response(resp);
+        let data = comic_data_json(&date, &comic_data, &range);
+        assert_eq!(data["day_of_week"], day_of_week, "Wrong day of the week");
+        assert_eq!(data["ordinal"], ordinal, "Wrong ordinal");
+        assert_eq!(data["is_first"], is_first, "Wrong first comic check");
+        assert_eq!(data["is_last"], is_last, "Wrong last comic check");
     }
 
-    #[test_case(Some((2000, 1, 1)); "missing comic")]
-    #[test_case(None; "generic 404")]
-    /// Test rendering of the 404 not found page template.
+    #[test_case(GetComicInfoState::Found; "comic exists")]
+    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[test_case(GetComicInfoState::Fail; "crash")]
+    #[actix_web::test]
+    /// Test whether GraphQL queries are resolved with the comic scraper.
     ///
     /// # Arguments
-    /// * `date_ymd` - A tuple containing the year, month and day of the missing comic, if any
-    fn test_404_page(date_ymd: Option<(i32, u32, u32)>) {
-        let date = date_ymd.map(|ymd| {
-            NaiveDate::from_ymd_opt(ymd.0, ymd.1, ymd.2).expect("Invalid test parameters")
-        });
-        let resp = serve_404_raw(date.as_ref()).expect("Error generating 404 page");
+    /// * `state` - The state denoting the behaviour of the viewer's scrapers
+    async fn test_serve_graphql(state: GetComicInfoState) {
+        let failing = matches!(state, GetComicInfoState::Fail);
+        let found = matches!(state, GetComicInfoState::Found);
+        let (viewer, _, _) = get_mock_viewer(state);
+        let request = Request::new(r#"{ comic(date: "2000-01-01") { date imgUrl } }"#);
+        let resp = viewer.serve_graphql(&build_schema(), request).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Wrong response status");
 
+        let bytes = to_bytes(resp.into_body())
+            .await
+            .expect("Couldn't read GraphQL response");
+        let response: serde_json::Value =
+            serde_json::from_slice(&bytes).expect("GraphQL response isn't valid JSON");
+        let expected = if found {
+            json!({
+                "date": "2000-01-01",
+                "imgUrl": format!("{APP_URL}2000-01-01/image"),
+            })
+        } else {
+            serde_json::Value::Null
+        };
+        assert_eq!(response["data"]["comic"], expected, "Wrong comic");
         assert_eq!(
-            resp.status(),
-            StatusCode::NOT_FOUND,
-            "Response is not status NOT FOUND"
+            response.get("errors").is_some(),
+            failing,
+            "Wrong errors: {response}"
         );
-        test_html_response(resp);
     }
 
-    #[test_case(""; "empty error msg")]
-    #[test_case("Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor
-    incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation
-    ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit
-    in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat
-    cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.";
-    "long error msg")]
-    /// Test rendering of the 500 internal server error page template.
+    #[test_case(false, false; "render and cache")]
+    #[test_case(true, false; "cached page")]
+    #[test_case(true, true; "not modified")]
+    #[actix_web::test]
+    /// Test whether rendered pages are cached, and served from the cache with a fresh nonce.
     ///
     /// # Arguments
-    /// * `error_msg` - The error message to be displayed in the page
-    fn test_500_page(error_msg: &str) {
-        let resp = serve_500(&AppError::Scrape(error_msg.into()));
-        assert_eq!(
-            resp.status(),
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Response is not status INTERNAL SERVER ERROR"
+    /// * `cached` - Whether the rendered page is in the cache
+    /// * `revalidate` - Whether the client has a valid cached copy
+    async fn test_serve_comic_page_cache(cached: bool, revalidate: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: REPO_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let inline_css = "main{max-width:800px}";
+
+        let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        let nav = get_navigation(&date, first_comic, last_comic, &[], false);
+        let etag = get_comic_etag(
+            &date,
+            &comic_data,
+            &nav,
+            Some(inline_css),
+            &get_prefs(Theme::Dark, Lang::English),
         );
-        test_html_response(resp);
-    }
+        let page = render_template(
+            &date,
+            &comic_data,
+            &nav,
+            Some(inline_css),
+            NONCE_PLACEHOLDER,
+            &get_prefs(Theme::Dark, Lang::English),
+        )
+        .expect("Error generating comic page");
 
-    #[test_case("static/styles.css", true; "app CSS")]
-    #[test_case("styles.css", false; "missing file")]
-    #[test_case("/", false; "invalid CSS path")]
-    #[actix_web::test]
-    /// Test serving of CSS files.
-    ///
-    /// # Arguments
-    /// * `path` - The path to the CSS file to be used for testing
-    /// * `should_serve` - Whether the expected behaviour is to serve a response or to crash
-    async fn test_css_serving(path: &str, should_serve: bool) {
-        let path = Path::new(path);
-        let resp = match serve_css_raw(path).await {
-            Ok(resp) => resp,
-            Err(AppError::NotFound(err)) => {
-                if should_serve {
-                    panic!("Error serving CSS that exists: {err}");
-                } else {
-                    return;
-                }
-            }
-            Err(err) => panic!("Error serving CSS: {err}"),
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_data()
+            .return_once(move |_| Ok(Some(comic_data)));
+        mock_comic_scraper
+            .expect_get_missing_dates()
+            .return_once(|| Ok(Vec::new()));
+        let expected_version = etag.tag().to_string();
+        let cached_page = page.clone();
+        mock_comic_scraper
+            .expect_get_cached_page()
+            .withf(move |_, version| version == expected_version)
+            .times(usize::from(!revalidate))
+            .returning(move |_, _| cached.then(|| cached_page.clone()));
+        mock_comic_scraper
+            .expect_cache_page()
+            .withf(move |_, _, other| other == page)
+            .times(usize::from(!cached))
+            .return_const(());
+        let viewer = Viewer {
+            inline_css: Some(inline_css.into()),
+            ..get_viewer(mock_comic_scraper, date)
         };
 
-        // Ensure that no CSS is served when it shouldn't.
-        if !should_serve {
-            panic!("CSS served even when path doesn't exist");
+        let if_none_match = IfNoneMatch::Items(vec![etag]);
+        let resp = viewer
+            .serve_comic(
+                &date,
+                None,
+                &CspNonce("nonce".into()),
+                revalidate.then_some(&if_none_match),
+                &get_prefs(Theme::Dark, Lang::English),
+            )
+            .await;
+        if revalidate {
+            assert_eq!(
+                resp.status(),
+                StatusCode::NOT_MODIFIED,
+                "Response is not status Not Modified"
+            );
+            return;
         }
-
-        // Check the response status.
         assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
-
-        // Check the "Content-Type" header.
-        let content_type = resp
-            .headers()
-            .get(CONTENT_TYPE)
-            .expect("Missing Content-Type header")
-            .to_str()
-            .expect("Content-Type header value not valid UTF-8");
+        assert!(resp.headers().contains_key(ETAG), "Response has no ETag");
+        let html = test_html_response(resp);
         assert!(
-            content_type.contains("text/css"),
-            "Response content type is not CSS"
+            html.contains("<style nonce=nonce>"),
+            "Page doesn't have the response's nonce"
+        );
+        assert!(
+            !html.contains(NONCE_PLACEHOLDER),
+            "Page still has the nonce placeholder"
         );
-
-        // Check if response body is valid UTF-8 and the CSS is parsable.
-        let body = resp
-            .into_body()
-            .try_into_bytes()
-            .expect("Could not read response body");
-        let body_utf8 = std::str::from_utf8(&body).expect("Response body not UTF-8");
-        // NOTE: This doesn't guarantee that the CSS is valid.
-        minifier::css::minify(body_utf8).expect("Response body not valid CSS");
-    }
-
-    /// Enum for the state of `Viewer::get_comic_info`.
-    #[derive(PartialEq, Eq)]
-    enum GetComicInfoState {
-        /// Comic info.
-        Found,
-        /// Comic info is missing, and no redirection is to be done.
-        MissingComic,
-        /// Crashes with a miscellaneous error.
-        Fail,
     }
 
-    /// Get a `Viewer` whose scrapers have been mocked, along with the data it works with.
-    ///
-    /// # Arguments
-    /// * `state` - The state denoting the behaviour of the viewer's scrapers
-    ///
-    /// # Returns
-    /// * The "mocked" viewer
-    /// * The test comic date
-    /// * The test comic data
-    fn get_mock_viewer(state: GetComicInfoState) -> (Viewer<MockPool>, NaiveDate, ComicData) {
-        let comic_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
-        let comic_data = ComicData {
-            title: String::new(),
-            img_url: String::new(),
-            img_width: 0,
-            img_height: 0,
-            permalink: String::new(),
-        };
-
-        // Set up the mock comic scraper.
+    #[actix_web::test]
+    /// Test whether the latest comic is the last comic, when the latest date is pinned.
+    async fn test_serve_latest_comic() {
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
         let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
-        let expected_comic_data = Some(comic_data.clone());
         mock_comic_scraper
             .expect_get_comic_data()
+            .withf(move |date| date == &last_comic)
             .times(1)
-            .returning(move |date| match state {
-                GetComicInfoState::Found if date == &comic_date => Ok(expected_comic_data.clone()),
-                GetComicInfoState::Fail => Err(AppError::Scrape("Manual error".into())),
-                _ => Ok(None),
+            .returning(|_| {
+                Ok(Some(ComicData {
+                    img_url: REPO_URL.into(), // Any URL should technically work.
+                    img_width: 1,
+                    img_height: 1,
+                    ..Default::default()
+                }))
             });
+        mock_comic_scraper
+            .expect_get_missing_dates()
+            .returning(|| Ok(Vec::new()));
+        mock_comic_scraper
+            .expect_get_cached_page()
+            .returning(|_, _| None);
+        mock_comic_scraper.expect_cache_page().return_const(());
+        let viewer = get_viewer(mock_comic_scraper, last_comic);
 
-        let viewer = Viewer {
-            comic_scraper: mock_comic_scraper,
-        };
-        (viewer, comic_date, comic_data)
+        let resp = viewer
+            .serve_latest_comic(
+                &CspNonce(String::new()),
+                None,
+                &get_prefs(Theme::Light, Lang::English),
+            )
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
     }
 
-    #[test_case(GetComicInfoState::Found; "comic exists")]
-    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
+    #[test_case(false, false, false; "GET")]
+    #[test_case(true, false, false; "HEAD")]
+    #[test_case(false, true, false; "WebP GET")]
+    #[test_case(true, true, false; "WebP HEAD")]
+    #[test_case(false, true, true; "overlaid GET")]
+    #[test_case(true, true, true; "overlaid HEAD")]
     #[actix_web::test]
-    /// Test the comic info retrieval by the viewer.
+    /// Test whether comic images are served with the right headers, and without a body for HEAD.
     ///
     /// # Arguments
-    /// * `state` - The state denoting the behaviour of the viewer's scrapers
-    async fn test_get_comic_info(state: GetComicInfoState) {
-        let is_missing = state == GetComicInfoState::MissingComic;
-        let (viewer, comic_date, comic_data) = get_mock_viewer(state);
-        match viewer.get_comic_info(&comic_date).await {
-            Ok(result_data) => {
-                assert_eq!(result_data, comic_data, "Viewer returned wrong comic data");
-            }
-            Err(AppError::NotFound(..)) if is_missing => {}
-            Err(err) => panic!("Viewer failed to get info: {err}"),
+    /// * `head` - Whether this is for a HEAD request
+    /// * `webp` - Whether the client accepts WebP images
+    /// * `overlay` - Whether an attribution is overlaid on the images
+    async fn test_serve_comic_image(head: bool, webp: bool, overlay: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        };
+
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        if overlay {
+            // The overlaid image is served even if the client accepts WebP.
+            let image = image.clone();
+            mock_comic_scraper
+                .expect_get_comic_image_overlaid()
+                .return_once(move |_, _| Ok(Some(image)));
+        } else if webp {
+            let image = image.clone();
+            mock_comic_scraper
+                .expect_get_comic_image_webp()
+                .return_once(move |_| Ok(Some(image)));
+        } else if head {
+            let info = ImageInfo::from(&image);
+            mock_comic_scraper
+                .expect_get_comic_image_info()
+                .return_once(move |_| Ok(Some(info)));
+        } else {
+            let image = image.clone();
+            mock_comic_scraper
+                .expect_get_comic_image()
+                .return_once(move |_| Ok(Some(image)));
+        }
+        let viewer = Viewer {
+            attribution: overlay
+                .then(|| Attribution::load("Attribution".into(), None).expect("Invalid font")),
+            ..get_viewer(mock_comic_scraper, date)
         };
+
+        let resp = viewer.serve_comic_image(&date, head, webp).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE),
+            Some(&"image/gif".try_into_value().unwrap()),
+            "Wrong image content type"
+        );
+        assert_eq!(
+            resp.headers().get(VARY),
+            Some(&ACCEPT.as_str().try_into_value().unwrap()),
+            "Missing Vary header"
+        );
+
+        if head {
+            assert_eq!(
+                resp.headers().get(CONTENT_LENGTH),
+                Some(&image.bytes.len().into()),
+                "Wrong image content length"
+            );
+        }
+
+        let body = to_bytes(resp.into_body())
+            .await
+            .expect("Could not read response body");
+        if head {
+            assert!(body.is_empty(), "HEAD response has a body");
+        } else {
+            assert_eq!(body, image.bytes, "Wrong image in response");
+        }
     }
 
-    #[test_case(GetComicInfoState::Found; "comic exists")]
-    #[test_case(GetComicInfoState::MissingComic; "missing comic")]
-    #[test_case(GetComicInfoState::Fail; "crash")]
+    #[test_case(false; "GET")]
+    #[test_case(true; "HEAD")]
     #[actix_web::test]
-    /// Test the comic info serving.
+    /// Test whether image variants are served with the right headers, and without a body for HEAD.
     ///
     /// # Arguments
-    /// * `state` - The state denoting the behaviour of the viewer's scrapers
-    async fn test_serve_comic(state: GetComicInfoState) {
-        let expected_status = match state {
-            GetComicInfoState::Found => StatusCode::OK,
-            GetComicInfoState::MissingComic => StatusCode::NOT_FOUND,
-            GetComicInfoState::Fail => StatusCode::INTERNAL_SERVER_ERROR,
+    /// * `head` - Whether this is for a HEAD request
+    async fn test_serve_comic_image_variant(head: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
         };
 
-        let (viewer, comic_date, _) = get_mock_viewer(state);
-        let resp = viewer.serve_comic(&comic_date).await;
-        assert_eq!(resp.status(), expected_status);
+        let mut mock_comic_scraper = ComicScraper::<MockPool>::default();
+        mock_comic_scraper
+            .expect_get_comic_image_variant()
+            .withf(|_, width| *width == 450)
+            .return_once({
+                let image = image.clone();
+                move |_, _| Ok(Some(image))
+            });
+        let viewer = get_viewer(mock_comic_scraper, date);
+
+        let resp = viewer.serve_comic_image_variant(&date, 450, head).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE),
+            Some(&"image/gif".try_into_value().unwrap()),
+            "Wrong image content type"
+        );
+        if head {
+            assert_eq!(
+                resp.headers().get(CONTENT_LENGTH),
+                Some(&image.bytes.len().into()),
+                "Wrong image content length"
+            );
+        }
+
+        let body = to_bytes(resp.into_body())
+            .await
+            .expect("Could not read response body");
+        if head {
+            assert!(body.is_empty(), "HEAD response has a body");
+        } else {
+            assert_eq!(body, image.bytes, "Wrong image in response");
+        }
     }
 }
@@ -0,0 +1,828 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Static assets that are minified, compressed and fingerprinted once, and then served from memory
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{
+    http::header::{
+        AcceptEncoding, CacheControl, CacheDirective, ContentEncoding, ContentType, ETag, Encoding,
+        EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified, VARY,
+    },
+    web::Bytes,
+    HttpResponse, HttpResponseBuilder,
+};
+use awc::Client;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use brotli::enc::BrotliEncoderParams;
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256, Sha384};
+use tracing::{info, warn};
+
+use crate::app::{etag_matches, load_minified_css, serve_404};
+use crate::constants::{ASSET_HASH_LEN, ASSET_MAX_AGE, BROTLI_QUALITY, CSS_MAX_AGE, STATIC_URL};
+use crate::errors::{AppError, AppResult, HttpError, VendorError};
+
+/// Validators of a static file, with which clients can revalidate their cached copies
+#[derive(Clone, Debug)]
+struct Validators {
+    /// The ETag of the file
+    etag: EntityTag,
+    /// The time when the file was last modified, truncated to seconds like in HTTP headers
+    last_modified: HttpDate,
+}
+
+impl Validators {
+    /// Get the validators of a static file.
+    ///
+    /// # Arguments
+    /// * `contents` - The contents of the file
+    /// * `last_modified` - The time when the file was last modified
+    fn new(contents: &[u8], last_modified: SystemTime) -> Self {
+        // The ETag is weak, since the file is compressed differently for different clients.
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let etag = EntityTag::new_weak(format!("{:016x}", hasher.finish()));
+        let secs = last_modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            etag,
+            last_modified: (UNIX_EPOCH + Duration::from_secs(secs)).into(),
+        }
+    }
+
+    /// Check whether the client's cached copy of the file is still valid.
+    ///
+    /// The modification time is only checked if the client has no ETags, as per RFC 9110.
+    ///
+    /// # Arguments
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `if_modified_since` - The time when the client's cached copy was last modified, if any
+    fn is_fresh(
+        &self,
+        if_none_match: Option<&IfNoneMatch>,
+        if_modified_since: Option<&IfModifiedSince>,
+    ) -> bool {
+        // A missing If-None-Match header is extracted as an empty list.
+        let has_etags = match if_none_match {
+            Some(IfNoneMatch::Items(tags)) => !tags.is_empty(),
+            Some(IfNoneMatch::Any) => true,
+            None => false,
+        };
+        match if_modified_since {
+            Some(IfModifiedSince(since)) if !has_etags => self.last_modified <= *since,
+            _ => etag_matches(if_none_match, &self.etag),
+        }
+    }
+
+    /// Add the validators to a response.
+    fn insert_headers(&self, resp: &mut HttpResponseBuilder) {
+        resp.insert_header(ETag(self.etag.clone()))
+            .insert_header(LastModified(self.last_modified));
+    }
+}
+
+/// A minified CSS file
+struct CssFile {
+    /// The minified CSS
+    css: String,
+    /// The minified CSS compressed with Brotli, if that makes it smaller
+    brotli: Option<Bytes>,
+    /// The minified CSS compressed with gzip, if that makes it smaller
+    gzip: Option<Bytes>,
+    /// The validators of the minified CSS
+    validators: Validators,
+}
+
+/// Compress a static file with an encoding.
+///
+/// # Arguments
+/// * `data` - The contents of the file
+/// * `encoding` - The encoding, which is either Brotli or gzip
+///
+/// # Returns
+/// The compressed file, if that's smaller than the file itself
+fn precompress(data: &[u8], encoding: ContentEncoding) -> Option<Bytes> {
+    let result = match encoding {
+        ContentEncoding::Brotli => {
+            let params = BrotliEncoderParams {
+                quality: BROTLI_QUALITY,
+                ..Default::default()
+            };
+            let mut compressed = Vec::new();
+            brotli::BrotliCompress(&mut &data[..], &mut compressed, &params).map(|_| compressed)
+        }
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        _ => return None,
+    };
+    match result {
+        Ok(compressed) if compressed.len() < data.len() => Some(compressed.into()),
+        Ok(_) => None,
+        Err(err) => {
+            // The file can still be compressed on the fly, so simply log the error.
+            warn!(
+                "Couldn't compress static file with {}: {err}",
+                encoding.as_str()
+            );
+            None
+        }
+    }
+}
+
+/// All CSS files in a directory, minified
+pub struct CssAssets {
+    /// The minified files, keyed by their file names
+    files: HashMap<String, CssFile>,
+}
+
+impl CssAssets {
+    /// Load and minify all CSS files in a directory.
+    ///
+    /// Files in subdirectories are skipped, since they can't be requested.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory with the CSS files
+    pub async fn load(dir: &Path) -> AppResult<Self> {
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|err| AppError::NotFound(err.to_string()))?;
+        let mut files = HashMap::new();
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|err| AppError::NotFound(err.to_string()))?
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "css") || !path.is_file() {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            let css = load_minified_css(&path).await?;
+            let last_modified = entry
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            let validators = Validators::new(css.as_bytes(), last_modified);
+            // Compress only once here, instead of on the fly for every request.
+            let brotli = precompress(css.as_bytes(), ContentEncoding::Brotli);
+            let gzip = precompress(css.as_bytes(), ContentEncoding::Gzip);
+            files.insert(
+                name.to_string(),
+                CssFile {
+                    css,
+                    brotli,
+                    gzip,
+                    validators,
+                },
+            );
+        }
+
+        info!(
+            "Loaded {} CSS file(s) from \"{}\"",
+            files.len(),
+            dir.display()
+        );
+        Ok(Self { files })
+    }
+
+    /// Get a minified CSS file, if it exists.
+    ///
+    /// # Arguments
+    /// * `name` - The file name of the CSS file
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files.get(name).map(|file| file.css.as_str())
+    }
+
+    /// Serve a minified CSS file.
+    ///
+    /// If the file doesn't exist, then a 404 not found response is returned. If the client's
+    /// cached copy is still valid, then a 304 not modified response is returned.
+    ///
+    /// The precompressed file is served if the client accepts its encoding. Otherwise, the file
+    /// is left to be compressed on the fly.
+    ///
+    /// # Arguments
+    /// * `name` - The file name of the CSS file
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `if_modified_since` - The time when the client's cached copy was last modified, if any
+    /// * `accept_encoding` - The encodings accepted by the client, if given
+    /// * `immutable` - Whether the file was requested by its current fingerprinted URL
+    pub fn serve(
+        &self,
+        name: &str,
+        if_none_match: Option<&IfNoneMatch>,
+        if_modified_since: Option<&IfModifiedSince>,
+        accept_encoding: Option<&AcceptEncoding>,
+        immutable: bool,
+    ) -> HttpResponse {
+        let Some(file) = self.files.get(name) else {
+            return serve_404(None);
+        };
+
+        let cache_control = if immutable {
+            immutable_cache_control()
+        } else {
+            CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(CSS_MAX_AGE),
+            ])
+        };
+        if file.validators.is_fresh(if_none_match, if_modified_since) {
+            let mut resp = HttpResponse::NotModified();
+            resp.insert_header(cache_control);
+            file.validators.insert_headers(&mut resp);
+            return resp.finish();
+        }
+
+        let mut resp = HttpResponse::Ok();
+        resp.content_type("text/css;charset=utf-8")
+            .insert_header(cache_control);
+        file.validators.insert_headers(&mut resp);
+
+        let mut supported = vec![Encoding::identity()];
+        if file.brotli.is_some() {
+            supported.push(Encoding::brotli());
+        }
+        if file.gzip.is_some() {
+            supported.push(Encoding::gzip());
+        }
+        let precompressed =
+            match accept_encoding.and_then(|accept| accept.negotiate(supported.iter())) {
+                Some(Encoding::Known(ContentEncoding::Brotli)) => file
+                    .brotli
+                    .clone()
+                    .map(|body| (ContentEncoding::Brotli, body)),
+                Some(Encoding::Known(ContentEncoding::Gzip)) => {
+                    file.gzip.clone().map(|body| (ContentEncoding::Gzip, body))
+                }
+                _ => None,
+            };
+        match precompressed {
+            // With the encoding set, the response isn't compressed again on the fly.
+            Some((encoding, body)) => resp
+                .insert_header(encoding)
+                .insert_header((VARY, "accept-encoding"))
+                .body(body),
+            None => resp.body(file.css.clone()),
+        }
+    }
+}
+
+/// A text file generated at startup, which is served from memory
+#[derive(Clone, Debug)]
+pub struct GeneratedFile {
+    /// The contents of the file
+    contents: String,
+    /// The validators of the file, which was last modified when generated
+    validators: Validators,
+}
+
+impl GeneratedFile {
+    /// Initialize a file generated now.
+    ///
+    /// # Arguments
+    /// * `contents` - The contents of the file
+    pub fn new(contents: String) -> Self {
+        let validators = Validators::new(contents.as_bytes(), SystemTime::now());
+        Self {
+            contents,
+            validators,
+        }
+    }
+
+    /// Serve the file.
+    ///
+    /// If the client's cached copy is still valid, then a 304 not modified response is returned.
+    ///
+    /// # Arguments
+    /// * `content_type` - The content type of the file
+    /// * `if_none_match` - The ETags of the client's cached copies, if any
+    /// * `if_modified_since` - The time when the client's cached copy was last modified, if any
+    pub fn serve(
+        &self,
+        content_type: ContentType,
+        if_none_match: Option<&IfNoneMatch>,
+        if_modified_since: Option<&IfModifiedSince>,
+    ) -> HttpResponse {
+        if self.validators.is_fresh(if_none_match, if_modified_since) {
+            let mut resp = HttpResponse::NotModified();
+            self.validators.insert_headers(&mut resp);
+            return resp.finish();
+        }
+        let mut resp = HttpResponse::Ok();
+        resp.content_type(content_type);
+        self.validators.insert_headers(&mut resp);
+        resp.body(self.contents.clone())
+    }
+}
+
+/// Manifest of the static files, which is shared by the templates and the static file handlers
+static ASSET_MANIFEST: OnceLock<AssetManifest> = OnceLock::new();
+
+/// Content hashes of all static files in a directory, for fingerprinting their URLs
+///
+/// A fingerprinted URL has the file's content hash before its extension, such as
+/// "/styles.0123456789.css" for "styles.css". Since the URL changes whenever the file does, it can
+/// be cached forever.
+#[derive(Debug, Default)]
+pub struct AssetManifest {
+    /// The content hashes of the files, keyed by their file names
+    hashes: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    /// Hash all files in a directory.
+    ///
+    /// Files in subdirectories are skipped, since they can't be requested.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory with the static files
+    pub async fn load(dir: &Path) -> AppResult<Self> {
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|err| AppError::NotFound(err.to_string()))?;
+        let mut hashes = HashMap::new();
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|err| AppError::NotFound(err.to_string()))?
+        {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            let contents = tokio::fs::read(&path)
+                .await
+                .map_err(|err| AppError::NotFound(err.to_string()))?;
+            let mut hash = String::with_capacity(ASSET_HASH_LEN);
+            for byte in Sha256::digest(&contents) {
+                // Writing to a string never fails.
+                let _ = write!(hash, "{byte:02x}");
+            }
+            hash.truncate(ASSET_HASH_LEN);
+            hashes.insert(name.to_string(), hash);
+        }
+
+        info!(
+            "Fingerprinted {} static file(s) in \"{}\"",
+            hashes.len(),
+            dir.display()
+        );
+        Ok(Self { hashes })
+    }
+
+    /// Get the URL of a static file, which is fingerprinted if the file is in the manifest.
+    ///
+    /// # Arguments
+    /// * `name` - The file name of the static file
+    pub fn url(&self, name: &str) -> String {
+        match (self.hashes.get(name), name.rsplit_once('.')) {
+            (Some(hash), Some((stem, ext))) => format!("{STATIC_URL}{stem}.{hash}.{ext}"),
+            _ => format!("{STATIC_URL}{name}"),
+        }
+    }
+
+    /// Get the file name of the static file requested by a possibly fingerprinted file name.
+    ///
+    /// Outdated fingerprints (such as in pages cached before a deployment) still resolve to the
+    /// file, but they aren't current, so the file shouldn't be cached forever for them.
+    ///
+    /// # Arguments
+    /// * `requested` - The requested file name
+    ///
+    /// # Returns
+    /// The file name, and whether the requested fingerprint is current
+    pub fn resolve(&self, requested: &str) -> (String, bool) {
+        let fingerprint = requested.rsplit_once('.').and_then(|(stem, ext)| {
+            let (stem, hash) = stem.rsplit_once('.')?;
+            let is_hash =
+                hash.len() == ASSET_HASH_LEN && hash.bytes().all(|byte| byte.is_ascii_hexdigit());
+            is_hash.then(|| (format!("{stem}.{ext}"), hash))
+        });
+        match fingerprint {
+            Some((name, hash)) if self.hashes.contains_key(&name) => {
+                let current = self.hashes[&name] == hash;
+                (name, current)
+            }
+            _ => (requested.to_string(), false),
+        }
+    }
+
+    /// Share this manifest with the templates and the static file handlers.
+    ///
+    /// Only the first manifest is shared, since the static files don't change while running.
+    pub fn share(self) {
+        let _ = ASSET_MANIFEST.set(self);
+    }
+}
+
+/// Get the URL of a static file, which is fingerprinted if the shared manifest has the file.
+///
+/// # Arguments
+/// * `name` - The file name of the static file
+pub fn asset_url(name: &str) -> String {
+    match ASSET_MANIFEST.get() {
+        Some(manifest) => manifest.url(name),
+        None => format!("{STATIC_URL}{name}"),
+    }
+}
+
+/// Get the file name of the static file requested by a possibly fingerprinted file name.
+///
+/// # Arguments
+/// * `requested` - The requested file name
+///
+/// # Returns
+/// The file name, and whether the requested fingerprint is current
+pub fn resolve_asset(requested: &str) -> (String, bool) {
+    match ASSET_MANIFEST.get() {
+        Some(manifest) => manifest.resolve(requested),
+        None => (requested.to_string(), false),
+    }
+}
+
+/// Get the caching policy for static files requested by their current fingerprinted URLs.
+pub fn immutable_cache_control() -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(ASSET_MAX_AGE),
+        CacheDirective::Extension("immutable".into(), None),
+    ])
+}
+
+/// Vendor a third-party stylesheet into a file, unless the file already exists.
+///
+/// The stylesheet is checked against its subresource integrity hash before being written, so that
+/// a compromised CDN can't change it.
+///
+/// # Arguments
+/// * `client` - The HTTP client for downloading the stylesheet
+/// * `url` - The URL of the stylesheet
+/// * `integrity` - The SHA-384 subresource integrity hash of the stylesheet
+/// * `path` - The path of the file to which the stylesheet is written
+pub async fn vendor_css(
+    client: &Client,
+    url: &str,
+    integrity: &str,
+    path: &Path,
+) -> Result<(), VendorError> {
+    if path.is_file() {
+        return Ok(());
+    }
+
+    let mut resp = client.get(url).send().await.map_err(HttpError::from)?;
+    if !resp.status().is_success() {
+        return Err(VendorError::Status(resp.status()));
+    }
+    let css = resp.body().await.map_err(HttpError::from)?;
+    if format!("sha384-{}", STANDARD.encode(Sha384::digest(&css))) != integrity {
+        return Err(VendorError::Integrity);
+    }
+
+    // Write to a temporary file first, so that a partially written stylesheet is never loaded.
+    let tmp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp_path, &css).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    info!("Vendored stylesheet from {url} into \"{}\"", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::read_to_string;
+    use std::io::Read;
+
+    use actix_web::{
+        body::MessageBody,
+        http::{
+            header::{Header, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE},
+            StatusCode,
+        },
+        test::TestRequest,
+    };
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::{CSS_FILE, RESP_TIMEOUT, STATIC_DIR};
+
+    #[test_case(CSS_FILE, StatusCode::OK; "app CSS")]
+    #[test_case("missing.css", StatusCode::NOT_FOUND; "missing file")]
+    #[test_case("script.js", StatusCode::NOT_FOUND; "not CSS")]
+    #[actix_web::test]
+    /// Test serving of CSS files.
+    ///
+    /// # Arguments
+    /// * `name` - The file name of the CSS file to be used for testing
+    /// * `expected_status` - The expected status of the response
+    async fn test_css_serving(name: &str, expected_status: StatusCode) {
+        let assets = CssAssets::load(Path::new(STATIC_DIR))
+            .await
+            .expect("Couldn't load CSS");
+        let resp = assets.serve(name, None, None, None, false);
+        assert_eq!(resp.status(), expected_status, "Wrong response status");
+        if expected_status != StatusCode::OK {
+            return;
+        }
+
+        // Check the headers.
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .expect("Missing Content-Type header")
+            .to_str()
+            .expect("Content-Type header value not valid UTF-8");
+        assert!(
+            content_type.contains("text/css"),
+            "Response content type is not CSS"
+        );
+        assert!(
+            resp.headers().contains_key(CACHE_CONTROL),
+            "Missing Cache-Control header"
+        );
+
+        // Check if response body is valid UTF-8 and the CSS is parsable.
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let body_utf8 = std::str::from_utf8(&body).expect("Response body not UTF-8");
+        // NOTE: This doesn't guarantee that the CSS is valid.
+        minifier::css::minify(body_utf8).expect("Response body not valid CSS");
+    }
+
+    #[actix_web::test]
+    /// Test whether a valid cached copy of a CSS file isn't served again.
+    async fn test_css_not_modified() {
+        let assets = CssAssets::load(Path::new(STATIC_DIR))
+            .await
+            .expect("Couldn't load CSS");
+        let etag = assets.files[CSS_FILE].validators.etag.clone();
+        let resp = assets.serve(
+            CSS_FILE,
+            Some(&IfNoneMatch::Items(vec![etag])),
+            None,
+            None,
+            false,
+        );
+        assert_eq!(
+            resp.status(),
+            StatusCode::NOT_MODIFIED,
+            "Response is not status Not Modified"
+        );
+    }
+
+    #[test_case(Some("unchanged"), None, true; "matching ETag")]
+    #[test_case(Some("changed"), None, false; "changed ETag")]
+    #[test_case(None, Some(0), true; "not modified since")]
+    #[test_case(None, Some(-1), false; "modified since")]
+    #[test_case(Some("changed"), Some(0), false; "ETag before modification time")]
+    #[test_case(None, None, false; "no validators")]
+    #[test_case(Some(""), Some(0), true; "no ETags")]
+    /// Test whether the client's cached copy is correctly revalidated.
+    ///
+    /// # Arguments
+    /// * `etag` - The contents for the ETag of the client's cached copy (empty for no ETags), if
+    ///   the header is given
+    /// * `since_offset` - The offset (in seconds) from the modification time of the file to that
+    ///   of the client's cached copy, if any
+    /// * `expected` - Whether the client's cached copy is expected to be valid
+    fn test_revalidation(etag: Option<&str>, since_offset: Option<i64>, expected: bool) {
+        let modified_secs = 1_000_000;
+        let last_modified = UNIX_EPOCH + Duration::from_secs(modified_secs);
+        let validators = Validators::new(b"unchanged", last_modified);
+        let if_none_match = etag.map(|etag| {
+            let etags =
+                (!etag.is_empty()).then(|| Validators::new(etag.as_bytes(), last_modified).etag);
+            IfNoneMatch::Items(etags.into_iter().collect())
+        });
+        let if_modified_since = since_offset.map(|offset| {
+            let since =
+                UNIX_EPOCH + Duration::from_secs(modified_secs.saturating_add_signed(offset));
+            IfModifiedSince(since.into())
+        });
+        assert_eq!(
+            validators.is_fresh(if_none_match.as_ref(), if_modified_since.as_ref()),
+            expected,
+            "Wrong validity of the client's copy"
+        );
+    }
+
+    #[test_case("br, gzip", Some("br"); "brotli")]
+    #[test_case("gzip", Some("gzip"); "gzip")]
+    #[test_case("identity", None; "identity")]
+    #[test_case("zstd", None; "unsupported")]
+    /// Test whether precompressed CSS is served to clients accepting its encoding.
+    ///
+    /// # Arguments
+    /// * `accept_encoding` - The value of the client's Accept-Encoding header
+    /// * `expected` - The expected encoding of the response, if precompressed
+    fn test_css_precompressed(accept_encoding: &str, expected: Option<&str>) {
+        let css = "main{max-width:800px}".repeat(100);
+        let file = CssFile {
+            brotli: precompress(css.as_bytes(), ContentEncoding::Brotli),
+            gzip: precompress(css.as_bytes(), ContentEncoding::Gzip),
+            css: css.clone(),
+            validators: Validators::new(css.as_bytes(), SystemTime::now()),
+        };
+        let assets = CssAssets {
+            files: [(CSS_FILE.into(), file)].into(),
+        };
+        let req = TestRequest::default()
+            .insert_header((ACCEPT_ENCODING, accept_encoding))
+            .to_http_request();
+        let accept_encoding = AcceptEncoding::parse(&req).expect("Invalid Accept-Encoding");
+
+        let resp = assets.serve(CSS_FILE, None, None, Some(&accept_encoding), false);
+        assert_eq!(resp.status(), StatusCode::OK, "Wrong response status");
+        let encoding = resp.headers().get(CONTENT_ENCODING).map(|value| {
+            value
+                .to_str()
+                .expect("Invalid Content-Encoding")
+                .to_string()
+        });
+        assert_eq!(encoding.as_deref(), expected, "Wrong encoding");
+
+        let body = resp
+            .into_body()
+            .try_into_bytes()
+            .expect("Could not read response body");
+        let mut decompressed = Vec::new();
+        match encoding.as_deref() {
+            Some("br") => {
+                brotli::BrotliDecompress(&mut &body[..], &mut decompressed).expect("Invalid Brotli")
+            }
+            Some(_) => {
+                flate2::read::GzDecoder::new(&body[..])
+                    .read_to_end(&mut decompressed)
+                    .expect("Invalid gzip");
+            }
+            None => decompressed = body.to_vec(),
+        }
+        assert_eq!(decompressed, css.as_bytes(), "Wrong CSS");
+    }
+
+    #[actix_web::test]
+    /// Test whether CSS that crashes the minifier is kept unminified.
+    async fn test_css_minification_fallback() {
+        let path = Path::new("testdata/css/unusual.css");
+        let original = read_to_string(path).expect("Couldn't read test CSS");
+        assert!(
+            minifier::css::minify(&original).is_err(),
+            "Test CSS doesn't crash the minifier"
+        );
+
+        let assets = CssAssets::load(Path::new("testdata/css"))
+            .await
+            .expect("Couldn't load CSS");
+        assert_eq!(
+            assets.get("unusual.css"),
+            Some(original.as_str()),
+            "Original CSS not kept"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether the static files are fingerprinted by their contents.
+    async fn test_manifest_load() {
+        let manifest = AssetManifest::load(Path::new(STATIC_DIR))
+            .await
+            .expect("Couldn't load static files");
+        let url = manifest.url(CSS_FILE);
+        let hash = url
+            .strip_prefix("/styles.")
+            .and_then(|rest| rest.strip_suffix(".css"))
+            .expect("Stylesheet URL isn't fingerprinted");
+        assert_eq!(hash.len(), ASSET_HASH_LEN, "Wrong length of the hash");
+        assert_eq!(
+            manifest.resolve(&url[1..]),
+            (CSS_FILE.to_string(), true),
+            "Fingerprinted URL doesn't resolve to the stylesheet"
+        );
+    }
+
+    #[test_case("styles.css", "/styles.0123456789.css"; "fingerprinted")]
+    #[test_case("missing.css", "/missing.css"; "missing")]
+    /// Test the URLs of static files.
+    ///
+    /// # Arguments
+    /// * `name` - The file name of the static file
+    /// * `expected` - The expected URL
+    fn test_manifest_url(name: &str, expected: &str) {
+        let manifest = AssetManifest {
+            hashes: [("styles.css".into(), "0123456789".into())].into(),
+        };
+        assert_eq!(manifest.url(name), expected, "Wrong URL");
+    }
+
+    #[test_case("styles.0123456789.css", "styles.css", true; "current")]
+    #[test_case("styles.9876543210.css", "styles.css", false; "outdated")]
+    #[test_case("styles.css", "styles.css", false; "not fingerprinted")]
+    #[test_case("styles.min.css", "styles.min.css", false; "not a hash")]
+    #[test_case("missing.0123456789.css", "missing.0123456789.css", false; "missing")]
+    /// Test resolving the requested file names of static files.
+    ///
+    /// # Arguments
+    /// * `requested` - The requested file name
+    /// * `expected_name` - The expected file name of the static file
+    /// * `expected_current` - Whether the fingerprint is expected to be current
+    fn test_manifest_resolve(requested: &str, expected_name: &str, expected_current: bool) {
+        let manifest = AssetManifest {
+            hashes: [("styles.css".into(), "0123456789".into())].into(),
+        };
+        assert_eq!(
+            manifest.resolve(requested),
+            (expected_name.to_string(), expected_current),
+            "Wrong resolution"
+        );
+    }
+
+    /// CSS for testing the vendoring of stylesheets
+    const VENDOR_CSS: &str = "body{margin:0}";
+    /// Subresource integrity hash of the CSS for testing the vendoring of stylesheets
+    const VENDOR_INTEGRITY: &str =
+        "sha384-624hJ8nEe/uDdupZe/6Ug/U4fU/QRVsiTGq3cDxFtyteEAMgXERWXujDDQlJgeyk";
+
+    #[test_case(200, VENDOR_INTEGRITY, true; "valid")]
+    #[test_case(200, "sha384-invalid", false; "wrong integrity")]
+    #[test_case(404, VENDOR_INTEGRITY, false; "missing")]
+    #[actix_web::test]
+    /// Test whether a stylesheet is vendored only if it's valid.
+    ///
+    /// # Arguments
+    /// * `status` - The status of the response with the stylesheet
+    /// * `integrity` - The integrity hash against which the stylesheet is checked
+    /// * `expected` - Whether the stylesheet is expected to be vendored
+    async fn test_vendor_css(status: u16, integrity: &str, expected: bool) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/style.css"))
+            .respond_with(ResponseTemplate::new(status).set_body_string(VENDOR_CSS))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let file_path =
+            std::env::temp_dir().join(format!("dilbert-viewer-{}.css", uuid::Uuid::new_v4()));
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(RESP_TIMEOUT))
+            .finish();
+        let url = format!("{}/style.css", mock_server.uri());
+        let result = vendor_css(&client, &url, integrity, &file_path).await;
+        let vendored = read_to_string(&file_path).ok();
+        let _ = std::fs::remove_file(&file_path);
+
+        assert_eq!(result.is_ok(), expected, "Wrong result: {result:?}");
+        assert_eq!(
+            vendored.as_deref(),
+            expected.then_some(VENDOR_CSS),
+            "Wrong vendored stylesheet"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether an already vendored stylesheet isn't downloaded again.
+    async fn test_vendor_css_existing() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let file_path =
+            std::env::temp_dir().join(format!("dilbert-viewer-{}.css", uuid::Uuid::new_v4()));
+        std::fs::write(&file_path, VENDOR_CSS).expect("Couldn't write test CSS");
+        let client = Client::default();
+        let result = vendor_css(&client, &mock_server.uri(), VENDOR_INTEGRITY, &file_path).await;
+        let _ = std::fs::remove_file(&file_path);
+
+        assert!(result.is_ok(), "Couldn't vendor stylesheet: {result:?}");
+        mock_server.verify().await;
+    }
+}
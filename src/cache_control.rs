@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Per-route caching policies for responses
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{CacheControl, CacheDirective, TryIntoHeaderPair},
+        StatusCode,
+    },
+    middleware::Next,
+    Error,
+};
+use tracing::error;
+
+use crate::constants::{COMIC_MAX_AGE, COMIC_PAGE_MAX_AGE, LATEST_MAX_AGE, STATIC_MAX_AGE};
+
+/// Get the caching policy for the responses of a route.
+///
+/// Comic images never change once published, so they're cached for long. The pages and text of
+/// comics can change with the app or with refreshed data, so they're cached briefly, and the latest
+/// comic even more briefly, since it changes daily. Random comics must be different
+/// for each request, so they're never cached.
+///
+/// # Arguments
+/// * `pattern` - The pattern of the matched route, if any
+/// * `cacheable` - Whether the response is successful, and so worth caching
+fn route_policy(pattern: Option<&str>, cacheable: bool) -> Option<CacheControl> {
+    let directives = match pattern {
+        Some("/random") => vec![CacheDirective::NoStore],
+        // The favorites and history of each client are in its cookies, so only it may cache them.
+        Some("/favorites" | "/history") => vec![CacheDirective::Private, CacheDirective::NoCache],
+        _ if !cacheable => return None,
+        Some("/") => vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(LATEST_MAX_AGE),
+        ],
+        Some("/{year}-{month}-{day}" | "/{date}/text" | "/api/v1/comic/{date}") => vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(COMIC_PAGE_MAX_AGE),
+            CacheDirective::MustRevalidate,
+        ],
+        Some(
+            "/{date}/image"
+            | "/{date}/image/{width}"
+            | "/{date}/preview"
+            | "/{date}/thumbnail"
+            | "/download/{date}",
+        ) => vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(COMIC_MAX_AGE),
+            CacheDirective::Extension("immutable".into(), None),
+        ],
+        // The service for static files is mounted at the root, so its pattern is empty.
+        Some("/{path}.js" | "") => vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(STATIC_MAX_AGE),
+        ],
+        _ => return None,
+    };
+    Some(CacheControl(directives))
+}
+
+/// Middleware to add a `Cache-Control` header to responses according to their route.
+///
+/// Responses whose handlers set their own `Cache-Control` header are left untouched.
+pub async fn cache_control_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let mut resp = next.call(req).await?;
+    let status = resp.status();
+    let cacheable = status.is_success() || status == StatusCode::NOT_MODIFIED;
+    let pattern = resp.request().match_pattern();
+    let Some(policy) = route_policy(pattern.as_deref(), cacheable) else {
+        return Ok(resp);
+    };
+
+    match policy.try_into_pair() {
+        Ok((name, value)) => {
+            if !resp.headers().contains_key(&name) {
+                resp.headers_mut().insert(name, value);
+            }
+        }
+        Err(err) => error!("Couldn't create Cache-Control header: {err}"),
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(Some("/"), true, Some("public, max-age=300"); "latest comic")]
+    #[test_case(Some("/{year}-{month}-{day}"), true, Some("public, max-age=3600, must-revalidate"); "comic")]
+    #[test_case(Some("/{date}/text"), true, Some("public, max-age=3600, must-revalidate"); "comic text")]
+    #[test_case(Some("/{date}/image"), true, Some("public, max-age=31536000, immutable"); "image")]
+    #[test_case(Some("/{year}-{month}-{day}"), false, None; "missing comic")]
+    #[test_case(Some("/{date}/thumbnail"), true, Some("public, max-age=31536000, immutable"); "thumbnail")]
+    #[test_case(Some("/random"), false, Some("no-store"); "random comic")]
+    #[test_case(Some("/favorites"), true, Some("private, no-cache"); "favorites")]
+    #[test_case(Some(""), true, Some("public, max-age=3600"); "static file")]
+    #[test_case(None, true, None; "unmatched")]
+    #[test_case(Some("/search"), true, None; "no policy")]
+    /// Test whether the right caching policy is chosen for each route.
+    ///
+    /// # Arguments
+    /// * `pattern` - The pattern of the matched route
+    /// * `cacheable` - Whether the response is successful
+    /// * `expected` - The expected `Cache-Control` header, if any
+    fn test_route_policy(pattern: Option<&str>, cacheable: bool, expected: Option<&str>) {
+        let policy = route_policy(pattern, cacheable).map(|policy| policy.to_string());
+        assert_eq!(policy.as_deref(), expected, "Wrong caching policy");
+    }
+}
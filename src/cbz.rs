@@ -0,0 +1,129 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Comic book archives (CBZ) of comics, written in chunks for streaming
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+use actix_web::web::Bytes;
+use chrono::{Datelike, NaiveDate};
+use zip::{
+    result::ZipResult,
+    write::{SimpleFileOptions, StreamWriter},
+    CompressionMethod, DateTime, ZipWriter,
+};
+
+/// Buffer for the parts of an archive that have been written, but not yet sent
+#[derive(Clone, Default)]
+struct ChunkBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl ChunkBuffer {
+    /// Take everything written so far, leaving the buffer empty.
+    fn take(&self) -> Bytes {
+        mem::take(&mut *self.0.borrow_mut()).into()
+    }
+}
+
+impl Write for ChunkBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer of a comic book archive, which gives the archive in chunks as comics are added
+///
+/// Comic book readers sort pages by their file names, so they should be named in order of the
+/// comics' dates. The images are already compressed, so they're stored as-is.
+pub struct CbzWriter {
+    /// The writer of the ZIP archive, which never seeks backwards
+    zip: ZipWriter<StreamWriter<ChunkBuffer>>,
+    /// The buffer that the archive is written to
+    buffer: ChunkBuffer,
+}
+
+impl Default for CbzWriter {
+    fn default() -> Self {
+        let buffer = ChunkBuffer::default();
+        Self {
+            zip: ZipWriter::new_stream(buffer.clone()),
+            buffer,
+        }
+    }
+}
+
+impl CbzWriter {
+    /// Add the image of a comic, and get the part of the archive written for it.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic, which is used as its modification time
+    /// * `name` - The file name of the image
+    /// * `image` - The bytes of the image
+    pub fn add_image(&mut self, date: &NaiveDate, name: &str, image: &[u8]) -> ZipResult<Bytes> {
+        let mut options =
+            SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        // ZIP archives can't represent dates before 1980, but no comic is that old.
+        if let Ok(modified) = DateTime::from_date_and_time(
+            date.year() as u16,
+            date.month() as u8,
+            date.day() as u8,
+            0,
+            0,
+            0,
+        ) {
+            options = options.last_modified_time(modified);
+        }
+        self.zip.start_file(name, options)?;
+        self.zip.write_all(image)?;
+        Ok(self.buffer.take())
+    }
+
+    /// Finish the archive, and get its remaining part.
+    pub fn finish(self) -> ZipResult<Bytes> {
+        self.zip.finish()?;
+        Ok(self.buffer.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    #[test]
+    /// Test whether the chunks of an archive form a valid archive with the comics in order.
+    fn test_cbz_writer() {
+        let mut writer = CbzWriter::default();
+        let mut archive = Vec::new();
+        let comics = [("2000-01-01.gif", b"first"), ("2000-01-02.gif", b"other")];
+        for (day, (name, image)) in comics.iter().enumerate() {
+            let date = NaiveDate::from_ymd_opt(2000, 1, day as u32 + 1).unwrap();
+            let chunk = writer
+                .add_image(&date, name, *image)
+                .expect("Couldn't add image");
+            assert!(!chunk.is_empty(), "Image wasn't written");
+            archive.extend_from_slice(&chunk);
+        }
+        archive.extend_from_slice(&writer.finish().expect("Couldn't finish archive"));
+
+        let mut archive = ZipArchive::new(Cursor::new(archive)).expect("Invalid archive");
+        assert_eq!(archive.len(), comics.len(), "Wrong number of files");
+        for (idx, (name, image)) in comics.iter().enumerate() {
+            let mut file = archive.by_index(idx).expect("Couldn't read file");
+            assert_eq!(file.name(), *name, "Wrong file name");
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents).unwrap();
+            assert_eq!(contents, *image, "Wrong file contents");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Circuit breaking of requests to the comic source during outages
+use std::num::NonZeroU32;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+/// State of the circuit breaker
+#[derive(Debug, Default)]
+struct State {
+    /// The number of consecutive failures
+    failures: u32,
+    /// The time until which requests are rejected, if the breaker is open
+    open_until: Option<Instant>,
+}
+
+/// Circuit breaker for requests to the comic source
+///
+/// This opens after a number of consecutive failures, and then rejects requests immediately for a
+/// cooldown period. After the cooldown, a single request is let through as a probe; the breaker
+/// closes if it succeeds, and stays open for another cooldown otherwise.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// The number of consecutive failures after which the breaker opens
+    threshold: NonZeroU32,
+    /// The time for which requests are rejected once the breaker opens
+    cooldown: Duration,
+    /// The current state, shared with all requests
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// Initialize a closed circuit breaker.
+    ///
+    /// # Arguments
+    /// * `threshold` - The number of consecutive failures after which the breaker opens
+    /// * `cooldown` - The time for which requests are rejected once the breaker opens
+    pub fn new(threshold: NonZeroU32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Lock the state, recovering it if the lock is poisoned.
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| {
+            // The state is always left consistent, so it's safe to keep using it.
+            warn!("Lock for the circuit breaker is poisoned: {err}");
+            err.into_inner()
+        })
+    }
+
+    /// Check whether a request is allowed to be sent.
+    ///
+    /// Once the cooldown is over, only the first request is allowed as a probe, and the breaker
+    /// stays open for the others until the probe finishes.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state();
+        let Some(open_until) = state.open_until else {
+            return true;
+        };
+
+        let now = Instant::now();
+        if now < open_until {
+            return false;
+        }
+        info!("Cooldown of the circuit breaker is over; probing the comic source");
+        state.open_until = Some(now + self.cooldown);
+        true
+    }
+
+    /// Get the time after which requests may be allowed again, which is zero if the breaker is
+    /// closed.
+    pub fn retry_after(&self) -> Duration {
+        self.state()
+            .open_until
+            .map_or(Duration::ZERO, |open_until| {
+                open_until.saturating_duration_since(Instant::now())
+            })
+    }
+
+    /// Record a successful request, which closes the breaker.
+    pub fn record_success(&self) {
+        let mut state = self.state();
+        if state.open_until.is_some() {
+            info!("Comic source has recovered; closing the circuit breaker");
+        }
+        *state = State::default();
+    }
+
+    /// Record a failed request, which opens the breaker if there are too many failures.
+    pub fn record_failure(&self) {
+        let mut state = self.state();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.threshold.get() {
+            if state.open_until.is_none() {
+                warn!(
+                    "Comic source failed {} times in a row; opening the circuit breaker",
+                    state.failures
+                );
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread::sleep;
+
+    use test_case::test_case;
+
+    /// Cooldown (in milliseconds) for the circuit breakers in tests
+    const COOLDOWN: u64 = 50;
+
+    /// Get a circuit breaker for tests, which opens after two failures.
+    fn get_breaker() -> CircuitBreaker {
+        CircuitBreaker::new(NonZeroU32::new(2).unwrap(), Duration::from_millis(COOLDOWN))
+    }
+
+    #[test]
+    /// Test whether the breaker opens only after the threshold of consecutive failures.
+    fn test_open() {
+        let breaker = get_breaker();
+        breaker.record_failure();
+        assert!(breaker.allow(), "Breaker opened before the threshold");
+
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(breaker.allow(), "Success didn't reset the failures");
+
+        breaker.record_failure();
+        assert!(!breaker.allow(), "Breaker didn't open at the threshold");
+    }
+
+    #[test_case(true; "probe succeeds")]
+    #[test_case(false; "probe fails")]
+    /// Test whether a single probe is allowed after the cooldown, which decides the state.
+    ///
+    /// # Arguments
+    /// * `success` - Whether the probe succeeds
+    fn test_probe(success: bool) {
+        let breaker = get_breaker();
+        breaker.record_failure();
+        breaker.record_failure();
+        sleep(Duration::from_millis(COOLDOWN));
+
+        assert!(breaker.allow(), "Probe wasn't allowed after the cooldown");
+        assert!(!breaker.allow(), "Multiple probes were allowed");
+
+        if success {
+            breaker.record_success();
+            assert!(
+                breaker.allow(),
+                "Breaker didn't close after a successful probe"
+            );
+        } else {
+            breaker.record_failure();
+            assert!(!breaker.allow(), "Breaker closed after a failed probe");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! HTTP clients for requests to the comic source
+use std::time::Duration;
+
+use awc::{
+    http::header::{HeaderName, HeaderValue, USER_AGENT},
+    Client,
+};
+
+use crate::config::Config;
+
+/// Find a header for requests to the comic source that is invalid, if any.
+///
+/// # Arguments
+/// * `config` - The configuration with the headers
+///
+/// # Returns
+/// The name of the invalid header
+pub fn find_invalid_header(config: &Config) -> Option<&str> {
+    if HeaderValue::from_str(&config.user_agent).is_err() {
+        return Some(USER_AGENT.as_str());
+    }
+    config
+        .source_headers
+        .iter()
+        .find(|(name, value)| {
+            name.parse::<HeaderName>().is_err() || HeaderValue::from_str(value).is_err()
+        })
+        .map(|(name, _)| name.as_str())
+}
+
+/// Build an HTTP client for requests to the comic source.
+///
+/// The client sends the configured User-Agent and extra headers with every request. These must
+/// have been checked with [`find_invalid_header`], since invalid headers cause a panic.
+///
+/// # Arguments
+/// * `config` - The configuration with the headers
+/// * `timeout` - The timeout for getting a response
+pub fn build_client(config: &Config, timeout: Duration) -> Client {
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .add_default_header((USER_AGENT, config.user_agent.as_str()));
+    for (name, value) in &config.source_headers {
+        builder = builder.add_default_header((name.as_str(), value.as_str()));
+    }
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use awc::http::{Method, StatusCode};
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{header, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::RESP_TIMEOUT;
+
+    #[test_case("bot/1.0", &[("From", "admin@example.com")], None; "valid")]
+    #[test_case("bot/1.0\n", &[], Some("user-agent"); "invalid user agent")]
+    #[test_case("bot/1.0", &[("Bad Name", "value")], Some("Bad Name"); "invalid name")]
+    #[test_case("bot/1.0", &[("From", "bad\nvalue")], Some("From"); "invalid value")]
+    /// Test whether invalid headers are found.
+    ///
+    /// # Arguments
+    /// * `user_agent` - The User-Agent header
+    /// * `headers` - The names and values of the extra headers
+    /// * `expected` - The name of the expected invalid header, if any
+    fn test_find_invalid_header(
+        user_agent: &str,
+        headers: &[(&str, &str)],
+        expected: Option<&str>,
+    ) {
+        let config = Config {
+            user_agent: user_agent.into(),
+            source_headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..Config::default()
+        };
+        assert_eq!(
+            find_invalid_header(&config),
+            expected,
+            "Wrong invalid header"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether the client sends the configured headers.
+    async fn test_client_headers() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(header("user-agent", "bot/1.0"))
+            .and(header("from", "admin@example.com"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = Config {
+            user_agent: "bot/1.0".into(),
+            source_headers: [("From".into(), "admin@example.com".into())].into(),
+            ..Config::default()
+        };
+        let client = build_client(&config, Duration::from_secs(RESP_TIMEOUT));
+        let resp = client
+            .get(mock_server.uri())
+            .send()
+            .await
+            .expect("Couldn't send request");
+
+        assert_eq!(resp.status(), StatusCode::OK, "Headers weren't sent");
+        mock_server.verify().await;
+    }
+}
@@ -0,0 +1,316 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Identification of clients by their IP addresses, including behind trusted proxies
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use actix_web::{
+    dev::ServiceRequest,
+    http::header::{HeaderMap, FORWARDED, X_FORWARDED_FOR},
+    web,
+};
+use serde::Deserialize;
+
+use crate::errors::IpRangeError;
+
+/// A range of IP addresses, given in CIDR notation or as a single address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct IpRange {
+    /// The first address in the range
+    addr: IpAddr,
+    /// The number of leading bits that are fixed in the range
+    prefix_len: u32,
+}
+
+impl FromStr for IpRange {
+    type Err = IpRangeError;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match range.split_once('/') {
+            Some((addr, prefix_len)) => (addr.parse()?, Some(prefix_len)),
+            None => (range.parse()?, None),
+        };
+        let max_len = match addr {
+            IpAddr::V4(_) => u32::BITS,
+            IpAddr::V6(_) => u128::BITS,
+        };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| IpRangeError::PrefixLen(len.into()))?,
+            None => max_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl IpRange {
+    /// Check whether an IP address is in this range.
+    ///
+    /// # Arguments
+    /// * `ip` - The IP address
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(u32::BITS - self.prefix_len);
+                let mask = mask.unwrap_or(0);
+                u32::from(addr) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(u128::BITS - self.prefix_len);
+                let mask = mask.unwrap_or(0);
+                u128::from(addr) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The forwarding header in which trusted proxies give the IP addresses of clients
+///
+/// Proxies usually only append to one of the headers and pass the other through from the client
+/// unchanged, so only the one they append to can be trusted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// The standard `Forwarded` header
+    Forwarded,
+    /// The `X-Forwarded-For` header, which is used by most proxies (such as Nginx and Heroku's)
+    #[default]
+    XForwardedFor,
+}
+
+impl FromStr for ForwardedHeader {
+    type Err = String;
+
+    fn from_str(header: &str) -> Result<Self, Self::Err> {
+        if header.eq_ignore_ascii_case("forwarded") {
+            Ok(Self::Forwarded)
+        } else if header.eq_ignore_ascii_case("x-forwarded-for") {
+            Ok(Self::XForwardedFor)
+        } else {
+            Err(format!(
+                "Invalid forwarding header: {header} (expected \"forwarded\" or \"x-forwarded-for\")"
+            ))
+        }
+    }
+}
+
+/// The proxies whose forwarding headers are trusted for the IP addresses of clients
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    /// The IP ranges of the proxies
+    ranges: Vec<IpRange>,
+    /// The forwarding header that the proxies set
+    header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    /// Parse the trusted proxies from a list of IP addresses or ranges in CIDR notation.
+    ///
+    /// # Arguments
+    /// * `ranges` - The IP addresses or ranges of the proxies
+    /// * `header` - The forwarding header that the proxies set
+    pub fn new(ranges: &[String], header: ForwardedHeader) -> Result<Self, IpRangeError> {
+        let ranges = ranges
+            .iter()
+            .map(|range| range.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { ranges, header })
+    }
+
+    /// Check whether an IP address belongs to a trusted proxy.
+    ///
+    /// # Arguments
+    /// * `ip` - The IP address
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Parse the IP address of a hop in a forwarding header, which may also have a port.
+///
+/// # Arguments
+/// * `hop` - The hop, such as "192.0.2.1", "192.0.2.1:80" or "[2001:db8::1]:80"
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// Get the hops through which a request was forwarded, from the client to the last proxy.
+///
+/// Only the given forwarding header is read, since the other one could have been set by the
+/// client.
+///
+/// # Arguments
+/// * `headers` - The headers of the request
+/// * `header` - The forwarding header that the trusted proxies set
+fn forwarded_hops(headers: &HeaderMap, header: ForwardedHeader) -> Vec<String> {
+    match header {
+        ForwardedHeader::Forwarded => headers
+            .get_all(FORWARDED)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split([',', ';']))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+            .collect(),
+        ForwardedHeader::XForwardedFor => headers
+            .get_all(X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|hop| hop.trim().to_string())
+            .filter(|hop| !hop.is_empty())
+            .collect(),
+    }
+}
+
+/// Get the IP address of the client that sent a request.
+///
+/// If the request came through trusted proxies, then the client is the last hop in the forwarding
+/// headers before them, since the hops before that could be forged by the client. Requests over a
+/// Unix domain socket are always from a local proxy, so their forwarding headers are trusted.
+///
+/// # Arguments
+/// * `req` - The request
+pub fn client_ip(req: &ServiceRequest) -> Option<String> {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let trusted = req.app_data::<web::Data<TrustedProxies>>();
+    let is_trusted = |ip| trusted.is_some_and(|trusted| trusted.contains(ip));
+    if let Some(peer) = peer {
+        if !is_trusted(peer) {
+            return Some(peer.to_string());
+        }
+    }
+
+    let header = trusted.map(|trusted| trusted.header).unwrap_or_default();
+    let mut client = peer.map(|peer| peer.to_string());
+    for hop in forwarded_hops(req.headers(), header).into_iter().rev() {
+        match parse_hop(&hop) {
+            Some(ip) if is_trusted(ip) => client = Some(ip.to_string()),
+            Some(ip) => return Some(ip.to_string()),
+            // This is an obfuscated identifier, such as "unknown", which is the best there is.
+            None => return Some(hop),
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+    use test_case::test_case;
+
+    #[test_case("10.0.0.0/8", "10.1.2.3", true; "in v4 range")]
+    #[test_case("10.0.0.0/8", "11.0.0.1", false; "outside v4 range")]
+    #[test_case("10.0.0.1", "10.0.0.1", true; "single v4 address")]
+    #[test_case("0.0.0.0/0", "1.2.3.4", true; "all v4 addresses")]
+    #[test_case("10.0.0.0/8", "::ffff:10.0.0.1", true; "mapped v4 address")]
+    #[test_case("2001:db8::/32", "2001:db8::1", true; "in v6 range")]
+    #[test_case("2001:db8::/32", "10.0.0.1", false; "different family")]
+    /// Test whether IP addresses are correctly checked against ranges.
+    ///
+    /// # Arguments
+    /// * `range` - The IP range
+    /// * `ip` - The IP address to check
+    /// * `expected` - Whether the address is expected to be in the range
+    fn test_ip_range(range: &str, ip: &str, expected: bool) {
+        let range: IpRange = range.parse().expect("Couldn't parse IP range");
+        let ip = ip.parse().unwrap();
+        assert_eq!(range.contains(ip), expected, "Wrong range check");
+    }
+
+    #[test_case("10.0.0.0/33"; "long prefix")]
+    #[test_case("10.0.0.0/a"; "invalid prefix")]
+    #[test_case("localhost"; "invalid address")]
+    /// Test whether invalid IP ranges are rejected.
+    ///
+    /// # Arguments
+    /// * `range` - The invalid IP range
+    fn test_invalid_ip_range(range: &str) {
+        assert!(range.parse::<IpRange>().is_err(), "Invalid range accepted");
+    }
+
+    #[test_case(&[], &[], "1.1.1.1"; "untrusted without headers")]
+    #[test_case(&[], &[("x-forwarded-for", "2.2.2.2")], "1.1.1.1"; "untrusted with headers")]
+    #[test_case(&["1.1.1.1"], &[("x-forwarded-for", "2.2.2.2")], "2.2.2.2"; "trusted")]
+    #[test_case(&["1.1.1.0/24"], &[("x-forwarded-for", "3.3.3.3, 2.2.2.2, 1.1.1.2")], "2.2.2.2"; "forged hops")]
+    #[test_case(&["1.1.1.1"], &[("x-forwarded-for", "2.2.2.2"), ("forwarded", "for=9.9.9.9")], "2.2.2.2"; "other header ignored")]
+    #[test_case(&["1.1.1.1"], &[("forwarded", "for=9.9.9.9")], "1.1.1.1"; "only other header")]
+    #[test_case(&["1.1.1.1"], &[], "1.1.1.1"; "trusted without headers")]
+    /// Test whether the client's IP address is found through trusted proxies only.
+    ///
+    /// The proxies are trusted to set the `X-Forwarded-For` header, so the `Forwarded` header
+    /// could have been set by the client.
+    ///
+    /// # Arguments
+    /// * `trusted` - The IP ranges of the trusted proxies
+    /// * `headers` - The forwarding headers of the request
+    /// * `expected` - The expected IP address of the client
+    fn test_client_ip(trusted: &[&str], headers: &[(&str, &str)], expected: &str) {
+        test_client_ip_with(trusted, ForwardedHeader::XForwardedFor, headers, expected);
+    }
+
+    #[test_case(&[("forwarded", "for=\"[2001:db8::1]:80\"")], "2001:db8::1"; "v6 with port")]
+    #[test_case(&[("forwarded", "for=2.2.2.2;proto=https, for=1.1.1.2")], "1.1.1.2"; "multiple hops")]
+    #[test_case(&[("forwarded", "for=unknown")], "unknown"; "obfuscated")]
+    #[test_case(&[("x-forwarded-for", "9.9.9.9"), ("forwarded", "for=2.2.2.2")], "2.2.2.2"; "other header ignored")]
+    /// Test whether the client's IP address is found from the standard `Forwarded` header.
+    ///
+    /// # Arguments
+    /// * `headers` - The forwarding headers of the request
+    /// * `expected` - The expected IP address of the client
+    fn test_client_ip_forwarded(headers: &[(&str, &str)], expected: &str) {
+        test_client_ip_with(&["1.1.1.1"], ForwardedHeader::Forwarded, headers, expected);
+    }
+
+    #[test_case("forwarded", Some(ForwardedHeader::Forwarded); "standard")]
+    #[test_case("X-Forwarded-For", Some(ForwardedHeader::XForwardedFor); "de facto")]
+    #[test_case("x-real-ip", None; "unsupported")]
+    /// Test parsing of the forwarding header to trust.
+    ///
+    /// # Arguments
+    /// * `header` - The name of the header
+    /// * `expected` - The expected header, if it's supported
+    fn test_forwarded_header(header: &str, expected: Option<ForwardedHeader>) {
+        assert_eq!(header.parse().ok(), expected, "Wrong forwarding header");
+    }
+
+    /// Check the client's IP address found for a request through a proxy at "1.1.1.1".
+    ///
+    /// # Arguments
+    /// * `trusted` - The IP ranges of the trusted proxies
+    /// * `header` - The forwarding header that the trusted proxies set
+    /// * `headers` - The forwarding headers of the request
+    /// * `expected` - The expected IP address of the client
+    fn test_client_ip_with(
+        trusted: &[&str],
+        header: ForwardedHeader,
+        headers: &[(&str, &str)],
+        expected: &str,
+    ) {
+        let trusted: Vec<_> = trusted.iter().map(|range| range.to_string()).collect();
+        let trusted =
+            TrustedProxies::new(&trusted, header).expect("Couldn't parse trusted proxies");
+        let mut req = TestRequest::get()
+            .peer_addr("1.1.1.1:1234".parse().unwrap())
+            .app_data(web::Data::new(trusted));
+        for header in headers {
+            req = req.append_header(*header);
+        }
+
+        let client = client_ip(&req.to_srv_request());
+        assert_eq!(client.as_deref(), Some(expected), "Wrong client IP");
+    }
+}
@@ -0,0 +1,262 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Configuration for running the viewer app
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::client_ip::ForwardedHeader;
+use crate::constants::{
+    ARC_BASE_URL, BREAKER_COOLDOWN, BREAKER_THRESHOLD, CDX_URL, CSP, DB_TIMEOUT, FEED_LEN, HOST,
+    MEMENTO_URL, RATE_LIMIT_BURST, READY_TIMEOUT, RESP_TIMEOUT, RETRY_ATTEMPTS, RETRY_BACKOFF,
+    ROBOTS_DISALLOW, SHUTDOWN_TIMEOUT, SOURCE_CONCURRENCY, USER_AGENT,
+};
+use crate::errors::ConfigFileError;
+
+/// Configuration for running the viewer app
+///
+/// This can be loaded from a TOML file with the same keys, where every key is optional.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address on which to run the server, or "unix:<path>" for a Unix domain socket
+    pub host: String,
+    /// The port on which to run the server, or a default (or any unused) port if not given
+    pub port: Option<u16>,
+    /// The number of worker threads, or the number of physical CPU cores if not given
+    pub workers: Option<usize>,
+    /// The URL to the Redis database, or an in-memory cache is used if not given
+    pub db_url: Option<String>,
+    /// The URLs to the seed nodes of a Redis cluster, which is used instead of the Redis database
+    /// if given
+    pub db_cluster_urls: Vec<String>,
+    /// Whether to serialize values cached in Redis to MessagePack instead of JSON
+    pub db_msgpack: bool,
+    /// Whether to verify the TLS certificate of the Redis database, instead of accepting any
+    /// certificate (such as a self-signed one)
+    pub db_tls_verify: bool,
+    /// Path to the CA certificate in PEM format with which to verify the Redis database, instead
+    /// of the system's trusted roots
+    pub db_ca_cert: Option<PathBuf>,
+    /// Path to the client certificate in PEM format, for mutual TLS with the Redis database
+    pub db_client_cert: Option<PathBuf>,
+    /// Path to the private key in PEM format for the client certificate, for mutual TLS with the
+    /// Redis database
+    pub db_client_key: Option<PathBuf>,
+    /// The base URL of the comic source, where "{}" is replaced by the archival timestamp
+    pub source_url: String,
+    /// The URL to the CDX API for looking up archived comics, where "{}" is replaced by the URL
+    pub cdx_url: String,
+    /// The URL to the Memento TimeTravel API for locating archived comics when the CDX API fails,
+    /// where "{}" is replaced by the URL
+    pub memento_url: String,
+    /// The User-Agent header for requests to the comic source
+    pub user_agent: String,
+    /// Extra headers for requests to the comic source, as a table of names to values
+    pub source_headers: BTreeMap<String, String>,
+    /// Path to the TLS certificate chain in PEM format, to serve over HTTPS
+    pub tls_cert: Option<PathBuf>,
+    /// Path to the TLS private key in PEM format, to serve over HTTPS
+    pub tls_key: Option<PathBuf>,
+    /// Timeout (in seconds) for getting a response from the comic source
+    pub resp_timeout: u64,
+    /// Maximum number of attempts for each request to the comic source, including the first one
+    pub retry_attempts: u32,
+    /// Delay (in milliseconds) before the first retry of a request, which doubles for each retry
+    pub retry_backoff: u64,
+    /// Whether to randomize the delays between retries
+    pub retry_jitter: bool,
+    /// Maximum number of concurrent requests to the comic source, or no limit if 0
+    pub source_concurrency: usize,
+    /// Maximum number of requests per minute to the comic source, or no limit if not given
+    pub source_rate_limit: Option<NonZeroU32>,
+    /// Number of consecutive failed requests to the comic source after which requests to it are
+    /// skipped, or never skipped if 0
+    pub breaker_threshold: u32,
+    /// Time (in seconds) for which requests to the comic source are skipped, before trying again
+    pub breaker_cooldown: u64,
+    /// Timeout (in seconds) for a single database operation
+    pub db_timeout: u64,
+    /// Timeout (in seconds) for each readiness check
+    pub ready_timeout: u64,
+    /// Timeout (in seconds) for open connections to close during a graceful shutdown
+    pub shutdown_timeout: u64,
+    /// Sustained number of requests allowed per minute from each client IP, or no limit if not
+    /// given
+    pub rate_limit: Option<NonZeroU32>,
+    /// Number of requests allowed in a burst from each client IP, when rate limited
+    pub rate_limit_burst: u32,
+    /// IP addresses or ranges (in CIDR notation) of the proxies whose forwarding headers are
+    /// trusted for the IPs of clients
+    pub trusted_proxies: Vec<String>,
+    /// The forwarding header that the trusted proxies set, which is the only one that's read
+    pub forwarded_header: ForwardedHeader,
+    /// Origins allowed to make cross-origin requests to the API, where "*" allows any origin
+    pub cors_origins: Vec<String>,
+    /// Content security policy, where "{}" is replaced by a per-response nonce
+    pub csp: String,
+    /// Whether to inline the app's stylesheet into comic pages
+    pub inline_css: bool,
+    /// Whether comic navigation wraps around at the first and last comics
+    pub nav_wrap: bool,
+    /// URL path prefixes that crawlers are disallowed from in `robots.txt`
+    pub robots_disallow: Vec<String>,
+    /// Number of days of comics in the feed
+    pub feed_len: usize,
+    /// Date of the last comic in the feed, or the last available comic if not given
+    pub feed_end: Option<NaiveDate>,
+    /// Date of the first comic with which to warm up the cache on startup, or no warm-up if not
+    /// given
+    pub warm_from: Option<NaiveDate>,
+    /// Date of the last comic with which to warm up the cache on startup, or the last available
+    /// comic if not given
+    pub warm_to: Option<NaiveDate>,
+    /// Whether to detect the latest comic from the source, instead of pinning it to the last
+    /// Dilbert comic
+    pub live_latest: bool,
+    /// Whether readiness checks also check that the comic source is reachable
+    pub ready_check_source: bool,
+    /// Whether to lock each scrape in the cache, so that instances of the app sharing it don't
+    /// scrape the same comic at the same time
+    pub scrape_lock: bool,
+    /// Path to a JSON dump of comic data to import into the cache on startup
+    pub seed_file: Option<PathBuf>,
+    /// Attribution to overlay on served comic images, along with their source URLs, or no overlay
+    /// if not given
+    pub attribution: Option<String>,
+    /// Path to the font for the attribution overlay, or a bundled font if not given
+    pub attribution_font: Option<PathBuf>,
+    /// Sentry DSN to which unexpected errors are reported, or no reporting if not given
+    pub sentry_dsn: Option<String>,
+    /// Token that clients must give to use the admin routes, which are disabled if not given
+    pub admin_token: Option<String>,
+    /// Secret from which the key for signing cookies is derived, or a random key if not given
+    pub cookie_secret: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: HOST.into(),
+            port: None,
+            workers: None,
+            db_url: None,
+            db_cluster_urls: Vec::new(),
+            db_msgpack: false,
+            db_tls_verify: false,
+            db_ca_cert: None,
+            db_client_cert: None,
+            db_client_key: None,
+            source_url: ARC_BASE_URL.into(),
+            cdx_url: CDX_URL.into(),
+            memento_url: MEMENTO_URL.into(),
+            user_agent: USER_AGENT.into(),
+            source_headers: BTreeMap::new(),
+            tls_cert: None,
+            tls_key: None,
+            resp_timeout: RESP_TIMEOUT,
+            retry_attempts: RETRY_ATTEMPTS,
+            retry_backoff: RETRY_BACKOFF,
+            retry_jitter: true,
+            source_concurrency: SOURCE_CONCURRENCY,
+            source_rate_limit: None,
+            breaker_threshold: BREAKER_THRESHOLD,
+            breaker_cooldown: BREAKER_COOLDOWN,
+            db_timeout: DB_TIMEOUT,
+            ready_timeout: READY_TIMEOUT,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            rate_limit: None,
+            rate_limit_burst: RATE_LIMIT_BURST,
+            trusted_proxies: Vec::new(),
+            forwarded_header: ForwardedHeader::default(),
+            cors_origins: Vec::new(),
+            csp: CSP.into(),
+            inline_css: false,
+            nav_wrap: false,
+            robots_disallow: ROBOTS_DISALLOW
+                .iter()
+                .map(|path| path.to_string())
+                .collect(),
+            feed_len: FEED_LEN,
+            feed_end: None,
+            warm_from: None,
+            warm_to: None,
+            live_latest: false,
+            ready_check_source: false,
+            scrape_lock: false,
+            seed_file: None,
+            attribution: None,
+            attribution_font: None,
+            sentry_dsn: None,
+            admin_token: None,
+            cookie_secret: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from a TOML file.
+    ///
+    /// Keys missing in the file are set to their defaults.
+    ///
+    /// # Arguments
+    /// * `path` - The path to the TOML file
+    pub fn from_file(path: &Path) -> Result<Self, ConfigFileError> {
+        Ok(toml::from_str(&read_to_string(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{remove_file, write};
+
+    #[test]
+    /// Test whether a config file overrides only the keys given in it.
+    fn test_partial_file() {
+        let contents = "\
+            host = \"127.0.0.1\"
+            port = 8080
+            ready_timeout = 5
+            feed_end = \"2000-01-02\"
+            forwarded_header = \"forwarded\"
+        ";
+        let path =
+            std::env::temp_dir().join(format!("dilbert-viewer-{}.toml", uuid::Uuid::new_v4()));
+        write(&path, contents).expect("Couldn't write test config file");
+
+        let config = Config::from_file(&path);
+        remove_file(&path).expect("Couldn't remove test config file");
+        let config = config.expect("Couldn't load test config file");
+
+        assert_eq!(config.host, "127.0.0.1", "Wrong host");
+        assert_eq!(config.port, Some(8080), "Wrong port");
+        assert_eq!(config.ready_timeout, 5, "Wrong readiness timeout");
+        assert_eq!(
+            config.feed_end,
+            NaiveDate::from_ymd_opt(2000, 1, 2),
+            "Wrong feed end date"
+        );
+        assert_eq!(
+            config.forwarded_header,
+            ForwardedHeader::Forwarded,
+            "Wrong forwarding header"
+        );
+        assert_eq!(config.source_url, ARC_BASE_URL, "Missing key not defaulted");
+        assert_eq!(config.feed_len, FEED_LEN, "Missing key not defaulted");
+    }
+
+    #[test]
+    /// Test whether unknown keys are rejected, so that typos don't go unnoticed.
+    fn test_unknown_key() {
+        let result = toml::from_str::<Config>("hots = \"127.0.0.1\"");
+        assert!(result.is_err(), "Unknown key was accepted");
+    }
+}
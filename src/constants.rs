@@ -12,14 +12,59 @@ pub const FIRST_COMIC: &str = "1989-04-16";
 pub const LAST_COMIC: &str = "2023-03-12";
 /// Date format used for URLs on "dilbert.com"
 pub const SRC_DATE_FMT: &str = "%Y-%m-%d";
+/// Compact date format accepted in comic URLs, which are redirected to the format for "dilbert.com"
+pub const COMPACT_DATE_FMT: &str = "%Y%m%d";
 /// Date format used for display with the comic on "dilbert.com"
 pub const DISP_DATE_FMT: &str = "%A %B %d, %Y";
+/// Date format used for URLs of the archive of a month
+pub const ARCHIVE_MONTH_FMT: &str = "%Y/%m";
+/// Date format used for URLs of the thumbnail grid of a month
+pub const GRID_MONTH_FMT: &str = "%Y-%m";
+/// Date format used for display of a month
+pub const DISP_MONTH_FMT: &str = "%B %Y";
+/// Date format used for display of a day of the year
+pub const DISP_DAY_FMT: &str = "%B %-d";
+
+/// Maximum number of days in a page of multiple comics
+pub const MAX_STRIP_LEN: i64 = 31;
+/// Default number of days of comics in the feed
+pub const FEED_LEN: usize = 10;
 
 // ==================================================
 // Parameters for scraping from "dilbert.com"
 // ==================================================
 /// Timeout (in seconds) for getting a response
 pub const RESP_TIMEOUT: u64 = 10;
+/// The User-Agent header for requests, identifying this app to the comic source
+// The Internet Archive asks bots to identify themselves with a way to contact their operators.
+pub const USER_AGENT: &str = concat!(
+    "dilbert-viewer/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/rharish101/dilbert-viewer)"
+);
+/// Pause (in milliseconds) after each scrape while warming up the cache
+// This leaves room for requests from visitors, on top of the throttle for the comic source.
+pub const WARM_INTERVAL: u64 = 1000;
+/// Environment variable for the directory where to record scraped pages as test fixtures
+// This is only used in debug builds, so that production deployments never write these.
+#[cfg(debug_assertions)]
+pub const SCRAPE_RECORD_DIR_VAR: &str = "SCRAPE_RECORD_DIR";
+/// Default maximum number of attempts for each request, including the first one
+pub const RETRY_ATTEMPTS: u32 = 3;
+/// Default delay (in milliseconds) before the first retry of a request
+pub const RETRY_BACKOFF: u64 = 500;
+/// Maximum delay (in seconds) asked for by a "Retry-After" header that a request waits to retry
+// Longer delays would make the request time out anyway, so requests are skipped until then.
+pub const RETRY_AFTER_MAX: u64 = 10;
+/// Default maximum number of concurrent requests
+// This avoids getting blocked by the Wayback Machine for sending too many requests.
+pub const SOURCE_CONCURRENCY: usize = 4;
+/// Default number of consecutive failed requests after which the circuit breaker opens
+pub const BREAKER_THRESHOLD: u32 = 5;
+/// Default time (in seconds) for which requests are skipped once the circuit breaker opens
+pub const BREAKER_COOLDOWN: u64 = 30;
+/// Number of days before today that are checked for the latest comic
+pub const LATEST_DATE_LOOKBACK: i64 = 7;
 
 // ==================================================
 // Parameters for caching to the database
@@ -29,38 +74,293 @@ pub const RESP_TIMEOUT: u64 = 10;
 pub const MAX_DB_CONN: usize = 19;
 /// Timeout (in seconds) for a single database operation
 pub const DB_TIMEOUT: u64 = 5;
+/// Marker prefixed to cached values serialized to MessagePack
+// This byte is never used in MessagePack, and can't start a JSON value.
+pub const MSGPACK_MARKER: u8 = 0xc1;
+/// Lua script for Redis that deletes a key only if it has the given value
+pub const DELETE_IF_EQ_SCRIPT: &str = r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#;
+/// Maximum size (in bytes) of the in-memory cache, which is used if the database is unavailable
+pub const MEM_CACHE_SIZE: usize = 64 * 1024 * 1024;
+/// Time (in seconds) for which the data of recent comics is cached
+// Recent comics are the most likely to be re-archived by the Wayback Machine, so refresh them often.
+pub const RECENT_CACHE_TTL: u64 = 24 * 60 * 60;
+/// Time (in seconds) for which the data of older comics is cached
+pub const OLD_CACHE_TTL: u64 = 30 * 24 * 60 * 60;
+/// Time (in seconds) for which the data of comics is kept after it goes stale
+// Stale data is served while it's refreshed in the background, so that requests don't wait on the
+// source.
+pub const STALE_CACHE_TTL: u64 = 30 * 24 * 60 * 60;
+/// Prefix for cache keys of the markers of fresh comic data
+// These expire when the data goes stale, while the data itself is kept for longer.
+pub const FRESH_CACHE_PREFIX: &str = "fresh";
+/// Number of days before the last comic within which comics are considered recent
+pub const RECENT_COMIC_DAYS: i64 = 7;
+/// Prefix for cache keys of comic images
+// This keeps the keys distinct from those of the comic data, which are just the dates.
+pub const IMG_CACHE_PREFIX: &str = "image";
+/// Time (in seconds) for which comic images are cached
+// Images are prefetched while scraping, so that they can be served even if their host goes down.
+// They don't change once published, so they're kept for much longer than the comic data.
+pub const IMG_CACHE_TTL: u64 = 365 * 24 * 60 * 60;
+/// Prefix for cache keys of comic images transcoded to WebP
+pub const WEBP_CACHE_PREFIX: &str = "webp";
+/// MIME type of WebP images
+pub const WEBP_MIME_TYPE: &str = "image/webp";
+/// Prefix for cache keys of social-share preview images of comics
+pub const PREVIEW_CACHE_PREFIX: &str = "preview";
+/// Width (in pixels) of preview images
+// This is the size recommended for OpenGraph images.
+pub const PREVIEW_WIDTH: u32 = 1200;
+/// Height (in pixels) of preview images
+pub const PREVIEW_HEIGHT: u32 = 630;
+/// Height (in pixels) of the banner with the title and date in preview images
+pub const PREVIEW_BANNER_HEIGHT: u32 = 130;
+/// Padding (in pixels) around the contents of preview images
+pub const PREVIEW_PADDING: u32 = 20;
+/// Font size (in pixels) of the title in preview images
+pub const PREVIEW_TITLE_SIZE: f32 = 48.0;
+/// Font size (in pixels) of the date in preview images
+pub const PREVIEW_DATE_SIZE: f32 = 32.0;
+/// MIME type of preview images
+// Comics are line art, which PNG compresses well without artifacts.
+pub const PREVIEW_MIME_TYPE: &str = "image/png";
+/// Prefix for cache keys of downscaled thumbnails of comics
+pub const THUMBNAIL_CACHE_PREFIX: &str = "thumbnail";
+/// Width (in pixels) of thumbnails
+// This fits three thumbnails side-by-side on most desktop screens.
+pub const THUMBNAIL_WIDTH: u32 = 300;
+/// Maximum height (in pixels) of thumbnails, for comics that are taller than usual
+pub const THUMBNAIL_MAX_HEIGHT: u32 = 300;
+/// MIME type of thumbnails
+pub const THUMBNAIL_MIME_TYPE: &str = "image/png";
+/// Prefix for cache keys of comic images with the attribution overlaid
+pub const OVERLAY_CACHE_PREFIX: &str = "overlay";
+/// Height (in pixels) of the band with the attribution at the bottom of comic images
+pub const OVERLAY_BAND_HEIGHT: u32 = 20;
+/// Font size (in pixels) of the attribution on comic images
+pub const OVERLAY_TEXT_SIZE: f32 = 14.0;
+/// Padding (in pixels) to the left of the attribution on comic images
+pub const OVERLAY_PADDING: u32 = 6;
+/// MIME type of comic images with the attribution overlaid
+pub const OVERLAY_MIME_TYPE: &str = "image/png";
+/// Prefix for cache keys of other resolutions of comic images
+// Each variant is keyed by its width as well, and the key without a width holds the cached widths.
+pub const VARIANT_CACHE_PREFIX: &str = "variant";
+/// Prefix for cache keys of rendered comic pages
+pub const PAGE_CACHE_PREFIX: &str = "page";
+/// Prefix for cache keys of the sets of dates of comics with each tag
+// These differ from the keys of the older indices, which were stored as values instead of sets.
+pub const TAG_CACHE_PREFIX: &str = "tag-set";
+/// Prefix for cache keys of the sets of dates of comics with each word in their titles or transcripts
+pub const SEARCH_CACHE_PREFIX: &str = "word-set";
+/// Cache key for the set of dates of comics that are missing from the source
+pub const MISSING_CACHE_KEY: &str = "missing-set";
+/// Prefix for cache keys of the locks for scraping each comic, shared by instances of the app
+pub const SCRAPE_LOCK_PREFIX: &str = "scrape-lock";
+/// Time (in seconds) after which a lock for scraping a comic expires
+// This frees the lock if its holder crashes, and covers a scrape with retries.
+pub const SCRAPE_LOCK_TTL: u64 = 30;
+/// Interval (in milliseconds) between checks of whether a locked scrape has finished
+pub const SCRAPE_LOCK_POLL: u64 = 200;
+/// Minimum length (in characters) of words that are indexed for search
+pub const MIN_SEARCH_WORD_LEN: usize = 2;
+/// Cache key for the date of the latest comic
+pub const LATEST_DATE_KEY: &str = "latest-date";
+/// Time (in seconds) for which the date of the latest comic is cached
+pub const LATEST_DATE_REFRESH: u64 = 2 * 60 * 60;
+/// Prefix for cache keys of the date of the "comic of the day" for each day
+pub const DAILY_COMIC_PREFIX: &str = "daily";
+/// Time (in seconds) for which the date of the "comic of the day" is cached
+// This only needs to outlive the day, with some slack for clock differences between instances.
+pub const DAILY_COMIC_TTL: u64 = 2 * 24 * 60 * 60;
+
+// ==================================================
+// Parameters for health checks
+// ==================================================
+/// Default timeout (in seconds) for each readiness check
+// This is shorter than the other timeouts, so that probes always respond promptly.
+pub const READY_TIMEOUT: u64 = 2;
+
+// ==================================================
+// Parameters for metrics
+// ==================================================
+/// Prefix for the names of the app's metrics
+pub const METRICS_PREFIX: &str = "dilbert_viewer";
+/// Content type of the metrics, which are in the Prometheus text format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// Upper bounds (in seconds) of the buckets in the histogram of scrape durations
+// Scrapes go through the Wayback Machine, so they take seconds even when all goes well.
+pub const SCRAPE_DURATION_BUCKETS: [f64; 7] = [0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0];
+
+// ==================================================
+// Parameters for rate limiting
+// ==================================================
+/// Default number of requests from each client that are allowed in a burst
+pub const RATE_LIMIT_BURST: u32 = 10;
+/// Maximum number of clients whose request counts are kept in memory
+// Beyond this, the least recently seen clients are forgotten, i.e. their limits are reset.
+pub const RATE_LIMIT_MAX_CLIENTS: usize = 10_000;
+/// Prefix for database keys of the request counts of clients
+pub const RATE_LIMIT_KEY_PREFIX: &str = "rate-limit";
+/// URL paths that aren't rate limited
+// Probes shouldn't fail because of the traffic from other clients behind the same address.
+pub const RATE_LIMIT_EXEMPT: [&str; 2] = ["/healthz", "/readyz"];
+
+// ==================================================
+// Parameters for logging and exporting traces
+// ==================================================
+/// Name of the span encapsulating the log events for each request
+pub const REQUEST_SPAN: &str = "request";
+/// Header with the ID of each request, which is taken from the request if given, and echoed back
+// This is the de facto standard header used by reverse proxies and load balancers.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Maximum length of a request ID given by the client
+// Longer IDs are likely abuse, so they're replaced by a generated one.
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+/// Environment variable for the OTLP endpoint to which traces are exported
+// This is the standard variable, which the exporter also reads to configure itself.
+pub const OTLP_ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+/// Name of the service in exported traces
+pub const OTEL_SERVICE_NAME: &str = "dilbert-viewer";
+/// Prefix of the targets of the viewer's own spans, which are the only ones exported
+pub const OTEL_TARGET: &str = "dilbert_viewer";
 
 // ==================================================
 // Miscellaneous
 // ==================================================
+/// Default address on which to run the server
+pub const HOST: &str = "0.0.0.0";
+/// Default port when one isn't specified
+// This is Heroku's default port when running locally
+pub const PORT: u16 = 5000;
+/// Prefix for addresses that are paths to Unix domain sockets
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+/// Default timeout (in seconds) for open connections to close during a graceful shutdown
+pub const SHUTDOWN_TIMEOUT: u64 = 30;
 /// Base URL for "dilbert.com"
 pub const SRC_BASE_URL: &str = "https://dilbert.com/";
 /// Base URL for Wayback Machine lookups
 pub const ARC_BASE_URL: &str = "https://web.archive.org/web/{}/https://dilbert.com";
 /// URL for archive.org CDX API
 // Docs: https://github.com/internetarchive/wayback/tree/master/wayback-cdx-server
+// The statuses of all captures are fetched, since captures of redirects mark missing comics.
 pub const CDX_URL: &str =
-    "https://web.archive.org/cdx/search/cdx?url={}&fl=timestamp&filter=statuscode:^2&limit=-1&to=20230312";
+    "https://web.archive.org/cdx/search/cdx?url={}&fl=timestamp,statuscode&limit=-5&to=20230312";
+/// URL for the Memento TimeTravel API, which finds the capture closest to the last comic
+// Docs: https://timetravel.mementoweb.org/guide/api/
+pub const MEMENTO_URL: &str = "https://timetravel.mementoweb.org/api/json/20230312/{}";
+/// Maximum number of archived snapshots of a comic's page that are tried, in order of preference
+pub const CDX_MAX_SNAPSHOTS: usize = 5;
 /// URL path prefix for each comic on "dilbert.com"
 pub const SRC_COMIC_PREFIX: &str = "strip/";
 /// Link to the public version of this app
 // Used in the OpenGraph tags
 pub const APP_URL: &str = "https://dilbert-viewer.herokuapp.com/";
+/// Author of the comics, as given in embeds
+pub const COMIC_AUTHOR: &str = "Scott Adams";
 /// Link to the public version of this repo
 // Mainly for publicity :P
 pub const REPO_URL: &str = "https://github.com/rharish101/dilbert-viewer";
 /// URL path for static files
-// This is set to root as it's easy to serve robots.txt by keeping it in static.
+// This is set to root to serve files like "/styles.css" and "/script.js" directly.
 pub const STATIC_URL: &str = "/";
 /// Location of static files
 pub const STATIC_DIR: &str = "static/";
-/// Content security policy
+/// Name of the app's stylesheet inside the directory of static files
+pub const CSS_FILE: &str = "styles.css";
+/// Name of the vendored Bootstrap stylesheet inside the directory of static files
+pub const VENDOR_CSS_FILE: &str = "bootstrap.min.css";
+/// URL from which the Bootstrap stylesheet is vendored
+// It's downloaded once and served by the app, so that the CSP needn't allow a third-party origin.
+pub const VENDOR_CSS_URL: &str =
+    "https://cdn.jsdelivr.net/npm/bootstrap@5.2.2/dist/css/bootstrap.min.css";
+/// Subresource integrity hash of the vendored Bootstrap stylesheet
+pub const VENDOR_CSS_INTEGRITY: &str =
+    "sha384-Zenh87qX5JnK2Jl0vWa8Ck2rdkQ2Bzep5IDxbcnCeuOxjzrPF/et3URy9Bv1WTRi";
+/// Time (in seconds) for which clients can cache CSS files without revalidating them
+// Their URLs don't change when they do, so keep this short.
+pub const CSS_MAX_AGE: u32 = 60 * 60;
+/// Time (in seconds) for which clients can cache other static files without revalidating them
+// Like CSS files, their URLs don't change when they do.
+pub const STATIC_MAX_AGE: u32 = 60 * 60;
+/// Quality of the Brotli compression of static files
+// This is the maximum, since static files are compressed only once at startup.
+pub const BROTLI_QUALITY: i32 = 11;
+/// Number of hex digits of the content hashes in the URLs of fingerprinted static files
+pub const ASSET_HASH_LEN: usize = 10;
+/// Time (in seconds) for which clients can cache fingerprinted static files
+// Their URLs change when they do, so they're never revalidated.
+pub const ASSET_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+/// Time (in seconds) for which clients can cache the page of the latest comic
+// This changes daily, so keep this short.
+pub const LATEST_MAX_AGE: u32 = 5 * 60;
+/// Time (in seconds) for which clients can cache the images of dated comics
+pub const COMIC_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+/// Time (in seconds) for which clients can cache the pages and text of dated comics
+// These change with the app (e.g. its templates) and with refreshed data, so keep this short.
+pub const COMIC_PAGE_MAX_AGE: u32 = 60 * 60;
+/// Name of the app, as shown when installed
+pub const APP_NAME: &str = "Dilbert Viewer";
+/// Short name of the app, as shown when installed where space is limited
+pub const APP_SHORT_NAME: &str = "Dilbert";
+/// Colour of the app's UI when installed, which matches the footer
+pub const APP_THEME_COLOUR: &str = "#212529";
+/// URL path to the app's icon, which is in the directory of static files
+pub const APP_ICON_URL: &str = "/icon.svg";
+/// Time (in seconds) for which clients can cache the web app manifest without revalidating it
+pub const MANIFEST_MAX_AGE: u32 = 24 * 60 * 60;
+/// Prefix of the file names of downloaded comic images, before their dates
+pub const DOWNLOAD_PREFIX: &str = "dilbert";
+/// MIME type of comic book archives (CBZ)
+pub const CBZ_CONTENT_TYPE: &str = "application/vnd.comicbook+zip";
+/// Date format of the months in the file names of comic book archives
+pub const CBZ_MONTH_FMT: &str = "%Y-%m";
+/// Maximum number of comics in each response of the batch API
+pub const API_MAX_COMICS: usize = 31;
+/// Maximum number of comics retrieved concurrently for each request to the batch API
+// Scraping is already throttled, but this also limits the load on the cache.
+pub const API_CONCURRENCY: usize = 8;
+/// Time (in seconds) for which clients can cache the responses to CORS preflight requests
+pub const CORS_MAX_AGE: usize = 60 * 60;
+/// Name of the cookie that stores the colour theme chosen by the client
+pub const THEME_COOKIE: &str = "theme";
+/// Time (in seconds) for which the chosen colour theme is remembered
+pub const THEME_COOKIE_MAX_AGE: i64 = 365 * 24 * 60 * 60;
+/// Name of the cookie that stores the favorite comics of the client
+pub const FAVORITES_COOKIE: &str = "favorites";
+/// Time (in seconds) for which the favorite comics are remembered
+pub const FAVORITES_COOKIE_MAX_AGE: i64 = 365 * 24 * 60 * 60;
+/// Maximum number of favorite comics that are remembered
+// Each comic takes 9 bytes in the cookie, which must fit in the 4KiB that browsers allow.
+pub const MAX_FAVORITES: usize = 300;
+/// Name of the cookie that stores the comics recently viewed by the client
+pub const HISTORY_COOKIE: &str = "history";
+/// Time (in seconds) for which the recently viewed comics are remembered
+pub const HISTORY_COOKIE_MAX_AGE: i64 = 30 * 24 * 60 * 60;
+/// Number of recently viewed comics that are remembered
+pub const HISTORY_LEN: usize = 20;
+/// Minimum length (in bytes) of the secret from which the key for signing cookies is derived
+pub const COOKIE_SECRET_MIN_LEN: usize = 32;
+/// Default content security policy
+// The "{}" is replaced by a per-response nonce, which allows only the inline content with that nonce.
+// Comic images are proxied through the app, and only the favicon is loaded from the Wayback Machine.
+// The service worker fetches pages from the app, so it needs "connect-src".
 pub const CSP: &str = "\
     default-src 'none';\
-    img-src assets.amuniversal.com dilbert.com web.archive.org;\
-    style-src 'self' cdn.jsdelivr.net;\
-    script-src 'self';\
+    img-src 'self' web.archive.org;\
+    style-src 'self' 'nonce-{}';\
+    script-src 'self' 'nonce-{}';\
+    connect-src 'self';\
+    manifest-src 'self';\
+    worker-src 'self';\
     frame-ancestors 'none'";
+/// Length of the nonce used in the content security policy
+pub const CSP_NONCE_LEN: usize = 32;
+/// Placeholder for the nonce in cached pages, which is replaced by each response's nonce
+// This has the same length and characters as a nonce, so that it's minified the same way.
+pub const NONCE_PLACEHOLDER: &str = "CSPNONCEPLACEHOLDERCSPNONCEPLACE";
+/// Default URL path prefixes of non-content pages that crawlers are disallowed from
+pub const ROBOTS_DISALLOW: [&str; 2] = ["/random", "/api/"];
 
 #[cfg(test)]
 mod tests {
@@ -110,16 +410,38 @@ mod tests {
         );
     }
 
+    #[test]
+    /// Test whether the app's stylesheet exists.
+    fn test_if_css_file_exists() {
+        assert!(
+            Path::new(STATIC_DIR).join(CSS_FILE).exists(),
+            "App's stylesheet doesn't exist"
+        );
+    }
+
+    #[test]
+    /// Test whether the app's icon exists.
+    fn test_if_app_icon_exists() {
+        assert!(
+            Path::new(STATIC_DIR)
+                .join(APP_ICON_URL.trim_start_matches('/'))
+                .exists(),
+            "App's icon doesn't exist"
+        );
+    }
+
     #[test]
     /// Test whether the content security policy (CSP) is a valid header value.
     ///
     /// Note that this doesn't check if the CSP follows the CSP format.
     fn test_content_security_policy_header_format() {
+        let policy_str = CSP.replace("{}", &"a".repeat(CSP_NONCE_LEN));
+
         // This panics if the *header* format is invalid (not CSP format).
-        DefaultHeaders::new().add(("Content-Security-Policy", CSP));
+        DefaultHeaders::new().add(("Content-Security-Policy", policy_str.as_str()));
 
         let policy = csp::Policy::parse(
-            CSP,
+            &policy_str,
             csp::PolicySource::Header,
             csp::PolicyDisposition::Enforce,
         );
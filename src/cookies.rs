@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Cookies that store lists of comics for clients, signed so that they can't be tampered with
+use actix_web::{
+    cookie::{time::Duration, Cookie, CookieJar, Key, SameSite},
+    web, HttpRequest,
+};
+use chrono::NaiveDate;
+use tracing::{debug, warn};
+
+use crate::constants::{COMPACT_DATE_FMT, COOKIE_SECRET_MIN_LEN};
+use crate::datetime::str_to_date;
+use crate::errors::CookieKeyError;
+
+/// Separator between the dates in the value of a cookie, which is allowed unquoted in cookies
+const DATE_SEP: char = '.';
+
+/// Key with which cookies are signed, which must be shared by all workers
+#[derive(Clone)]
+pub struct CookieKey(Key);
+
+impl CookieKey {
+    /// Derive the key from a secret, or generate a random key if no secret is given.
+    ///
+    /// Random keys change whenever the app restarts, which invalidates all signed cookies.
+    ///
+    /// # Arguments
+    /// * `secret` - The secret from which to derive the key, if any
+    pub fn new(secret: Option<&str>) -> Result<Self, CookieKeyError> {
+        match secret {
+            Some(secret) if secret.len() < COOKIE_SECRET_MIN_LEN => {
+                Err(CookieKeyError::TooShort(COOKIE_SECRET_MIN_LEN))
+            }
+            Some(secret) => Ok(Self(Key::derive_from(secret.as_bytes()))),
+            None => {
+                warn!("No cookie secret given, so cookies will be invalidated on restart");
+                Ok(Self(Key::generate()))
+            }
+        }
+    }
+}
+
+/// Encode dates into the value of a cookie.
+///
+/// # Arguments
+/// * `dates` - The dates, in the order to store them
+fn encode_dates(dates: &[NaiveDate]) -> String {
+    dates
+        .iter()
+        .map(|date| date.format(COMPACT_DATE_FMT).to_string())
+        .collect::<Vec<_>>()
+        .join(&DATE_SEP.to_string())
+}
+
+/// Decode dates from the value of a cookie, skipping invalid ones.
+///
+/// # Arguments
+/// * `value` - The value of the cookie
+fn decode_dates(value: &str) -> Vec<NaiveDate> {
+    value
+        .split(DATE_SEP)
+        .filter_map(|date| str_to_date(date, COMPACT_DATE_FMT).ok())
+        .collect()
+}
+
+/// Get the dates stored in a signed cookie.
+///
+/// If the cookie is missing, or its signature is invalid (such as when it's been tampered with),
+/// then no dates are returned.
+///
+/// # Arguments
+/// * `req` - The request with the cookie
+/// * `name` - The name of the cookie
+pub fn get_signed_dates(req: &HttpRequest, name: &str) -> Vec<NaiveDate> {
+    let (Some(key), Some(cookie)) = (req.app_data::<web::Data<CookieKey>>(), req.cookie(name))
+    else {
+        return Vec::new();
+    };
+
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    match jar.signed(&key.0).get(name) {
+        Some(cookie) => decode_dates(cookie.value()),
+        None => {
+            debug!("Ignoring the {name} cookie with an invalid signature");
+            Vec::new()
+        }
+    }
+}
+
+/// Get a signed cookie that stores dates.
+///
+/// # Arguments
+/// * `key` - The key with which to sign the cookie
+/// * `name` - The name of the cookie
+/// * `dates` - The dates, in the order to store them
+/// * `max_age` - The time (in seconds) for which the cookie is stored
+pub fn signed_dates_cookie(
+    key: &CookieKey,
+    name: &'static str,
+    dates: &[NaiveDate],
+    max_age: i64,
+) -> Cookie<'static> {
+    let cookie = Cookie::build(name, encode_dates(dates))
+        .path("/")
+        .max_age(Duration::seconds(max_age))
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .finish();
+
+    let mut jar = CookieJar::new();
+    jar.signed_mut(&key.0).add(cookie);
+    // The signed cookie has just been added, so it always exists.
+    jar.get(name)
+        .cloned()
+        .unwrap_or_else(|| Cookie::named(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+    use test_case::test_case;
+
+    /// Secret from which to derive the keys for tests
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    /// Test whether dates can be read back from their signed cookie, in order.
+    fn test_signed_dates() {
+        let key = CookieKey::new(Some(SECRET)).expect("Couldn't derive key");
+        let dates = [
+            NaiveDate::from_ymd_opt(2000, 1, 2).unwrap(),
+            NaiveDate::from_ymd_opt(2000, 1, 1).unwrap(),
+        ];
+        let cookie = signed_dates_cookie(&key, "dates", &dates, 60);
+
+        let req = TestRequest::default()
+            .cookie(cookie)
+            .app_data(web::Data::new(key))
+            .to_http_request();
+        assert_eq!(get_signed_dates(&req, "dates"), dates, "Wrong dates");
+    }
+
+    #[test_case(false; "tampered")]
+    #[test_case(true; "other key")]
+    /// Test whether cookies with invalid signatures are ignored.
+    ///
+    /// # Arguments
+    /// * `other_key` - Whether the cookie is signed with another key, instead of being tampered
+    fn test_invalid_signature(other_key: bool) {
+        let key = CookieKey::new(Some(SECRET)).expect("Couldn't derive key");
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut cookie = signed_dates_cookie(&key, "dates", &[date], 60);
+
+        let key = if other_key {
+            CookieKey::new(None).expect("Couldn't generate key")
+        } else {
+            let value = cookie.value().replace("20000101", "20000102");
+            cookie.set_value(value);
+            key
+        };
+        let req = TestRequest::default()
+            .cookie(cookie)
+            .app_data(web::Data::new(key))
+            .to_http_request();
+        assert!(
+            get_signed_dates(&req, "dates").is_empty(),
+            "Invalid signature accepted"
+        );
+    }
+
+    #[test]
+    /// Test whether short secrets are rejected.
+    fn test_short_secret() {
+        assert!(
+            CookieKey::new(Some("secret")).is_err(),
+            "Short secret accepted"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Per-response content security policy (CSP) with nonces for inline content
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{HeaderValue, CONTENT_SECURITY_POLICY},
+        StatusCode,
+    },
+    middleware::Next,
+    web, Error, HttpMessage,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use tracing::error;
+
+use crate::constants::CSP_NONCE_LEN;
+
+/// The CSP nonce for a single request-response
+///
+/// This is stored in the request's extensions, so that handlers can inject it into inline tags.
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    /// Generate a new random nonce.
+    fn generate() -> Self {
+        let nonce = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(CSP_NONCE_LEN)
+            .map(char::from)
+            .collect();
+        Self(nonce)
+    }
+}
+
+/// The content security policy for all responses
+///
+/// Each "{}" in it is a placeholder for the per-response nonce.
+pub struct CspTemplate(pub String);
+
+impl CspTemplate {
+    /// Get the content security policy that allows inline content with the given nonce.
+    pub fn policy(&self, nonce: &CspNonce) -> String {
+        self.0.replace("{}", &nonce.0)
+    }
+}
+
+/// Middleware to add a content security policy with a fresh nonce to every response.
+pub async fn csp_middleware(
+    template: web::Data<CspTemplate>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let nonce = CspNonce::generate();
+    req.extensions_mut().insert(nonce.clone());
+
+    let mut resp = next.call(req).await?;
+    // Clients update their cached copy's headers with those of a 304, so a fresh nonce in it
+    // wouldn't match the nonce in the cached page.
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(resp);
+    }
+    match HeaderValue::from_str(&template.policy(&nonce)) {
+        Ok(policy) => {
+            resp.headers_mut().insert(CONTENT_SECURITY_POLICY, policy);
+        }
+        Err(err) => error!("Couldn't create CSP header: {err}"),
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constants::CSP;
+
+    #[test]
+    /// Test whether each generated nonce is put into the policy and is unique.
+    fn test_nonce_in_policy() {
+        let nonce = CspNonce::generate();
+        let other = CspNonce::generate();
+        assert_eq!(nonce.0.len(), CSP_NONCE_LEN, "Nonce has the wrong length");
+        assert_ne!(nonce.0, other.0, "Nonces are not unique");
+
+        let policy = CspTemplate(CSP.into()).policy(&nonce);
+        assert!(
+            policy.contains(&format!("'nonce-{}'", nonce.0)),
+            "Nonce not in the policy"
+        );
+        assert!(!policy.contains("{}"), "Policy has unfilled placeholders");
+    }
+}
@@ -3,7 +3,16 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 //! Datetime utilities for the viewer app
-use chrono::{format::ParseResult, NaiveDate};
+use std::time::SystemTime;
+
+use chrono::{format::ParseResult, DateTime, Days, NaiveDate, Utc};
+
+use crate::constants::{FIRST_COMIC, LAST_COMIC, SRC_DATE_FMT};
+
+/// Get the current date in UTC.
+pub fn curr_date() -> NaiveDate {
+    DateTime::<Utc>::from(SystemTime::now()).date_naive()
+}
 
 /// Convert the date string (assumed in UTC) to a `chrono::NaiveDate` struct.
 ///
@@ -14,6 +23,32 @@ pub fn str_to_date(date: &str, fmt: &str) -> ParseResult<NaiveDate> {
     NaiveDate::parse_from_str(date, fmt)
 }
 
+/// Get the sequence number of a comic, where the first comic is number 1.
+///
+/// Comics were published daily, so this is the number of days since the first comic, plus one.
+/// If the date is before the first comic, then None is returned.
+///
+/// # Arguments
+/// * `date` - The date of the comic
+pub fn date_to_number(date: &NaiveDate) -> Option<u32> {
+    let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).ok()?;
+    let days = date.signed_duration_since(first_comic).num_days();
+    u32::try_from(days).ok()?.checked_add(1)
+}
+
+/// Get the date of the comic with the given sequence number, where the first comic is number 1.
+///
+/// If there's no comic with that number, then None is returned.
+///
+/// # Arguments
+/// * `number` - The sequence number of the comic
+pub fn number_to_date(number: u32) -> Option<NaiveDate> {
+    let first_comic = str_to_date(FIRST_COMIC, SRC_DATE_FMT).ok()?;
+    let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).ok()?;
+    let date = first_comic.checked_add_days(Days::new(number.checked_sub(1)?.into()))?;
+    (date <= last_comic).then_some(date)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -22,6 +57,8 @@ mod tests {
 
     #[test_case("2000-01-01", "%Y-%m-%d", 2000, 1, 1; "yyyy-mm-dd valid")]
     #[test_case("2000-01-00", "%Y-%m-%d", 2000, 1, 0; "yyyy-mm-dd invalid")]
+    #[test_case("20000101", "%Y%m%d", 2000, 1, 1; "yyyymmdd valid")]
+    #[test_case("20000132", "%Y%m%d", 0, 0, 0; "yyyymmdd invalid")]
     #[test_case("Saturday January 01, 2000", "%A %B %d, %Y", 2000, 1, 1; "day MM dd, yyyy valid")]
     #[test_case("Sunday January 01, 2000", "%A %B %d, %Y", 0, 0, 0; "day MM dd, yyyy invalid")]
     /// Test the string to date converter.
@@ -37,4 +74,34 @@ mod tests {
         let expected = NaiveDate::from_ymd_opt(year, month, day);
         assert_eq!(result, expected);
     }
+
+    #[test_case(1, Some("1989-04-16"); "first comic")]
+    #[test_case(4000, Some("2000-03-28"); "middle comic")]
+    #[test_case(12384, Some("2023-03-12"); "last comic")]
+    #[test_case(0, None; "zero")]
+    #[test_case(12385, None; "after last comic")]
+    #[test_case(u32::MAX, None; "too large")]
+    /// Test the conversion between comic sequence numbers and dates.
+    ///
+    /// # Arguments
+    /// * `number` - The sequence number of the comic
+    /// * `date` - The expected date of the comic, if it exists
+    fn test_comic_number(number: u32, date: Option<&str>) {
+        let date = date.map(|date| str_to_date(date, SRC_DATE_FMT).unwrap());
+        assert_eq!(number_to_date(number), date, "Wrong date for number");
+        if let Some(date) = date {
+            assert_eq!(date_to_number(&date), Some(number), "Wrong number for date");
+        }
+    }
+
+    #[test]
+    /// Test whether dates before the first comic have no sequence number.
+    fn test_number_before_first() {
+        let date = NaiveDate::from_ymd_opt(1989, 4, 15).unwrap();
+        assert_eq!(
+            date_to_number(&date),
+            None,
+            "Date before first has a number"
+        );
+    }
 }
@@ -3,59 +3,224 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 //! Utilities for working with the database
+use std::path::Path;
 use std::time::Duration;
 
-use async_trait::async_trait;
-use deadpool_redis::{Config as RedisConfig, Connection, Pool, PoolError, Runtime};
-use redis::{aio::ConnectionLike, AsyncCommands, RedisResult};
+use deadpool_redis::{
+    cluster::{Config as ClusterConfig, Connection as ClusterConnection, Pool as ClusterPool},
+    Config as RedisConfig, ConfigError, Connection, Manager, Pool, PoolError, Runtime,
+};
+use redis::{
+    aio::ConnectionLike, AsyncCommands, Client, ClientTlsConfig, Cmd, Pipeline, RedisFuture,
+    TlsCertificates, Value,
+};
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::{instrument, warn};
 
-use crate::constants::{DB_TIMEOUT, MAX_DB_CONN};
-use crate::errors::DbInitError;
+use crate::constants::{DELETE_IF_EQ_SCRIPT, MAX_DB_CONN, MSGPACK_MARKER};
+use crate::errors::{AppResult, DbInitError};
+use crate::memory_db::MemoryPool;
 
-/// Trait to get and set Redis key-values with automatic serde (de)serialization using JSON.
-// `redis::RedisFuture` is basically a future returned by `async_trait`, so using the latter is
-// basically free convenience.
-#[async_trait]
-pub trait SerdeAsyncCommands: AsyncCommands {
-    /// Get a possibly-null value given a key.
+/// Trait for caches of key-values with automatic serde (de)serialization
+pub trait CacheBackend {
+    /// Get a possibly-missing value given a key.
+    async fn get<K, V>(&self, key: &K) -> AppResult<Option<V>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned;
+
+    /// Get possibly-missing values for many keys at once.
+    ///
+    /// This needs only one round trip to the cache, unlike getting each value separately.
     ///
-    /// The null value indicates a missing key in the DB.
-    async fn get<K, RV: DeserializeOwned>(&mut self, key: K) -> RedisResult<Option<RV>>
+    /// # Arguments
+    /// * `keys` - The keys to get
+    ///
+    /// # Returns
+    /// The values, in the same order as the keys
+    async fn get_many<K, V>(&self, keys: &[K]) -> AppResult<Vec<Option<V>>>
     where
-        K: Serialize + Send + Sync,
-    {
-        let data: Option<Vec<u8>> = AsyncCommands::get(self, serde_json::to_vec(&key)?).await?;
-        Ok(if let Some(data) = data {
-            Some(serde_json::from_slice(data.as_slice())?)
-        } else {
-            None
-        })
-    }
+        K: Serialize + Sync,
+        V: DeserializeOwned;
 
-    /// Set a value for a given key.
-    async fn set<K, V>(&mut self, key: K, value: V) -> RedisResult<()>
+    /// Set a value for a given key, which expires after the given time.
+    ///
+    /// # Arguments
+    /// * `key` - The key to set
+    /// * `value` - The value for the key
+    /// * `seconds` - The time (in seconds) after which the key expires
+    async fn set<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<()>
     where
-        K: Serialize + Send + Sync,
-        V: Serialize + Send + Sync,
-    {
-        AsyncCommands::set::<_, _, ()>(
-            self,
-            serde_json::to_vec(&key)?,
-            serde_json::to_vec(&value)?,
-        )
-        .await?;
-        Ok(())
+        K: Serialize + Sync,
+        V: Serialize + Sync;
+
+    /// Set a value for a given key only if the key doesn't exist, which expires after the given
+    /// time.
+    ///
+    /// This is atomic, so that it can be used as a lock shared by all users of the cache.
+    ///
+    /// # Arguments
+    /// * `key` - The key to set
+    /// * `value` - The value for the key
+    /// * `seconds` - The time (in seconds) after which the key expires
+    ///
+    /// # Returns
+    /// Whether the value was set
+    async fn set_nx<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync;
+
+    /// Delete the value for a given key, if it exists.
+    async fn delete<K>(&self, key: &K) -> AppResult<()>
+    where
+        K: Serialize + Sync;
+
+    /// Delete the value for a given key, only if it equals the given value.
+    ///
+    /// This is atomic, so that a lock set with [`Self::set_nx`] is only released by its holder.
+    ///
+    /// # Arguments
+    /// * `key` - The key to delete
+    /// * `value` - The value that the key must have
+    ///
+    /// # Returns
+    /// Whether the value was deleted
+    async fn delete_if_eq<K, V>(&self, key: &K, value: &V) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync;
+
+    /// Add a member to the set for a given key, and reset the time after which the set expires.
+    ///
+    /// This is atomic, so that concurrent additions to the same set aren't lost.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the set
+    /// * `member` - The member to add
+    /// * `seconds` - The time (in seconds) after which the set expires
+    async fn add_to_set<K, M>(&self, key: &K, member: &M, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync;
+
+    /// Remove a member from the set for a given key, if it's in the set.
+    ///
+    /// This is atomic, so that concurrent changes to the same set aren't lost.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the set
+    /// * `member` - The member to remove
+    async fn remove_from_set<K, M>(&self, key: &K, member: &M) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync;
+
+    /// Get all members of the set for a given key, in no particular order.
+    ///
+    /// If the set doesn't exist, then it's treated as empty. Members that can't be deserialized
+    /// are skipped.
+    async fn get_set<K, M>(&self, key: &K) -> AppResult<Vec<M>>
+    where
+        K: Serialize + Sync,
+        M: DeserializeOwned;
+
+    /// Delete all values in the cache.
+    async fn clear(&self) -> AppResult<()>;
+
+    /// Check whether the cache is reachable.
+    async fn ping(&self) -> AppResult<()>;
+}
+
+/// Serialize a value for a cache.
+///
+/// Values serialized to MessagePack are prefixed with [`MSGPACK_MARKER`], so that they can be told
+/// apart from JSON values.
+///
+/// # Arguments
+/// * `value` - The value to serialize
+/// * `msgpack` - Whether to serialize to MessagePack instead of JSON
+pub(crate) fn serialize_value<V: Serialize>(value: &V, msgpack: bool) -> AppResult<Vec<u8>> {
+    if msgpack {
+        let mut data = vec![MSGPACK_MARKER];
+        rmp_serde::encode::write_named(&mut data, value)?;
+        Ok(data)
+    } else {
+        Ok(serde_json::to_vec(value)?)
     }
 }
 
-// Auto-implement it where possible.
-impl<T> SerdeAsyncCommands for T where T: AsyncCommands {}
+/// Deserialize the members of a set from a cache, skipping those that can't be deserialized.
+///
+/// Members are always serialized to JSON, so that equal members are stored identically.
+///
+/// # Arguments
+/// * `members` - The serialized members
+pub(crate) fn deserialize_members<M, D>(members: impl IntoIterator<Item = D>) -> Vec<M>
+where
+    M: DeserializeOwned,
+    D: AsRef<[u8]>,
+{
+    members
+        .into_iter()
+        .filter_map(|data| match serde_json::from_slice(data.as_ref()) {
+            Ok(member) => Some(member),
+            Err(err) => {
+                warn!("Skipping set member that couldn't be deserialized: {err}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Deserialize a possibly-missing value from a cache.
+///
+/// Both JSON and MessagePack values are supported, irrespective of the format used for new values.
+/// Values that can't be deserialized (e.g. if they were cached by an older version of the app)
+/// are treated as missing, and are deleted from the cache.
+///
+/// # Arguments
+/// * `cache` - The cache from which the value was retrieved
+/// * `key` - The key of the value
+/// * `data` - The serialized value, if it exists
+pub(crate) async fn deserialize_value<C, K, V>(
+    cache: &C,
+    key: &K,
+    data: Option<Vec<u8>>,
+) -> AppResult<Option<V>>
+where
+    C: CacheBackend,
+    K: Serialize + Sync,
+    V: DeserializeOwned,
+{
+    let Some(data) = data else {
+        return Ok(None);
+    };
+    let result = match data.split_first() {
+        Some((&MSGPACK_MARKER, msgpack_data)) => {
+            rmp_serde::from_slice(msgpack_data).map_err(|err| err.to_string())
+        }
+        _ => serde_json::from_slice(data.as_slice()).map_err(|err| err.to_string()),
+    };
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => {
+            warn!("Deleting cached value that couldn't be deserialized: {err}");
+            cache.delete(key).await?;
+            Ok(None)
+        }
+    }
+}
 
 /// Convenient trait for possibly-mocked Redis connection pools.
 pub trait RedisPool {
-    type ConnType: ConnectionLike + SerdeAsyncCommands;
+    type ConnType: ConnectionLike + AsyncCommands;
     async fn get(&self) -> Result<Self::ConnType, PoolError>;
+
+    /// Check whether new values are serialized to MessagePack instead of JSON.
+    fn uses_msgpack(&self) -> bool {
+        false
+    }
 }
 
 // Implement it for `deadpool-redis`.
@@ -66,20 +231,480 @@ impl RedisPool for Pool {
     }
 }
 
+/// Pool of connections to Redis, which is either a single server or a cluster
+#[derive(Clone)]
+pub struct DbPool {
+    /// The underlying pool of connections
+    conns: DbConns,
+    /// Whether new values are serialized to MessagePack instead of JSON
+    msgpack: bool,
+}
+
+/// Underlying pool of connections to Redis
+#[derive(Clone)]
+enum DbConns {
+    /// Pool of connections to a single Redis server
+    Single(Pool),
+    /// Pool of connections to a Redis cluster
+    Cluster(ClusterPool),
+}
+
+impl DbPool {
+    /// Set whether new values are serialized to MessagePack instead of JSON.
+    ///
+    /// This is more compact and faster than JSON. Existing values are read in either format.
+    pub fn with_msgpack(mut self, msgpack: bool) -> Self {
+        self.msgpack = msgpack;
+        self
+    }
+}
+
+impl From<DbConns> for DbPool {
+    fn from(conns: DbConns) -> Self {
+        Self {
+            conns,
+            msgpack: false,
+        }
+    }
+}
+
+/// Connection to Redis, which is either a single server or a cluster
+pub enum DbConnection {
+    /// Connection to a single Redis server
+    Single(Connection),
+    /// Connection to a Redis cluster, which routes each command to the right nodes
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for DbConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Self::Single(conn) => conn.req_packed_command(cmd),
+            Self::Cluster(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Self::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            Self::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Single(conn) => conn.get_db(),
+            Self::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
+
+impl RedisPool for DbPool {
+    type ConnType = DbConnection;
+    async fn get(&self) -> Result<Self::ConnType, PoolError> {
+        match &self.conns {
+            DbConns::Single(pool) => pool.get().await.map(DbConnection::Single),
+            DbConns::Cluster(pool) => pool.get().await.map(DbConnection::Cluster),
+        }
+    }
+
+    fn uses_msgpack(&self) -> bool {
+        self.msgpack
+    }
+}
+
+// Every Redis connection pool can be used as a cache.
+impl<T: RedisPool> CacheBackend for T {
+    #[instrument(name = "redis_get", skip_all)]
+    async fn get<K, V>(&self, key: &K) -> AppResult<Option<V>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        let data: Option<Vec<u8>> = conn.get(serde_json::to_vec(key)?).await?;
+        deserialize_value(self, key, data).await
+    }
+
+    #[instrument(name = "redis_get_many", skip_all)]
+    async fn get_many<K, V>(&self, keys: &[K]) -> AppResult<Vec<Option<V>>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        // Redis rejects MGET without keys.
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let serialized_keys = keys
+            .iter()
+            .map(serde_json::to_vec)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut conn = RedisPool::get(self).await?;
+        let data: Vec<Option<Vec<u8>>> = conn.mget(serialized_keys).await?;
+
+        let mut values = Vec::with_capacity(keys.len());
+        for (key, data) in keys.iter().zip(data) {
+            values.push(deserialize_value(self, key, data).await?);
+        }
+        Ok(values)
+    }
+
+    #[instrument(name = "redis_set", skip_all)]
+    async fn set<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        conn.set_ex::<_, _, ()>(
+            serde_json::to_vec(key)?,
+            serialize_value(value, self.uses_msgpack())?,
+            seconds,
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "redis_set_nx", skip_all)]
+    async fn set_nx<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        // The reply is nil if the key already exists.
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(serde_json::to_vec(key)?)
+            .arg(serialize_value(value, self.uses_msgpack())?)
+            .arg("NX")
+            .arg("EX")
+            .arg(seconds)
+            .query_async(&mut conn)
+            .await?;
+        Ok(reply.is_some())
+    }
+
+    #[instrument(name = "redis_delete", skip_all)]
+    async fn delete<K>(&self, key: &K) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        conn.del::<_, ()>(serde_json::to_vec(key)?).await?;
+        Ok(())
+    }
+
+    #[instrument(name = "redis_delete_if_eq", skip_all)]
+    async fn delete_if_eq<K, V>(&self, key: &K, value: &V) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        // The reply is the number of deleted keys.
+        let deleted: u64 = redis::cmd("EVAL")
+            .arg(DELETE_IF_EQ_SCRIPT)
+            .arg(1)
+            .arg(serde_json::to_vec(key)?)
+            .arg(serialize_value(value, self.uses_msgpack())?)
+            .query_async(&mut conn)
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    #[instrument(name = "redis_add_to_set", skip_all)]
+    async fn add_to_set<K, M>(&self, key: &K, member: &M, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let mut conn = RedisPool::get(self).await?;
+        conn.sadd::<_, _, ()>(&key, serde_json::to_vec(member)?)
+            .await?;
+        // Each command is atomic on its own, and the expiry is the same for concurrent additions.
+        conn.expire::<_, ()>(&key, seconds.try_into().unwrap_or(i64::MAX))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "redis_remove_from_set", skip_all)]
+    async fn remove_from_set<K, M>(&self, key: &K, member: &M) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        conn.srem::<_, _, ()>(serde_json::to_vec(key)?, serde_json::to_vec(member)?)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(name = "redis_get_set", skip_all)]
+    async fn get_set<K, M>(&self, key: &K) -> AppResult<Vec<M>>
+    where
+        K: Serialize + Sync,
+        M: DeserializeOwned,
+    {
+        let mut conn = RedisPool::get(self).await?;
+        let members: Vec<Vec<u8>> = conn.smembers(serde_json::to_vec(key)?).await?;
+        Ok(deserialize_members(members))
+    }
+
+    #[instrument(name = "redis_clear", skip_all)]
+    async fn clear(&self) -> AppResult<()> {
+        let mut conn = RedisPool::get(self).await?;
+        redis::cmd("FLUSHDB").query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    #[instrument(name = "redis_ping", skip_all)]
+    async fn ping(&self) -> AppResult<()> {
+        let mut conn = RedisPool::get(self).await?;
+        redis::cmd("PING").query_async::<String>(&mut conn).await?;
+        Ok(())
+    }
+}
+
+/// Cache that is either Redis or an in-memory fallback
+#[derive(Clone)]
+pub enum CachePool {
+    /// Pool of connections to Redis
+    Redis(DbPool),
+    /// In-memory cache
+    Memory(MemoryPool),
+}
+
+impl CacheBackend for CachePool {
+    async fn get<K, V>(&self, key: &K) -> AppResult<Option<V>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        match self {
+            Self::Redis(pool) => CacheBackend::get(pool, key).await,
+            Self::Memory(pool) => pool.get(key).await,
+        }
+    }
+
+    async fn get_many<K, V>(&self, keys: &[K]) -> AppResult<Vec<Option<V>>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        match self {
+            Self::Redis(pool) => pool.get_many(keys).await,
+            Self::Memory(pool) => pool.get_many(keys).await,
+        }
+    }
+
+    async fn set<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.set(key, value, seconds).await,
+            Self::Memory(pool) => pool.set(key, value, seconds).await,
+        }
+    }
+
+    async fn set_nx<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.set_nx(key, value, seconds).await,
+            Self::Memory(pool) => pool.set_nx(key, value, seconds).await,
+        }
+    }
+
+    async fn delete<K>(&self, key: &K) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.delete(key).await,
+            Self::Memory(pool) => pool.delete(key).await,
+        }
+    }
+
+    async fn delete_if_eq<K, V>(&self, key: &K, value: &V) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.delete_if_eq(key, value).await,
+            Self::Memory(pool) => pool.delete_if_eq(key, value).await,
+        }
+    }
+
+    async fn add_to_set<K, M>(&self, key: &K, member: &M, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.add_to_set(key, member, seconds).await,
+            Self::Memory(pool) => pool.add_to_set(key, member, seconds).await,
+        }
+    }
+
+    async fn remove_from_set<K, M>(&self, key: &K, member: &M) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        match self {
+            Self::Redis(pool) => pool.remove_from_set(key, member).await,
+            Self::Memory(pool) => pool.remove_from_set(key, member).await,
+        }
+    }
+
+    async fn get_set<K, M>(&self, key: &K) -> AppResult<Vec<M>>
+    where
+        K: Serialize + Sync,
+        M: DeserializeOwned,
+    {
+        match self {
+            Self::Redis(pool) => pool.get_set(key).await,
+            Self::Memory(pool) => pool.get_set(key).await,
+        }
+    }
+
+    async fn clear(&self) -> AppResult<()> {
+        match self {
+            Self::Redis(pool) => pool.clear().await,
+            Self::Memory(pool) => pool.clear().await,
+        }
+    }
+
+    async fn ping(&self) -> AppResult<()> {
+        match self {
+            Self::Redis(pool) => pool.ping().await,
+            Self::Memory(pool) => pool.ping().await,
+        }
+    }
+}
+
+/// Settings for TLS connections to the database
+#[derive(Clone, Default)]
+pub struct DbTls {
+    /// Whether to verify the database's certificate
+    pub verify: bool,
+    /// The CA certificate and the client certificate and key, if either is used instead of the
+    /// system's trusted roots and no client certificate respectively
+    pub certs: Option<TlsCertificates>,
+}
+
+impl DbTls {
+    /// Load the TLS settings, with certificates and keys from PEM files.
+    ///
+    /// Verification is always enabled if any certificate is given.
+    ///
+    /// # Arguments
+    /// * `verify` - Whether to verify the database's certificate
+    /// * `ca_cert` - The path to the CA certificate with which to verify the database's
+    ///   certificate, instead of the system's trusted roots
+    /// * `client_cert` - The path to the client certificate for mutual TLS
+    /// * `client_key` - The path to the private key for the client certificate
+    pub fn load(
+        verify: bool,
+        ca_cert: Option<&Path>,
+        client_cert: Option<&Path>,
+        client_key: Option<&Path>,
+    ) -> Result<Self, DbInitError> {
+        let root_cert = ca_cert.map(std::fs::read).transpose()?;
+        let client_tls = match (client_cert, client_key) {
+            (Some(cert_path), Some(key_path)) => Some(ClientTlsConfig {
+                client_cert: std::fs::read(cert_path)?,
+                client_key: std::fs::read(key_path)?,
+            }),
+            (None, None) => None,
+            _ => return Err(DbInitError::MissingClientTls),
+        };
+
+        let certs = (root_cert.is_some() || client_tls.is_some()).then_some(TlsCertificates {
+            client_tls,
+            root_cert,
+        });
+        Ok(Self {
+            verify: verify || certs.is_some(),
+            certs,
+        })
+    }
+
+    /// Get the URL for connecting to the database with these settings.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to the database
+    fn apply(&self, url: String) -> String {
+        if self.verify {
+            url
+        } else {
+            // Heroku needs SSL for its Redis addon, but uses a self-signed certificate. So simply
+            // disable verification while keeping SSL.
+            url + "#insecure"
+        }
+    }
+}
+
 /// Initialize the database connection pool for caching data.
 ///
 /// # Arguments
 /// * `url` - The URL used to connect to the database
-pub fn get_db_pool(url: String) -> Result<deadpool_redis::Pool, DbInitError> {
-    // Heroku needs SSL for its Redis addon, but uses a self-signed certificate. So simply disable
-    // verification while keeping SSL.
-    let config = RedisConfig::from_url(url + "#insecure");
-    let pool_builder = config
+/// * `timeout` - The timeout for a single database operation
+/// * `tls` - The settings for TLS connections to the database
+pub fn get_db_pool(url: String, timeout: Duration, tls: &DbTls) -> Result<DbPool, DbInitError> {
+    let url = tls.apply(url);
+    let pool_builder = if let Some(certs) = &tls.certs {
+        // The pool's config can't hold certificates, so give them to its manager directly.
+        let client = Client::build_with_tls(url, certs.clone()).map_err(ConfigError::Redis)?;
+        let manager =
+            Manager::new(client.get_connection_info().clone()).map_err(ConfigError::Redis)?;
+        Pool::builder(manager)
+    } else {
+        RedisConfig::from_url(url).builder()?
+    };
+    let pool_builder = pool_builder
+        .runtime(Runtime::Tokio1)
+        .max_size(MAX_DB_CONN)
+        .wait_timeout(Some(timeout));
+    Ok(DbConns::Single(pool_builder.build()?).into())
+}
+
+/// Initialize the connection pool for caching data in a Redis cluster.
+///
+/// The rest of the cluster's nodes are discovered from the given seed nodes, so not all of them
+/// need to be given.
+///
+/// # Arguments
+/// * `urls` - The URLs used to connect to the seed nodes of the cluster
+/// * `timeout` - The timeout for a single database operation
+/// * `tls` - The settings for TLS connections to the cluster, which can't have certificates
+pub fn get_db_cluster_pool(
+    urls: Vec<String>,
+    timeout: Duration,
+    tls: &DbTls,
+) -> Result<DbPool, DbInitError> {
+    if tls.certs.is_some() {
+        return Err(DbInitError::ClusterCerts);
+    }
+    let urls: Vec<_> = urls.into_iter().map(|url| tls.apply(url)).collect();
+    let pool_builder = ClusterConfig::from_urls(urls)
         .builder()?
         .runtime(Runtime::Tokio1)
         .max_size(MAX_DB_CONN)
-        .wait_timeout(Some(Duration::from_secs(DB_TIMEOUT)));
-    Ok(pool_builder.build()?)
+        .wait_timeout(Some(timeout));
+    Ok(DbConns::Cluster(pool_builder.build()?).into())
 }
 
 #[cfg(test)]
@@ -108,3 +733,392 @@ pub mod mock {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::mock::MockPool;
+    use super::*;
+
+    use redis::{Cmd, Value};
+    use redis_test::{IntoRedisValue, MockCmd, MockRedisConnection};
+    use test_case::test_case;
+
+    use crate::scrapers::{ComicData, ComicImage};
+
+    /// Path to the test certificate
+    const CERT_PATH: &str = "testdata/tls/cert.pem";
+    /// Path to the test private key
+    const KEY_PATH: &str = "testdata/tls/key.pem";
+
+    /// Initialize a mock Redis pool that expects the given commands.
+    ///
+    /// # Arguments
+    /// * `commands` - The commands that are expected to be requested, along with their replies
+    async fn get_mock_pool(commands: Vec<MockCmd>) -> MockPool {
+        // Connections are taken out of the pool on use, so add one for each command. These share
+        // the queue of expected commands.
+        let db = MockPool::new(commands.len());
+        let conn = MockRedisConnection::new(commands);
+        for _ in 0..db.status().max_size {
+            if let Err((_, err)) = db.add(conn.clone()).await {
+                panic!("Couldn't add mock DB connection to mock DB pool: {err}");
+            };
+        }
+        db
+    }
+
+    #[actix_web::test]
+    /// Test whether the Redis backend sends the right commands to set, get and delete values.
+    async fn test_redis_commands() {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let value = serde_json::to_vec("value").expect("Couldn't serialize mock cache value");
+        let db = get_mock_pool(vec![
+            MockCmd::new(Cmd::set_ex(key.clone(), value.clone(), 60), Ok(Value::Okay)),
+            MockCmd::new(Cmd::get(key.clone()), Ok(value.into_redis_value())),
+            MockCmd::new(Cmd::del(key.clone()), Ok(Value::Int(1))),
+            MockCmd::new(Cmd::get(key), Ok(Value::Nil)),
+        ])
+        .await;
+
+        db.set(&"key", &"value", 60)
+            .await
+            .expect("Couldn't set value");
+        let value: Option<String> = CacheBackend::get(&db, &"key")
+            .await
+            .expect("Couldn't get value");
+        assert_eq!(value.as_deref(), Some("value"), "Got wrong value");
+
+        db.delete(&"key").await.expect("Couldn't delete value");
+        let deleted: Option<String> = CacheBackend::get(&db, &"key")
+            .await
+            .expect("Couldn't get value");
+        assert_eq!(deleted, None, "Got deleted value");
+    }
+
+    #[actix_web::test]
+    /// Test whether the Redis backend gets many values with one command.
+    async fn test_redis_get_many() {
+        let keys = ["key1", "key2"]
+            .map(|key| serde_json::to_vec(key).expect("Couldn't serialize mock cache key"));
+        let value = serde_json::to_vec("value").expect("Couldn't serialize mock cache value");
+        let db = get_mock_pool(vec![MockCmd::new(
+            redis::cmd("MGET").arg(&keys),
+            Ok(Value::Array(vec![value.into_redis_value(), Value::Nil])),
+        )])
+        .await;
+
+        let values: Vec<Option<String>> = db
+            .get_many(&["key1", "key2"])
+            .await
+            .expect("Couldn't get values");
+        assert_eq!(values, [Some("value".into()), None], "Got wrong values");
+
+        let no_values: Vec<Option<String>> = db
+            .get_many::<&str, _>(&[])
+            .await
+            .expect("Couldn't get values");
+        assert!(no_values.is_empty(), "Got values for no keys");
+    }
+
+    #[test_case(b"invalid"; "JSON")]
+    #[test_case(&[MSGPACK_MARKER, MSGPACK_MARKER]; "MessagePack")]
+    #[actix_web::test]
+    /// Test whether values that can't be deserialized are deleted and treated as missing.
+    ///
+    /// # Arguments
+    /// * `data` - The serialized value
+    async fn test_invalid_value(data: &[u8]) {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let db = get_mock_pool(vec![
+            MockCmd::new(Cmd::get(key.clone()), Ok(data.to_vec().into_redis_value())),
+            MockCmd::new(Cmd::del(key), Ok(Value::Int(1))),
+        ])
+        .await;
+
+        let value: Option<String> = CacheBackend::get(&db, &"key")
+            .await
+            .expect("Couldn't get value");
+        assert_eq!(value, None, "Got value that couldn't be deserialized");
+    }
+
+    #[test_case(false; "JSON")]
+    #[test_case(true; "MessagePack")]
+    #[actix_web::test]
+    /// Test whether values are deserialized in the format they were serialized in.
+    ///
+    /// # Arguments
+    /// * `msgpack` - Whether to serialize to MessagePack instead of JSON
+    async fn test_value_format(msgpack: bool) {
+        let comic_data = ComicData {
+            title: "Title".into(),
+            img_url: "https://example.com/comic.gif".into(),
+            img_width: 1,
+            img_height: 1,
+            permalink: "https://example.com/comic".into(),
+            tags: vec!["tag".into()],
+            ..Default::default()
+        };
+        let data = serialize_value(&comic_data, msgpack).expect("Couldn't serialize value");
+        assert_eq!(
+            data.first() == Some(&MSGPACK_MARKER),
+            msgpack,
+            "Value serialized in the wrong format"
+        );
+
+        let db = get_mock_pool(Vec::new()).await;
+        let value: Option<ComicData> = deserialize_value(&db, &"key", Some(data))
+            .await
+            .expect("Couldn't deserialize value");
+        assert_eq!(value, Some(comic_data), "Got wrong value");
+    }
+
+    #[actix_web::test]
+    /// Test whether the bytes of comic images are serialized to MessagePack as binary data.
+    async fn test_image_msgpack() {
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: vec![u8::MAX; 1024],
+        };
+        let data = serialize_value(&image, true).expect("Couldn't serialize value");
+        // An array of integers would take two bytes for each integer above 127.
+        assert!(
+            data.len() < 2 * image.bytes.len(),
+            "Image bytes not serialized as binary data"
+        );
+
+        let db = get_mock_pool(Vec::new()).await;
+        let value: Option<ComicImage> = deserialize_value(&db, &"key", Some(data))
+            .await
+            .expect("Couldn't deserialize value");
+        assert_eq!(value, Some(image), "Got wrong value");
+    }
+
+    /// A mock Redis pool that serializes new values to MessagePack.
+    struct MsgPackPool(MockPool);
+
+    impl RedisPool for MsgPackPool {
+        type ConnType = MockRedisConnection;
+        async fn get(&self) -> Result<Self::ConnType, PoolError> {
+            RedisPool::get(&self.0).await
+        }
+
+        fn uses_msgpack(&self) -> bool {
+            true
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether the Redis backend sets values in MessagePack, and still reads JSON values.
+    async fn test_redis_msgpack() {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let msgpack_value = serialize_value(&"value", true).expect("Couldn't serialize value");
+        let json_value = serde_json::to_vec("legacy").expect("Couldn't serialize value");
+        let db = MsgPackPool(
+            get_mock_pool(vec![
+                MockCmd::new(Cmd::set_ex(key.clone(), msgpack_value, 60), Ok(Value::Okay)),
+                MockCmd::new(Cmd::get(key), Ok(json_value.into_redis_value())),
+            ])
+            .await,
+        );
+
+        db.set(&"key", &"value", 60)
+            .await
+            .expect("Couldn't set value");
+        let value: Option<String> = CacheBackend::get(&db, &"key")
+            .await
+            .expect("Couldn't get value");
+        assert_eq!(value.as_deref(), Some("legacy"), "Got wrong legacy value");
+    }
+
+    #[test_case(Value::Okay, true; "missing key")]
+    #[test_case(Value::Nil, false; "existing key")]
+    #[actix_web::test]
+    /// Test whether the Redis backend sets values only for missing keys.
+    ///
+    /// # Arguments
+    /// * `reply` - The reply of Redis to the command
+    /// * `expected` - Whether the value is expected to be set
+    async fn test_redis_set_nx(reply: Value, expected: bool) {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let value = serde_json::to_vec("value").expect("Couldn't serialize mock cache value");
+        let cmd = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(60)
+            .clone();
+        let db = get_mock_pool(vec![MockCmd::new(cmd, Ok(reply))]).await;
+
+        let set = db
+            .set_nx(&"key", &"value", 60)
+            .await
+            .expect("Couldn't set value");
+        assert_eq!(set, expected, "Wrong result of setting value");
+    }
+
+    #[test_case(Value::Int(1), true; "equal value")]
+    #[test_case(Value::Int(0), false; "different value")]
+    #[actix_web::test]
+    /// Test whether the Redis backend deletes values only if they're equal to the given value.
+    ///
+    /// # Arguments
+    /// * `reply` - The reply of Redis to the command
+    /// * `expected` - Whether the value is expected to be deleted
+    async fn test_redis_delete_if_eq(reply: Value, expected: bool) {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let value = serde_json::to_vec("value").expect("Couldn't serialize mock cache value");
+        let cmd = redis::cmd("EVAL")
+            .arg(DELETE_IF_EQ_SCRIPT)
+            .arg(1)
+            .arg(key)
+            .arg(value)
+            .clone();
+        let db = get_mock_pool(vec![MockCmd::new(cmd, Ok(reply))]).await;
+
+        let deleted = db
+            .delete_if_eq(&"key", &"value")
+            .await
+            .expect("Couldn't delete value");
+        assert_eq!(deleted, expected, "Wrong result of deleting value");
+    }
+
+    #[test_case(&["redis://127.0.0.1:7000", "redis://127.0.0.1:7001"], true; "valid")]
+    #[test_case(&["redis://127.0.0.1:7000", "invalid"], false; "invalid URL")]
+    #[actix_web::test]
+    /// Test whether a pool for a Redis cluster is created only for valid seed URLs.
+    ///
+    /// # Arguments
+    /// * `urls` - The URLs to the seed nodes of the cluster
+    /// * `valid` - Whether the URLs are expected to be valid
+    async fn test_db_cluster_pool(urls: &[&str], valid: bool) {
+        let urls = urls.iter().map(|url| url.to_string()).collect();
+        match get_db_cluster_pool(urls, Duration::from_secs(1), &DbTls::default()) {
+            Ok(DbPool {
+                conns: DbConns::Cluster(_),
+                ..
+            }) => assert!(valid, "Pool created for invalid URLs"),
+            Ok(_) => panic!("Pool is not for a cluster"),
+            Err(err) => assert!(!valid, "Couldn't create pool: {err}"),
+        }
+    }
+
+    #[test_case(false, None, None, None, false, false; "insecure")]
+    #[test_case(true, None, None, None, true, false; "verify")]
+    #[test_case(false, Some(CERT_PATH), None, None, true, true; "CA certificate")]
+    #[test_case(false, None, Some(CERT_PATH), Some(KEY_PATH), true, true; "mutual TLS")]
+    /// Test whether the TLS settings for the database are loaded.
+    ///
+    /// # Arguments
+    /// * `verify` - Whether verification is requested
+    /// * `ca_cert` - The path to the CA certificate, if any
+    /// * `client_cert` - The path to the client certificate, if any
+    /// * `client_key` - The path to the client key, if any
+    /// * `expected_verify` - Whether verification is expected to be enabled
+    /// * `expected_certs` - Whether certificates are expected to be loaded
+    fn test_db_tls(
+        verify: bool,
+        ca_cert: Option<&str>,
+        client_cert: Option<&str>,
+        client_key: Option<&str>,
+        expected_verify: bool,
+        expected_certs: bool,
+    ) {
+        let tls = DbTls::load(
+            verify,
+            ca_cert.map(Path::new),
+            client_cert.map(Path::new),
+            client_key.map(Path::new),
+        )
+        .expect("Couldn't load TLS settings");
+        assert_eq!(tls.verify, expected_verify, "Wrong verification setting");
+        assert_eq!(tls.certs.is_some(), expected_certs, "Wrong certificates");
+    }
+
+    #[test]
+    /// Test whether a client certificate without a key is rejected.
+    fn test_db_tls_missing_key() {
+        let result = DbTls::load(false, None, Some(Path::new(CERT_PATH)), None);
+        assert!(
+            matches!(result, Err(DbInitError::MissingClientTls)),
+            "Client certificate without a key wasn't rejected"
+        );
+    }
+
+    #[test_case("rediss://127.0.0.1:6379", true; "TLS")]
+    #[test_case("redis://127.0.0.1:6379", false; "no TLS")]
+    #[actix_web::test]
+    /// Test whether a pool with TLS certificates is created only for TLS URLs.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to the database
+    /// * `valid` - Whether the URL is expected to be valid with certificates
+    async fn test_db_pool_certs(url: &str, valid: bool) {
+        let tls = DbTls::load(false, Some(Path::new(CERT_PATH)), None, None)
+            .expect("Couldn't load TLS settings");
+        let result = get_db_pool(url.into(), Duration::from_secs(1), &tls);
+        assert_eq!(result.is_ok(), valid, "Wrong result of creating pool");
+    }
+
+    #[actix_web::test]
+    /// Test whether TLS certificates for a Redis cluster are rejected.
+    async fn test_db_cluster_pool_certs() {
+        let tls = DbTls::load(false, Some(Path::new(CERT_PATH)), None, None)
+            .expect("Couldn't load TLS settings");
+        let result = get_db_cluster_pool(
+            vec!["rediss://127.0.0.1:7000".into()],
+            Duration::from_secs(1),
+            &tls,
+        );
+        assert!(
+            matches!(result, Err(DbInitError::ClusterCerts)),
+            "Certificates for a cluster weren't rejected"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether the Redis backend uses Redis sets for sets of members.
+    async fn test_redis_sets() {
+        let key = serde_json::to_vec("key").expect("Couldn't serialize mock cache key");
+        let member = serde_json::to_vec("member").expect("Couldn't serialize mock set member");
+        let invalid = b"\xc1".to_vec();
+        let db = get_mock_pool(vec![
+            MockCmd::new(
+                redis::cmd("SADD").arg(&key).arg(&member).clone(),
+                Ok(Value::Int(1)),
+            ),
+            MockCmd::new(
+                redis::cmd("EXPIRE").arg(&key).arg(60).clone(),
+                Ok(Value::Int(1)),
+            ),
+            MockCmd::new(
+                redis::cmd("SMEMBERS").arg(&key).clone(),
+                Ok(Value::Array(vec![
+                    Value::BulkString(member.clone()),
+                    Value::BulkString(invalid),
+                ])),
+            ),
+            MockCmd::new(
+                redis::cmd("SREM").arg(&key).arg(&member).clone(),
+                Ok(Value::Int(1)),
+            ),
+        ])
+        .await;
+
+        db.add_to_set(&"key", &"member", 60)
+            .await
+            .expect("Couldn't add member to set");
+        let members: Vec<String> = db.get_set(&"key").await.expect("Couldn't get set");
+        assert_eq!(members, ["member"], "Wrong members of set");
+        db.remove_from_set(&"key", &"member")
+            .await
+            .expect("Couldn't remove member from set");
+    }
+
+    #[actix_web::test]
+    /// Test whether the Redis backend flushes the database to clear the cache.
+    async fn test_redis_clear() {
+        let db = get_mock_pool(vec![MockCmd::new(redis::cmd("FLUSHDB"), Ok(Value::Okay))]).await;
+        db.clear().await.expect("Couldn't clear cache");
+    }
+}
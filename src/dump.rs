@@ -0,0 +1,191 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Dumps of the cached comic data, for seeding the caches of new instances
+//!
+//! A dump is a JSON object mapping the dates of comics (in the format YYYY-MM-DD) to their data.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use tracing::{info, warn};
+
+use crate::db::CacheBackend;
+use crate::errors::DumpError;
+use crate::scrapers::{cache_comic_data, ComicData, ComicSource};
+
+/// The data of comics in a dump, keyed by their dates
+type Dump = BTreeMap<NaiveDate, ComicData>;
+
+/// Import a dump of comic data into the cache.
+///
+/// Comics that are already cached are skipped, since their data may be fresher than the dump's.
+/// Comics outside the source's range and incomplete data are also skipped.
+///
+/// # Arguments
+/// * `db` - The cache for comics
+/// * `source` - The source of the comics
+/// * `path` - The path to the dump file
+///
+/// # Returns
+/// * The number of comics imported
+pub async fn import_dump<T: CacheBackend>(
+    db: &T,
+    source: &dyn ComicSource,
+    path: &Path,
+) -> Result<usize, DumpError> {
+    let dump: Dump = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let range = source.date_range()?;
+
+    let mut imported = 0;
+    for (date, comic_data) in dump {
+        if !range.contains(&date) || !comic_data.is_complete() {
+            warn!("Skipping invalid comic in dump for {date}: {comic_data:?}");
+            continue;
+        }
+        let cached: Option<ComicData> = db.get(&date).await?;
+        if cached.is_none() {
+            cache_comic_data(db, source, &date, &comic_data).await?;
+            imported += 1;
+        }
+    }
+    info!("Imported {imported} comics from dump: {}", path.display());
+    Ok(imported)
+}
+
+/// Export the cached comic data to a dump.
+///
+/// # Arguments
+/// * `db` - The cache for comics
+/// * `source` - The source of the comics
+/// * `path` - The path to the dump file, which is overwritten if it exists
+///
+/// # Returns
+/// * The number of comics exported
+pub async fn export_dump<T: CacheBackend>(
+    db: &T,
+    source: &dyn ComicSource,
+    path: &Path,
+) -> Result<usize, DumpError> {
+    let range = source.date_range()?;
+
+    let mut dump = Dump::new();
+    for date in range
+        .start()
+        .iter_days()
+        .take_while(|date| range.contains(date))
+    {
+        if let Some(comic_data) = db.get(&date).await? {
+            dump.insert(date, comic_data);
+        }
+    }
+    tokio::fs::write(path, serde_json::to_vec(&dump)?).await?;
+    info!("Exported {} comics to dump: {}", dump.len(), path.display());
+    Ok(dump.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    use crate::constants::{MEM_CACHE_SIZE, TAG_CACHE_PREFIX};
+    use crate::memory_db::MemoryPool;
+    use crate::scrapers::DilbertSource;
+
+    /// Get the data for a test comic.
+    ///
+    /// # Arguments
+    /// * `title` - The title of the comic
+    fn get_comic_data(title: &str) -> ComicData {
+        ComicData {
+            title: title.into(),
+            img_url: "https://example.com/image".into(),
+            img_width: 1,
+            img_height: 1,
+            tags: vec!["Boss".into()],
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether a dump exported by one cache can be imported into another.
+    async fn test_roundtrip() {
+        let source = DilbertSource::default();
+        let path =
+            std::env::temp_dir().join(format!("dilbert-viewer-{}.json", uuid::Uuid::new_v4()));
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = get_comic_data("Title");
+
+        let old_db = MemoryPool::new(MEM_CACHE_SIZE);
+        cache_comic_data(&old_db, &source, &date, &comic_data)
+            .await
+            .expect("Couldn't set mock cache entry");
+        let exported = export_dump(&old_db, &source, &path).await;
+
+        let new_db = MemoryPool::new(MEM_CACHE_SIZE);
+        let imported = import_dump(&new_db, &source, &path).await;
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("Couldn't remove test dump file");
+
+        assert_eq!(exported.expect("Couldn't export dump"), 1, "Wrong export");
+        assert_eq!(imported.expect("Couldn't import dump"), 1, "Wrong import");
+        let cached: Option<ComicData> = new_db.get(&date).await.expect("Couldn't get cache entry");
+        assert_eq!(cached, Some(comic_data), "Imported the wrong comic data");
+        let tagged: Vec<NaiveDate> = new_db
+            .get_set(&(TAG_CACHE_PREFIX, "boss"))
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(tagged, [date], "Imported comic isn't indexed");
+    }
+
+    #[test_case("2000-01-01", true, false; "already cached")]
+    #[test_case("1900-01-01", false, false; "out of range")]
+    #[test_case("2000-01-01", false, true; "new")]
+    #[actix_web::test]
+    /// Test which comics in a dump are imported.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic in the dump
+    /// * `cached` - Whether the comic is already cached
+    /// * `expected` - Whether the comic is expected to be imported
+    async fn test_import_skips(date: &str, cached: bool, expected: bool) {
+        let source = DilbertSource::default();
+        let path =
+            std::env::temp_dir().join(format!("dilbert-viewer-{}.json", uuid::Uuid::new_v4()));
+        let date: NaiveDate = date.parse().unwrap();
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        if cached {
+            cache_comic_data(&db, &source, &date, &get_comic_data("Cached"))
+                .await
+                .expect("Couldn't set mock cache entry");
+        }
+
+        let dump = Dump::from([(date, get_comic_data("Dumped"))]);
+        tokio::fs::write(&path, serde_json::to_vec(&dump).unwrap())
+            .await
+            .expect("Couldn't write test dump file");
+        let imported = import_dump(&db, &source, &path).await;
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("Couldn't remove test dump file");
+
+        assert_eq!(
+            imported.expect("Couldn't import dump"),
+            usize::from(expected),
+            "Wrong number of imported comics"
+        );
+        if cached {
+            let comic_data: Option<ComicData> =
+                db.get(&date).await.expect("Couldn't get cache entry");
+            assert_eq!(
+                comic_data.map(|comic_data| comic_data.title).as_deref(),
+                Some("Cached"),
+                "Cached comic was overwritten"
+            );
+        }
+    }
+}
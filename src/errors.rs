@@ -4,8 +4,12 @@
 
 //! Custom error definitions
 use std::env;
+use std::net::AddrParseError;
+use std::rc::Rc;
+use std::time::Duration;
 
 use awc::error::{PayloadError, SendRequestError};
+use awc::http::StatusCode;
 use deadpool_redis::{BuildError, ConfigError, PoolError};
 use minify_html::Error as MinifyHtmlError;
 use thiserror::Error;
@@ -22,6 +26,90 @@ pub enum DbInitError {
     /// Error initializing the DB pool
     #[error("Error initializing the database pool: {0}")]
     Build(#[from] BuildError),
+    /// Error reading the TLS certificates for the database
+    #[error("Error reading the TLS certificates for the database: {0}")]
+    Tls(#[from] std::io::Error),
+    /// Only one of the client certificate and key for mutual TLS
+    #[error("Both the client certificate and key are needed for mutual TLS with the database")]
+    MissingClientTls,
+    /// Custom TLS certificates for a Redis cluster
+    #[error("Custom TLS certificates aren't supported for a Redis cluster")]
+    ClusterCerts,
+}
+
+#[derive(Error, Debug)]
+/// Errors when loading the configuration from a file
+pub enum ConfigFileError {
+    /// Error reading the file
+    #[error("Error reading the config file: {0}")]
+    Io(#[from] std::io::Error),
+    /// Invalid TOML, or invalid values for keys
+    #[error("Error parsing the config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+#[derive(Error, Debug)]
+/// Errors when importing or exporting a dump of the cached comic data
+pub enum DumpError {
+    /// Error reading or writing the dump file
+    #[error("Error accessing the dump file: {0}")]
+    Io(#[from] std::io::Error),
+    /// Invalid JSON in the dump file
+    #[error("Error parsing the dump file: {0}")]
+    Parse(#[from] serde_json::Error),
+    /// Error reading from or writing to the cache
+    #[error("Error accessing the cache: {0}")]
+    Cache(#[from] AppError),
+}
+
+#[derive(Error, Debug)]
+/// Errors when loading the TLS certificate and private key
+pub enum TlsError {
+    /// Error reading the certificate or key file
+    #[error("Error reading TLS file: {0}")]
+    Io(#[from] std::io::Error),
+    /// No private key in the key file
+    #[error("No private key found in the TLS key file")]
+    MissingKey,
+    /// Invalid certificate or key
+    #[error("Invalid TLS certificate or key: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+#[derive(Error, Debug)]
+/// Errors when vendoring a third-party stylesheet into the directory of static files
+pub enum VendorError {
+    /// Error downloading the stylesheet
+    #[error("Error downloading the stylesheet: {0}")]
+    Http(#[from] HttpError),
+    /// Unsuccessful response when downloading the stylesheet
+    #[error("Unexpected response status when downloading the stylesheet: {0}")]
+    Status(StatusCode),
+    /// The downloaded stylesheet doesn't match its integrity hash
+    #[error("The downloaded stylesheet doesn't match its integrity hash")]
+    Integrity,
+    /// Error writing the stylesheet
+    #[error("Error writing the stylesheet: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+/// Errors when parsing a range of IP addresses
+pub enum IpRangeError {
+    /// Invalid IP address
+    #[error("Invalid IP address: {0}")]
+    Addr(#[from] AddrParseError),
+    /// Invalid length of the prefix in CIDR notation
+    #[error("Invalid prefix length: {0}")]
+    PrefixLen(String),
+}
+
+#[derive(Error, Debug)]
+/// Errors when deriving the key with which cookies are signed
+pub enum CookieKeyError {
+    /// The secret is too short to derive a secure key from
+    #[error("The cookie secret must be at least {0} bytes long")]
+    TooShort(usize),
 }
 
 #[derive(Error, Debug)]
@@ -39,9 +127,6 @@ pub enum MinificationError {
     /// Error minifying HTML
     #[error("Error minifying HTML: {0:?}")]
     Html(MinifyHtmlError),
-    /// Error minifying CSS
-    #[error("Error minifying CSS: {0}")]
-    Css(String),
 }
 
 impl From<MinifyHtmlError> for MinificationError {
@@ -59,9 +144,15 @@ pub enum AppError {
     /// Errors when executing a DB query
     #[error("Database error: {0}")]
     Db(#[from] redis::RedisError),
+    /// Errors in the in-memory cache
+    #[error("In-memory cache error: {0}")]
+    Cache(String),
     /// Errors when serializing/deserializing a DB query argument/result
     #[error("(De)serialization error: {0}")]
     Serde(#[from] serde_json::Error),
+    /// Errors when serializing a DB query argument to MessagePack
+    #[error("MessagePack serialization error: {0}")]
+    MsgPack(#[from] rmp_serde::encode::Error),
     /// Errors when building an HTTP client, or when making HTTP requests
     #[error("HTTP client error: {0}")]
     Http(HttpError),
@@ -77,15 +168,61 @@ pub enum AppError {
     /// Errors in parsing UTF-8 from files
     #[error("Error parsing UTF-8: {0}")]
     Utf8(#[from] std::str::Utf8Error),
-    /// Errors in minifying HTML/CSS
+    /// Errors in minifying HTML
     #[error("Minification error: {0}")]
     Minify(#[from] MinificationError),
+    /// Errors in decoding or encoding images
+    #[error("Image error: {0}")]
+    Image(#[from] image::ImageError),
+    /// Errors in loading fonts for drawing text on images
+    #[error("Font error: {0}")]
+    Font(#[from] ab_glyph::InvalidFont),
     /// Errors in scraping info from "dilbert.com"
     #[error("Scraping error: {0}")]
     Scrape(String),
+    /// Errors when looking up the archived versions of a comic's page with the CDX API
+    #[error("CDX API error: {0}")]
+    Cdx(String),
+    /// Errors when locating an archived version of a comic's page with the Memento API
+    #[error("Memento API error: {0}")]
+    Memento(String),
     /// Errors when no comic exists for a given date
     #[error("{0}")]
     NotFound(String),
+    /// Errors from a retrieval shared with other concurrent requests, along with whether this
+    /// request is the one that made the retrieval
+    #[error("{0}")]
+    Shared(Rc<AppError>, bool),
+    /// Errors when the request itself is invalid
+    #[error("{0}")]
+    BadRequest(String),
+    /// Errors when requests to the comic source are skipped during an outage, along with the time
+    /// after which they can be retried
+    #[error("{0}")]
+    SourceUnavailable(String, Duration),
+}
+
+impl AppError {
+    /// Check whether this error is unexpected, and so worth reporting.
+    ///
+    /// Missing comics, invalid requests and outages of the comic source are expected, so they
+    /// aren't reported. The failures that caused an outage are reported on their own. Errors shared
+    /// with other requests are only reported by the request that made the retrieval.
+    pub fn is_reportable(&self) -> bool {
+        match self {
+            Self::NotFound(_) | Self::BadRequest(_) | Self::SourceUnavailable(..) => false,
+            Self::Shared(err, leader) => *leader && err.is_reportable(),
+            _ => true,
+        }
+    }
+
+    /// Get the original error, looking through errors shared with other requests.
+    pub fn original(&self) -> &Self {
+        match self {
+            Self::Shared(err, _) => err.original(),
+            _ => self,
+        }
+    }
 }
 
 impl<E> From<E> for AppError
@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Favorite comics of clients, stored in a signed cookie instead of accounts
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+use actix_web::{cookie::Cookie, dev::Payload, FromRequest, HttpRequest};
+use chrono::NaiveDate;
+
+use crate::constants::{FAVORITES_COOKIE, FAVORITES_COOKIE_MAX_AGE, MAX_FAVORITES};
+use crate::cookies::{get_signed_dates, signed_dates_cookie, CookieKey};
+
+/// The comics that a client has marked as favorites, in the order they were marked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Favorites(Vec<NaiveDate>);
+
+impl Favorites {
+    /// Check whether a comic is a favorite.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    pub fn contains(&self, date: &NaiveDate) -> bool {
+        self.0.contains(date)
+    }
+
+    /// Get the dates of the favorite comics, the most recently marked first.
+    pub fn dates(&self) -> impl Iterator<Item = &NaiveDate> {
+        self.0.iter().rev()
+    }
+
+    /// Mark a comic as a favorite if it isn't one, and unmark it otherwise.
+    ///
+    /// Cookies are limited in size, so the earliest marked favorites are dropped if there are too
+    /// many. This returns whether the comic is now a favorite.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    pub fn toggle(&mut self, date: NaiveDate) -> bool {
+        if let Some(idx) = self.0.iter().position(|favorite| *favorite == date) {
+            self.0.remove(idx);
+            return false;
+        }
+        self.0.push(date);
+        let excess = self.0.len().saturating_sub(MAX_FAVORITES);
+        self.0.drain(..excess);
+        true
+    }
+
+    /// Get the cookie that stores these favorites.
+    ///
+    /// # Arguments
+    /// * `key` - The key with which to sign the cookie
+    pub fn cookie(&self, key: &CookieKey) -> Cookie<'static> {
+        signed_dates_cookie(key, FAVORITES_COOKIE, &self.0, FAVORITES_COOKIE_MAX_AGE)
+    }
+}
+
+impl FromRequest for Favorites {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Get the favorites of the client, which are empty if the cookie has been tampered with.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self(get_signed_dates(req, FAVORITES_COOKIE))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test whether toggling marks and unmarks comics, keeping the most recent ones.
+    fn test_toggle() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut favorites = Favorites::default();
+        assert!(favorites.toggle(date), "Comic wasn't marked");
+        assert!(favorites.contains(&date), "Marked comic is missing");
+        assert!(!favorites.toggle(date), "Comic wasn't unmarked");
+        assert!(!favorites.contains(&date), "Unmarked comic is present");
+
+        for date in date.iter_days().take(MAX_FAVORITES + 1) {
+            favorites.toggle(date);
+        }
+        assert_eq!(favorites.0.len(), MAX_FAVORITES, "Too many favorites");
+        assert!(
+            !favorites.contains(&date),
+            "Earliest favorite wasn't dropped"
+        );
+        assert_eq!(
+            favorites.dates().next().copied(),
+            date.iter_days().nth(MAX_FAVORITES),
+            "Latest favorite isn't first"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! GraphQL API for the data of comics
+use std::ops::RangeInclusive;
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Error, Object, Result, Schema, SimpleObject,
+};
+use chrono::NaiveDate;
+use futures::{
+    channel::{mpsc::UnboundedSender, oneshot},
+    future::join_all,
+};
+use rand::thread_rng;
+
+use crate::app::check_range;
+use crate::constants::{APP_URL, SRC_DATE_FMT};
+use crate::random::random_comic_date;
+use crate::scrapers::ComicData;
+
+/// A request for the data of a comic on a date, along with where to send the result
+pub type ComicRequest = (
+    NaiveDate,
+    oneshot::Sender<Result<Option<ComicData>, String>>,
+);
+
+/// Requester of the data of comics, for the resolvers of GraphQL queries
+///
+/// The comic scraper can't be shared across threads, unlike the resolvers, so they send requests
+/// for the data to the worker serving the GraphQL request instead.
+pub struct ComicRequester(pub UnboundedSender<ComicRequest>);
+
+impl ComicRequester {
+    /// Get the requested comic, if it exists.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    async fn get(&self, date: NaiveDate) -> Result<Option<Comic>> {
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .unbounded_send((date, sender))
+            .map_err(|_| Error::new("Comic requests are closed"))?;
+        let comic_data = receiver
+            .await
+            .map_err(|_| Error::new("Comic request was cancelled"))?
+            .map_err(Error::new)?;
+        Ok(comic_data.map(|comic_data| Comic::new(date, comic_data)))
+    }
+}
+
+/// The inclusive range of dates of the available comics, for the resolvers of GraphQL queries
+pub struct ComicRange(pub RangeInclusive<NaiveDate>);
+
+/// A comic, with URLs to its page and image served by the app
+#[derive(SimpleObject)]
+pub struct Comic {
+    /// The date of the comic
+    date: NaiveDate,
+    /// The title of the comic, which may be empty
+    title: String,
+    /// The URL to the comic's page
+    url: String,
+    /// The URL to the comic's image
+    img_url: String,
+    /// The width of the image
+    img_width: i32,
+    /// The height of the image
+    img_height: i32,
+    /// The permalink to the comic on the source
+    permalink: String,
+    /// The transcript of the comic's text, if available
+    transcript: Option<String>,
+    /// The tags of the comic, without the leading "#"
+    tags: Vec<String>,
+}
+
+impl Comic {
+    /// Initialize a comic from its scraped data.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    /// * `comic_data` - The data of the comic
+    fn new(date: NaiveDate, comic_data: ComicData) -> Self {
+        let date_str = date.format(SRC_DATE_FMT);
+        Self {
+            date,
+            title: comic_data.title,
+            url: format!("{APP_URL}{date_str}"),
+            img_url: format!("{APP_URL}{date_str}/image"),
+            img_width: comic_data.img_width,
+            img_height: comic_data.img_height,
+            permalink: comic_data.permalink,
+            transcript: comic_data.transcript,
+            tags: comic_data.tags,
+        }
+    }
+}
+
+/// The root of all GraphQL queries
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The comic on a date, if it exists
+    async fn comic(&self, ctx: &Context<'_>, date: NaiveDate) -> Result<Option<Comic>> {
+        ctx.data::<ComicRequester>()?.get(date).await
+    }
+
+    /// The comics in an inclusive range of dates, skipping missing ones
+    async fn comics_in_range(
+        &self,
+        ctx: &Context<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Comic>> {
+        let range = &ctx.data::<ComicRange>()?.0;
+        check_range(&from, &to, range).map_err(|err| Error::new(err.to_string()))?;
+        let requester = ctx.data::<ComicRequester>()?;
+        let dates = from.iter_days().take_while(|date| *date <= to);
+        let comics = join_all(dates.map(|date| requester.get(date))).await;
+        comics.into_iter().filter_map(Result::transpose).collect()
+    }
+
+    /// A random comic, if there's one on the chosen date
+    async fn random_comic(&self, ctx: &Context<'_>) -> Result<Option<Comic>> {
+        let date = random_comic_date(&mut thread_rng());
+        ctx.data::<ComicRequester>()?.get(date).await
+    }
+}
+
+/// The schema of the GraphQL API
+pub type ComicSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Build the schema of the GraphQL API.
+pub fn build_schema() -> ComicSchema {
+    Schema::new(QueryRoot, EmptyMutation, EmptySubscription)
+}
@@ -5,58 +5,689 @@
 //! Route handlers for the server
 //!
 //! This is kept separate from `lib.rs`, since actix-web handlers are pub by default.
+use std::num::NonZeroUsize;
 use std::path::Path;
 
-use actix_web::{get, http::header::LOCATION, web, HttpResponse, Responder};
-use chrono::{Duration, NaiveDate};
-use deadpool_redis::Pool;
-use rand::{thread_rng, Rng};
+use actix_web::{
+    delete, get,
+    http::{
+        header::{
+            Accept, AcceptEncoding, ContentType, IfModifiedSince, IfNoneMatch, TryIntoHeaderPair,
+            LOCATION, REFERER,
+        },
+        Method, StatusCode, Uri,
+    },
+    post, route, web, HttpRequest, HttpResponse, Responder,
+};
+use async_graphql::http::parse_query_string;
+use chrono::NaiveDate;
+use rand::thread_rng;
+use serde::Deserialize;
 use tracing::info;
 
-use crate::app::{serve_404, serve_css, serve_js, Viewer};
-use crate::constants::{FIRST_COMIC, LAST_COMIC, SRC_DATE_FMT, STATIC_DIR};
-use crate::datetime::str_to_date;
+use crate::app::{
+    serve_400, serve_404, serve_500, serve_archive_index, serve_archive_month, serve_grid,
+    serve_js, serve_manifest, serve_themed_404, serve_warm, OutOfRange, Viewer,
+};
+use crate::assets::{immutable_cache_control, resolve_asset, CssAssets};
+use crate::constants::{COMPACT_DATE_FMT, SRC_DATE_FMT, STATIC_DIR};
+use crate::cookies::CookieKey;
+use crate::csp::CspNonce;
+use crate::datetime::{curr_date, number_to_date, str_to_date};
+use crate::db::CachePool;
+use crate::favorites::Favorites;
+use crate::graphql::ComicSchema;
+use crate::health::ReadinessProbe;
+use crate::history::{record_view, History};
+use crate::i18n::Lang;
+use crate::metrics::METRICS;
+use crate::prefs::ClientPrefs;
+use crate::random::{random_comic_date, DailyComic};
+use crate::theme::{get_return_path, Theme};
+use crate::transcode::accepts_webp;
 
 /// Serve the last comic.
 #[get("/")]
-async fn last_comic(viewer: web::Data<Viewer<Pool>>) -> impl Responder {
-    // If there is no comic for this date yet, "dilbert.com" will redirect to the homepage. The
-    // code can handle this by instead showing the contents of the last comic.
-    let last = str_to_date(LAST_COMIC, SRC_DATE_FMT)
-        .expect("Variable LAST_COMIC not in format of variable SRC_DATE_FMT");
-    viewer.serve_comic(&last).await
+async fn last_comic(
+    viewer: web::Data<Viewer<CachePool>>,
+    nonce: web::ReqData<CspNonce>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    prefs: ClientPrefs,
+) -> impl Responder {
+    viewer
+        .serve_latest_comic(&nonce, if_none_match.as_deref(), &prefs)
+        .await
+}
+
+/// Query parameters for comic pages
+#[derive(Deserialize)]
+struct ComicQuery {
+    /// The end of the available comics past which the client originally requested a date, if it
+    /// was redirected to this comic
+    clamped: Option<OutOfRange>,
 }
 
-/// Serve the comic requested in the given URL.
+/// Serve the comic requested in the given URL, and record it in the client's history.
+///
+/// Dates that aren't in the canonical format (such as "2000-1-1") are permanently redirected to
+/// the canonical URL, so that each comic has a single URL.
 #[get("/{year}-{month}-{day}")]
 async fn comic_page(
-    viewer: web::Data<Viewer<Pool>>,
-    path: web::Path<(i32, u32, u32)>,
+    req: HttpRequest,
+    viewer: web::Data<Viewer<CachePool>>,
+    nonce: web::ReqData<CspNonce>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    prefs: ClientPrefs,
+    path: web::Path<(String, String, String)>,
+    query: web::Query<ComicQuery>,
 ) -> impl Responder {
     let (year, month, day) = path.into_inner();
+    let requested = format!("{year}-{month}-{day}");
 
     // Check to see if the date is invalid.
-    if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
-        viewer.serve_comic(&date).await
+    let Some(date) = parse_date_parts(&year, &month, &day) else {
+        info!("Invalid date requested: ({requested})");
+        return serve_themed_404(None, prefs.theme);
+    };
+
+    if requested == date.format(SRC_DATE_FMT).to_string() {
+        let mut resp = viewer
+            .serve_comic(
+                &date,
+                query.clamped,
+                &nonce,
+                if_none_match.as_deref(),
+                &prefs,
+            )
+            .await;
+        // Redirects and errors aren't views of the comic.
+        if resp.status().is_success() || resp.status() == StatusCode::NOT_MODIFIED {
+            record_view(&req, date, &mut resp);
+        }
+        resp
+    } else {
+        redirect_to_comic(&requested, &date)
+    }
+}
+
+/// Redirect the slash-separated date in the given URL (such as "2000/01/01") to the comic's
+/// canonical URL.
+#[get(r"/{year:\d+}/{month:\d+}/{day:\d+}")]
+async fn slash_comic_page(
+    theme: Theme,
+    path: web::Path<(String, String, String)>,
+) -> impl Responder {
+    let (year, month, day) = path.into_inner();
+    let requested = format!("{year}/{month}/{day}");
+    match parse_date_parts(&year, &month, &day) {
+        Some(date) => redirect_to_comic(&requested, &date),
+        None => {
+            info!("Invalid date requested: ({requested})");
+            serve_themed_404(None, theme)
+        }
+    }
+}
+
+/// Redirect the compact date in the given URL (such as "20000101") to the comic's canonical URL.
+#[get(r"/{date:\d{8}}")]
+async fn compact_comic_page(theme: Theme, path: web::Path<String>) -> impl Responder {
+    let requested = path.into_inner();
+    match str_to_date(&requested, COMPACT_DATE_FMT) {
+        Ok(date) => redirect_to_comic(&requested, &date),
+        Err(_) => {
+            info!("Invalid date requested: ({requested})");
+            serve_themed_404(None, theme)
+        }
+    }
+}
+
+/// Parse a date from its year, month and day, which needn't be zero-padded.
+///
+/// # Arguments
+/// * `year` - The year of the date
+/// * `month` - The month of the date
+/// * `day` - The day of the date
+fn parse_date_parts(year: &str, month: &str, day: &str) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+}
+
+/// Permanently redirect a non-canonical comic URL to the comic's canonical URL.
+///
+/// # Arguments
+/// * `requested` - The date as given in the non-canonical URL
+/// * `date` - The date of the comic
+fn redirect_to_comic(requested: &str, date: &NaiveDate) -> HttpResponse {
+    let canonical = date.format(SRC_DATE_FMT).to_string();
+    info!("Redirecting non-canonical date {requested} to {canonical}");
+    HttpResponse::MovedPermanently()
+        .append_header((LOCATION, format!("/{canonical}")))
+        .finish()
+}
+
+/// Serve the image of the comic requested in the given URL.
+///
+/// This serves the image from the same origin, so that browsers don't hotlink the source.
+#[route("/{date}/image", method = "GET", method = "HEAD")]
+async fn comic_image(
+    viewer: web::Data<Viewer<CachePool>>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    accept: Option<web::Header<Accept>>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    let webp = accept.is_some_and(|accept| accepts_webp(&accept));
+
+    // Check to see if the date is invalid.
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => {
+            viewer
+                .serve_comic_image(&date, req.method() == Method::HEAD, webp)
+                .await
+        }
+        Err(_) => {
+            info!("Invalid date requested for image: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve another resolution of the image of the comic requested in the given URL.
+#[route("/{date}/image/{width}", method = "GET", method = "HEAD")]
+async fn comic_image_variant(
+    viewer: web::Data<Viewer<CachePool>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (date_str, width_str) = path.into_inner();
+    match (str_to_date(&date_str, SRC_DATE_FMT), width_str.parse()) {
+        (Ok(date), Ok(width)) => {
+            viewer
+                .serve_comic_image_variant(&date, width, req.method() == Method::HEAD)
+                .await
+        }
+        _ => {
+            info!("Invalid image variant requested: ({date_str}, {width_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve the image of the comic requested in the given URL as a download.
+#[get("/download/{date}")]
+async fn comic_download(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_comic_download(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for download: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Query parameters for the export of comic book archives
+#[derive(Deserialize)]
+struct CbzQuery {
+    /// The month of the comics, in the format "YYYY-MM"
+    month: String,
+}
+
+/// Serve a comic book archive (CBZ) of the comics in the month given in the query.
+#[get("/export/cbz")]
+async fn export_cbz(
+    viewer: web::Data<Viewer<CachePool>>,
+    query: web::Query<CbzQuery>,
+) -> impl Responder {
+    match str_to_date(&format!("{}-01", query.month), SRC_DATE_FMT) {
+        Ok(month) => viewer.into_inner().serve_cbz(&month).await,
+        Err(_) => {
+            info!("Invalid month requested for export: ({})", query.month);
+            serve_400(&format!("Invalid month: {}", query.month))
+        }
+    }
+}
+
+/// Serve the text-only view of the comic requested in the given URL.
+#[get("/{date}/text")]
+async fn comic_text(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_comic_text(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for text view: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve the social-share preview image of the comic requested in the given URL.
+#[get("/{date}/preview")]
+async fn comic_preview(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_comic_preview(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for preview: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve the downscaled thumbnail of the comic requested in the given URL.
+#[get("/{date}/thumbnail")]
+async fn comic_thumbnail(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_comic_thumbnail(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for thumbnail: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve the data of the comic requested in the given URL as JSON.
+#[get("/v1/comic/{date}")]
+async fn api_comic(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_comic_data(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for API: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Query parameters for the batch API
+#[derive(Deserialize)]
+struct ComicsQuery {
+    /// The date of the first comic in the range
+    from: NaiveDate,
+    /// The date of the last comic in the range
+    to: NaiveDate,
+    /// The maximum number of comics to serve
+    limit: Option<NonZeroUsize>,
+}
+
+/// Serve the data of the comics in the range given in the query as JSON.
+#[get("/v1/comics")]
+async fn api_comics(
+    viewer: web::Data<Viewer<CachePool>>,
+    query: web::Query<ComicsQuery>,
+) -> impl Responder {
+    viewer
+        .serve_comics_data(&query.from, &query.to, query.limit)
+        .await
+}
+
+/// Serve the response to a GraphQL query.
+///
+/// The query is given as JSON in the body of POST requests, or in the query string of GET requests.
+#[route("/graphql", method = "GET", method = "POST")]
+async fn graphql_query(
+    viewer: web::Data<Viewer<CachePool>>,
+    schema: web::Data<ComicSchema>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> impl Responder {
+    let request = if req.method() == Method::POST {
+        serde_json::from_slice(&body).map_err(|err| err.to_string())
+    } else {
+        parse_query_string(req.query_string()).map_err(|err| err.to_string())
+    };
+    match request {
+        Ok(request) => viewer.serve_graphql(&schema, request).await,
+        Err(err) => {
+            info!("Invalid GraphQL request: {err}");
+            serve_400(&format!("Invalid GraphQL request: {err}"))
+        }
+    }
+}
+
+/// Query parameters for oEmbed requests
+#[derive(Deserialize)]
+struct OembedQuery {
+    /// The URL of the comic page to embed
+    url: String,
+    /// The format of the response, of which only JSON is supported
+    format: Option<String>,
+}
+
+/// Serve the oEmbed response for the comic page whose URL is given in the query.
+///
+/// Only the URL's path is checked, so that the URLs of any instance of the viewer can be embedded.
+#[get("/oembed")]
+async fn comic_oembed(
+    viewer: web::Data<Viewer<CachePool>>,
+    lang: Lang,
+    query: web::Query<OembedQuery>,
+) -> impl Responder {
+    if query
+        .format
+        .as_deref()
+        .is_some_and(|format| format != "json")
+    {
+        info!("Unsupported oEmbed format requested: {:?}", query.format);
+        return HttpResponse::NotImplemented().finish();
+    }
+
+    let date = query
+        .url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|uri| str_to_date(uri.path().trim_start_matches('/'), SRC_DATE_FMT).ok());
+    match date {
+        Some(date) => viewer.serve_oembed(&date, lang).await,
+        None => {
+            info!("Invalid URL requested for oEmbed: ({})", query.url);
+            serve_404(None)
+        }
+    }
+}
+
+/// Purge the comic requested in the given URL from the cache.
+///
+/// This is an admin route, so it's mounted under "/admin".
+#[delete("/cache/{date}")]
+async fn purge_comic(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_purge(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for purging: ({date_str})");
+            serve_400(&format!("Invalid date: {date_str}"))
+        }
+    }
+}
+
+/// Purge everything from the cache.
+///
+/// This is an admin route, so it's mounted under "/admin".
+#[delete("/cache")]
+async fn purge_cache(viewer: web::Data<Viewer<CachePool>>) -> impl Responder {
+    viewer.serve_purge_all().await
+}
+
+/// Serve the metrics for the cache and for scrapes, in the Prometheus text format.
+///
+/// This is an admin route, so it's mounted under "/admin".
+#[get("/metrics")]
+async fn admin_metrics() -> impl Responder {
+    METRICS.serve_metrics()
+}
+
+/// Reset the metrics for the cache and for scrapes, and serve their values before the reset as JSON.
+///
+/// This is an admin route, so it's mounted under "/admin".
+#[post("/stats/reset")]
+async fn reset_stats() -> impl Responder {
+    METRICS.serve_reset()
+}
+
+/// Query parameters for warming up the cache
+#[derive(Deserialize)]
+struct WarmQuery {
+    /// The date of the first comic
+    from: String,
+    /// The date of the last comic, or the last available comic if not given
+    to: Option<String>,
+}
+
+/// Warm up the cache with the comics in the date range given in the query, in the background.
+///
+/// This is an admin route, so it's mounted under "/admin".
+#[post("/warm")]
+async fn warm_cache(
+    viewer: web::Data<Viewer<CachePool>>,
+    query: web::Query<WarmQuery>,
+) -> impl Responder {
+    let from = str_to_date(&query.from, SRC_DATE_FMT);
+    let to = query
+        .to
+        .as_deref()
+        .map(|to| str_to_date(to, SRC_DATE_FMT))
+        .transpose();
+    match (from, to) {
+        (Ok(from), Ok(to)) => serve_warm(viewer.into_inner(), from, to).await,
+        _ => {
+            info!(
+                "Invalid dates requested for warming up the cache: ({}, {:?})",
+                query.from, query.to
+            );
+            serve_400("Invalid dates for warming up the cache")
+        }
+    }
+}
+
+/// Serve all comics in the inclusive date range given in the URL.
+#[get("/{start}..{end}")]
+async fn comic_range(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (start, end) = path.into_inner();
+
+    // Check to see if either date is invalid.
+    match (
+        str_to_date(&start, SRC_DATE_FMT),
+        str_to_date(&end, SRC_DATE_FMT),
+    ) {
+        (Ok(start), Ok(end)) => viewer.serve_strip(&start, &end).await,
+        _ => {
+            info!("Invalid date range requested: ({start}..{end})");
+            serve_400(&format!("Invalid date range: {start}..{end}"))
+        }
+    }
+}
+
+/// Serve all comics in the week with the date given in the URL.
+#[get("/week/{date}")]
+async fn comic_week(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    match str_to_date(&date_str, SRC_DATE_FMT) {
+        Ok(date) => viewer.serve_week(&date).await,
+        Err(_) => {
+            info!("Invalid date requested for week: ({date_str})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve the comics with the tag given in the URL.
+#[get("/tag/{name}")]
+async fn comic_tag(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    viewer.serve_tag(&path.into_inner()).await
+}
+
+/// Query parameters for searching comics
+#[derive(Deserialize)]
+struct SearchQuery {
+    /// The search query
+    #[serde(default)]
+    q: String,
+}
+
+/// Serve the comics that match the search query given in the URL.
+#[get("/search")]
+async fn comic_search(
+    viewer: web::Data<Viewer<CachePool>>,
+    query: web::Query<SearchQuery>,
+) -> impl Responder {
+    viewer.serve_search(&query.q).await
+}
+
+/// Serve the comics on today's day of the year, across all years.
+#[get("/onthisday")]
+async fn on_this_day(viewer: web::Data<Viewer<CachePool>>) -> impl Responder {
+    viewer.serve_on_this_day(&curr_date()).await
+}
+
+/// Serve an index of all years of comics.
+#[get("/archive")]
+async fn archive(viewer: web::Data<Viewer<CachePool>>) -> impl Responder {
+    match viewer.get_comic_range().await {
+        Ok(range) => serve_archive_index(None, &range),
+        Err(err) => serve_500(&err, None),
+    }
+}
+
+/// Serve an index of the months of comics in the year given in the URL.
+#[get("/archive/{year}")]
+async fn archive_year(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let year = path.into_inner();
+    match year.parse() {
+        Ok(year_num) if year == format!("{year_num:04}") => match viewer.get_comic_range().await {
+            Ok(range) => serve_archive_index(Some(year_num), &range),
+            Err(err) => serve_500(&err, None),
+        },
+        _ => {
+            info!("Invalid year requested for archive: ({year})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Serve a calendar of the comics in the month given in the URL.
+///
+/// Months that aren't in the canonical format (such as "2000/1") are permanently redirected to the
+/// canonical URL.
+#[get("/archive/{year}/{month}")]
+async fn archive_month(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (year, month) = path.into_inner();
+    let (Ok(year_num), Ok(month_num)) = (year.parse::<i32>(), month.parse::<u32>()) else {
+        info!("Invalid month requested for archive: ({year}/{month})");
+        return serve_404(None);
+    };
+
+    let canonical = format!("{year_num:04}/{month_num:02}");
+    if format!("{year}/{month}") == canonical {
+        match viewer.get_comic_range().await {
+            Ok(range) => serve_archive_month(year_num, month_num, &range),
+            Err(err) => serve_500(&err, None),
+        }
+    } else {
+        HttpResponse::MovedPermanently()
+            .append_header((LOCATION, format!("/archive/{canonical}")))
+            .finish()
+    }
+}
+
+/// Serve a grid of thumbnails of the comics in the month given in the URL.
+///
+/// Months that aren't in the canonical format (such as "2000-1") are permanently redirected to the
+/// canonical URL.
+#[get("/grid/{year}-{month}")]
+async fn month_grid(
+    viewer: web::Data<Viewer<CachePool>>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (year, month) = path.into_inner();
+    let (Ok(year_num), Ok(month_num)) = (year.parse::<i32>(), month.parse::<u32>()) else {
+        info!("Invalid month requested for grid: ({year}-{month})");
+        return serve_404(None);
+    };
+
+    let canonical = format!("{year_num:04}-{month_num:02}");
+    if format!("{year}-{month}") == canonical {
+        match viewer.get_comic_range().await {
+            Ok(range) => serve_grid(year_num, month_num, &range),
+            Err(err) => serve_500(&err, None),
+        }
     } else {
-        info!("Invalid date requested: ({year}-{month}-{day})");
-        serve_404(None)
+        HttpResponse::MovedPermanently()
+            .append_header((LOCATION, format!("/grid/{canonical}")))
+            .finish()
     }
 }
 
+/// Serve an Atom feed of recent comics.
+#[get("/feed.xml")]
+async fn comic_feed(viewer: web::Data<Viewer<CachePool>>) -> impl Responder {
+    viewer.serve_feed().await
+}
+
+/// Serve whether the server is alive.
+#[get("/healthz")]
+async fn liveness() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body("ok")
+}
+
+/// Serve whether the server and its dependencies are ready to handle requests.
+#[get("/readyz")]
+async fn readiness(probe: web::Data<ReadinessProbe<CachePool>>) -> impl Responder {
+    probe.serve_readiness().await
+}
+
+/// Serve the `robots.txt` for crawlers.
+#[get("/robots.txt")]
+async fn robots_txt(
+    viewer: web::Data<Viewer<CachePool>>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+) -> impl Responder {
+    viewer.serve_robots_txt(if_none_match.as_deref(), if_modified_since.as_deref())
+}
+
+/// Serve the web app manifest.
+#[get("/manifest.webmanifest")]
+async fn web_manifest() -> impl Responder {
+    serve_manifest()
+}
+
+/// Query parameters for random comics
+#[derive(Deserialize)]
+struct RandomQuery {
+    /// Whether to choose the "comic of the day", which is the same for everyone on a given day
+    #[serde(default)]
+    daily: bool,
+}
+
 /// Serve a random comic.
 #[get("/random")]
-async fn random_comic() -> impl Responder {
-    let first = str_to_date(FIRST_COMIC, SRC_DATE_FMT)
-        .expect("Variable FIRST_COMIC not in format of variable SRC_DATE_FMT");
-    let last = str_to_date(LAST_COMIC, SRC_DATE_FMT)
-        .expect("Variable LAST_COMIC not in format of variable SRC_DATE_FMT");
-
-    let mut rng = thread_rng();
-    // Offset (in days) from the first date
-    let rand_offset = rng.gen_range(0..(last - first).num_days());
-    let rand_date = first + Duration::days(rand_offset);
-    info!("Chose random comic date: {rand_date}");
+async fn random_comic(
+    daily_comic: web::Data<DailyComic<CachePool>>,
+    query: web::Query<RandomQuery>,
+) -> impl Responder {
+    let rand_date = if query.daily {
+        daily_comic.get_date(&curr_date()).await
+    } else {
+        let rand_date = random_comic_date(&mut thread_rng());
+        info!("Chose random comic date: {rand_date}");
+        rand_date
+    };
 
     let location = format!("/{}", rand_date.format(SRC_DATE_FMT));
     HttpResponse::TemporaryRedirect()
@@ -64,18 +695,118 @@ async fn random_comic() -> impl Responder {
         .finish()
 }
 
+/// Redirect to the comic with the sequence number given in the URL.
+///
+/// The first comic is number 1, and comics were published daily, so each number always maps to
+/// the same comic. Hence, the redirection is permanent.
+#[get("/n/{number}")]
+async fn numbered_comic(path: web::Path<String>) -> impl Responder {
+    let number = path.into_inner();
+    match number.parse().ok().and_then(number_to_date) {
+        Some(date) => HttpResponse::MovedPermanently()
+            .append_header((LOCATION, format!("/{}", date.format(SRC_DATE_FMT))))
+            .finish(),
+        None => {
+            info!("Invalid comic number requested: ({number})");
+            serve_404(None)
+        }
+    }
+}
+
+/// Form for choosing a colour theme
+#[derive(Deserialize)]
+struct ThemeForm {
+    /// The chosen colour theme
+    theme: Theme,
+}
+
+/// Remember the chosen colour theme in a cookie, and go back to the page it was chosen on.
+#[post("/theme")]
+async fn set_theme(req: HttpRequest, form: web::Form<ThemeForm>) -> impl Responder {
+    let location = get_return_path(req.headers().get(REFERER));
+    info!("Setting theme to {}", form.theme.as_str());
+    HttpResponse::SeeOther()
+        .cookie(form.theme.cookie())
+        .append_header((LOCATION, location))
+        .finish()
+}
+
+/// Mark the comic in the given URL as a favorite if it isn't one, and unmark it otherwise.
+///
+/// The favorites are remembered in a cookie, and the client goes back to the page on which the
+/// favorite was toggled.
+#[post("/favorite/{date}")]
+async fn toggle_favorite(
+    req: HttpRequest,
+    key: web::Data<CookieKey>,
+    mut favorites: Favorites,
+    path: web::Path<String>,
+) -> impl Responder {
+    let date_str = path.into_inner();
+    let Ok(date) = str_to_date(&date_str, SRC_DATE_FMT) else {
+        info!("Invalid date requested for favorite: ({date_str})");
+        return serve_404(None);
+    };
+
+    if favorites.toggle(date) {
+        info!("Marking comic for {date} as a favorite");
+    } else {
+        info!("Unmarking comic for {date} as a favorite");
+    }
+    let location = get_return_path(req.headers().get(REFERER));
+    HttpResponse::SeeOther()
+        .cookie(favorites.cookie(&key))
+        .append_header((LOCATION, location))
+        .finish()
+}
+
+/// Serve the favorite comics of the client.
+#[get("/favorites")]
+async fn favorite_comics(
+    viewer: web::Data<Viewer<CachePool>>,
+    favorites: Favorites,
+) -> impl Responder {
+    viewer.serve_favorites(&favorites).await
+}
+
+/// Serve the comics recently viewed by the client.
+#[get("/history")]
+async fn comic_history(viewer: web::Data<Viewer<CachePool>>, history: History) -> impl Responder {
+    viewer.serve_history(&history).await
+}
+
 /// Serve CSS after minification.
+///
+/// The CSS can be requested by its fingerprinted URL, in which case it's cached forever.
 #[get("/{path}.css")]
-async fn minify_css(path: web::Path<String>) -> impl Responder {
-    let stem = path.into_inner();
-    let css_path = Path::new(STATIC_DIR).join(stem + ".css");
-    serve_css(&css_path).await
+async fn minify_css(
+    assets: web::Data<CssAssets>,
+    if_none_match: Option<web::Header<IfNoneMatch>>,
+    if_modified_since: Option<web::Header<IfModifiedSince>>,
+    accept_encoding: Option<web::Header<AcceptEncoding>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let (name, immutable) = resolve_asset(&(path.into_inner() + ".css"));
+    assets.serve(
+        &name,
+        if_none_match.as_deref(),
+        if_modified_since.as_deref(),
+        accept_encoding.as_deref(),
+        immutable,
+    )
 }
 
 /// Serve JS after minification.
+///
+/// The JS can be requested by its fingerprinted URL, in which case it's cached forever.
 #[get("/{path}.js")]
 async fn minify_js(path: web::Path<String>) -> impl Responder {
-    let stem = path.into_inner();
-    let js_path = Path::new(STATIC_DIR).join(stem + ".js");
-    serve_js(&js_path).await
+    let (name, immutable) = resolve_asset(&(path.into_inner() + ".js"));
+    let mut resp = serve_js(&Path::new(STATIC_DIR).join(name)).await;
+    if immutable && resp.status().is_success() {
+        if let Ok((name, value)) = immutable_cache_control().try_into_pair() {
+            resp.headers_mut().insert(name, value);
+        }
+    }
+    resp
 }
@@ -0,0 +1,229 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Readiness checks for the server's dependencies
+use std::future::Future;
+use std::time::Duration;
+
+use actix_web::{http::header::ContentType, rt::time::timeout, HttpResponse};
+use awc::Client;
+use futures::join;
+use tracing::warn;
+
+use crate::constants::SRC_BASE_URL;
+use crate::db::CacheBackend;
+use crate::errors::{AppError, AppResult};
+
+/// Checker for whether the server's dependencies are available
+pub struct ReadinessProbe<T: CacheBackend + 'static> {
+    /// The database pool to ping, if caching is enabled
+    db: Option<T>,
+    /// The client for checking the comic source
+    http_client: Client,
+    /// The URL to the CDX API of the comic source, if it should be checked
+    cdx_url: Option<String>,
+    /// The timeout for each check
+    timeout: Duration,
+}
+
+impl<T: CacheBackend + 'static> ReadinessProbe<T> {
+    /// Initialize the readiness probe.
+    ///
+    /// # Arguments
+    /// * `db` - The database pool to ping, if caching is enabled
+    /// * `http_client` - The client for checking the comic source
+    /// * `cdx_url` - The URL to the CDX API of the comic source, if it should be checked
+    /// * `timeout` - The timeout for each check
+    pub fn new(
+        db: Option<T>,
+        http_client: Client,
+        cdx_url: Option<String>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            db,
+            http_client,
+            cdx_url,
+            timeout,
+        }
+    }
+
+    /// Check whether the database is reachable, if caching is enabled.
+    async fn check_db(&self) -> AppResult<()> {
+        if let Some(db) = &self.db {
+            db.ping().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check whether the comic source is reachable, if enabled.
+    async fn check_source(&self) -> AppResult<()> {
+        let Some(cdx_url) = &self.cdx_url else {
+            return Ok(());
+        };
+
+        // A HEAD request avoids transferring the (possibly large) CDX API response.
+        let resp = self
+            .http_client
+            .head(cdx_url.replace("{}", SRC_BASE_URL))
+            .send()
+            .await?;
+        let status = resp.status();
+        if status.is_server_error() {
+            Err(AppError::Scrape(format!(
+                "Comic source responded with: {status}"
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run a single check, bounded by the timeout.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the checked dependency
+    /// * `check` - The check to run
+    async fn run_check(
+        &self,
+        name: &str,
+        check: impl Future<Output = AppResult<()>>,
+    ) -> Result<(), String> {
+        match timeout(self.timeout, check).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => Err(format!("{name} check failed: {err}")),
+            Err(_) => Err(format!("{name} check timed out")),
+        }
+    }
+
+    /// Serve whether the server is ready to handle requests.
+    ///
+    /// If any dependency is unavailable, then a 503 service unavailable response is returned.
+    pub async fn serve_readiness(&self) -> HttpResponse {
+        let (db, source) = join!(
+            self.run_check("Database", self.check_db()),
+            self.run_check("Comic source", self.check_source())
+        );
+        let failures: Vec<_> = [db, source].into_iter().filter_map(Result::err).collect();
+
+        if failures.is_empty() {
+            HttpResponse::Ok()
+                .content_type(ContentType::plaintext())
+                .body("ready")
+        } else {
+            let failures = failures.join("\n");
+            warn!("Not ready: {failures}");
+            HttpResponse::ServiceUnavailable()
+                .content_type(ContentType::plaintext())
+                .body(format!("not ready\n{failures}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use actix_web::http::StatusCode;
+    use redis::{Cmd, Value};
+    use redis_test::{MockCmd, MockRedisConnection};
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::{DB_TIMEOUT, READY_TIMEOUT};
+    use crate::db::mock::MockPool;
+
+    /// Timeout (in milliseconds) for readiness checks that are expected to time out
+    const SHORT_READY_TIMEOUT: u64 = 100;
+
+    #[test_case(true, StatusCode::OK; "database up")]
+    #[test_case(false, StatusCode::SERVICE_UNAVAILABLE; "database error")]
+    #[actix_web::test]
+    /// Test whether readiness reflects the result of pinging the database.
+    ///
+    /// # Arguments
+    /// * `db_up` - Whether the database responds to the ping successfully
+    /// * `expected_status` - The expected HTTP status code
+    async fn test_db_readiness(db_up: bool, expected_status: StatusCode) {
+        let reply = if db_up {
+            Ok(Value::SimpleString("PONG".into()))
+        } else {
+            Err(redis::RedisError::from((
+                redis::ErrorKind::ResponseError,
+                "Manual error",
+            )))
+        };
+        let ping_cmd = MockCmd::new(Cmd::new().arg("PING"), reply);
+        let db = MockPool::new(1);
+        if let Err((_, err)) = db.add(MockRedisConnection::new([ping_cmd])).await {
+            panic!("Couldn't add mock connection to pool: {err}");
+        }
+
+        let probe = ReadinessProbe::new(
+            Some(db),
+            Client::default(),
+            None,
+            Duration::from_secs(READY_TIMEOUT),
+        );
+        let resp = probe.serve_readiness().await;
+        assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    }
+
+    #[actix_web::test]
+    /// Test whether readiness responds promptly when the database hangs.
+    async fn test_db_readiness_timeout() {
+        // A pool with no connections never gives a connection, just like a hung database.
+        let db = MockPool::new(1);
+        let probe = ReadinessProbe::new(
+            Some(db),
+            Client::default(),
+            None,
+            Duration::from_millis(SHORT_READY_TIMEOUT),
+        );
+
+        let start = Instant::now();
+        let resp = probe.serve_readiness().await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Hung database reported as ready"
+        );
+        assert!(
+            start.elapsed() < Duration::from_secs(DB_TIMEOUT),
+            "Readiness check didn't time out promptly"
+        );
+    }
+
+    #[test_case(StatusCode::OK, StatusCode::OK; "source up")]
+    #[test_case(StatusCode::BAD_GATEWAY, StatusCode::SERVICE_UNAVAILABLE; "source down")]
+    #[actix_web::test]
+    /// Test whether readiness reflects the availability of the comic source.
+    ///
+    /// # Arguments
+    /// * `source_status` - The HTTP status code returned by the comic source
+    /// * `expected_status` - The expected HTTP status code
+    async fn test_source_readiness(source_status: StatusCode, expected_status: StatusCode) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(source_status.as_u16()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let probe = ReadinessProbe::<MockPool>::new(
+            None,
+            Client::default(),
+            Some(format!("{}/cdx", mock_server.uri())),
+            Duration::from_secs(READY_TIMEOUT),
+        );
+        let resp = probe.serve_readiness().await;
+        assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    }
+}
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Recently viewed comics of clients, stored in a signed cookie
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{CacheControl, CacheDirective, TryIntoHeaderPair},
+    web, FromRequest, HttpRequest, HttpResponse,
+};
+use chrono::NaiveDate;
+use tracing::error;
+
+use crate::constants::{HISTORY_COOKIE, HISTORY_COOKIE_MAX_AGE, HISTORY_LEN};
+use crate::cookies::{get_signed_dates, signed_dates_cookie, CookieKey};
+
+/// The comics that a client has recently viewed, the most recent first
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct History(Vec<NaiveDate>);
+
+impl History {
+    /// Get the dates of the recently viewed comics, the most recent first.
+    pub fn dates(&self) -> &[NaiveDate] {
+        &self.0
+    }
+
+    /// Record a comic as the most recently viewed one, dropping the oldest ones beyond the limit.
+    ///
+    /// This returns whether the history changed, which it doesn't if the comic was already the
+    /// most recent one.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    pub fn record(&mut self, date: NaiveDate) -> bool {
+        if self.0.first() == Some(&date) {
+            return false;
+        }
+        self.0.retain(|viewed| *viewed != date);
+        self.0.insert(0, date);
+        self.0.truncate(HISTORY_LEN);
+        true
+    }
+}
+
+impl FromRequest for History {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Get the history of the client, which is empty if the cookie has been tampered with.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self(get_signed_dates(req, HISTORY_COOKIE))))
+    }
+}
+
+/// Record a comic in the history of the client it's served to, by updating the cookie in the
+/// response.
+///
+/// The cookie is specific to the client, so the response is marked as private whenever it's set,
+/// which stops shared caches from serving it to other clients.
+///
+/// # Arguments
+/// * `req` - The request for the comic
+/// * `date` - The date of the comic
+/// * `resp` - The response with the comic
+pub fn record_view(req: &HttpRequest, date: NaiveDate, resp: &mut HttpResponse) {
+    let Some(key) = req.app_data::<web::Data<CookieKey>>() else {
+        return;
+    };
+    let Ok(mut history) = History::extract(req).into_inner();
+    if !history.record(date) {
+        return;
+    }
+
+    let cookie = signed_dates_cookie(key, HISTORY_COOKIE, &history.0, HISTORY_COOKIE_MAX_AGE);
+    if let Err(err) = resp.add_cookie(&cookie) {
+        error!("Couldn't set the history cookie: {err}");
+        return;
+    }
+    let policy = CacheControl(vec![CacheDirective::Private, CacheDirective::NoCache]);
+    match policy.try_into_pair() {
+        Ok((name, value)) => {
+            resp.headers_mut().insert(name, value);
+        }
+        Err(err) => error!("Couldn't create Cache-Control header: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::{
+        http::header::{CACHE_CONTROL, SET_COOKIE},
+        test::TestRequest,
+    };
+    use test_case::test_case;
+
+    #[test]
+    /// Test whether viewed comics are recorded most recent first, without duplicates.
+    fn test_record() {
+        let dates: Vec<_> = (1..=3)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+        let mut history = History::default();
+        for date in &dates {
+            assert!(history.record(*date), "New comic wasn't recorded");
+        }
+        assert!(
+            !history.record(dates[2]),
+            "Most recent comic was recorded again"
+        );
+        assert!(history.record(dates[0]), "Earlier comic wasn't moved up");
+        assert_eq!(
+            history.dates(),
+            [dates[0], dates[2], dates[1]],
+            "Wrong history"
+        );
+
+        for date in dates[0].iter_days().skip(10).take(HISTORY_LEN) {
+            history.record(date);
+        }
+        assert_eq!(history.dates().len(), HISTORY_LEN, "History is too long");
+        assert!(
+            !history.dates().contains(&dates[0]),
+            "Oldest comic wasn't dropped"
+        );
+    }
+
+    #[test_case(false; "new comic")]
+    #[test_case(true; "already most recent")]
+    /// Test whether the history cookie is set only if the history changes, making the response
+    /// private.
+    ///
+    /// # Arguments
+    /// * `viewed` - Whether the comic is already the most recently viewed one
+    fn test_record_view(viewed: bool) {
+        let key = CookieKey::new(Some("0123456789abcdef0123456789abcdef")).unwrap();
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut req = TestRequest::default();
+        if viewed {
+            let cookie = signed_dates_cookie(&key, HISTORY_COOKIE, &[date], 60);
+            req = req.cookie(cookie);
+        }
+        let req = req.app_data(web::Data::new(key)).to_http_request();
+
+        let mut resp = HttpResponse::Ok().finish();
+        record_view(&req, date, &mut resp);
+        assert_eq!(
+            resp.headers().contains_key(SET_COOKIE),
+            !viewed,
+            "Wrong setting of the history cookie"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(CACHE_CONTROL)
+                .and_then(|value| value.to_str().ok()),
+            (!viewed).then_some("private, no-cache"),
+            "Wrong caching policy"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Translations of comic pages into the languages preferred by clients
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{AcceptLanguage, Header, Preference},
+    web, FromRequest, HttpRequest,
+};
+use chrono::{Locale, NaiveDate};
+use serde::Deserialize;
+
+use crate::constants::DISP_DATE_FMT;
+
+/// The UI strings of comic pages in a language
+#[derive(Debug)]
+pub struct Strings {
+    /// The format for displaying the date of a comic
+    pub date_fmt: &'static str,
+    /// The title of a comic without a title, before its date
+    pub comic_strip_on: &'static str,
+    /// The description of a comic, before its date
+    pub description_prefix: &'static str,
+    /// The description of a comic, after its date
+    pub description_suffix: &'static str,
+    /// The alt text of a comic's image, before its date
+    pub comic_for: &'static str,
+    /// The label of a comic's transcript
+    pub transcript: &'static str,
+    /// The label of a comic's tags
+    pub tags: &'static str,
+    /// The label of the navigation buttons
+    pub navigation: &'static str,
+    /// The label of the button for the first comic
+    pub first_comic: &'static str,
+    /// The label of the button for the previous comic
+    pub previous_comic: &'static str,
+    /// The text of the button for a random comic
+    pub random: &'static str,
+    /// The label of the button for a random comic
+    pub random_comic: &'static str,
+    /// The label of the button for the next comic
+    pub next_comic: &'static str,
+    /// The label of the button for the latest comic
+    pub latest_comic: &'static str,
+    /// The label of the search box
+    pub search_comics: &'static str,
+    /// The text of the search button
+    pub search: &'static str,
+    /// The text of the link to the original comic
+    pub original_comic: &'static str,
+    /// The label of the link to the original comic
+    pub original_comic_link: &'static str,
+    /// The text of the link to download the comic's image
+    pub download: &'static str,
+    /// The label of the link to download the comic's image
+    pub download_comic: &'static str,
+    /// The label of the star that marks the comic as a favorite
+    pub add_favorite: &'static str,
+    /// The label of the star that unmarks the comic as a favorite
+    pub remove_favorite: &'static str,
+    /// The abbreviation shown before the sequence number of the comic
+    pub number_prefix: &'static str,
+    /// The label of the link to the comic by its sequence number
+    pub comic_number: &'static str,
+    /// The notice shown when redirected to the first comic from a date before it
+    pub before_first_comic: &'static str,
+    /// The notice shown when redirected to the latest comic from a date after it
+    pub after_latest_comic: &'static str,
+}
+
+/// UI strings in English
+const ENGLISH: Strings = Strings {
+    date_fmt: DISP_DATE_FMT,
+    comic_strip_on: "Comic Strip on",
+    description_prefix: "Dilbert comic strip on",
+    description_suffix: ", viewed using a simple comic viewer.",
+    comic_for: "Comic for",
+    transcript: "Transcript",
+    tags: "Tags",
+    navigation: "Navigation buttons",
+    first_comic: "First comic",
+    previous_comic: "Previous comic",
+    random: "Random",
+    random_comic: "Random comic",
+    next_comic: "Next comic",
+    latest_comic: "Latest comic",
+    search_comics: "Search comics",
+    search: "Search",
+    original_comic: "Original Comic",
+    original_comic_link: "Link to the original comic",
+    download: "Download",
+    download_comic: "Download the comic",
+    add_favorite: "Add to favorites",
+    remove_favorite: "Remove from favorites",
+    number_prefix: "No.",
+    comic_number: "Link to the comic by its number",
+    before_first_comic:
+        "The requested date is before the first comic, so the first comic is shown instead.",
+    after_latest_comic:
+        "The requested date is after the latest comic, so the latest comic is shown instead.",
+};
+
+/// UI strings in German
+const GERMAN: Strings = Strings {
+    date_fmt: "%A, %-d. %B %Y",
+    comic_strip_on: "Comicstrip vom",
+    description_prefix: "Dilbert-Comicstrip vom",
+    description_suffix: ", angezeigt mit einem einfachen Comic-Viewer.",
+    comic_for: "Comic vom",
+    transcript: "Transkript",
+    tags: "Schlagwörter",
+    navigation: "Navigationsschaltflächen",
+    first_comic: "Erster Comic",
+    previous_comic: "Vorheriger Comic",
+    random: "Zufällig",
+    random_comic: "Zufälliger Comic",
+    next_comic: "Nächster Comic",
+    latest_comic: "Neuester Comic",
+    search_comics: "Comics durchsuchen",
+    search: "Suchen",
+    original_comic: "Original-Comic",
+    original_comic_link: "Link zum Original-Comic",
+    download: "Herunterladen",
+    download_comic: "Comic herunterladen",
+    add_favorite: "Zu Favoriten hinzufügen",
+    remove_favorite: "Aus Favoriten entfernen",
+    number_prefix: "Nr.",
+    comic_number: "Link zum Comic über seine Nummer",
+    before_first_comic: "Das angefragte Datum liegt vor dem ersten Comic, daher wird stattdessen der erste Comic angezeigt.",
+    after_latest_comic: "Das angefragte Datum liegt nach dem neuesten Comic, daher wird stattdessen der neueste Comic angezeigt.",
+};
+
+/// UI strings in French
+const FRENCH: Strings = Strings {
+    date_fmt: "%A %-d %B %Y",
+    comic_strip_on: "Bande dessinée du",
+    description_prefix: "Bande dessinée Dilbert du",
+    description_suffix: ", affichée avec une simple visionneuse de bandes dessinées.",
+    comic_for: "Bande dessinée du",
+    transcript: "Transcription",
+    tags: "Étiquettes",
+    navigation: "Boutons de navigation",
+    first_comic: "Première bande dessinée",
+    previous_comic: "Bande dessinée précédente",
+    random: "Au hasard",
+    random_comic: "Bande dessinée au hasard",
+    next_comic: "Bande dessinée suivante",
+    latest_comic: "Dernière bande dessinée",
+    search_comics: "Rechercher des bandes dessinées",
+    search: "Rechercher",
+    original_comic: "Bande dessinée originale",
+    original_comic_link: "Lien vers la bande dessinée originale",
+    download: "Télécharger",
+    download_comic: "Télécharger la bande dessinée",
+    add_favorite: "Ajouter aux favoris",
+    remove_favorite: "Retirer des favoris",
+    number_prefix: "N°",
+    comic_number: "Lien vers la bande dessinée par son numéro",
+    before_first_comic: "La date demandée est antérieure à la première bande dessinée, qui est donc affichée à la place.",
+    after_latest_comic: "La date demandée est postérieure à la dernière bande dessinée, qui est donc affichée à la place.",
+};
+
+/// UI strings in Spanish
+const SPANISH: Strings = Strings {
+    date_fmt: "%A, %-d de %B de %Y",
+    comic_strip_on: "Tira cómica del",
+    description_prefix: "Tira cómica de Dilbert del",
+    description_suffix: ", vista con un sencillo visor de cómics.",
+    comic_for: "Cómic del",
+    transcript: "Transcripción",
+    tags: "Etiquetas",
+    navigation: "Botones de navegación",
+    first_comic: "Primer cómic",
+    previous_comic: "Cómic anterior",
+    random: "Aleatorio",
+    random_comic: "Cómic aleatorio",
+    next_comic: "Cómic siguiente",
+    latest_comic: "Último cómic",
+    search_comics: "Buscar cómics",
+    search: "Buscar",
+    original_comic: "Cómic original",
+    original_comic_link: "Enlace al cómic original",
+    download: "Descargar",
+    download_comic: "Descargar el cómic",
+    add_favorite: "Añadir a favoritos",
+    remove_favorite: "Quitar de favoritos",
+    number_prefix: "N.º",
+    comic_number: "Enlace al cómic por su número",
+    before_first_comic: "La fecha solicitada es anterior al primer cómic, así que se muestra el primer cómic en su lugar.",
+    after_latest_comic: "La fecha solicitada es posterior al último cómic, así que se muestra el último cómic en su lugar.",
+};
+
+/// A language into which comic pages are translated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Lang {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+}
+
+impl Lang {
+    /// Get the language given its (primary) language code, if it's supported.
+    ///
+    /// # Arguments
+    /// * `code` - The language code, such as "en" or "en-GB"
+    pub fn from_code(code: &str) -> Option<Self> {
+        let primary = code.split('-').next().unwrap_or(code);
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Self::English),
+            "de" => Some(Self::German),
+            "fr" => Some(Self::French),
+            "es" => Some(Self::Spanish),
+            _ => None,
+        }
+    }
+
+    /// Get the language code, as used in the "lang" attribute of HTML.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::German => "de",
+            Self::French => "fr",
+            Self::Spanish => "es",
+        }
+    }
+
+    /// Get the UI strings in this language.
+    pub fn strings(self) -> &'static Strings {
+        match self {
+            Self::English => &ENGLISH,
+            Self::German => &GERMAN,
+            Self::French => &FRENCH,
+            Self::Spanish => &SPANISH,
+        }
+    }
+
+    /// Format the date of a comic for display in this language.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    pub fn format_date(self, date: &NaiveDate) -> String {
+        let locale = match self {
+            Self::English => Locale::en_US,
+            Self::German => Locale::de_DE,
+            Self::French => Locale::fr_FR,
+            Self::Spanish => Locale::es_ES,
+        };
+        date.format_localized(self.strings().date_fmt, locale)
+            .to_string()
+    }
+
+    /// Choose the language for a client.
+    ///
+    /// An explicitly requested language takes priority over the client's preferred languages. If
+    /// neither are supported, then the default language is used.
+    ///
+    /// # Arguments
+    /// * `requested` - The language code requested through the URL, if any
+    /// * `accept_language` - The languages preferred by the client, if given
+    pub fn negotiate(requested: Option<&str>, accept_language: Option<&AcceptLanguage>) -> Self {
+        if let Some(lang) = requested.and_then(Self::from_code) {
+            return lang;
+        }
+        accept_language
+            .into_iter()
+            .flat_map(AcceptLanguage::ranked)
+            .find_map(|pref| match pref {
+                Preference::Specific(tag) => Self::from_code(tag.as_str()),
+                Preference::Any => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Query parameters for choosing a language
+#[derive(Deserialize)]
+struct LangQuery {
+    /// The code of the requested language
+    lang: Option<String>,
+}
+
+impl FromRequest for Lang {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Choose the language from the "lang" query parameter and the "Accept-Language" header.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let query = web::Query::<LangQuery>::from_query(req.query_string()).ok();
+        let requested = query.as_ref().and_then(|query| query.lang.as_deref());
+        let accept_language = AcceptLanguage::parse(req).ok();
+        ready(Ok(Self::negotiate(requested, accept_language.as_ref())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::http::header::{q, QualityItem};
+    use test_case::test_case;
+
+    #[test_case(None, &[], Lang::English; "nothing")]
+    #[test_case(Some("de"), &[("fr", 1.0)], Lang::German; "requested")]
+    #[test_case(Some("xx"), &[("fr", 1.0)], Lang::French; "unsupported request")]
+    #[test_case(None, &[("xx", 1.0), ("es-MX", 0.8)], Lang::Spanish; "first supported")]
+    #[test_case(None, &[("en", 0.5), ("de-AT", 0.9)], Lang::German; "ranked by quality")]
+    #[test_case(None, &[("xx", 1.0)], Lang::English; "unsupported")]
+    /// Test the choice of language for a client.
+    ///
+    /// # Arguments
+    /// * `requested` - The explicitly requested language, if any
+    /// * `preferred` - The client's preferred languages with their qualities
+    /// * `expected` - The expected language
+    fn test_negotiate(requested: Option<&str>, preferred: &[(&str, f32)], expected: Lang) {
+        let accept_language = AcceptLanguage(
+            preferred
+                .iter()
+                .map(|(code, quality)| {
+                    QualityItem::new(Preference::Specific(code.parse().unwrap()), q(*quality))
+                })
+                .collect(),
+        );
+        assert_eq!(
+            Lang::negotiate(requested, Some(&accept_language)),
+            expected,
+            "Wrong language chosen"
+        );
+    }
+
+    #[test_case(Lang::English, "Saturday January 01, 2000"; "English")]
+    #[test_case(Lang::German, "Samstag, 1. Januar 2000"; "German")]
+    #[test_case(Lang::French, "samedi 1 janvier 2000"; "French")]
+    #[test_case(Lang::Spanish, "sábado, 1 de enero de 2000"; "Spanish")]
+    /// Test the display of dates in each language.
+    ///
+    /// # Arguments
+    /// * `lang` - The language
+    /// * `expected` - The expected display of the date
+    fn test_format_date(lang: Lang, expected: &str) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        assert_eq!(lang.format_date(&date), expected, "Wrong display of date");
+    }
+}
@@ -4,43 +4,152 @@
 
 //! The public interface for running the viewer app
 //!
-//! This file is separated from `main.rs` for the sole purpose of integration testing.
+//! This file is separated from `main.rs` for integration testing, and for embedding the viewer in
+//! other apps.
+mod admin;
 mod app;
+mod assets;
+mod cache_control;
+mod cbz;
+mod circuit;
+mod client;
+mod client_ip;
+mod config;
 mod constants;
+mod cookies;
+mod csp;
 mod datetime;
 mod db;
+mod dump;
 mod errors;
+mod favorites;
+mod graphql;
 mod handlers;
+mod health;
+mod history;
+mod i18n;
 mod logging;
-mod scraper;
+mod memory_db;
+mod metrics;
+mod overlay;
+mod prefs;
+mod preview;
+mod random;
+mod rate_limit;
+mod reporting;
+mod retry;
+mod scrapers;
+mod singleflight;
 mod templates;
+mod theme;
+mod throttle;
+mod thumbnail;
+mod tls;
+mod transcode;
 
-use actix_files::Files;
+use actix_cors::Cors;
+use actix_files::{Files, NamedFile};
 use actix_web::{
     body::MessageBody,
-    dev::{ServiceRequest, ServiceResponse},
-    middleware::{Compress, DefaultHeaders, Logger},
-    web, App, Error as WebError, HttpServer,
+    dev::{Server as ActixServer, ServerHandle, ServiceRequest, ServiceResponse},
+    http::{header::TryIntoHeaderPair, Uri},
+    middleware::{from_fn, Compress, Condition, Logger},
+    rt::{signal, spawn},
+    web, App, Error as WebError, FromRequest, HttpServer,
 };
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{select, Either};
+use portpicker::{is_free, pick_unused_port};
 use tracing::{error, info};
 
-use crate::app::{serve_404, Viewer};
-use crate::constants::{ARC_BASE_URL, CDX_URL, CSP, STATIC_DIR, STATIC_URL};
-use crate::db::get_db_pool;
-use crate::handlers::{comic_page, last_comic, minify_css, minify_js, random_comic};
+use crate::admin::{admin_middleware, AdminToken};
+use crate::app::{
+    check_templates, get_warm_dates, render_robots_txt, serve_404, serve_themed_404, Viewer,
+};
+use crate::assets::{
+    immutable_cache_control, resolve_asset, vendor_css, AssetManifest, CssAssets, GeneratedFile,
+};
+use crate::cache_control::cache_control_middleware;
+use crate::circuit::CircuitBreaker;
+use crate::client::{build_client, find_invalid_header};
+use crate::client_ip::{client_ip, TrustedProxies};
+use crate::constants::{
+    CORS_MAX_AGE, CSS_FILE, MEM_CACHE_SIZE, PORT, STATIC_DIR, STATIC_URL, UNIX_SOCKET_PREFIX,
+    VENDOR_CSS_FILE, VENDOR_CSS_INTEGRITY, VENDOR_CSS_URL, WARM_INTERVAL,
+};
+use crate::cookies::CookieKey;
+use crate::csp::{csp_middleware, CspTemplate};
+use crate::db::{get_db_cluster_pool, get_db_pool, CachePool, DbPool, DbTls};
+use crate::dump::{export_dump, import_dump};
+use crate::errors::DbInitError;
+use crate::graphql::build_schema;
+use crate::handlers::{
+    admin_metrics, api_comic, api_comics, archive, archive_month, archive_year, comic_download,
+    comic_feed, comic_history, comic_image, comic_image_variant, comic_oembed, comic_page,
+    comic_preview, comic_range, comic_search, comic_tag, comic_text, comic_thumbnail, comic_week,
+    compact_comic_page, export_cbz, favorite_comics, graphql_query, last_comic, liveness,
+    minify_css, minify_js, month_grid, numbered_comic, on_this_day, purge_cache, purge_comic,
+    random_comic, readiness, reset_stats, robots_txt, set_theme, slash_comic_page, toggle_favorite,
+    warm_cache, web_manifest,
+};
+use crate::health::ReadinessProbe;
 use crate::logging::TracingWrapper;
+use crate::memory_db::MemoryPool;
+use crate::overlay::Attribution;
+use crate::random::DailyComic;
+use crate::rate_limit::{rate_limit_middleware, RateLimit, RateLimitStore, RateLimiter};
+use crate::reporting::init_reporting;
+use crate::scrapers::{ComicSource, DilbertSource};
+use crate::theme::Theme;
+use crate::throttle::Throttle;
+use crate::tls::load_tls_config;
+
+pub use crate::client_ip::ForwardedHeader;
+pub use crate::config::Config;
+pub use crate::logging::{get_otel_layer, JsonFormat, OtelGuard};
 
 /// Handle invalid URLs by sending 404s.
 ///
-/// This is to be invoked when the actix static file service doesn't find a file.
+/// This is to be invoked when the actix static file service doesn't find a file. Since the
+/// service doesn't know the fingerprinted URLs of static files, these are served here.
 async fn invalid_url(req: ServiceRequest) -> Result<ServiceResponse, WebError> {
     let (http_req, _payload) = req.into_parts();
-    Ok(ServiceResponse::new(http_req, serve_404(None)))
+    if let Some(requested) = http_req.path().strip_prefix(STATIC_URL) {
+        let (name, immutable) = resolve_asset(requested);
+        if name != requested && !name.contains('/') {
+            if let Ok(file) = NamedFile::open_async(Path::new(STATIC_DIR).join(name)).await {
+                let mut resp = file.into_response(&http_req);
+                if immutable {
+                    let (name, value) = immutable_cache_control().try_into_pair()?;
+                    resp.headers_mut().insert(name, value);
+                }
+                return Ok(ServiceResponse::new(http_req, resp));
+            }
+        }
+    }
+
+    let Ok(theme) = Theme::extract(&http_req).await;
+    Ok(ServiceResponse::new(
+        http_req,
+        serve_themed_404(None, theme),
+    ))
 }
 
 /// Get the static file handling service.
+///
+/// The files have strong ETags and Last-Modified headers, so that clients can revalidate their
+/// cached copies without downloading them again.
 fn get_static_service() -> Files {
-    let mut service = Files::new(STATIC_URL, String::from(STATIC_DIR)).default_handler(invalid_url);
+    let mut service = Files::new(STATIC_URL, String::from(STATIC_DIR))
+        .use_etag(true)
+        .use_last_modified(true)
+        .default_handler(invalid_url);
     if let Ok(bytes) = serve_404(None).into_body().try_into_bytes() {
         if let Ok(html) = std::str::from_utf8(&bytes) {
             service = service.index_file(html);
@@ -53,68 +162,638 @@ fn get_static_service() -> Files {
     service
 }
 
-/// Run the server.
+/// Get the CORS middleware for the API, which allows requests from the given origins.
+///
+/// # Arguments
+/// * `origins` - The allowed origins, where "*" allows any origin
+fn get_cors(origins: &[String]) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(["GET"])
+        .max_age(CORS_MAX_AGE);
+    for origin in origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+/// Choose the port to use, with a fallback.
+///
+/// # Arguments
+/// * `port` - The port given by the user, if any
+fn choose_port(port: Option<u16>) -> u16 {
+    if let Some(port) = port {
+        port
+    } else if is_free(PORT) {
+        PORT
+    } else if let Some(port) = pick_unused_port() {
+        port
+    } else {
+        panic!("Couldn't find any unused TCP port")
+    }
+}
+
+/// The address to which the server is bound
+enum BindAddress {
+    /// A TCP address, including the port
+    Tcp(String),
+    /// The path to a Unix domain socket
+    Unix(PathBuf),
+}
+
+impl BindAddress {
+    /// Get the address to bind to.
+    ///
+    /// # Arguments
+    /// * `host` - The host given by the user, which can be a Unix socket path prefixed by "unix:"
+    /// * `port` - The port given by the user, if any
+    fn new(host: &str, port: Option<u16>) -> Self {
+        if let Some(path) = host.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return Self::Unix(PathBuf::from(path));
+        }
+
+        let port = choose_port(port);
+        if host.contains(':') {
+            // IPv6 addresses need to be enclosed in brackets to specify the port.
+            Self::Tcp(format!("[{host}]:{port}"))
+        } else {
+            Self::Tcp(format!("{host}:{port}"))
+        }
+    }
+}
+
+/// Remove a Unix domain socket left behind by a previous run, so that it can be bound again.
+///
+/// Anything other than a socket at the path is left as is.
 ///
 /// # Arguments
-/// * `host` - The host and port where to start the server
-/// * `db_url` - The optional URL to the database
-/// * `source_url` - The optional URL to the custom comic source
-/// * `cdx_url` - The optional URL to the custom comic source
-/// * `workers` - The optional number of workers to use
-pub async fn run(
-    host: String,
-    db_url: Option<String>,
-    source_url: Option<String>,
-    cdx_url: Option<String>,
-    workers: Option<usize>,
-) -> std::io::Result<()> {
-    // Create all worker-shared (i.e. thread-safe) structs here
-    let db_pool = if let Some(db_url) = db_url {
-        match get_db_pool(db_url) {
-            Ok(pool) => Some(pool),
+/// * `path` - The path to the socket
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+/// Get the pool of connections to Redis, which is a cluster if the URLs to its seed nodes are
+/// given.
+///
+/// # Arguments
+/// * `config` - The configuration with the database URLs and settings
+///
+/// # Returns
+/// The result of creating the pool, or None if no database URL is given
+fn get_redis_pool(config: &Config) -> Option<Result<DbPool, DbInitError>> {
+    let timeout = Duration::from_secs(config.db_timeout);
+    let load_tls = || {
+        DbTls::load(
+            config.db_tls_verify,
+            config.db_ca_cert.as_deref(),
+            config.db_client_cert.as_deref(),
+            config.db_client_key.as_deref(),
+        )
+    };
+    let result = if config.db_cluster_urls.is_empty() {
+        let url = config.db_url.clone()?;
+        load_tls().and_then(|tls| get_db_pool(url, timeout, &tls))
+    } else {
+        let urls = config.db_cluster_urls.clone();
+        load_tls().and_then(|tls| get_db_cluster_pool(urls, timeout, &tls))
+    };
+    Some(result.map(|pool| pool.with_msgpack(config.db_msgpack)))
+}
+
+/// Get the cache for comics, falling back to an in-memory cache if the database is unavailable.
+///
+/// # Arguments
+/// * `config` - The configuration with the database URLs
+fn get_cache_pool(config: &Config) -> CachePool {
+    if let Some(result) = get_redis_pool(config) {
+        match result {
+            Ok(pool) => CachePool::Redis(pool),
             Err(err) => {
-                error!("Couldn't create DB pool: {err}. No caching will be available.",);
-                None
+                error!("Couldn't create DB pool: {err}. Falling back to an in-memory cache.");
+                CachePool::Memory(MemoryPool::new(MEM_CACHE_SIZE))
             }
         }
     } else {
-        error!("No DB URL given. No caching will be available.");
-        None
-    };
+        error!("No DB URL given. Falling back to an in-memory cache.");
+        CachePool::Memory(MemoryPool::new(MEM_CACHE_SIZE))
+    }
+}
+
+/// Get the source of the comics.
+///
+/// # Arguments
+/// * `config` - The configuration with the URLs of the source
+fn get_source(config: &Config) -> Arc<dyn ComicSource> {
+    Arc::new(DilbertSource::new(
+        config.source_url.clone(),
+        config.cdx_url.clone(),
+        config.memento_url.clone(),
+    ))
+}
+
+/// Builder for a [`Server`]
+///
+/// Options that aren't set explicitly are taken from the config, which is the default config
+/// unless set with [`ServerBuilder::config`].
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: Config,
+}
+
+impl ServerBuilder {
+    /// Set the whole config, overriding any options set previously.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the address on which to run the server.
+    ///
+    /// This can also be a path to a Unix domain socket prefixed by "unix:", such as
+    /// "unix:/run/dilbert-viewer.sock".
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.config.host = host.into();
+        self
+    }
+
+    /// Set the port on which to run the server.
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = Some(port);
+        self
+    }
+
+    /// Set the number of worker threads.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.config.workers = Some(workers);
+        self
+    }
+
+    /// Set the URL to the Redis database used for caching.
+    pub fn db(mut self, db_url: impl Into<String>) -> Self {
+        self.config.db_url = Some(db_url.into());
+        self
+    }
+
+    /// Set the URLs to the seed nodes of the Redis cluster used for caching, instead of a single
+    /// Redis database.
+    pub fn db_cluster<I, S>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.db_cluster_urls = urls.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the base URL of the comic source.
+    pub fn source(mut self, source_url: impl Into<String>) -> Self {
+        self.config.source_url = source_url.into();
+        self
+    }
 
-    let mut server = HttpServer::new(move || {
-        // Create all worker-specific (i.e. thread-unsafe) structs here
-        let viewer = Viewer::new(
-            db_pool.clone(),
-            source_url.clone().unwrap_or_else(|| ARC_BASE_URL.into()),
-            cdx_url.clone().unwrap_or_else(|| CDX_URL.into()),
+    /// Set the URL to the CDX API for looking up archived comics.
+    pub fn cdx(mut self, cdx_url: impl Into<String>) -> Self {
+        self.config.cdx_url = cdx_url.into();
+        self
+    }
+
+    /// Set the URL to the Memento TimeTravel API for locating archived comics.
+    pub fn memento(mut self, memento_url: impl Into<String>) -> Self {
+        self.config.memento_url = memento_url.into();
+        self
+    }
+
+    /// Set the User-Agent header for requests to the comic source.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set the timeout (in seconds) for open connections to close during a graceful shutdown.
+    pub fn shutdown_timeout(mut self, secs: u64) -> Self {
+        self.config.shutdown_timeout = secs;
+        self
+    }
+
+    /// Serve over HTTPS, using the given PEM files for the certificate chain and private key.
+    pub fn tls(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.config.tls_cert = Some(cert_path.into());
+        self.config.tls_key = Some(key_path.into());
+        self
+    }
+
+    /// Build the server.
+    pub fn build(self) -> Server {
+        Server {
+            config: self.config,
+        }
+    }
+}
+
+/// The viewer's server, which can be embedded in other apps
+pub struct Server {
+    config: Config,
+}
+
+impl Server {
+    /// Get a builder for the server.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Start the server.
+    ///
+    /// The server is accepting connections once this returns. The returned server must be awaited
+    /// (or spawned) for it to run, and its handle can be used to stop it (gracefully or not).
+    ///
+    /// Process signals are not handled, so that apps embedding the viewer can handle them.
+    pub async fn run(self) -> std::io::Result<ActixServer> {
+        let config = self.config;
+
+        // Fail fast on broken templates, instead of serving 500s for every request.
+        if let Err(err) = check_templates() {
+            error!("Template self-test failed: {err}");
+            return Err(std::io::Error::other(format!(
+                "Template self-test failed: {err}"
+            )));
+        }
+
+        let tls_config = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert_path), Some(key_path)) => Some(
+                load_tls_config(cert_path, key_path)
+                    .map_err(|err| std::io::Error::other(format!("Invalid TLS setup: {err}")))?,
+            ),
+            (None, None) => None,
+            _ => {
+                return Err(std::io::Error::other(
+                    "Both the TLS certificate and key are needed for HTTPS",
+                ))
+            }
+        };
+        let address = BindAddress::new(&config.host, config.port);
+        if tls_config.is_some() && matches!(address, BindAddress::Unix(_)) {
+            return Err(std::io::Error::other(
+                "HTTPS isn't supported over Unix domain sockets",
+            ));
+        }
+
+        // Create all worker-shared (i.e. thread-safe) structs here
+        let db_pool = get_cache_pool(&config);
+        let source = get_source(&config);
+        if let Some(path) = &config.seed_file {
+            // The cache can still be filled by scraping, so simply log the error.
+            if let Err(err) = import_dump(&db_pool, source.as_ref(), path).await {
+                error!("Couldn't seed the cache from {}: {err}", path.display());
+            }
+        }
+
+        // The third-party stylesheet is served by the app, so vendor it before loading the
+        // stylesheets. The pages are still usable without it, so simply log the error.
+        let vendor_path = Path::new(STATIC_DIR).join(VENDOR_CSS_FILE);
+        let vendor_client = build_client(&config, Duration::from_secs(config.resp_timeout));
+        if let Err(err) = vendor_css(
+            &vendor_client,
+            VENDOR_CSS_URL,
+            VENDOR_CSS_INTEGRITY,
+            &vendor_path,
+        )
+        .await
+        {
+            error!("Couldn't vendor the stylesheet from {VENDOR_CSS_URL}: {err}");
+        }
+
+        // Fingerprint the static files only once, and after vendoring, so that all are included.
+        AssetManifest::load(Path::new(STATIC_DIR))
+            .await
+            .map_err(|err| std::io::Error::other(format!("Couldn't load static files: {err}")))?
+            .share();
+
+        // Minify the stylesheets only once, instead of on every request.
+        let css_assets = CssAssets::load(Path::new(STATIC_DIR))
+            .await
+            .map_err(|err| std::io::Error::other(format!("Couldn't load CSS: {err}")))?;
+        let inline_css = if config.inline_css {
+            let css = css_assets.get(CSS_FILE).map(String::from);
+            if css.is_none() {
+                error!("Couldn't find CSS for inlining. The CSS will be linked instead.");
+            }
+            css
+        } else {
+            None
+        };
+        let css_assets = web::Data::new(css_assets);
+
+        // Load the font only once, so that an unavailable font is only warned about once.
+        let attribution = config
+            .attribution
+            .clone()
+            .and_then(|text| Attribution::load(text, config.attribution_font.as_deref()));
+        let robots_content = GeneratedFile::new(render_robots_txt(&config.robots_disallow));
+        let ready_cdx_url = config.ready_check_source.then(|| config.cdx_url.clone());
+        let ready_timeout = Duration::from_secs(config.ready_timeout);
+        // The warm-up runs on only one worker, so that each comic is scraped once.
+        let warm_pending = Arc::new(AtomicBool::new(config.warm_from.is_some()));
+        let csp_template = web::Data::new(CspTemplate(config.csp.clone()));
+        let trusted_proxies = TrustedProxies::new(&config.trusted_proxies, config.forwarded_header)
+            .map_err(|err| std::io::Error::other(format!("Invalid trusted proxies: {err}")))?;
+        let trusted_proxies = web::Data::new(trusted_proxies);
+        let graphql_schema = web::Data::new(build_schema());
+        // Invalid origins would otherwise make every worker fail to start.
+        if let Some(origin) = config
+            .cors_origins
+            .iter()
+            .find(|origin| *origin != "*" && origin.parse::<Uri>().is_err())
+        {
+            return Err(std::io::Error::other(format!(
+                "Invalid CORS origin: {origin}"
+            )));
+        }
+        // Invalid headers would otherwise make every worker fail to start.
+        if let Some(name) = find_invalid_header(&config) {
+            return Err(std::io::Error::other(format!(
+                "Invalid header for the comic source: {name}"
+            )));
+        }
+        // This is shared by all workers, so that the limits apply to the whole app.
+        let mut throttle = Throttle::new(
+            NonZeroUsize::new(config.source_concurrency),
+            config.source_rate_limit,
         );
-        let static_service = get_static_service();
-        Files::new(STATIC_URL, String::from(STATIC_DIR)).default_handler(invalid_url);
-        let default_headers = DefaultHeaders::new().add(("Content-Security-Policy", CSP));
-
-        App::new()
-            .app_data(web::Data::new(viewer))
-            .wrap(Compress::default())
-            .wrap(default_headers)
-            .wrap(Logger::new(
-                "ip=%{r}a req_line=\"%r\" referer=\"%{Referer}i\" user_agent=\"%{User-Agent}i\" \
-                status=%s size=%bB time=%Ts",
-            ))
-            .wrap(TracingWrapper)
-            .service(last_comic)
-            .service(comic_page)
-            .service(random_comic)
-            .service(minify_css)
-            .service(minify_js)
-            // This should be at the end, otherwise everything after this will be ignored.
-            .service(static_service)
-    });
-
-    if let Some(workers) = workers {
-        server = server.workers(workers);
+        if let Some(threshold) = NonZeroU32::new(config.breaker_threshold) {
+            let cooldown = Duration::from_secs(config.breaker_cooldown);
+            throttle = throttle.with_breaker(CircuitBreaker::new(threshold, cooldown));
+        }
+        let throttle = Arc::new(throttle);
+        let rate_limiter = config.rate_limit.map(|per_minute| {
+            let limit = RateLimit {
+                per_minute,
+                burst: NonZeroU32::new(config.rate_limit_burst).unwrap_or(NonZeroU32::MIN),
+            };
+            // Share the limits with other instances of the app, if they share the database.
+            let store = match &db_pool {
+                CachePool::Redis(pool) => RateLimitStore::Redis(pool.clone()),
+                CachePool::Memory(_) => RateLimitStore::memory(),
+            };
+            web::Data::new(RateLimiter::new(limit, store))
+        });
+        // An empty token would let anyone in, so treat it as not given.
+        let admin_token = config
+            .admin_token
+            .clone()
+            .filter(|token| !token.is_empty())
+            .map(|token| web::Data::new(AdminToken(token)));
+        // This is shared by all workers, so that they accept each other's cookies.
+        let cookie_key = CookieKey::new(config.cookie_secret.as_deref())
+            .map_err(|err| std::io::Error::other(format!("Invalid cookie secret: {err}")))?;
+        let cookie_key = web::Data::new(cookie_key);
+
+        let workers = config.workers;
+        let shutdown_timeout = config.shutdown_timeout;
+
+        let mut server = HttpServer::new(move || {
+            // Create all worker-specific (i.e. thread-unsafe) structs here
+            let viewer = web::Data::new(
+                Viewer::new(
+                    Some(db_pool.clone()),
+                    source.clone(),
+                    &config,
+                    inline_css.clone(),
+                    robots_content.clone(),
+                    throttle.clone(),
+                )
+                .with_attribution(attribution.clone()),
+            );
+            if let Some(from) = config.warm_from {
+                if warm_pending.swap(false, Ordering::Relaxed) {
+                    let viewer = viewer.clone();
+                    let to = config.warm_to;
+                    spawn(async move {
+                        // The range is clamped here, since the latest comic may need detection.
+                        let dates = viewer
+                            .get_comic_range()
+                            .await
+                            .and_then(|range| get_warm_dates(from, to, &range));
+                        match dates {
+                            Ok(dates) => {
+                                viewer
+                                    .warm_cache(dates, Duration::from_millis(WARM_INTERVAL))
+                                    .await;
+                            }
+                            Err(err) => error!("Invalid warm-up dates: {err}"),
+                        }
+                    });
+                }
+            }
+            let probe = ReadinessProbe::new(
+                Some(db_pool.clone()),
+                build_client(&config, ready_timeout),
+                ready_cdx_url.clone(),
+                ready_timeout,
+            );
+            let daily_comic = DailyComic::new(Some(db_pool.clone()));
+            let static_service = get_static_service();
+            Files::new(STATIC_URL, String::from(STATIC_DIR)).default_handler(invalid_url);
+
+            let mut app = App::new()
+                .app_data(viewer)
+                .app_data(web::Data::new(probe))
+                .app_data(web::Data::new(daily_comic))
+                .app_data(css_assets.clone())
+                .app_data(csp_template.clone())
+                .app_data(trusted_proxies.clone())
+                .app_data(cookie_key.clone())
+                .app_data(graphql_schema.clone());
+            if let Some(rate_limiter) = &rate_limiter {
+                app = app.app_data(rate_limiter.clone());
+            }
+            if let Some(admin_token) = &admin_token {
+                app = app.app_data(admin_token.clone());
+            }
+
+            app.wrap(from_fn(rate_limit_middleware))
+                .wrap(Compress::default())
+                .wrap(from_fn(csp_middleware))
+                .wrap(from_fn(cache_control_middleware))
+                .wrap(
+                    Logger::new(
+                        "ip=%{client_ip}xi req_line=\"%r\" referer=\"%{Referer}i\" \
+                        user_agent=\"%{User-Agent}i\" status=%s size=%bB time=%Ts",
+                    )
+                    .custom_request_replace("client_ip", |req| {
+                        client_ip(req).unwrap_or_else(|| "-".into())
+                    }),
+                )
+                .wrap(TracingWrapper)
+                .service(last_comic)
+                // This should be before `comic_page`, since that also matches date ranges.
+                .service(comic_range)
+                .service(comic_page)
+                .service(compact_comic_page)
+                .service(slash_comic_page)
+                .service(comic_image)
+                .service(comic_image_variant)
+                .service(comic_text)
+                .service(comic_preview)
+                .service(comic_thumbnail)
+                .service(comic_download)
+                .service(export_cbz)
+                .service(random_comic)
+                .service(numbered_comic)
+                .service(comic_feed)
+                .service(comic_oembed)
+                .service(graphql_query)
+                .service(comic_tag)
+                .service(comic_week)
+                .service(comic_search)
+                .service(on_this_day)
+                .service(set_theme)
+                .service(toggle_favorite)
+                .service(favorite_comics)
+                .service(comic_history)
+                .service(archive)
+                .service(archive_year)
+                .service(archive_month)
+                .service(month_grid)
+                .service(liveness)
+                .service(readiness)
+                .service(robots_txt)
+                .service(web_manifest)
+                .service(
+                    web::scope("/api")
+                        .wrap(Condition::new(
+                            !config.cors_origins.is_empty(),
+                            get_cors(&config.cors_origins),
+                        ))
+                        .service(api_comic)
+                        .service(api_comics),
+                )
+                .service(
+                    web::scope("/admin")
+                        .wrap(from_fn(admin_middleware))
+                        .service(purge_comic)
+                        .service(purge_cache)
+                        .service(warm_cache)
+                        .service(admin_metrics)
+                        .service(reset_stats),
+                )
+                .service(minify_css)
+                .service(minify_js)
+                // This should be at the end, otherwise everything after this will be ignored.
+                .service(static_service)
+        });
+
+        if let Some(workers) = workers {
+            server = server.workers(workers);
+        };
+        server = server.shutdown_timeout(shutdown_timeout).disable_signals();
+
+        match (address, tls_config) {
+            (BindAddress::Tcp(host), Some(tls_config)) => {
+                info!("Starting HTTPS server at {host}");
+                Ok(server.bind_rustls_0_23(host, tls_config)?.run())
+            }
+            (BindAddress::Tcp(host), None) => {
+                info!("Starting server at {host}");
+                Ok(server.bind(host)?.run())
+            }
+            #[cfg(unix)]
+            (BindAddress::Unix(path), _) => {
+                remove_stale_socket(&path)?;
+                info!("Starting server at {}", path.display());
+                Ok(server.bind_uds(path)?.run())
+            }
+            #[cfg(not(unix))]
+            (BindAddress::Unix(_), _) => Err(std::io::Error::other(
+                "Unix domain sockets are only supported on Unix",
+            )),
+        }
+    }
+}
+
+/// Wait for a termination signal (SIGTERM or SIGINT), and then stop the server gracefully.
+///
+/// A graceful stop stops accepting new connections, and waits for in-flight requests (including
+/// their scrapes and cache writes) to finish, until the shutdown timeout.
+///
+/// # Arguments
+/// * `handle` - The handle to the running server
+async fn stop_on_signal(handle: ServerHandle) {
+    #[cfg(unix)]
+    let signal_name = {
+        use signal::unix::{signal, SignalKind};
+
+        let (mut sigterm, mut sigint) = match (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+        ) {
+            (Ok(sigterm), Ok(sigint)) => (sigterm, sigint),
+            (Err(err), _) | (_, Err(err)) => {
+                error!("Couldn't listen for termination signals: {err}");
+                return;
+            }
+        };
+        let received = match select(pin!(sigterm.recv()), pin!(sigint.recv())).await {
+            Either::Left(_) => "SIGTERM",
+            Either::Right(_) => "SIGINT",
+        };
+        received
     };
 
-    info!("Starting server at {host}");
-    server.bind(host)?.run().await
+    #[cfg(not(unix))]
+    let signal_name = {
+        if let Err(err) = signal::ctrl_c().await {
+            error!("Couldn't listen for termination signals: {err}");
+            return;
+        }
+        "Ctrl-C"
+    };
+
+    info!("Received {signal_name}; stopping the server gracefully");
+    handle.stop(true).await;
+}
+
+/// Export the cached comic data to a JSON dump, for seeding the caches of other instances.
+///
+/// # Arguments
+/// * `config` - The configuration with the database to export from
+/// * `path` - The path to the dump file, which is overwritten if it exists
+///
+/// # Returns
+/// * The number of comics exported
+pub async fn export(config: &Config, path: &Path) -> std::io::Result<usize> {
+    let Some(result) = get_redis_pool(config) else {
+        return Err(std::io::Error::other(
+            "A database URL is needed to export its cached comics",
+        ));
+    };
+    let db_pool =
+        result.map_err(|err| std::io::Error::other(format!("Couldn't create DB pool: {err}")))?;
+    export_dump(&db_pool, get_source(config).as_ref(), path)
+        .await
+        .map_err(|err| std::io::Error::other(format!("Couldn't export dump: {err}")))
+}
+
+/// Run the server until it's stopped by a termination signal.
+///
+/// Unexpected errors are also reported to Sentry, if a DSN is configured.
+///
+/// # Arguments
+/// * `config` - The configuration for the server
+pub async fn run(config: Config) -> std::io::Result<()> {
+    // Errors are reported as long as `_reporting` is not dropped.
+    let _reporting = config.sentry_dsn.as_deref().map(init_reporting);
+    let server = Server::builder().config(config).build().run().await?;
+    spawn(stop_on_signal(server.handle()));
+    server.await?;
+    info!("Server stopped");
+    Ok(())
 }
@@ -6,25 +6,215 @@
 //
 // SPDX-License-Identifier: MIT
 
+use std::fmt::{Debug, Result as FmtResult};
 use std::future::{ready, Future, Ready};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use actix_web::{
     body::{BodySize, MessageBody},
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
     web::Bytes,
     Error,
 };
+use chrono::{DateTime, SecondsFormat, Utc};
+use opentelemetry::{
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{runtime::TokioCurrentThread, trace::TracerProvider, Resource};
 use pin_project::{pin_project, pinned_drop};
-use tracing::{info_span, Span};
+use sentry::Hub;
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    info_span, Event, Level, Span, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    filter::Targets,
+    fmt::{
+        format::{JsonFields, Writer},
+        FmtContext, FormatEvent, FormattedFields,
+    },
+    registry::LookupSpan,
+    Layer,
+};
 use uuid::Uuid;
 
+use crate::constants::{
+    MAX_REQUEST_ID_LEN, OTEL_SERVICE_NAME, OTEL_TARGET, OTLP_ENDPOINT_VAR, REQUEST_ID_HEADER,
+    REQUEST_SPAN,
+};
+use crate::reporting::request_hub;
+
+/// Guard for the export of traces, which flushes the remaining spans when dropped
+pub struct OtelGuard(TracerProvider);
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.shutdown() {
+            // The logger may already be gone, so print the error instead.
+            eprintln!("Couldn't flush exported traces: {err}");
+        }
+    }
+}
+
+/// Get a layer that exports spans to an OpenTelemetry collector over OTLP, if one is configured.
+///
+/// The collector is configured with the standard `OTEL_EXPORTER_OTLP_*` environment variables,
+/// and nothing is exported if `OTEL_EXPORTER_OTLP_ENDPOINT` isn't set. Only the viewer's own spans
+/// are exported, such as those of requests, cache operations and scrapes.
+///
+/// This must be called within a Tokio runtime.
+///
+/// # Returns
+/// * The layer for exporting spans, if configured
+/// * The guard that flushes the exported spans when dropped
+pub fn get_otel_layer<S>() -> Result<Option<(impl Layer<S>, OtelGuard)>, TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if std::env::var_os(OTLP_ENDPOINT_VAR).is_none() {
+        return Ok(None);
+    }
+
+    let exporter = SpanExporter::builder().with_tonic().build()?;
+    // The batches are exported from a separate thread, so that flushing them on exit doesn't
+    // block on the app's single-threaded runtime.
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, TokioCurrentThread)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            OTEL_SERVICE_NAME,
+        )]))
+        .build();
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(OTEL_SERVICE_NAME))
+        .with_filter(Targets::new().with_target(OTEL_TARGET, Level::INFO));
+    Ok(Some((layer, OtelGuard(provider))))
+}
+
+/// Formatter for log events as JSON objects, one per line
+///
+/// The fields of each event are flattened into its object, along with the ID of the request that
+/// it belongs to (as `request_id`), so that log aggregators can query them without any parsing.
+/// This needs the fields of spans to be formatted with `JsonFields`.
+pub struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, JsonFields>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FmtResult {
+        // Events from the `log` crate (such as the access log) have their metadata in fields.
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".into(),
+            DateTime::<Utc>::from(SystemTime::now())
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        object.insert("level".into(), metadata.level().as_str().into());
+        object.insert("target".into(), metadata.target().into());
+
+        if let Some(span) = ctx
+            .event_scope()
+            .and_then(|mut scope| scope.find(|span| span.name() == REQUEST_SPAN))
+        {
+            let extensions = span.extensions();
+            let request_id = extensions
+                .get::<FormattedFields<JsonFields>>()
+                .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+                .and_then(|mut fields| fields.remove("id"));
+            if let Some(request_id) = request_id {
+                object.insert("request_id".into(), request_id);
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Visitor for recording the fields of a log event into a JSON object
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl JsonVisitor<'_> {
+    /// Record a field, unless it's the metadata of an event from the `log` crate.
+    ///
+    /// # Arguments
+    /// * `field` - The field to record
+    /// * `value` - The value of the field
+    fn insert(&mut self, field: &Field, value: Value) {
+        if !field.name().starts_with("log.") {
+            self.0.insert(field.name().into(), value);
+        }
+    }
+}
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.insert(field, format!("{value:?}").into());
+    }
+}
+
+/// Get the ID of a request, which is taken from its `X-Request-ID` header if valid.
+///
+/// Otherwise, a random UUID is generated.
+///
+/// # Arguments
+/// * `req` - The request
+fn get_request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|byte| byte.is_ascii_graphic())
+        })
+        .map_or_else(|| Uuid::new_v4().to_string(), String::from)
+}
+
 #[derive(Default)]
 /// Wrapper for encapsulating all log events within a response to a request inside a span
 ///
 /// This span will have a field that contains the unique ID for each request, which is used to
-/// distinguish log events for different request-responses.
+/// distinguish log events for different request-responses. The ID is taken from the request's
+/// `X-Request-ID` header if given, and is echoed back in the response's `X-Request-ID` header.
 pub struct TracingWrapper;
 
 impl<S, B> Transform<S, ServiceRequest> for TracingWrapper
@@ -61,12 +251,19 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let root_span = info_span!("request", id=%Uuid::new_v4());
-        let fut = root_span.in_scope(|| self.service.call(req));
+        let request_id = get_request_id(&req);
+        let root_span = info_span!(REQUEST_SPAN, id=%request_id);
+        // Errors reported while handling the request are tagged with its ID.
+        let hub = request_hub(&request_id);
+        let fut = Hub::run(hub.clone(), || {
+            root_span.in_scope(|| self.service.call(req))
+        });
 
         TracingResponse {
             fut,
             span: root_span,
+            hub,
+            request_id,
         }
     }
 }
@@ -76,6 +273,8 @@ pub struct TracingResponse<F> {
     #[pin]
     fut: F,
     span: Span,
+    hub: Arc<Hub>,
+    request_id: String,
 }
 
 #[pin_project(project = PinOptionProj)]
@@ -105,14 +304,21 @@ where
         let fut = this.fut;
         let span = this.span;
 
-        span.in_scope(|| match fut.poll(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(outcome) => Poll::Ready(outcome.map(|service_response| {
-                service_response.map_body(|_, body| StreamSpan {
-                    body: PinOption::Some(body),
-                    span: span.clone(),
-                })
-            })),
+        Hub::run(this.hub.clone(), || {
+            span.in_scope(|| match fut.poll(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(outcome) => Poll::Ready(outcome.map(|mut service_response| {
+                    if let Ok(value) = HeaderValue::from_str(this.request_id) {
+                        service_response
+                            .headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    service_response.map_body(|_, body| StreamSpan {
+                        body: PinOption::Some(body),
+                        span: span.clone(),
+                    })
+                })),
+            })
         })
     }
 }
@@ -160,3 +366,91 @@ impl<B> PinnedDrop for StreamSpan<B> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    use actix_web::test::TestRequest;
+    use test_case::test_case;
+    use tracing::info;
+    use tracing_subscriber::{fmt::MakeWriter, prelude::*};
+
+    /// Writer for logs that collects them in memory
+    #[derive(Clone, Default)]
+    struct MemoryWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for MemoryWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl MakeWriter<'_> for MemoryWriter {
+        type Writer = Self;
+
+        fn make_writer(&self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    /// Test whether JSON logs have the event's fields and the request ID flattened.
+    fn test_json_format() {
+        let writer = MemoryWriter::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer.clone())
+                .fmt_fields(JsonFields::new())
+                .event_format(JsonFormat),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            info_span!(REQUEST_SPAN, id = "abc").in_scope(|| {
+                info_span!("inner", date = "2000-01-01").in_scope(|| info!(status = 200, "Done"));
+            });
+        });
+
+        let logs = writer.0.lock().unwrap();
+        let log: Map<String, Value> =
+            serde_json::from_slice(&logs).expect("Log isn't a JSON object");
+        assert_eq!(log["level"], "INFO", "Wrong level");
+        assert_eq!(log["message"], "Done", "Wrong message");
+        assert_eq!(log["status"], 200, "Wrong event field");
+        assert_eq!(log["request_id"], "abc", "Wrong request ID");
+    }
+
+    #[test_case(Some("abc-123"), true; "valid")]
+    #[test_case(Some("abc 123"), false; "invalid characters")]
+    #[test_case(Some(""), false; "empty")]
+    #[test_case(None, false; "missing")]
+    /// Test whether valid request IDs are taken from the request, and others are generated.
+    ///
+    /// # Arguments
+    /// * `header` - The request's `X-Request-ID` header, if any
+    /// * `taken` - Whether the ID is expected to be taken from the header
+    fn test_request_id(header: Option<&str>, taken: bool) {
+        let mut req = TestRequest::default();
+        if let Some(header) = header {
+            req = req.insert_header((REQUEST_ID_HEADER, header));
+        }
+        let request_id = get_request_id(&req.to_srv_request());
+
+        if taken {
+            assert_eq!(
+                Some(request_id.as_str()),
+                header,
+                "ID isn't from the header"
+            );
+        } else {
+            assert!(Uuid::parse_str(&request_id).is_ok(), "ID isn't generated");
+        }
+    }
+}
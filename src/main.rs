@@ -3,38 +3,436 @@
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
 //! The main file for running the viewer app
-use std::env;
 use std::io::stdout;
-use std::str::FromStr;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::path::{Path, PathBuf};
 
-use portpicker::{is_free, pick_unused_port};
-use tracing::error;
-use tracing_appender::non_blocking::WorkerGuard;
-use tracing_subscriber::filter::{EnvFilter, LevelFilter};
-
-/// Default port when one isn't specified
-// This is Heroku's default port when running locally
-const PORT: u16 = 5000;
+use chrono::NaiveDate;
+use clap::{builder::FalseyValueParser, Parser, ValueEnum};
+use dilbert_viewer::{get_otel_layer, Config, ForwardedHeader, JsonFormat, OtelGuard};
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use tracing::{Level, Subscriber};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{
+    filter::{filter_fn, EnvFilter, LevelFilter},
+    fmt::{self, format::JsonFields},
+    prelude::*,
+    registry::LookupSpan,
+    Layer,
+};
 
 /// Default log level
 const LOG_LEVEL: LevelFilter = LevelFilter::WARN;
 
-// Environment variables that are read
-/// Port on which to run the server
-const PORT_VAR: &str = "PORT";
-/// Log level
-const LOG_VAR: &str = "RUST_LOG";
-/// Redis database connection URL
-const REDIS_URL_VAR: &str = "REDIS_URL";
+/// Target of the log events in the access log
+const ACCESS_LOG_TARGET: &str = "actix_web::middleware::logger";
+
+/// Number of rotated access log files to keep, apart from the current one
+const ACCESS_LOG_MAX_FILES: usize = 7;
+
+/// Date format for dates given as arguments
+const DATE_FMT: &str = "%Y-%m-%d";
+
+/// Format of the logs
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// JSON objects, one per line, with the request ID as a field
+    Json,
+}
+
+/// Simple viewer webpage for Dilbert by Scott Adams
+///
+/// Every option can also be given through the environment variable listed with it, or through the
+/// config file. Options given as arguments or environment variables override the config file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "DILBERT_VIEWER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Port on which to run the server [default: 5000, or any unused port if taken]
+    #[arg(short, long, env = "PORT")]
+    port: Option<u16>,
+
+    /// Address on which to run the server, or "unix:<path>" for a Unix socket [default: 0.0.0.0]
+    #[arg(short, long, env = "BIND")]
+    bind: Option<String>,
+
+    /// Redis database connection URL [default: cache in memory]
+    #[arg(long, env = "REDIS_URL")]
+    db_url: Option<String>,
+
+    /// Comma-separated URLs of seed nodes of a Redis cluster, used instead of the Redis URL
+    /// [default: no cluster]
+    #[arg(long, env = "REDIS_CLUSTER_URLS")]
+    db_cluster_urls: Option<String>,
+
+    /// Serialize values cached in Redis to MessagePack instead of JSON, to save memory
+    ///
+    /// Values cached earlier in either format are still read.
+    #[arg(long, env = "REDIS_MSGPACK", value_parser = FalseyValueParser::new())]
+    db_msgpack: bool,
+
+    /// Verify the TLS certificate of the Redis database, instead of accepting any certificate
+    ///
+    /// This is implied by giving a CA or client certificate for the Redis database.
+    #[arg(long, env = "REDIS_TLS_VERIFY", value_parser = FalseyValueParser::new())]
+    db_tls_verify: bool,
+
+    /// Path to the CA certificate in PEM format with which to verify the Redis database
+    /// [default: the system's trusted roots]
+    #[arg(long, env = "REDIS_CA_CERT")]
+    db_ca_cert: Option<PathBuf>,
+
+    /// Path to the client certificate in PEM format, for mutual TLS with the Redis database
+    /// [requires: --db-client-key]
+    #[arg(long, env = "REDIS_CLIENT_CERT")]
+    db_client_cert: Option<PathBuf>,
+
+    /// Path to the private key in PEM format for the client certificate of the Redis database
+    /// [requires: --db-client-cert]
+    #[arg(long, env = "REDIS_CLIENT_KEY")]
+    db_client_key: Option<PathBuf>,
+
+    /// Base URL of the comic source [default: the Wayback Machine's archive of "dilbert.com"]
+    #[arg(long, env = "SOURCE_URL")]
+    source_url: Option<String>,
+
+    /// User-Agent header for requests to the comic source [default: identifies this app]
+    #[arg(long, env = "USER_AGENT")]
+    user_agent: Option<String>,
+
+    /// Maximum number of concurrent requests to the comic source, or 0 for no limit [default: 4]
+    #[arg(long, env = "SOURCE_CONCURRENCY")]
+    source_concurrency: Option<usize>,
+
+    /// Maximum number of requests per minute to the comic source [default: no limit]
+    #[arg(long, env = "SOURCE_RATE_LIMIT")]
+    source_rate_limit: Option<NonZeroU32>,
+
+    /// Path to the TLS certificate chain in PEM format, to serve over HTTPS [requires: --tls-key]
+    #[arg(long, env = "TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to the TLS private key in PEM format, to serve over HTTPS [requires: --tls-cert]
+    #[arg(long, env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Sustained number of requests allowed per minute from each client IP [default: no limit]
+    #[arg(long, env = "RATE_LIMIT")]
+    rate_limit: Option<NonZeroU32>,
+
+    /// Number of worker threads [default: the number of physical CPU cores]
+    #[arg(short, long, env = "WORKERS")]
+    workers: Option<usize>,
+
+    /// Log level, as a `tracing` filter directive
+    #[arg(long, env = "RUST_LOG", default_value_t = LOG_LEVEL.to_string())]
+    log_level: String,
+
+    /// Format of the logs
+    #[arg(long, env = "LOG_FORMAT", value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Path to a file to which the access log is also written, which is rotated daily
+    #[arg(long, env = "ACCESS_LOG")]
+    access_log: Option<PathBuf>,
+
+    /// Maximum size (in MiB) of the access log file, after which it's rotated [default: no limit]
+    #[arg(long, env = "ACCESS_LOG_MAX_SIZE", requires = "access_log")]
+    access_log_max_size: Option<NonZeroU64>,
+
+    /// Inline the CSS into comic pages
+    #[arg(long, env = "INLINE_CSS", value_parser = FalseyValueParser::new())]
+    inline_css: bool,
+
+    /// Make comic navigation wrap around at the first and last comics
+    #[arg(long, env = "NAV_WRAP", value_parser = FalseyValueParser::new())]
+    nav_wrap: bool,
+
+    /// Comma-separated IP addresses or CIDR ranges of proxies trusted for the IPs of clients
+    ///
+    /// Use "0.0.0.0/0,::/0" to trust all proxies, such as on Heroku. [default: none]
+    #[arg(long, env = "TRUSTED_PROXIES")]
+    trusted_proxies: Option<String>,
+
+    /// Forwarding header that the trusted proxies set: "x-forwarded-for" or "forwarded"
+    ///
+    /// Only this header is read, since proxies pass the other one through from clients.
+    /// [default: x-forwarded-for]
+    #[arg(long, env = "FORWARDED_HEADER")]
+    forwarded_header: Option<ForwardedHeader>,
+
+    /// Comma-separated origins allowed to make cross-origin requests to the API, or "*" for any
+    /// [default: none]
+    #[arg(long, env = "CORS_ORIGINS")]
+    cors_origins: Option<String>,
+
+    /// Comma-separated URL path prefixes that crawlers are disallowed from in `robots.txt`
+    ///
+    /// An empty list allows crawling everything. [default: /random,/api/]
+    #[arg(long, env = "ROBOTS_DISALLOW")]
+    robots_disallow: Option<String>,
+
+    /// Number of days of comics in the feed [default: 10]
+    #[arg(long, env = "FEED_LEN")]
+    feed_len: Option<usize>,
+
+    /// Date of the last comic in the feed, in the format YYYY-MM-DD [default: the last comic]
+    #[arg(long, env = "FEED_END", value_parser = parse_date)]
+    feed_end: Option<NaiveDate>,
+
+    /// Date of the first comic to scrape into the cache on startup, in the format YYYY-MM-DD
+    /// [default: no warm-up]
+    #[arg(long, env = "WARM_FROM", value_parser = parse_date)]
+    warm_from: Option<NaiveDate>,
+
+    /// Date of the last comic to scrape into the cache on startup, in the format YYYY-MM-DD
+    /// [default: the last comic]
+    #[arg(long, env = "WARM_TO", value_parser = parse_date)]
+    warm_to: Option<NaiveDate>,
+
+    /// Detect the latest comic from the source, for sources that still publish new comics
+    ///
+    /// Otherwise, the latest comic is the last Dilbert comic.
+    #[arg(long, env = "LIVE_LATEST", value_parser = FalseyValueParser::new())]
+    live_latest: bool,
+
+    /// Timeout (in seconds) for each readiness check [default: 2]
+    #[arg(long, env = "READY_TIMEOUT")]
+    ready_timeout: Option<u64>,
+
+    /// Make readiness checks also check that the comic source is reachable
+    #[arg(long, env = "READY_CHECK_SOURCE", value_parser = FalseyValueParser::new())]
+    ready_check_source: bool,
+
+    /// Lock each scrape in the cache, for multiple instances of the viewer sharing the database
+    ///
+    /// Otherwise, only concurrent requests to the same worker share a scrape.
+    #[arg(long, env = "SCRAPE_LOCK", value_parser = FalseyValueParser::new())]
+    scrape_lock: bool,
+
+    /// Path to a JSON dump of comic data to import into the cache on startup
+    #[arg(long, env = "SEED_FILE")]
+    seed_file: Option<PathBuf>,
+
+    /// Attribution to overlay on served comic images, along with their source URLs [default: no
+    /// overlay]
+    #[arg(long, env = "ATTRIBUTION")]
+    attribution: Option<String>,
+
+    /// Path to a TrueType or OpenType font for the attribution overlay [default: a bundled font]
+    #[arg(long, env = "ATTRIBUTION_FONT")]
+    attribution_font: Option<PathBuf>,
+
+    /// Sentry DSN to which unexpected errors are reported [default: no reporting]
+    #[arg(long, env = "SENTRY_DSN")]
+    sentry_dsn: Option<String>,
+
+    /// Token that clients must give (as a bearer token) to use the admin routes [default: no
+    /// admin routes]
+    #[arg(long, env = "ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+
+    /// Secret (of at least 32 bytes) from which the key for signing cookies is derived [default:
+    /// a random key, which forgets clients' favorites on restart]
+    #[arg(long, env = "COOKIE_SECRET", hide_env_values = true)]
+    cookie_secret: Option<String>,
+
+    /// Export the comic data cached in the database to a JSON dump at this path, and exit
+    #[arg(long)]
+    export_dump: Option<PathBuf>,
+}
+
+impl Args {
+    /// Override the options in the config with the ones given as arguments.
+    ///
+    /// # Arguments
+    /// * `config` - The config to override
+    fn override_config(&self, config: &mut Config) {
+        if let Some(port) = self.port {
+            config.port = Some(port);
+        }
+        if let Some(bind) = &self.bind {
+            config.host.clone_from(bind);
+        }
+        if let Some(db_url) = &self.db_url {
+            config.db_url = Some(db_url.clone());
+        }
+        if let Some(urls) = &self.db_cluster_urls {
+            config.db_cluster_urls = split_list(urls);
+        }
+        if let Some(ca_cert) = &self.db_ca_cert {
+            config.db_ca_cert = Some(ca_cert.clone());
+        }
+        if let Some(client_cert) = &self.db_client_cert {
+            config.db_client_cert = Some(client_cert.clone());
+        }
+        if let Some(client_key) = &self.db_client_key {
+            config.db_client_key = Some(client_key.clone());
+        }
+        if let Some(source_url) = &self.source_url {
+            config.source_url.clone_from(source_url);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            config.user_agent.clone_from(user_agent);
+        }
+        if let Some(source_concurrency) = self.source_concurrency {
+            config.source_concurrency = source_concurrency;
+        }
+        if let Some(source_rate_limit) = self.source_rate_limit {
+            config.source_rate_limit = Some(source_rate_limit);
+        }
+        if let Some(tls_cert) = &self.tls_cert {
+            config.tls_cert = Some(tls_cert.clone());
+        }
+        if let Some(tls_key) = &self.tls_key {
+            config.tls_key = Some(tls_key.clone());
+        }
+        if let Some(rate_limit) = self.rate_limit {
+            config.rate_limit = Some(rate_limit);
+        }
+        if let Some(workers) = self.workers {
+            config.workers = Some(workers);
+        }
+        // Flags can only enable options, so that the config file can also enable them.
+        config.inline_css |= self.inline_css;
+        config.nav_wrap |= self.nav_wrap;
+        config.live_latest |= self.live_latest;
+        config.ready_check_source |= self.ready_check_source;
+        config.scrape_lock |= self.scrape_lock;
+        config.db_tls_verify |= self.db_tls_verify;
+        config.db_msgpack |= self.db_msgpack;
+        if let Some(proxies) = &self.trusted_proxies {
+            config.trusted_proxies = split_list(proxies);
+        }
+        if let Some(header) = self.forwarded_header {
+            config.forwarded_header = header;
+        }
+        if let Some(origins) = &self.cors_origins {
+            config.cors_origins = split_list(origins);
+        }
+        if let Some(paths) = &self.robots_disallow {
+            config.robots_disallow = split_list(paths);
+        }
+        if let Some(feed_len) = self.feed_len {
+            config.feed_len = feed_len;
+        }
+        if let Some(feed_end) = self.feed_end {
+            config.feed_end = Some(feed_end);
+        }
+        if let Some(warm_from) = self.warm_from {
+            config.warm_from = Some(warm_from);
+        }
+        if let Some(warm_to) = self.warm_to {
+            config.warm_to = Some(warm_to);
+        }
+        if let Some(ready_timeout) = self.ready_timeout {
+            config.ready_timeout = ready_timeout;
+        }
+        if let Some(seed_file) = &self.seed_file {
+            config.seed_file = Some(seed_file.clone());
+        }
+        if let Some(attribution) = &self.attribution {
+            config.attribution = Some(attribution.clone());
+        }
+        if let Some(attribution_font) = &self.attribution_font {
+            config.attribution_font = Some(attribution_font.clone());
+        }
+        if let Some(sentry_dsn) = &self.sentry_dsn {
+            config.sentry_dsn = Some(sentry_dsn.clone());
+        }
+        if let Some(admin_token) = &self.admin_token {
+            config.admin_token = Some(admin_token.clone());
+        }
+        if let Some(cookie_secret) = &self.cookie_secret {
+            config.cookie_secret = Some(cookie_secret.clone());
+        }
+    }
+}
+
+/// Parse a date given as an argument.
+fn parse_date(date: &str) -> Result<NaiveDate, chrono::ParseError> {
+    NaiveDate::parse_from_str(date, DATE_FMT)
+}
+
+/// Split a comma-separated list, ignoring empty items.
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Guards for the logger, which keep it active as long as they aren't dropped
+struct LogGuards {
+    /// The guard for the non-blocking writer of logs
+    _stdout: WorkerGuard,
+    /// The guard for the non-blocking writer of the access log file, if enabled
+    _access_log: Option<WorkerGuard>,
+    /// The guard for the export of spans, if enabled
+    _otel: Option<OtelGuard>,
+}
+
+/// Get a layer that formats logs into the given writer.
+///
+/// # Arguments
+/// * `writer` - The writer for the logs
+/// * `log_format` - The format of the logs
+/// * `ansi` - Whether to use ANSI escape codes for colours, for text logs
+fn get_fmt_layer<S>(
+    writer: NonBlocking,
+    log_format: LogFormat,
+    ansi: bool,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match log_format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonFormat)
+            .boxed(),
+    }
+}
 
-/// Initialize the logger from the `RUST_LOG` environment variable, with a default.
-fn init_logger() -> WorkerGuard {
+/// Open the access log file, which is rotated daily and when it exceeds the maximum size.
+///
+/// # Arguments
+/// * `path` - The path to the access log file
+/// * `max_size` - The maximum size (in MiB) of the file, if any
+fn open_access_log(
+    path: &Path,
+    max_size: Option<NonZeroU64>,
+) -> std::io::Result<BasicRollingFileAppender> {
+    let mut condition = RollingConditionBasic::new().daily();
+    if let Some(max_size) = max_size {
+        condition = condition.max_size(max_size.get().saturating_mul(1024 * 1024));
+    }
+    BasicRollingFileAppender::new(path, condition, ACCESS_LOG_MAX_FILES)
+}
+
+/// Initialize the logger with the log level and format given as arguments.
+///
+/// The access log is also written to a file, if one is given. Spans are also exported to an
+/// OpenTelemetry collector, if one is configured through the environment.
+///
+/// # Arguments
+/// * `args` - The arguments with the logging options
+fn init_logger(args: &Args) -> LogGuards {
     // Log to stdout in a non-blocking way using a logging thread.
     let (writer, guard) = tracing_appender::non_blocking(stdout());
 
-    // Use the `RUST_LOG` env var, like `env_logger`, but with a default.
+    // Use the same format as the `RUST_LOG` env var, like `env_logger`, but with a default.
     let builder = EnvFilter::builder().with_default_directive(LOG_LEVEL.into());
-    let filter = match builder.parse(env::var(LOG_VAR).unwrap_or_default()) {
+    let filter = match builder.parse(&args.log_level) {
         Ok(filter) => filter,
         Err(err) => {
             println!("Invalid log level: {err}");
@@ -42,43 +440,76 @@ fn init_logger() -> WorkerGuard {
         }
     };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(writer)
-        .init();
+    // The access log file is independent of the log level for stdout.
+    let (access_log_layer, access_log_guard) = match args
+        .access_log
+        .as_deref()
+        .map(|path| open_access_log(path, args.access_log_max_size))
+    {
+        Some(Ok(file)) => {
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            // Spans are enabled so that the access log has the IDs of requests.
+            let layer =
+                get_fmt_layer(writer, args.log_format, false).with_filter(filter_fn(|metadata| {
+                    metadata.is_span()
+                        || (metadata.target() == ACCESS_LOG_TARGET
+                            && *metadata.level() <= Level::INFO)
+                }));
+            (Some(layer), Some(guard))
+        }
+        Some(Err(err)) => {
+            println!("Couldn't open the access log file: {err}");
+            (None, None)
+        }
+        None => (None, None),
+    };
 
-    guard
-}
+    let (otel_layer, otel_guard) = match get_otel_layer() {
+        Ok(Some((layer, guard))) => (Some(layer), Some(guard)),
+        Ok(None) => (None, None),
+        Err(err) => {
+            println!("Couldn't set up exporting traces: {err}");
+            (None, None)
+        }
+    };
 
-/// Choose the port from an environment variable, with a fallback.
-fn choose_port() -> u16 {
-    if let Some(port) = env::var(PORT_VAR)
-        .ok()
-        .and_then(|port| u16::from_str(&port).ok())
-    {
-        port
-    } else if is_free(PORT) {
-        PORT
-    } else if let Some(port) = pick_unused_port() {
-        port
-    } else {
-        panic!("Couldn't find any unused TCP port")
+    tracing_subscriber::registry()
+        .with(get_fmt_layer(writer, args.log_format, true).with_filter(filter))
+        .with(access_log_layer)
+        .with(otel_layer)
+        .init();
+
+    LogGuards {
+        _stdout: guard,
+        _access_log: access_log_guard,
+        _otel: otel_guard,
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // The non-blocking writer stays active as long as `_guard` is not dropped.
-    let _guard = init_logger();
+    let args = Args::parse();
 
-    let host = format!("0.0.0.0:{}", choose_port());
+    // The non-blocking writers and the export of spans stay active as long as `_guards` is not
+    // dropped.
+    let _guards = init_logger(&args);
 
-    let db_url = if let Ok(db_url) = env::var(REDIS_URL_VAR) {
-        Some(db_url)
+    let mut config = if let Some(path) = &args.config {
+        Config::from_file(path).map_err(|err| {
+            std::io::Error::other(format!(
+                "Couldn't load config from {}: {err}",
+                path.display()
+            ))
+        })?
     } else {
-        error!("Missing environment variable for the database URL: {REDIS_URL_VAR}");
-        None
+        Config::default()
     };
+    args.override_config(&mut config);
 
-    dilbert_viewer::run(host, db_url, None, None, None).await
+    if let Some(path) = &args.export_dump {
+        let count = dilbert_viewer::export(&config, path).await?;
+        println!("Exported {count} comics to {}", path.display());
+        return Ok(());
+    }
+    dilbert_viewer::run(config).await
 }
@@ -0,0 +1,459 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! In-memory LRU cache, for use when the database is unavailable
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, warn};
+
+use crate::db::{deserialize_members, deserialize_value, CacheBackend};
+use crate::errors::{AppError, AppResult};
+
+/// A cached value along with its expiry
+struct Entry {
+    /// The cached value
+    value: Vec<u8>,
+    /// The time after which the value is invalid, if any
+    expiry: Option<Instant>,
+}
+
+/// LRU store of key-values, which is limited by the total size of the keys and values
+struct LruStore {
+    /// The cached entries, ordered by recency of use
+    entries: LruCache<Vec<u8>, Entry>,
+    /// The total size (in bytes) of the keys and values
+    size: usize,
+    /// The maximum total size (in bytes) of the keys and values
+    max_size: usize,
+}
+
+impl LruStore {
+    /// Get the value for a key, if it exists and hasn't expired.
+    fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let expired = match self.entries.get(key) {
+            Some(entry) => entry.expiry.is_some_and(|expiry| expiry <= Instant::now()),
+            None => return None,
+        };
+        if expired {
+            self.remove(key);
+            None
+        } else {
+            self.entries.get(key).map(|entry| entry.value.clone())
+        }
+    }
+
+    /// Set the value for a key, evicting the least recently used entries if needed.
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>, expiry: Option<Instant>) {
+        self.remove(&key);
+        self.size += key.len() + value.len();
+        self.entries.put(key, Entry { value, expiry });
+
+        while self.size > self.max_size {
+            if let Some((key, entry)) = self.entries.pop_lru() {
+                debug!("Evicting entry from in-memory cache");
+                self.size -= key.len() + entry.value.len();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Get the members of the set for a key, if it exists and hasn't expired, along with its expiry.
+    ///
+    /// Sets are stored as sorted JSON arrays of the serialized members.
+    fn get_set(&mut self, key: &[u8]) -> (BTreeSet<String>, Option<Instant>) {
+        let Some(value) = self.get(key) else {
+            return (BTreeSet::new(), None);
+        };
+        let expiry = self.entries.peek(key).and_then(|entry| entry.expiry);
+        let members = serde_json::from_slice(&value).unwrap_or_else(|err| {
+            warn!("Replacing set that couldn't be deserialized: {err}");
+            BTreeSet::new()
+        });
+        (members, expiry)
+    }
+
+    /// Remove the entry for a key, if it exists.
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.pop(key) {
+            self.size -= key.len() + entry.value.len();
+        }
+    }
+
+    /// Remove all entries.
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+    }
+}
+
+/// A handle to the in-memory cache
+///
+/// All clones of a handle share the same cache.
+#[derive(Clone)]
+pub struct MemoryPool {
+    store: Arc<Mutex<LruStore>>,
+}
+
+impl MemoryPool {
+    /// Initialize an empty in-memory cache.
+    ///
+    /// # Arguments
+    /// * `max_size` - The maximum total size (in bytes) of the cached keys and values
+    pub fn new(max_size: usize) -> Self {
+        let store = LruStore {
+            entries: LruCache::unbounded(),
+            size: 0,
+            max_size,
+        };
+        Self {
+            store: Arc::new(Mutex::new(store)),
+        }
+    }
+
+    /// Lock the underlying store for exclusive access.
+    fn lock(&self) -> AppResult<MutexGuard<'_, LruStore>> {
+        self.store
+            .lock()
+            .map_err(|_| AppError::Cache("Cache lock is poisoned".into()))
+    }
+}
+
+// Keys and values are serialized to JSON, just like with Redis by default.
+impl CacheBackend for MemoryPool {
+    async fn get<K, V>(&self, key: &K) -> AppResult<Option<V>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        let data = self.lock()?.get(&serde_json::to_vec(key)?);
+        deserialize_value(self, key, data).await
+    }
+
+    async fn get_many<K, V>(&self, keys: &[K]) -> AppResult<Vec<Option<V>>>
+    where
+        K: Serialize + Sync,
+        V: DeserializeOwned,
+    {
+        let serialized_keys = keys
+            .iter()
+            .map(serde_json::to_vec)
+            .collect::<Result<Vec<_>, _>>()?;
+        let data: Vec<_> = {
+            let mut store = self.lock()?;
+            serialized_keys.iter().map(|key| store.get(key)).collect()
+        };
+
+        let mut values = Vec::with_capacity(keys.len());
+        for (key, data) in keys.iter().zip(data) {
+            values.push(deserialize_value(self, key, data).await?);
+        }
+        Ok(values)
+    }
+
+    async fn set<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let value = serde_json::to_vec(value)?;
+        let expiry = Instant::now() + Duration::from_secs(seconds);
+        self.lock()?.set(key, value, Some(expiry));
+        Ok(())
+    }
+
+    async fn set_nx<K, V>(&self, key: &K, value: &V, seconds: u64) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let value = serde_json::to_vec(value)?;
+        let expiry = Instant::now() + Duration::from_secs(seconds);
+        // Hold the lock for both the check and the update, so that they're atomic.
+        let mut store = self.lock()?;
+        if store.get(&key).is_some() {
+            return Ok(false);
+        }
+        store.set(key, value, Some(expiry));
+        Ok(true)
+    }
+
+    async fn delete<K>(&self, key: &K) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        self.lock()?.remove(&key);
+        Ok(())
+    }
+
+    async fn delete_if_eq<K, V>(&self, key: &K, value: &V) -> AppResult<bool>
+    where
+        K: Serialize + Sync,
+        V: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let value = serde_json::to_vec(value)?;
+        // Hold the lock for both the check and the removal, so that they're atomic.
+        let mut store = self.lock()?;
+        if store.get(&key) != Some(value) {
+            return Ok(false);
+        }
+        store.remove(&key);
+        Ok(true)
+    }
+
+    async fn add_to_set<K, M>(&self, key: &K, member: &M, seconds: u64) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let member = serde_json::to_string(member)?;
+        let expiry = Instant::now() + Duration::from_secs(seconds);
+        // Hold the lock for both the retrieval and the update, so that they're atomic.
+        let mut store = self.lock()?;
+        let (mut members, _) = store.get_set(&key);
+        members.insert(member);
+        store.set(key, serde_json::to_vec(&members)?, Some(expiry));
+        Ok(())
+    }
+
+    async fn remove_from_set<K, M>(&self, key: &K, member: &M) -> AppResult<()>
+    where
+        K: Serialize + Sync,
+        M: Serialize + Sync,
+    {
+        let key = serde_json::to_vec(key)?;
+        let member = serde_json::to_string(member)?;
+        // Hold the lock for both the retrieval and the update, so that they're atomic.
+        let mut store = self.lock()?;
+        let (mut members, expiry) = store.get_set(&key);
+        if members.remove(&member) {
+            store.set(key, serde_json::to_vec(&members)?, expiry);
+        }
+        Ok(())
+    }
+
+    async fn get_set<K, M>(&self, key: &K) -> AppResult<Vec<M>>
+    where
+        K: Serialize + Sync,
+        M: DeserializeOwned,
+    {
+        let key = serde_json::to_vec(key)?;
+        let (members, _) = self.lock()?.get_set(&key);
+        Ok(deserialize_members(members.iter().map(String::as_bytes)))
+    }
+
+    async fn clear(&self) -> AppResult<()> {
+        self.lock()?.clear();
+        Ok(())
+    }
+
+    async fn ping(&self) -> AppResult<()> {
+        self.lock().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::join_all;
+
+    use super::*;
+
+    /// Expiry time (in seconds) for test entries that shouldn't expire during the test
+    const TEST_TTL: u64 = 60;
+
+    #[actix_web::test]
+    /// Test whether values can be set, retrieved and deleted.
+    async fn test_set_get_delete() {
+        let pool = MemoryPool::new(1024);
+
+        let missing: Option<String> = pool.get(&"key").await.expect("Couldn't get value");
+        assert_eq!(missing, None, "Got value for missing key");
+
+        pool.set(&"key", &"value", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+        // Values should be shared across clones.
+        let other_pool = pool.clone();
+        let value: Option<String> = other_pool.get(&"key").await.expect("Couldn't get value");
+        assert_eq!(value.as_deref(), Some("value"), "Got wrong value");
+
+        pool.delete(&"key").await.expect("Couldn't delete value");
+        let deleted: Option<String> = other_pool.get(&"key").await.expect("Couldn't get value");
+        assert_eq!(deleted, None, "Got deleted value");
+    }
+
+    #[actix_web::test]
+    /// Test whether many values can be retrieved at once.
+    async fn test_get_many() {
+        let pool = MemoryPool::new(1024);
+        pool.set(&"key1", &"value", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+
+        let values: Vec<Option<String>> = pool
+            .get_many(&["key1", "key2"])
+            .await
+            .expect("Couldn't get values");
+        assert_eq!(values, [Some("value".into()), None], "Got wrong values");
+    }
+
+    #[actix_web::test]
+    /// Test whether values are set only for missing keys.
+    async fn test_set_nx() {
+        let pool = MemoryPool::new(1024);
+
+        let set = pool
+            .set_nx(&"key", &"first", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+        assert!(set, "Value wasn't set for missing key");
+        let set = pool
+            .set_nx(&"key", &"second", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+        assert!(!set, "Value was set for existing key");
+
+        let value: Option<String> = pool.get(&"key").await.expect("Couldn't get value");
+        assert_eq!(
+            value.as_deref(),
+            Some("first"),
+            "Existing value was overwritten"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether values are deleted only if they're equal to the given value.
+    async fn test_delete_if_eq() {
+        let pool = MemoryPool::new(1024);
+        pool.set(&"key", &"value", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+
+        let deleted = pool
+            .delete_if_eq(&"key", &"other")
+            .await
+            .expect("Couldn't delete value");
+        assert!(!deleted, "Value was deleted despite a different value");
+        let deleted = pool
+            .delete_if_eq(&"key", &"value")
+            .await
+            .expect("Couldn't delete value");
+        assert!(deleted, "Value wasn't deleted despite an equal value");
+
+        let value: Option<String> = pool.get(&"key").await.expect("Couldn't get value");
+        assert!(value.is_none(), "Value wasn't deleted");
+    }
+
+    #[actix_web::test]
+    /// Test whether members can be added to and removed from sets, including concurrently.
+    async fn test_sets() {
+        let pool = MemoryPool::new(1024);
+        let members: Vec<String> = pool.get_set(&"key").await.expect("Couldn't get set");
+        assert!(members.is_empty(), "Got members of a missing set");
+
+        let members: Vec<u32> = (0..8).collect();
+        join_all(
+            members
+                .iter()
+                .map(|member| pool.add_to_set(&"key", member, TEST_TTL)),
+        )
+        .await
+        .into_iter()
+        .collect::<AppResult<()>>()
+        .expect("Couldn't add member to set");
+        pool.remove_from_set(&"key", &3)
+            .await
+            .expect("Couldn't remove member from set");
+        pool.remove_from_set(&"key", &9)
+            .await
+            .expect("Couldn't remove missing member from set");
+
+        let mut members: Vec<u32> = pool.get_set(&"key").await.expect("Couldn't get set");
+        members.sort_unstable();
+        assert_eq!(members, [0, 1, 2, 4, 5, 6, 7], "Wrong members of set");
+    }
+
+    #[actix_web::test]
+    /// Test whether sets expire after their expiry time.
+    async fn test_set_expiry() {
+        let pool = MemoryPool::new(1024);
+        pool.add_to_set(&"key", &"member", 0)
+            .await
+            .expect("Couldn't add member to set");
+
+        let members: Vec<String> = pool.get_set(&"key").await.expect("Couldn't get set");
+        assert!(members.is_empty(), "Got members of an expired set");
+    }
+
+    #[actix_web::test]
+    /// Test whether all values are deleted when the cache is cleared.
+    async fn test_clear() {
+        let pool = MemoryPool::new(1024);
+
+        for key in ["k1", "k2"] {
+            pool.set(&key, &"v", TEST_TTL)
+                .await
+                .expect("Couldn't set value");
+        }
+        pool.clear().await.expect("Couldn't clear cache");
+
+        for key in ["k1", "k2"] {
+            let value: Option<String> = pool.get(&key).await.expect("Couldn't get value");
+            assert_eq!(value, None, "Got value after clearing for key: {key}");
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether expired values are not retrieved.
+    async fn test_expiry() {
+        let pool = MemoryPool::new(1024);
+
+        pool.set(&"key", &"value", 0)
+            .await
+            .expect("Couldn't set value");
+        let value: Option<String> = pool.get(&"key").await.expect("Couldn't get value");
+        assert_eq!(value, None, "Got expired value");
+    }
+
+    #[actix_web::test]
+    /// Test whether the least recently used entries are evicted when the cache is full.
+    async fn test_lru_eviction() {
+        // Each entry is the JSON-serialized key and value, i.e. 8 bytes in total.
+        let pool = MemoryPool::new(16);
+
+        for key in ["k1", "k2"] {
+            pool.set(&key, &"v", TEST_TTL)
+                .await
+                .expect("Couldn't set value");
+        }
+        // Use the first key, so that the second one is the least recently used.
+        let _: Option<String> = pool.get(&"k1").await.expect("Couldn't get value");
+        pool.set(&"k3", &"v", TEST_TTL)
+            .await
+            .expect("Couldn't set value");
+
+        for (key, exists) in [("k1", true), ("k2", false), ("k3", true)] {
+            let value: Option<String> = pool.get(&key).await.expect("Couldn't get value");
+            assert_eq!(value.is_some(), exists, "Wrong eviction for key: {key}");
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether the in-memory cache responds to pings.
+    async fn test_ping() {
+        MemoryPool::new(1024)
+            .ping()
+            .await
+            .expect("In-memory cache didn't respond to ping");
+    }
+}
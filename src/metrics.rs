@@ -0,0 +1,387 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Metrics for the effectiveness of the cache and for scrapes, in the Prometheus text format
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use actix_web::HttpResponse;
+use serde::Serialize;
+
+use crate::constants::{METRICS_CONTENT_TYPE, METRICS_PREFIX, SCRAPE_DURATION_BUCKETS};
+use crate::errors::AppError;
+
+/// Metrics shared by all workers
+pub static METRICS: Metrics = Metrics::new();
+
+/// Result of looking up a comic's data in the cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheLookup {
+    /// The data is cached and fresh
+    Hit,
+    /// The data is cached, but stale
+    Stale,
+    /// The data isn't cached
+    Miss,
+}
+
+impl CacheLookup {
+    /// All results, in the order in which they're stored
+    const ALL: [Self; 3] = [Self::Hit, Self::Stale, Self::Miss];
+
+    /// Get the name of the result, as used in metrics and traces.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hit => "hit",
+            Self::Stale => "stale",
+            Self::Miss => "miss",
+        }
+    }
+}
+
+/// Category of the failure of a scrape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrapeFailure {
+    /// The comic doesn't exist
+    Missing,
+    /// Requests to the comic source were skipped, such as during an outage
+    SourceUnavailable,
+    /// The archived versions of the comic's page couldn't be located
+    Archive,
+    /// A request to the comic source failed
+    Http,
+    /// The comic's page couldn't be parsed
+    Parse,
+    /// Any other failure
+    Other,
+}
+
+impl ScrapeFailure {
+    /// All categories, in the order in which they're stored
+    const ALL: [Self; 6] = [
+        Self::Missing,
+        Self::SourceUnavailable,
+        Self::Archive,
+        Self::Http,
+        Self::Parse,
+        Self::Other,
+    ];
+
+    /// Get the name of the category, as used in metrics and traces.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::SourceUnavailable => "source_unavailable",
+            Self::Archive => "archive",
+            Self::Http => "http",
+            Self::Parse => "parse",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl From<&AppError> for ScrapeFailure {
+    fn from(err: &AppError) -> Self {
+        match err.original() {
+            AppError::NotFound(_) => Self::Missing,
+            AppError::SourceUnavailable(..) => Self::SourceUnavailable,
+            AppError::Cdx(_) | AppError::Memento(_) => Self::Archive,
+            AppError::Http(_) => Self::Http,
+            AppError::Scrape(_)
+            | AppError::HtmlParse(_)
+            | AppError::DateParse(_)
+            | AppError::Utf8(_)
+            | AppError::Serde(_) => Self::Parse,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// The number of scrapes that fell in a bucket of durations, excluding longer buckets
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ScrapeBucket {
+    /// The upper bound (in seconds) of the bucket
+    pub le: f64,
+    /// The number of scrapes in the bucket
+    pub count: u64,
+}
+
+/// The values of all counters at some instant, such as just before they're reset
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    /// The number of lookups in the cache for each result
+    pub cache_lookups: BTreeMap<&'static str, u64>,
+    /// The number of scrapes in each bucket of durations
+    pub scrape_buckets: Vec<ScrapeBucket>,
+    /// The total number of scrapes
+    pub scrape_count: u64,
+    /// The total duration (in seconds) of all scrapes
+    pub scrape_seconds: f64,
+    /// The number of failed scrapes in each category
+    pub scrape_failures: BTreeMap<&'static str, u64>,
+}
+
+/// Counters for the cache and for scrapes
+pub struct Metrics {
+    /// The number of lookups in the cache for each result
+    cache_lookups: [AtomicU64; CacheLookup::ALL.len()],
+    /// The number of scrapes that fell in each bucket of durations, excluding longer buckets
+    scrape_buckets: [AtomicU64; SCRAPE_DURATION_BUCKETS.len()],
+    /// The total number of scrapes
+    scrape_count: AtomicU64,
+    /// The total duration (in microseconds) of all scrapes
+    scrape_micros: AtomicU64,
+    /// The number of failed scrapes in each category
+    scrape_failures: [AtomicU64; ScrapeFailure::ALL.len()],
+}
+
+impl Metrics {
+    /// Initialize the metrics with all counters at zero.
+    const fn new() -> Self {
+        Self {
+            cache_lookups: [const { AtomicU64::new(0) }; CacheLookup::ALL.len()],
+            scrape_buckets: [const { AtomicU64::new(0) }; SCRAPE_DURATION_BUCKETS.len()],
+            scrape_count: AtomicU64::new(0),
+            scrape_micros: AtomicU64::new(0),
+            scrape_failures: [const { AtomicU64::new(0) }; ScrapeFailure::ALL.len()],
+        }
+    }
+
+    /// Record a lookup of a comic's data in the cache.
+    pub fn record_lookup(&self, lookup: CacheLookup) {
+        self.cache_lookups[lookup as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a scrape of a comic's data.
+    ///
+    /// # Arguments
+    /// * `duration` - The time taken by the scrape
+    /// * `failure` - The category of the scrape's failure, if it failed
+    pub fn record_scrape(&self, duration: Duration, failure: Option<ScrapeFailure>) {
+        let secs = duration.as_secs_f64();
+        if let Some(bucket) = SCRAPE_DURATION_BUCKETS.iter().position(|&le| secs <= le) {
+            self.scrape_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.scrape_count.fetch_add(1, Ordering::Relaxed);
+        self.scrape_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        if let Some(failure) = failure {
+            self.scrape_failures[failure as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Reset all counters to zero, and return their values from just before the reset.
+    ///
+    /// Each counter is swapped atomically, so increments from other workers during the reset are
+    /// either in the returned snapshot or kept after the reset, but never lost. Prometheus treats
+    /// the drop in the counters as a counter reset, like that from a restart.
+    pub fn reset(&self) -> MetricsSnapshot {
+        let swap = |count: &AtomicU64| count.swap(0, Ordering::Relaxed);
+        MetricsSnapshot {
+            cache_lookups: CacheLookup::ALL
+                .iter()
+                .zip(&self.cache_lookups)
+                .map(|(lookup, count)| (lookup.as_str(), swap(count)))
+                .collect(),
+            scrape_buckets: SCRAPE_DURATION_BUCKETS
+                .iter()
+                .zip(&self.scrape_buckets)
+                .map(|(&le, count)| ScrapeBucket {
+                    le,
+                    count: swap(count),
+                })
+                .collect(),
+            scrape_count: swap(&self.scrape_count),
+            scrape_seconds: swap(&self.scrape_micros) as f64 / 1e6,
+            scrape_failures: ScrapeFailure::ALL
+                .iter()
+                .zip(&self.scrape_failures)
+                .map(|(failure, count)| (failure.as_str(), swap(count)))
+                .collect(),
+        }
+    }
+
+    /// Write the metrics in the Prometheus text format.
+    ///
+    /// # Arguments
+    /// * `out` - The writer for the metrics
+    fn write_to(&self, out: &mut impl Write) -> std::fmt::Result {
+        let prefix = METRICS_PREFIX;
+
+        writeln!(
+            out,
+            "# HELP {prefix}_cache_lookups_total Lookups of comic data in the cache, by result"
+        )?;
+        writeln!(out, "# TYPE {prefix}_cache_lookups_total counter")?;
+        for (lookup, count) in CacheLookup::ALL.iter().zip(&self.cache_lookups) {
+            writeln!(
+                out,
+                "{prefix}_cache_lookups_total{{result=\"{}\"}} {}",
+                lookup.as_str(),
+                count.load(Ordering::Relaxed)
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP {prefix}_scrape_duration_seconds Time taken to scrape comic data"
+        )?;
+        writeln!(out, "# TYPE {prefix}_scrape_duration_seconds histogram")?;
+        let mut cumulative = 0;
+        for (le, count) in SCRAPE_DURATION_BUCKETS.iter().zip(&self.scrape_buckets) {
+            cumulative += count.load(Ordering::Relaxed);
+            writeln!(
+                out,
+                "{prefix}_scrape_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}"
+            )?;
+        }
+        let scrape_count = self.scrape_count.load(Ordering::Relaxed);
+        let scrape_secs = self.scrape_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        writeln!(
+            out,
+            "{prefix}_scrape_duration_seconds_bucket{{le=\"+Inf\"}} {scrape_count}"
+        )?;
+        writeln!(out, "{prefix}_scrape_duration_seconds_sum {scrape_secs}")?;
+        writeln!(out, "{prefix}_scrape_duration_seconds_count {scrape_count}")?;
+
+        writeln!(
+            out,
+            "# HELP {prefix}_scrape_failures_total Failed scrapes of comic data, by category"
+        )?;
+        writeln!(out, "# TYPE {prefix}_scrape_failures_total counter")?;
+        for (failure, count) in ScrapeFailure::ALL.iter().zip(&self.scrape_failures) {
+            writeln!(
+                out,
+                "{prefix}_scrape_failures_total{{category=\"{}\"}} {}",
+                failure.as_str(),
+                count.load(Ordering::Relaxed)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        // Writing to a string never fails.
+        let _ = self.write_to(&mut text);
+        text
+    }
+
+    /// Serve the metrics in the Prometheus text format.
+    pub fn serve_metrics(&self) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type(METRICS_CONTENT_TYPE)
+            .body(self.render())
+    }
+
+    /// Reset all counters, and serve their values from just before the reset as JSON.
+    pub fn serve_reset(&self) -> HttpResponse {
+        HttpResponse::Ok().json(self.reset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use test_case::test_case;
+
+    #[test_case(AppError::NotFound(String::new()), ScrapeFailure::Missing; "missing")]
+    #[test_case(AppError::SourceUnavailable(String::new(), Duration::ZERO), ScrapeFailure::SourceUnavailable; "source unavailable")]
+    #[test_case(AppError::Cdx(String::new()), ScrapeFailure::Archive; "CDX")]
+    #[test_case(AppError::Scrape(String::new()), ScrapeFailure::Parse; "scrape")]
+    #[test_case(AppError::Cache(String::new()), ScrapeFailure::Other; "other")]
+    #[test_case(AppError::Shared(Rc::new(AppError::Cdx(String::new())), false), ScrapeFailure::Archive; "shared")]
+    /// Test whether errors are put in the right categories of failures.
+    ///
+    /// # Arguments
+    /// * `err` - The error of the scrape
+    /// * `expected` - The expected category of the failure
+    fn test_failure_category(err: AppError, expected: ScrapeFailure) {
+        assert_eq!(ScrapeFailure::from(&err), expected, "Wrong category");
+    }
+
+    #[test]
+    /// Test whether the recorded metrics are rendered.
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record_lookup(CacheLookup::Hit);
+        metrics.record_lookup(CacheLookup::Hit);
+        metrics.record_lookup(CacheLookup::Miss);
+        metrics.record_scrape(Duration::from_millis(800), None);
+        metrics.record_scrape(Duration::from_secs(100), Some(ScrapeFailure::Http));
+
+        let text = metrics.render();
+        for line in [
+            "dilbert_viewer_cache_lookups_total{result=\"hit\"} 2",
+            "dilbert_viewer_cache_lookups_total{result=\"stale\"} 0",
+            "dilbert_viewer_cache_lookups_total{result=\"miss\"} 1",
+            "dilbert_viewer_scrape_duration_seconds_bucket{le=\"0.5\"} 0",
+            "dilbert_viewer_scrape_duration_seconds_bucket{le=\"1\"} 1",
+            "dilbert_viewer_scrape_duration_seconds_bucket{le=\"40\"} 1",
+            "dilbert_viewer_scrape_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "dilbert_viewer_scrape_duration_seconds_sum 100.8",
+            "dilbert_viewer_scrape_duration_seconds_count 2",
+            "dilbert_viewer_scrape_failures_total{category=\"http\"} 1",
+            "dilbert_viewer_scrape_failures_total{category=\"parse\"} 0",
+        ] {
+            assert!(
+                text.lines().any(|text_line| text_line == line),
+                "Missing line {line:?} in metrics:\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    /// Test whether resetting the metrics zeroes them, and returns their values before the reset.
+    fn test_reset() {
+        let metrics = Metrics::new();
+        metrics.record_lookup(CacheLookup::Hit);
+        metrics.record_lookup(CacheLookup::Stale);
+        metrics.record_scrape(Duration::from_millis(800), None);
+        metrics.record_scrape(Duration::from_millis(1500), Some(ScrapeFailure::Missing));
+
+        let snapshot = metrics.reset();
+        assert_eq!(
+            snapshot.cache_lookups,
+            BTreeMap::from([("hit", 1), ("miss", 0), ("stale", 1)]),
+            "Wrong cache lookups in snapshot"
+        );
+        assert_eq!(snapshot.scrape_count, 2, "Wrong scrape count in snapshot");
+        assert_eq!(
+            snapshot.scrape_seconds, 2.3,
+            "Wrong scrape duration in snapshot"
+        );
+        assert_eq!(
+            snapshot.scrape_buckets.iter().map(|b| b.count).sum::<u64>(),
+            2,
+            "Wrong scrape buckets in snapshot"
+        );
+        assert_eq!(
+            snapshot.scrape_failures["missing"], 1,
+            "Wrong scrape failures in snapshot"
+        );
+
+        let after = metrics.reset();
+        assert!(
+            after.cache_lookups.values().all(|&count| count == 0),
+            "Cache lookups weren't reset"
+        );
+        assert!(
+            after.scrape_buckets.iter().all(|bucket| bucket.count == 0),
+            "Scrape buckets weren't reset"
+        );
+        assert_eq!(after.scrape_count, 0, "Scrape count wasn't reset");
+        assert_eq!(after.scrape_seconds, 0.0, "Scrape duration wasn't reset");
+        assert!(
+            after.scrape_failures.values().all(|&count| count == 0),
+            "Scrape failures weren't reset"
+        );
+    }
+}
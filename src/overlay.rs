@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Attribution overlays on comics' images, for redistributing them with credit
+//!
+//! The attribution is drawn in a translucent band along the bottom of the comic, followed by the
+//! URL of the comic on its source.
+use std::fmt;
+use std::io::Cursor;
+use std::path::Path;
+
+use ab_glyph::FontArc;
+use image::{ImageFormat, Rgb};
+use imageproc::drawing::draw_text_mut;
+use tracing::{debug, warn};
+
+use crate::constants::{
+    OVERLAY_BAND_HEIGHT, OVERLAY_MIME_TYPE, OVERLAY_PADDING, OVERLAY_TEXT_SIZE,
+};
+use crate::errors::AppResult;
+use crate::preview::{fit_text, FONT};
+use crate::scrapers::ComicImage;
+
+/// The colour of the band behind the attribution
+const BAND: Rgb<u8> = Rgb([0x21, 0x25, 0x29]);
+/// The opacity of the band, so that the comic still shows through it
+const BAND_OPACITY: f32 = 0.75;
+/// The colour of the attribution
+const TEXT: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// The attribution to overlay on comics' images, along with the font to draw it in
+#[derive(Clone)]
+pub struct Attribution {
+    /// The text of the attribution
+    text: String,
+    /// The font in which the attribution is drawn
+    font: FontArc,
+}
+
+impl fmt::Debug for Attribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The font is just a large blob of bytes, so it's left out.
+        f.debug_struct("Attribution")
+            .field("text", &self.text)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Attribution {
+    /// Load the attribution, with its font from a file if given, or else the bundled font.
+    ///
+    /// If the font can't be loaded, then None is returned with a warning, so that images are
+    /// served without the overlay instead of not at all.
+    ///
+    /// # Arguments
+    /// * `text` - The text of the attribution
+    /// * `font_path` - The path to a TrueType or OpenType font for the attribution, if any
+    pub fn load(text: String, font_path: Option<&Path>) -> Option<Self> {
+        let bytes = match font_path {
+            Some(path) => match std::fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    warn!("Couldn't read attribution font, so it won't be overlaid: {err}");
+                    return None;
+                }
+            },
+            None => FONT.to_vec(),
+        };
+        match FontArc::try_from_vec(bytes) {
+            Ok(font) => Some(Self { text, font }),
+            Err(err) => {
+                warn!("Invalid attribution font, so it won't be overlaid: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// Render a comic's image with the attribution overlaid on it.
+///
+/// This is CPU-intensive, so it should be run on a blocking thread.
+///
+/// # Arguments
+/// * `image` - The comic's image
+/// * `attribution` - The attribution to overlay
+/// * `source_url` - The URL of the comic on its source, which may be empty
+pub fn render_overlay(
+    image: &ComicImage,
+    attribution: &Attribution,
+    source_url: &str,
+) -> AppResult<ComicImage> {
+    let mut canvas = image::load_from_memory(&image.bytes)?.to_rgb8();
+    let (width, height) = canvas.dimensions();
+
+    // Blend the band into the bottom of the comic, so that the text is readable over it.
+    let band_top = height.saturating_sub(OVERLAY_BAND_HEIGHT);
+    for y in band_top..height {
+        for x in 0..width {
+            let pixel = canvas.get_pixel_mut(x, y);
+            for (channel, band) in pixel.0.iter_mut().zip(BAND.0) {
+                let blended =
+                    f32::from(*channel) * (1.0 - BAND_OPACITY) + f32::from(band) * BAND_OPACITY;
+                *channel = blended.round() as u8;
+            }
+        }
+    }
+
+    let text = if source_url.is_empty() {
+        attribution.text.clone()
+    } else {
+        format!("{} · {source_url}", attribution.text)
+    };
+    let text = fit_text(
+        &text,
+        &attribution.font,
+        OVERLAY_TEXT_SIZE,
+        width.saturating_sub(2 * OVERLAY_PADDING),
+    );
+    // Centre the text vertically in the band.
+    let y = band_top + OVERLAY_BAND_HEIGHT.saturating_sub(OVERLAY_TEXT_SIZE as u32) / 2;
+    draw_text_mut(
+        &mut canvas,
+        TEXT,
+        OVERLAY_PADDING as i32,
+        y as i32,
+        OVERLAY_TEXT_SIZE,
+        &attribution.font,
+        &text,
+    );
+
+    let mut bytes = Vec::new();
+    canvas.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    debug!("Rendered overlaid image of {} bytes", bytes.len());
+    Ok(ComicImage {
+        content_type: OVERLAY_MIME_TYPE.into(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::RgbImage;
+    use test_case::test_case;
+
+    #[test_case("https://dilbert.com/strip/2000-01-01"; "with source URL")]
+    #[test_case(""; "without source URL")]
+    /// Test whether the overlaid image keeps the comic's size, with the band drawn over it.
+    ///
+    /// # Arguments
+    /// * `source_url` - The URL of the comic on its source
+    fn test_render_overlay(source_url: &str) {
+        let mut bytes = Vec::new();
+        RgbImage::from_pixel(900, 280, Rgb([255, 255, 255]))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .expect("Couldn't encode test image");
+        let image = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+        let attribution =
+            Attribution::load("Dilbert by Scott Adams".into(), None).expect("Invalid font");
+
+        let overlaid = render_overlay(&image, &attribution, source_url).expect("Couldn't render");
+        assert_eq!(
+            overlaid.content_type, OVERLAY_MIME_TYPE,
+            "Wrong content type"
+        );
+        let decoded = image::load_from_memory(&overlaid.bytes)
+            .expect("Invalid overlaid image")
+            .to_rgb8();
+        assert_eq!(decoded.dimensions(), (900, 280), "Wrong image size");
+        assert_eq!(
+            decoded.get_pixel(0, 0),
+            &Rgb([255, 255, 255]),
+            "Comic above the band was changed"
+        );
+        assert_ne!(
+            decoded.get_pixel(899, 279),
+            &Rgb([255, 255, 255]),
+            "Band wasn't drawn"
+        );
+    }
+
+    #[test]
+    /// Test whether an unavailable font disables the overlay, instead of failing.
+    fn test_missing_font() {
+        let path = std::env::temp_dir().join(format!("{}.ttf", uuid::Uuid::new_v4()));
+        assert!(
+            Attribution::load("Attribution".into(), Some(&path)).is_none(),
+            "Overlay enabled without its font"
+        );
+    }
+}
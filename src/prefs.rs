@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Preferences of clients that affect the comic pages served to them
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+
+use crate::favorites::Favorites;
+use crate::i18n::Lang;
+use crate::theme::Theme;
+
+/// The preferences of a client for comic pages
+#[derive(Clone, Debug, Default)]
+pub struct ClientPrefs {
+    /// The colour theme chosen by the client
+    pub theme: Theme,
+    /// The language of the page
+    pub lang: Lang,
+    /// The comics that the client has marked as favorites
+    pub favorites: Favorites,
+}
+
+impl FromRequest for ClientPrefs {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Get all preferences of the client, whose extractors are all ready immediately.
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Ok(theme) = Theme::from_request(req, payload).into_inner();
+        let Ok(lang) = Lang::from_request(req, payload).into_inner();
+        let Ok(favorites) = Favorites::from_request(req, payload).into_inner();
+        ready(Ok(Self {
+            theme,
+            lang,
+            favorites,
+        }))
+    }
+}
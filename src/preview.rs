@@ -0,0 +1,160 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Social-share preview images of comics
+//!
+//! A preview has the comic's image scaled to fit above a banner with the comic's title and date.
+//! This lets sites that unfurl links show the comic without hotlinking its original image.
+use std::io::Cursor;
+
+use ab_glyph::{Font, FontRef};
+use image::{imageops, ImageFormat, Rgb, RgbImage};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_text_mut, text_size},
+    rect::Rect,
+};
+use tracing::debug;
+
+use crate::constants::{
+    PREVIEW_BANNER_HEIGHT, PREVIEW_DATE_SIZE, PREVIEW_HEIGHT, PREVIEW_MIME_TYPE, PREVIEW_PADDING,
+    PREVIEW_TITLE_SIZE, PREVIEW_WIDTH,
+};
+use crate::errors::AppResult;
+use crate::scrapers::ComicImage;
+
+/// The font for the text in previews
+// This is compiled in, so that the server doesn't depend on the fonts installed where it runs.
+pub(crate) const FONT: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+/// The colour of the area around the comic, which matches the comics' own background
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+/// The colour of the banner, which matches the footer of the pages
+const BANNER: Rgb<u8> = Rgb([0x21, 0x25, 0x29]);
+/// The colour of the text in the banner
+const TEXT: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Shorten text with an ellipsis, so that it fits within a width when drawn.
+///
+/// # Arguments
+/// * `text` - The text to shorten
+/// * `font` - The font in which the text is drawn
+/// * `size` - The font size (in pixels)
+/// * `max_width` - The maximum width (in pixels) of the drawn text
+pub(crate) fn fit_text(text: &str, font: &impl Font, size: f32, max_width: u32) -> String {
+    if text_size(size, font, text).0 <= max_width {
+        return text.into();
+    }
+    let mut chars: Vec<_> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let shortened = format!("{}…", String::from_iter(&chars).trim_end());
+        if text_size(size, font, &shortened).0 <= max_width {
+            return shortened;
+        }
+    }
+    String::new()
+}
+
+/// Render the preview image of a comic.
+///
+/// This is CPU-intensive, so it should be run on a blocking thread.
+///
+/// # Arguments
+/// * `image` - The comic's image
+/// * `title` - The comic's title, which may be empty
+/// * `date` - The comic's date, formatted for display
+pub fn render_preview(image: &ComicImage, title: &str, date: &str) -> AppResult<ComicImage> {
+    let font = FontRef::try_from_slice(FONT)?;
+    let mut canvas = RgbImage::from_pixel(PREVIEW_WIDTH, PREVIEW_HEIGHT, BACKGROUND);
+
+    // Scale the comic to fit above the banner, keeping its aspect ratio.
+    let comic_height = PREVIEW_HEIGHT - PREVIEW_BANNER_HEIGHT;
+    let comic = image::load_from_memory(&image.bytes)?
+        .resize(
+            PREVIEW_WIDTH - 2 * PREVIEW_PADDING,
+            comic_height - 2 * PREVIEW_PADDING,
+            imageops::FilterType::Lanczos3,
+        )
+        .to_rgb8();
+    let x = (PREVIEW_WIDTH - comic.width()) / 2;
+    let y = (comic_height - comic.height()) / 2;
+    imageops::overlay(&mut canvas, &comic, x.into(), y.into());
+
+    // Draw the banner, with the title (if any) above the date.
+    let banner = Rect::at(0, comic_height as i32).of_size(PREVIEW_WIDTH, PREVIEW_BANNER_HEIGHT);
+    draw_filled_rect_mut(&mut canvas, banner, BANNER);
+    let max_width = PREVIEW_WIDTH - 2 * PREVIEW_PADDING;
+    let (x, mut y) = (
+        PREVIEW_PADDING as i32,
+        (comic_height + PREVIEW_PADDING) as i32,
+    );
+    if !title.is_empty() {
+        let title = fit_text(title, &font, PREVIEW_TITLE_SIZE, max_width);
+        draw_text_mut(&mut canvas, TEXT, x, y, PREVIEW_TITLE_SIZE, &font, &title);
+        y += PREVIEW_TITLE_SIZE as i32;
+    }
+    draw_text_mut(&mut canvas, TEXT, x, y, PREVIEW_DATE_SIZE, &font, date);
+
+    let mut bytes = Vec::new();
+    canvas.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    debug!("Rendered preview image of {} bytes", bytes.len());
+    Ok(ComicImage {
+        content_type: PREVIEW_MIME_TYPE.into(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(""; "untitled")]
+    #[test_case("Title"; "titled")]
+    #[test_case(&"Very long title ".repeat(20); "long title")]
+    /// Test whether previews have the right size, for comics of any shape.
+    ///
+    /// # Arguments
+    /// * `title` - The comic's title
+    fn test_render_preview(title: &str) {
+        let mut bytes = Vec::new();
+        RgbImage::from_fn(900, 280, |x, y| Rgb([x as u8, y as u8, 0]))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .expect("Couldn't encode test image");
+        let image = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+
+        let preview = render_preview(&image, title, "January 1, 2000").expect("Couldn't render");
+        assert_eq!(
+            preview.content_type, PREVIEW_MIME_TYPE,
+            "Wrong content type"
+        );
+        let decoded = image::load_from_memory(&preview.bytes).expect("Invalid preview image");
+        assert_eq!(
+            (decoded.width(), decoded.height()),
+            (PREVIEW_WIDTH, PREVIEW_HEIGHT),
+            "Wrong preview size"
+        );
+    }
+
+    #[test]
+    /// Test whether long text is shortened to fit.
+    fn test_fit_text() {
+        let font = FontRef::try_from_slice(FONT).expect("Invalid font");
+        let text = "Very long title ".repeat(20);
+        let fitted = fit_text(&text, &font, PREVIEW_TITLE_SIZE, 500);
+        assert!(fitted.ends_with('…'), "Long text wasn't shortened");
+        assert!(
+            text_size(PREVIEW_TITLE_SIZE, &font, &fitted).0 <= 500,
+            "Shortened text doesn't fit"
+        );
+        assert_eq!(
+            fit_text("Short", &font, PREVIEW_TITLE_SIZE, 500),
+            "Short",
+            "Short text was changed"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Random choice of comics
+use chrono::{Datelike, Duration, NaiveDate};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tracing::{error, info};
+
+use crate::constants::{
+    DAILY_COMIC_PREFIX, DAILY_COMIC_TTL, FIRST_COMIC, LAST_COMIC, SRC_DATE_FMT,
+};
+use crate::datetime::str_to_date;
+use crate::db::CacheBackend;
+
+/// Choose the date of a random comic.
+///
+/// # Arguments
+/// * `rng` - The random number generator to use
+pub fn random_comic_date<R: Rng>(rng: &mut R) -> NaiveDate {
+    let first = str_to_date(FIRST_COMIC, SRC_DATE_FMT)
+        .expect("Variable FIRST_COMIC not in format of variable SRC_DATE_FMT");
+    let last = str_to_date(LAST_COMIC, SRC_DATE_FMT)
+        .expect("Variable LAST_COMIC not in format of variable SRC_DATE_FMT");
+
+    // Offset (in days) from the first date
+    let rand_offset = rng.gen_range(0..(last - first).num_days());
+    first + Duration::days(rand_offset)
+}
+
+/// Picker of the "comic of the day"
+///
+/// The comic is chosen by seeding the RNG with the day, so that every instance of the app picks the
+/// same one. The choice is also cached, so that it stays the same across versions of the RNG.
+pub struct DailyComic<T: CacheBackend + 'static> {
+    /// The cache for the chosen dates, if caching is enabled
+    db: Option<T>,
+}
+
+impl<T: CacheBackend + 'static> DailyComic<T> {
+    /// Initialize the picker.
+    ///
+    /// # Arguments
+    /// * `db` - The optional cache for the chosen dates
+    pub fn new(db: Option<T>) -> Self {
+        Self { db }
+    }
+
+    /// Get the date of the comic of the given day.
+    ///
+    /// # Arguments
+    /// * `today` - The day for which the comic is chosen
+    pub async fn get_date(&self, today: &NaiveDate) -> NaiveDate {
+        let key = (DAILY_COMIC_PREFIX, today);
+        if let Some(db) = &self.db {
+            match db.get(&key).await {
+                Ok(Some(date)) => return date,
+                Ok(None) => (),
+                // The date can always be chosen again, so simply log the error.
+                Err(err) => error!("Error retrieving comic of the day from cache: {err}"),
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(today.num_days_from_ce().unsigned_abs().into());
+        let date = random_comic_date(&mut rng);
+        info!("Chose comic of the day for {today}: {date}");
+
+        if let Some(db) = &self.db {
+            if let Err(err) = db.set(&key, &date, DAILY_COMIC_TTL).await {
+                error!("Error caching comic of the day: {err}");
+            }
+        }
+        date
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constants::MEM_CACHE_SIZE;
+    use crate::memory_db::MemoryPool;
+
+    #[actix_web::test]
+    /// Test whether the comic of the day is the same for the same day, and is cached.
+    async fn test_daily_comic() {
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+
+        let date = DailyComic::new(Some(db.clone())).get_date(&today).await;
+        let uncached = DailyComic::<MemoryPool>::new(None).get_date(&today).await;
+        assert_eq!(date, uncached, "Comic of the day isn't deterministic");
+
+        let cached: Option<NaiveDate> = db
+            .get(&(DAILY_COMIC_PREFIX, today))
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(cached, Some(date), "Comic of the day wasn't cached");
+    }
+
+    #[actix_web::test]
+    /// Test whether a cached comic of the day is used.
+    async fn test_daily_comic_cached() {
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let expected = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        db.set(&(DAILY_COMIC_PREFIX, today), &expected, DAILY_COMIC_TTL)
+            .await
+            .expect("Couldn't set mock cache entry");
+
+        let date = DailyComic::new(Some(db)).get_date(&today).await;
+        assert_eq!(date, expected, "Didn't use the cached comic of the day");
+    }
+}
@@ -0,0 +1,398 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Per-client rate limiting of requests, using token buckets
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error,
+};
+use lru::LruCache;
+use tracing::{error, info};
+
+use crate::app::serve_429;
+use crate::client_ip::client_ip;
+use crate::constants::{RATE_LIMIT_EXEMPT, RATE_LIMIT_KEY_PREFIX, RATE_LIMIT_MAX_CLIENTS};
+use crate::db::{DbPool, RedisPool};
+use crate::errors::{AppError, AppResult};
+
+/// Lua script to atomically take a token from a client's bucket in Redis
+///
+/// This returns the time (in milliseconds) after which a token will be available, which is 0 if
+/// a token was taken. Unused buckets expire once they would be full again.
+const REDIS_SCRIPT: &str = "
+local burst = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or burst
+local updated = tonumber(bucket[2]) or now
+tokens = math.min(burst, tokens + math.max(0, now - updated) * rate)
+local wait = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    wait = math.ceil((1 - tokens) / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', tostring(now))
+redis.call('PEXPIRE', KEYS[1], math.ceil(burst / rate))
+return wait
+";
+
+/// The limits on requests from each client
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// The sustained number of requests allowed per minute
+    pub per_minute: NonZeroU32,
+    /// The number of requests allowed in a burst
+    pub burst: NonZeroU32,
+}
+
+impl RateLimit {
+    /// Get the number of tokens added to each bucket per millisecond.
+    fn rate_per_ms(&self) -> f64 {
+        f64::from(self.per_minute.get()) / 60_000.0
+    }
+}
+
+/// A client's bucket of tokens, where each request takes a token
+#[derive(Debug)]
+pub struct Bucket {
+    /// The number of available tokens, which can be fractional
+    tokens: f64,
+    /// When the tokens were last updated
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Initialize a full bucket.
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit.burst.get()),
+            updated: now,
+        }
+    }
+
+    /// Refill the bucket for the elapsed time, and take a token if available.
+    ///
+    /// If no token is available, then the time after which one will be available is returned.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        let rate = limit.rate_per_ms();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64() * 1000.0;
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(limit.burst.get()));
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate / 1000.0))
+        }
+    }
+}
+
+/// Storage for the buckets of all clients
+pub enum RateLimitStore<P: RedisPool = DbPool> {
+    /// Buckets in Redis, which are shared with other instances of the app
+    Redis(P),
+    /// Buckets in memory, which are evicted for the least recently seen clients if there are too
+    /// many
+    Memory(Mutex<LruCache<String, Bucket>>),
+}
+
+impl<P: RedisPool> RateLimitStore<P> {
+    /// Initialize an in-memory store.
+    pub fn memory() -> Self {
+        let capacity = NonZeroUsize::new(RATE_LIMIT_MAX_CLIENTS).unwrap_or(NonZeroUsize::MIN);
+        Self::Memory(Mutex::new(LruCache::new(capacity)))
+    }
+}
+
+/// Rate limiter for requests from each client
+pub struct RateLimiter<P: RedisPool = DbPool> {
+    limit: RateLimit,
+    store: RateLimitStore<P>,
+}
+
+impl<P: RedisPool> RateLimiter<P> {
+    /// Initialize a rate limiter.
+    ///
+    /// # Arguments
+    /// * `limit` - The limits on requests from each client
+    /// * `store` - The storage for the buckets of all clients
+    pub fn new(limit: RateLimit, store: RateLimitStore<P>) -> Self {
+        Self { limit, store }
+    }
+
+    /// Take a token for a request from a client, without handling errors.
+    async fn take_raw(&self, client: &str) -> AppResult<Result<(), Duration>> {
+        match &self.store {
+            RateLimitStore::Memory(buckets) => {
+                let mut buckets = buckets
+                    .lock()
+                    .map_err(|err| AppError::Cache(format!("Lock is poisoned: {err}")))?;
+                let now = Instant::now();
+                let bucket =
+                    buckets.get_or_insert_mut(client.to_string(), || Bucket::new(&self.limit, now));
+                Ok(bucket.take(&self.limit, now))
+            }
+            RateLimitStore::Redis(pool) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                self.take_redis(pool, client, now).await
+            }
+        }
+    }
+
+    /// Take a token for a request from a client using the buckets in Redis.
+    ///
+    /// # Arguments
+    /// * `pool` - The pool of Redis connections
+    /// * `client` - The identifier of the client
+    /// * `now` - The current UNIX time in milliseconds
+    async fn take_redis(
+        &self,
+        pool: &P,
+        client: &str,
+        now: u64,
+    ) -> AppResult<Result<(), Duration>> {
+        let mut conn = pool.get().await?;
+        let wait: u64 = redis::cmd("EVAL")
+            .arg(REDIS_SCRIPT)
+            .arg(1)
+            .arg(format!("{RATE_LIMIT_KEY_PREFIX}:{client}"))
+            .arg(self.limit.burst.get())
+            .arg(self.limit.rate_per_ms())
+            .arg(now)
+            .query_async(&mut conn)
+            .await?;
+        if wait == 0 {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(Duration::from_millis(wait)))
+        }
+    }
+
+    /// Take a token for a request from a client.
+    ///
+    /// If the client is rate-limited, then the time after which it can retry is returned. If the
+    /// store fails, then the request is allowed, so that the app stays usable.
+    ///
+    /// # Arguments
+    /// * `client` - The identifier of the client, such as its IP address
+    pub async fn take(&self, client: &str) -> Result<(), Duration> {
+        match self.take_raw(client).await {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Error in rate limiting: {err}");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Middleware to reject requests from clients that exceed the rate limit.
+///
+/// Clients are identified by their IP address, which respects the headers of trusted proxies like
+/// the logs do.
+/// This does nothing if no rate limiter is registered as app data.
+pub async fn rate_limit_middleware(
+    limiter: Option<web::Data<RateLimiter>>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let client = client_ip(&req);
+    if let (Some(limiter), Some(client)) = (limiter, client) {
+        if !RATE_LIMIT_EXEMPT.contains(&req.path()) {
+            if let Err(wait) = limiter.take(&client).await {
+                info!("Rate limited client: {client}");
+                return Ok(req.into_response(serve_429(wait)).map_into_right_body());
+            }
+        }
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::{
+        http::{header::RETRY_AFTER, StatusCode},
+        middleware::from_fn,
+        test::{call_service, init_service, TestRequest},
+        App, HttpResponse,
+    };
+    use redis::Value;
+    use redis_test::{MockCmd, MockRedisConnection};
+
+    use crate::db::mock::MockPool;
+
+    /// Get the limits for tests.
+    ///
+    /// # Arguments
+    /// * `per_minute` - The sustained number of requests allowed per minute
+    /// * `burst` - The number of requests allowed in a burst
+    fn get_limit(per_minute: u32, burst: u32) -> RateLimit {
+        RateLimit {
+            per_minute: NonZeroU32::new(per_minute).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        }
+    }
+
+    #[test]
+    /// Test whether a bucket allows a burst, and then refills at the sustained rate.
+    fn test_bucket() {
+        let limit = get_limit(60, 2);
+        let start = Instant::now();
+        let mut bucket = Bucket::new(&limit, start);
+
+        assert!(bucket.take(&limit, start).is_ok(), "Burst not allowed");
+        assert!(bucket.take(&limit, start).is_ok(), "Burst not allowed");
+        let wait = bucket
+            .take(&limit, start)
+            .expect_err("Request over the burst allowed");
+        assert!(
+            wait <= Duration::from_secs(1),
+            "Wait is longer than the time for a token: {wait:?}"
+        );
+
+        // 1 token is refilled every second.
+        let later = start + Duration::from_secs(1);
+        assert!(bucket.take(&limit, later).is_ok(), "Bucket wasn't refilled");
+        assert!(
+            bucket.take(&limit, later).is_err(),
+            "Bucket was refilled too much"
+        );
+
+        // The bucket never holds more than the burst.
+        let much_later = start + Duration::from_secs(60);
+        assert!(bucket.take(&limit, much_later).is_ok(), "Burst not allowed");
+        assert!(bucket.take(&limit, much_later).is_ok(), "Burst not allowed");
+        assert!(
+            bucket.take(&limit, much_later).is_err(),
+            "Bucket was filled over the burst"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether each client gets its own bucket in memory.
+    async fn test_memory_clients() {
+        let limiter = RateLimiter::<MockPool>::new(get_limit(1, 1), RateLimitStore::memory());
+        assert!(
+            limiter.take("1.1.1.1").await.is_ok(),
+            "First request denied"
+        );
+        assert!(
+            limiter.take("1.1.1.1").await.is_err(),
+            "Request over the limit allowed"
+        );
+        assert!(
+            limiter.take("2.2.2.2").await.is_ok(),
+            "Other client's request denied"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether buckets in Redis are used, and whether Redis errors are raised.
+    async fn test_redis() {
+        let limit = get_limit(60, 1);
+        let now = 1_000_000;
+        let get_cmd = |reply| {
+            let cmd = redis::cmd("EVAL")
+                .arg(REDIS_SCRIPT)
+                .arg(1)
+                .arg(format!("{RATE_LIMIT_KEY_PREFIX}:1.1.1.1"))
+                .arg(limit.burst.get())
+                .arg(limit.rate_per_ms())
+                .arg(now)
+                .clone();
+            MockCmd::new(cmd, reply)
+        };
+        let error = redis::RedisError::from((redis::ErrorKind::ResponseError, "Manual error"));
+        let commands = vec![
+            get_cmd(Ok(Value::Int(0))),
+            get_cmd(Ok(Value::Int(1500))),
+            get_cmd(Err(error)),
+        ];
+
+        // Connections are taken out of the pool on use, so add one for each command.
+        let pool = MockPool::new(commands.len());
+        let conn = MockRedisConnection::new(commands);
+        for _ in 0..pool.status().max_size {
+            if let Err((_, err)) = pool.add(conn.clone()).await {
+                panic!("Couldn't add mock connection to pool: {err}");
+            }
+        }
+        let limiter = RateLimiter::new(limit, RateLimitStore::memory());
+
+        let result = limiter
+            .take_redis(&pool, "1.1.1.1", now)
+            .await
+            .expect("Couldn't take token");
+        assert_eq!(result, Ok(()), "Request denied");
+
+        let result = limiter
+            .take_redis(&pool, "1.1.1.1", now)
+            .await
+            .expect("Couldn't take token");
+        assert_eq!(
+            result,
+            Err(Duration::from_millis(1500)),
+            "Wrong wait for a denied request"
+        );
+
+        let result = limiter.take_redis(&pool, "1.1.1.1", now).await;
+        assert!(result.is_err(), "Redis error wasn't raised");
+    }
+
+    #[actix_web::test]
+    /// Test whether the middleware responds with a 429 with the time after which to retry.
+    async fn test_middleware() {
+        let limiter = web::Data::new(RateLimiter::<DbPool>::new(
+            get_limit(1, 1),
+            RateLimitStore::memory(),
+        ));
+        let app = init_service(
+            App::new()
+                .app_data(limiter)
+                .wrap(from_fn(rate_limit_middleware))
+                .default_service(web::to(HttpResponse::Ok)),
+        )
+        .await;
+        let get_request = |path| {
+            TestRequest::get()
+                .uri(path)
+                .peer_addr("1.1.1.1:1234".parse().unwrap())
+                .to_request()
+        };
+
+        let resp = call_service(&app, get_request("/")).await;
+        assert_eq!(resp.status(), StatusCode::OK, "First request denied");
+
+        let resp = call_service(&app, get_request("/")).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::TOO_MANY_REQUESTS,
+            "Request over the limit allowed"
+        );
+        assert_eq!(
+            resp.headers()
+                .get(RETRY_AFTER)
+                .map(|value| value.as_bytes()),
+            Some(&b"60"[..]),
+            "Wrong time after which to retry"
+        );
+
+        let resp = call_service(&app, get_request(RATE_LIMIT_EXEMPT[0])).await;
+        assert_eq!(resp.status(), StatusCode::OK, "Exempt path was limited");
+    }
+}
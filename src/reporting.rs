@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Opt-in reporting of errors to Sentry
+//!
+//! Reports are tagged with the ID of the request that raised them (as in the request's span), and
+//! with the date of the comic involved, if any. When reporting isn't enabled, reporting errors is
+//! a no-op.
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use sentry::{ClientInitGuard, ClientOptions, Hub};
+
+use crate::constants::SRC_DATE_FMT;
+use crate::errors::AppError;
+
+/// Enable reporting errors to Sentry.
+///
+/// # Arguments
+/// * `dsn` - The Sentry DSN to which errors are reported
+///
+/// # Returns
+/// * The guard that flushes the remaining reports when dropped
+pub fn init_reporting(dsn: &str) -> ClientInitGuard {
+    sentry::init((
+        dsn,
+        ClientOptions {
+            release: sentry::release_name!(),
+            ..Default::default()
+        },
+    ))
+}
+
+/// Get the hub for reporting the errors raised when handling a request.
+///
+/// If reporting is enabled, then this is a new hub whose reports are tagged with the request's ID.
+/// Otherwise, this is the current hub, to avoid a new hub for each request.
+///
+/// # Arguments
+/// * `request_id` - The unique ID of the request
+pub fn request_hub(request_id: &str) -> Arc<Hub> {
+    let hub = Hub::current();
+    if hub.client().is_none() {
+        return hub;
+    }
+    let hub = Arc::new(Hub::new_from_top(hub));
+    hub.configure_scope(|scope| scope.set_tag("request_id", request_id));
+    hub
+}
+
+/// Report an unexpected error.
+///
+/// # Arguments
+/// * `err` - The error to report
+/// * `date` - The date of the comic involved in the error, if any
+pub fn report_error(err: &AppError, date: Option<&NaiveDate>) {
+    if !err.is_reportable() {
+        return;
+    }
+    sentry::with_scope(
+        |scope| {
+            if let Some(date) = date {
+                scope.set_tag("date", date.format(SRC_DATE_FMT));
+            }
+        },
+        || sentry::capture_error(err.original()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use test_case::test_case;
+    use uuid::Uuid;
+
+    #[test_case(AppError::Scrape("Error".into()), true; "unexpected")]
+    #[test_case(AppError::NotFound("Missing".into()), false; "expected")]
+    #[test_case(AppError::Shared(Rc::new(AppError::Scrape("Error".into())), true), true; "shared by retriever")]
+    #[test_case(AppError::Shared(Rc::new(AppError::Scrape("Error".into())), false), false; "shared with others")]
+    /// Test whether reported errors are tagged with the request ID and the comic's date.
+    ///
+    /// # Arguments
+    /// * `err` - The error to report
+    /// * `reported` - Whether the error is expected to be reported
+    fn test_report_error(err: AppError, reported: bool) {
+        let request_id = Uuid::new_v4().to_string();
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let events = sentry::test::with_captured_events(|| {
+            Hub::run(request_hub(&request_id), || report_error(&err, Some(&date)));
+        });
+
+        assert_eq!(
+            events.len(),
+            usize::from(reported),
+            "Wrong number of reports"
+        );
+        if let Some(event) = events.first() {
+            assert_eq!(
+                event.tags.get("request_id"),
+                Some(&request_id),
+                "Wrong request ID"
+            );
+            assert_eq!(
+                event.tags.get("date").map(String::as_str),
+                Some("2000-01-01"),
+                "Wrong date"
+            );
+        }
+    }
+}
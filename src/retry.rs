@@ -0,0 +1,321 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Retrying of transient failures in requests to the comic source
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+use actix_web::rt::time::sleep;
+use awc::{
+    error::SendRequestError,
+    http::{
+        header::{HeaderMap, HttpDate, RETRY_AFTER},
+        StatusCode,
+    },
+    ClientResponse,
+};
+use rand::{thread_rng, Rng};
+use tracing::warn;
+
+use crate::constants::{RETRY_AFTER_MAX, RETRY_ATTEMPTS, RETRY_BACKOFF};
+
+/// Policy for retrying requests that fail transiently
+///
+/// Timeouts, connection errors, 429 Too Many Requests and 5xx responses are considered transient.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for each request, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, which doubles for each subsequent retry
+    pub backoff: Duration,
+    /// Whether to randomize each delay, so that concurrent retries are spread out
+    pub jitter: bool,
+}
+
+/// Get the reason for a transient failure of a request, if it failed transiently.
+///
+/// # Arguments
+/// * `result` - The result of sending the request
+pub fn transient_failure<S>(
+    result: &Result<ClientResponse<S>, SendRequestError>,
+) -> Option<String> {
+    match result {
+        Ok(resp)
+            if resp.status().is_server_error()
+                || resp.status() == StatusCode::TOO_MANY_REQUESTS =>
+        {
+            Some(resp.status().to_string())
+        }
+        Err(err @ (SendRequestError::Timeout | SendRequestError::Connect(_))) => {
+            Some(err.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Get the delay asked for by the "Retry-After" header of a response, if any.
+///
+/// The header is either a number of seconds, or an HTTP date after which to retry.
+///
+/// # Arguments
+/// * `headers` - The headers of the response
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date: SystemTime = value.parse::<HttpDate>().ok()?.into();
+    // Dates in the past mean that it's fine to retry now.
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: RETRY_ATTEMPTS,
+            backoff: Duration::from_millis(RETRY_BACKOFF),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Get the delay before a retry.
+    ///
+    /// # Arguments
+    /// * `retry` - The index of the retry, starting from 0
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self.backoff.saturating_mul(2u32.saturating_pow(retry));
+        if self.jitter {
+            // Pick uniformly up to the exponential delay, i.e. "full jitter".
+            delay.mul_f64(thread_rng().gen())
+        } else {
+            delay
+        }
+    }
+
+    /// Send a request, retrying it on transient failures.
+    ///
+    /// Retries wait for at least the delay asked for by the "Retry-After" header of the response,
+    /// if any. If that's too long, or if every attempt fails, then the result of the last attempt
+    /// is returned.
+    ///
+    /// # Arguments
+    /// * `send` - Function to send a fresh request for each attempt
+    pub async fn send<F, Fut, S>(&self, mut send: F) -> Result<ClientResponse<S>, SendRequestError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<ClientResponse<S>, SendRequestError>>,
+    {
+        let mut retry = 0;
+        loop {
+            let result = send().await;
+            let Some(reason) = transient_failure(&result) else {
+                return result;
+            };
+            if retry + 1 >= self.max_attempts {
+                return result;
+            }
+
+            let delay = match result
+                .as_ref()
+                .ok()
+                .and_then(|resp| retry_after(resp.headers()))
+            {
+                Some(wait) if wait > Duration::from_secs(RETRY_AFTER_MAX) => {
+                    warn!("Request failed transiently ({reason}); asked to retry after {wait:?}");
+                    return result;
+                }
+                Some(wait) => wait.max(self.delay(retry)),
+                None => self.delay(retry),
+            };
+            warn!("Request failed transiently ({reason}); retrying in {delay:?}");
+            sleep(delay).await;
+            retry += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use actix_web::http::Method;
+    use awc::Client;
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    /// Test whether the delays double with each retry, and stay within the limit with jitter.
+    fn test_delay() {
+        let mut policy = RetryPolicy {
+            max_attempts: 4,
+            backoff: Duration::from_millis(100),
+            jitter: false,
+        };
+        let delays: Vec<_> = (0..3).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400].map(Duration::from_millis),
+            "Delays don't back off exponentially"
+        );
+
+        policy.jitter = true;
+        for (retry, max_delay) in (0..3).zip(delays) {
+            assert!(
+                policy.delay(retry) <= max_delay,
+                "Delay with jitter is above the exponential backoff"
+            );
+        }
+    }
+
+    #[test_case(StatusCode::SERVICE_UNAVAILABLE, 2, 3, StatusCode::OK; "recovers")]
+    #[test_case(StatusCode::SERVICE_UNAVAILABLE, 3, 3, StatusCode::SERVICE_UNAVAILABLE; "gives up")]
+    #[test_case(StatusCode::NOT_FOUND, 1, 1, StatusCode::NOT_FOUND; "not transient")]
+    #[actix_web::test]
+    /// Test whether only transient failures are retried, up to the maximum attempts.
+    ///
+    /// # Arguments
+    /// * `status` - The status of the failed responses
+    /// * `failures` - The number of failed responses before a successful one
+    /// * `expected_attempts` - The expected number of requests sent
+    /// * `expected_status` - The expected status of the final response
+    async fn test_send(
+        status: StatusCode,
+        failures: u64,
+        expected_attempts: u64,
+        expected_status: StatusCode,
+    ) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(status.as_u16()))
+            .up_to_n_times(failures)
+            .expect(failures.min(expected_attempts))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+            .expect(expected_attempts - failures.min(expected_attempts))
+            .mount(&mock_server)
+            .await;
+
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            jitter: false,
+        };
+        let client = Client::default();
+        let url = format!("{}/page", mock_server.uri());
+        let resp = policy
+            .send(|| client.get(&url).send())
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(resp.status(), expected_status, "Wrong final response");
+        mock_server.verify().await;
+    }
+
+    #[test_case(Some("120"), Some(120); "seconds")]
+    #[test_case(Some("Wed, 21 Oct 2015 07:28:00 GMT"), Some(0); "past date")]
+    #[test_case(Some("soon"), None; "invalid")]
+    #[test_case(None, None; "missing")]
+    /// Test whether delays in "Retry-After" headers are parsed.
+    ///
+    /// # Arguments
+    /// * `value` - The value of the header, if any
+    /// * `expected` - The expected delay in seconds, if any
+    fn test_retry_after(value: Option<&str>, expected: Option<u64>) {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = value {
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+        }
+        assert_eq!(
+            retry_after(&headers),
+            expected.map(Duration::from_secs),
+            "Wrong delay"
+        );
+    }
+
+    #[test]
+    /// Test whether delays until future dates in "Retry-After" headers are parsed.
+    fn test_retry_after_date() {
+        let date = SystemTime::now() + Duration::from_secs(120);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            HttpDate::from(date).to_string().parse().unwrap(),
+        );
+
+        let delay = retry_after(&headers).expect("Date wasn't parsed");
+        assert!(
+            delay > Duration::from_secs(100) && delay <= Duration::from_secs(120),
+            "Wrong delay: {delay:?}"
+        );
+    }
+
+    #[test_case("1", 2, StatusCode::OK; "waits")]
+    #[test_case("3600", 1, StatusCode::TOO_MANY_REQUESTS; "too long")]
+    #[actix_web::test]
+    /// Test whether retries wait for the delay asked for by the "Retry-After" header.
+    ///
+    /// # Arguments
+    /// * `delay` - The value of the "Retry-After" header
+    /// * `expected_attempts` - The expected number of requests sent
+    /// * `expected_status` - The expected status of the final response
+    async fn test_send_retry_after(
+        delay: &str,
+        expected_attempts: u64,
+        expected_status: StatusCode,
+    ) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(StatusCode::TOO_MANY_REQUESTS.as_u16())
+                    .insert_header(RETRY_AFTER.as_str(), delay),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+            .expect(expected_attempts - 1)
+            .mount(&mock_server)
+            .await;
+
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(1),
+            jitter: false,
+        };
+        let client = Client::default();
+        let url = format!("{}/page", mock_server.uri());
+        let start = Instant::now();
+        let resp = policy
+            .send(|| client.get(&url).send())
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(resp.status(), expected_status, "Wrong final response");
+        if expected_status.is_success() {
+            assert!(
+                start.elapsed() >= Duration::from_secs(1),
+                "Retried before the delay"
+            );
+        }
+        mock_server.verify().await;
+    }
+}
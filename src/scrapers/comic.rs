@@ -0,0 +1,3498 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Scraper to get info for requested Dilbert comics
+
+use actix_web::rt::{spawn, task::spawn_blocking, time::sleep};
+use awc::{
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        StatusCode,
+    },
+    Client, ClientResponse,
+};
+use chrono::NaiveDate;
+#[cfg(test)]
+use mockall::automock;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+#[cfg(debug_assertions)]
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, field, info, info_span, instrument, warn, Instrument, Span};
+
+#[cfg(debug_assertions)]
+use crate::constants::SCRAPE_RECORD_DIR_VAR;
+use crate::constants::{
+    DISP_DATE_FMT, FRESH_CACHE_PREFIX, IMG_CACHE_PREFIX, IMG_CACHE_TTL, MAX_STRIP_LEN,
+    MIN_SEARCH_WORD_LEN, MISSING_CACHE_KEY, OLD_CACHE_TTL, OVERLAY_CACHE_PREFIX, PAGE_CACHE_PREFIX,
+    PREVIEW_CACHE_PREFIX, RECENT_CACHE_TTL, RECENT_COMIC_DAYS, SCRAPE_LOCK_POLL,
+    SCRAPE_LOCK_PREFIX, SCRAPE_LOCK_TTL, SEARCH_CACHE_PREFIX, SRC_DATE_FMT, STALE_CACHE_TTL,
+    TAG_CACHE_PREFIX, THUMBNAIL_CACHE_PREFIX, VARIANT_CACHE_PREFIX, WEBP_CACHE_PREFIX,
+    WEBP_MIME_TYPE,
+};
+use crate::db::CacheBackend;
+use crate::errors::{AppError, AppResult};
+use crate::metrics::{CacheLookup, ScrapeFailure, METRICS};
+use crate::overlay::{render_overlay, Attribution};
+use crate::preview::render_preview;
+use crate::reporting::report_error;
+use crate::retry::RetryPolicy;
+use crate::singleflight::SingleFlight;
+use crate::throttle::Throttle;
+use crate::thumbnail::render_thumbnail;
+use crate::transcode::transcode_to_webp;
+
+use super::{fetch_comic_page, fetch_memento_page, get_known_latest_date, ComicSource};
+
+pub use scraper::*;
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Debug, Clone, Default)]
+pub struct ComicData {
+    /// The title of the comic
+    pub title: String,
+
+    /// The URL to the comic image
+    pub img_url: String,
+
+    /// The width of the image
+    pub img_width: i32,
+
+    /// The height of the image
+    pub img_height: i32,
+
+    /// Other resolutions of the image, if the source offers them, from the smallest
+    // Data cached before image variants were scraped doesn't have this field.
+    #[serde(default)]
+    pub img_variants: Vec<ImageVariant>,
+
+    /// The permalink to the comic
+    pub permalink: String,
+
+    /// The transcript of the comic's text, if available
+    // Data cached before transcripts were scraped doesn't have this field.
+    #[serde(default)]
+    pub transcript: Option<String>,
+
+    /// The tags of the comic, without the leading "#"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ComicData {
+    /// Check whether the data is complete enough to be cached.
+    ///
+    /// Upstream glitches can result in scrapes without an image, and caching these would
+    /// serve the broken data until the entry is evicted.
+    pub fn is_complete(&self) -> bool {
+        !self.img_url.is_empty()
+    }
+
+    /// Get the words in the title and the transcript, for search.
+    fn search_words(&self) -> Vec<String> {
+        let transcript = self.transcript.as_deref().unwrap_or_default();
+        get_search_words(&format!("{} {transcript}", self.title))
+    }
+
+    /// Check whether the comic has a tag.
+    ///
+    /// # Arguments
+    /// * `tag` - The normalized tag
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|other| normalize_tag(other) == tag)
+    }
+}
+
+/// Another resolution of a comic's image, as offered by the source
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash, Debug, Clone)]
+pub struct ImageVariant {
+    /// The URL to the image
+    pub url: String,
+
+    /// The width of the image
+    pub width: i32,
+}
+
+/// The image of a comic, as served by the source
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct ComicImage {
+    /// The MIME type of the image
+    pub content_type: String,
+
+    /// The raw bytes of the image
+    // This stores them as binary data in MessagePack, instead of as an array of integers.
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+/// The metadata of a comic's image, without the image itself
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ImageInfo {
+    /// The MIME type of the image
+    pub content_type: String,
+
+    /// The size of the image in bytes, if known
+    pub content_length: Option<u64>,
+}
+
+impl From<&ComicImage> for ImageInfo {
+    fn from(image: &ComicImage) -> Self {
+        Self {
+            content_type: image.content_type.clone(),
+            content_length: Some(image.bytes.len() as u64),
+        }
+    }
+}
+
+/// Get the time (in seconds) for which to cache the data of a comic.
+///
+/// Recent comics are cached for a shorter time than older ones, since their archived versions are
+/// more likely to change.
+///
+/// # Arguments
+/// * `latest` - The date of the latest comic
+/// * `date` - The date of the comic
+fn get_cache_ttl(latest: &NaiveDate, date: &NaiveDate) -> u64 {
+    if (*latest - *date).num_days() < RECENT_COMIC_DAYS {
+        RECENT_CACHE_TTL
+    } else {
+        OLD_CACHE_TTL
+    }
+}
+
+/// Get the absolute URL to a comic's image.
+///
+/// Some scraped image URLs are protocol-relative (i.e. they start with "//").
+fn get_absolute_img_url(img_url: &str) -> String {
+    if img_url.starts_with("//") {
+        format!("https:{img_url}")
+    } else {
+        img_url.into()
+    }
+}
+
+/// Normalize a tag, so that different spellings of it are treated the same.
+///
+/// # Arguments
+/// * `tag` - The tag, with or without the leading "#"
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().trim_start_matches('#').trim().to_lowercase()
+}
+
+/// Get the distinct words in a text, normalized for search.
+///
+/// Words that are too short to be useful for search are skipped.
+///
+/// # Arguments
+/// * `text` - The text, such as the title of a comic or a search query
+pub fn get_search_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for word in text
+        .split(|chr: char| !chr.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_SEARCH_WORD_LEN)
+    {
+        let word = word.to_lowercase();
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Get the MIME type of a comic's image from the source's response.
+///
+/// This fails if the response isn't an image, so that error pages aren't served as images.
+fn get_img_content_type<S>(resp: &ClientResponse<S>) -> AppResult<String> {
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with("image/") {
+        Ok(content_type.into())
+    } else {
+        Err(AppError::Scrape(format!(
+            "Comic image has an unexpected content type: {content_type:?}"
+        )))
+    }
+}
+
+/// Record a scraped page to disk, for use as a test fixture.
+///
+/// This is best-effort, so errors are only logged.
+///
+/// # Arguments
+/// * `dir` - The directory where to record the page
+/// * `date` - The date of the comic in the page
+/// * `content` - The raw HTML of the page
+#[cfg(debug_assertions)]
+async fn record_scrape(dir: &Path, date: &NaiveDate, content: &str) {
+    let path = dir.join(format!("{}.html", date.format(SRC_DATE_FMT)));
+    let result = match tokio::fs::create_dir_all(dir).await {
+        Ok(()) => tokio::fs::write(&path, content).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => info!("Recorded scraped page to: {}", path.display()),
+        Err(err) => warn!("Couldn't record scraped page to {}: {err}", path.display()),
+    }
+}
+
+/// Add a comic to an index of comics, such as those with a tag or a word.
+///
+/// # Arguments
+/// * `db` - The cache in which the index is stored
+/// * `key` - The cache key of the index
+/// * `date` - The date of the comic
+async fn add_to_index<T: CacheBackend, K: Serialize + Sync>(
+    db: &T,
+    key: &K,
+    date: &NaiveDate,
+) -> AppResult<()> {
+    // The index should outlive the comics in it, which are re-cached when stale.
+    db.add_to_set(key, date, OLD_CACHE_TTL).await
+}
+
+/// Remove a comic from an index of comics, such as that of missing comics.
+///
+/// # Arguments
+/// * `db` - The cache in which the index is stored
+/// * `key` - The cache key of the index
+/// * `date` - The date of the comic
+async fn remove_from_index<T: CacheBackend, K: Serialize + Sync>(
+    db: &T,
+    key: &K,
+    date: &NaiveDate,
+) -> AppResult<()> {
+    db.remove_from_set(key, date).await
+}
+
+/// Get the dates of the comics in an index of comics, in order.
+///
+/// # Arguments
+/// * `db` - The cache in which the index is stored
+/// * `key` - The cache key of the index
+async fn get_index<T: CacheBackend, K: Serialize + Sync>(
+    db: &T,
+    key: &K,
+) -> AppResult<Vec<NaiveDate>> {
+    let mut dates: Vec<NaiveDate> = db.get_set(key).await?;
+    dates.sort_unstable();
+    Ok(dates)
+}
+
+/// Cache the data of a comic, and add the comic to the indices of its tags and words.
+///
+/// # Arguments
+/// * `db` - The cache for comics
+/// * `source` - The source of the comic
+/// * `date` - The date of the comic
+/// * `comic_data` - The data of the comic
+pub async fn cache_comic_data<T: CacheBackend>(
+    db: &T,
+    source: &dyn ComicSource,
+    date: &NaiveDate,
+    comic_data: &ComicData,
+) -> AppResult<()> {
+    let latest = get_known_latest_date(db, source).await?;
+    let ttl = get_cache_ttl(&latest, date);
+    db.set(date, comic_data, ttl + STALE_CACHE_TTL).await?;
+    db.set(&(FRESH_CACHE_PREFIX, date), &true, ttl).await?;
+    for tag in &comic_data.tags {
+        add_to_index(db, &(TAG_CACHE_PREFIX, normalize_tag(tag)), date).await?;
+    }
+    for word in comic_data.search_words() {
+        add_to_index(db, &(SEARCH_CACHE_PREFIX, word), date).await?;
+    }
+    Ok(())
+}
+
+mod inner {
+    use super::*;
+
+    /// Struct that does the actual scraping/caching.
+    ///
+    /// This is separated out for the sole purpose of mock tests.
+    pub(super) struct InnerComicScraper<T: CacheBackend + 'static> {
+        pub(super) db: Option<T>,
+        pub(super) source: Arc<dyn ComicSource>,
+        pub(super) http_client: Client,
+        pub(super) retry: RetryPolicy,
+        pub(super) throttle: Arc<Throttle>,
+    }
+
+    #[cfg_attr(test, automock)]
+    impl<T: CacheBackend + 'static> InnerComicScraper<T> {
+        /// Initialize a comics scraper.
+        #[cfg_attr(test, allow(dead_code))]
+        pub fn new(
+            db: Option<T>,
+            source: Arc<dyn ComicSource>,
+            http_client: Client,
+            retry: RetryPolicy,
+            throttle: Arc<Throttle>,
+        ) -> Self {
+            Self {
+                db,
+                source,
+                http_client,
+                retry,
+                throttle,
+            }
+        }
+
+        /// Get the cached comic data from the database, along with whether it's still fresh.
+        ///
+        /// If the comic date entry isn't in the cache, None is returned. The result of the lookup is
+        /// recorded in the metrics and in the `cache` field of the span.
+        #[instrument(skip(self), fields(cache = field::Empty))]
+        pub(super) async fn get_cached_data(
+            &self,
+            date: &NaiveDate,
+        ) -> AppResult<Option<(ComicData, bool)>> {
+            let cached: AppResult<_> = async {
+                let Some(db) = &self.db else {
+                    return Ok(None);
+                };
+
+                // None would mean that the comic for this date wasn't cached, or the date is
+                // invalid (i.e. it would redirect to the homepage).
+                let comic_data: Option<ComicData> = db.get(date).await?;
+                debug!("Retrieved data from DB: {comic_data:?}");
+                let Some(comic_data) = comic_data else {
+                    return Ok(None);
+                };
+                let fresh: Option<bool> = db.get(&(FRESH_CACHE_PREFIX, date)).await?;
+                Ok(Some((comic_data, fresh.is_some())))
+            }
+            .await;
+
+            if let Ok(cached) = &cached {
+                let lookup = match cached {
+                    Some((_, true)) => CacheLookup::Hit,
+                    Some((_, false)) => CacheLookup::Stale,
+                    None => CacheLookup::Miss,
+                };
+                METRICS.record_lookup(lookup);
+                Span::current().record("cache", lookup.as_str());
+            }
+            cached
+        }
+
+        /// Get the cached data of many comics from the database at once, whether fresh or not.
+        ///
+        /// Comics that aren't in the cache are None.
+        ///
+        /// # Arguments
+        /// * `dates` - The dates of the comics
+        pub(super) async fn get_cached_data_many(
+            &self,
+            dates: &[NaiveDate],
+        ) -> AppResult<Vec<Option<ComicData>>> {
+            let Some(db) = &self.db else {
+                return Ok(vec![None; dates.len()]);
+            };
+            db.get_many(dates).await
+        }
+
+        /// Cache the comic data into the database.
+        pub(super) async fn cache_data(
+            &self,
+            comic_data: &ComicData,
+            date: &NaiveDate,
+        ) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+
+            debug!("Attempting to update cache with: {comic_data:?}");
+            cache_comic_data(db, self.source.as_ref(), date, comic_data).await?;
+            info!("Successfully cached data for {date} in cache");
+            Ok(())
+        }
+
+        /// Record that a comic is missing from the source.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the missing comic
+        pub(super) async fn cache_missing(&self, date: &NaiveDate) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+            add_to_index(db, &MISSING_CACHE_KEY, date).await?;
+            info!("Recorded {date} as missing in cache");
+            Ok(())
+        }
+
+        /// Try to take the lock for scraping a comic, which is shared with other instances of the
+        /// app through the cache.
+        ///
+        /// The lock expires on its own, in case its holder crashes. Without a cache, there's no
+        /// other instance to share it with, so it's always taken.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `token` - The token unique to this acquisition of the lock, needed to release it
+        ///
+        /// # Returns
+        /// Whether the lock was taken
+        pub(super) async fn lock_scrape(&self, date: &NaiveDate, token: &str) -> AppResult<bool> {
+            let Some(db) = &self.db else {
+                return Ok(true);
+            };
+            db.set_nx(&(SCRAPE_LOCK_PREFIX, date), &token, SCRAPE_LOCK_TTL)
+                .await
+        }
+
+        /// Check whether the lock for scraping a comic is held.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        pub(super) async fn is_scrape_locked(&self, date: &NaiveDate) -> AppResult<bool> {
+            let Some(db) = &self.db else {
+                return Ok(false);
+            };
+            let token: Option<String> = db.get(&(SCRAPE_LOCK_PREFIX, date)).await?;
+            Ok(token.is_some())
+        }
+
+        /// Release the lock for scraping a comic, if it's still held with the given token.
+        ///
+        /// If the lock expired and was taken by another instance, then it's left as it is.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `token` - The token with which the lock was taken
+        pub(super) async fn unlock_scrape(&self, date: &NaiveDate, token: &str) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+            if !db.delete_if_eq(&(SCRAPE_LOCK_PREFIX, date), &token).await? {
+                warn!("Scrape lock for {date} expired before it was released");
+            }
+            Ok(())
+        }
+
+        /// Get the dates of the comics known to be missing from the source, in order.
+        pub(super) async fn get_missing_dates(&self) -> AppResult<Vec<NaiveDate>> {
+            let Some(db) = &self.db else {
+                return Ok(Vec::new());
+            };
+            get_index(db, &MISSING_CACHE_KEY).await
+        }
+
+        /// Remove a comic from the cache, along with its images and any record of it missing.
+        ///
+        /// Its rendered pages are left as they are, since they're versioned by the comic's data.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        pub(super) async fn purge(&self, date: &NaiveDate) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+            db.delete(date).await?;
+            let widths: Option<Vec<i32>> = db.get(&(VARIANT_CACHE_PREFIX, date)).await?;
+            for width in widths.unwrap_or_default() {
+                db.delete(&(VARIANT_CACHE_PREFIX, date, width)).await?;
+            }
+            for prefix in [
+                FRESH_CACHE_PREFIX,
+                IMG_CACHE_PREFIX,
+                WEBP_CACHE_PREFIX,
+                PREVIEW_CACHE_PREFIX,
+                THUMBNAIL_CACHE_PREFIX,
+                OVERLAY_CACHE_PREFIX,
+                VARIANT_CACHE_PREFIX,
+            ] {
+                db.delete(&(prefix, date)).await?;
+            }
+            remove_from_index(db, &MISSING_CACHE_KEY, date).await?;
+            info!("Purged {date} from cache");
+            Ok(())
+        }
+
+        /// Remove everything from the cache.
+        pub(super) async fn purge_all(&self) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+            db.clear().await?;
+            info!("Purged everything from cache");
+            Ok(())
+        }
+
+        /// Get the dates of the cached comics with a term, such as a tag or a word, in order.
+        ///
+        /// # Arguments
+        /// * `prefix` - The prefix for the kind of term
+        /// * `term` - The normalized term
+        pub(super) async fn get_indexed_dates(
+            &self,
+            prefix: &'static str,
+            term: &str,
+        ) -> AppResult<Vec<NaiveDate>> {
+            let Some(db) = &self.db else {
+                return Ok(Vec::new());
+            };
+            get_index(db, &(prefix, term)).await
+        }
+
+        /// Get the cached image of a comic from the database.
+        ///
+        /// If the image isn't in the cache, None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `prefix` - The prefix for the kind of image, such as the original or a transcoded one
+        pub(super) async fn get_cached_image(
+            &self,
+            date: &NaiveDate,
+            prefix: &'static str,
+        ) -> AppResult<Option<ComicImage>> {
+            let Some(db) = &self.db else {
+                return Ok(None);
+            };
+
+            let image: Option<ComicImage> = db.get(&(prefix, date)).await?;
+            debug!(
+                "Retrieved image from DB of size: {:?}B",
+                image.as_ref().map(|image| image.bytes.len())
+            );
+            Ok(image)
+        }
+
+        /// Cache the image of a comic into the database.
+        ///
+        /// # Arguments
+        /// * `image` - The image
+        /// * `date` - The date of the comic
+        /// * `prefix` - The prefix for the kind of image, such as the original or a transcoded one
+        pub(super) async fn cache_image(
+            &self,
+            image: &ComicImage,
+            date: &NaiveDate,
+            prefix: &'static str,
+        ) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+
+            db.set(&(prefix, date), image, IMG_CACHE_TTL).await?;
+            info!("Successfully cached image for {date} in cache");
+            Ok(())
+        }
+
+        /// Get a cached variant of the image of a comic from the database.
+        ///
+        /// If the variant isn't in the cache, None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `width` - The width of the variant
+        pub(super) async fn get_cached_variant(
+            &self,
+            date: &NaiveDate,
+            width: i32,
+        ) -> AppResult<Option<ComicImage>> {
+            let Some(db) = &self.db else {
+                return Ok(None);
+            };
+            db.get(&(VARIANT_CACHE_PREFIX, date, width)).await
+        }
+
+        /// Cache a variant of the image of a comic into the database.
+        ///
+        /// The width is also recorded, so that the variant can be purged along with the comic.
+        ///
+        /// # Arguments
+        /// * `image` - The variant of the image
+        /// * `date` - The date of the comic
+        /// * `width` - The width of the variant
+        pub(super) async fn cache_variant(
+            &self,
+            image: &ComicImage,
+            date: &NaiveDate,
+            width: i32,
+        ) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+
+            let key = (VARIANT_CACHE_PREFIX, date);
+            let mut widths: Vec<i32> = db.get(&key).await?.unwrap_or_default();
+            if !widths.contains(&width) {
+                widths.push(width);
+                db.set(&key, &widths, IMG_CACHE_TTL).await?;
+            }
+            db.set(&(VARIANT_CACHE_PREFIX, date, width), image, IMG_CACHE_TTL)
+                .await?;
+            info!("Successfully cached image variant of width {width} for {date} in cache");
+            Ok(())
+        }
+
+        /// Get the cached rendered page of a comic from the database.
+        ///
+        /// If the page isn't in the cache, None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `version` - The version of the page, which identifies all inputs to the page
+        pub(super) async fn get_cached_page(
+            &self,
+            date: &NaiveDate,
+            version: &str,
+        ) -> AppResult<Option<String>> {
+            let Some(db) = &self.db else {
+                return Ok(None);
+            };
+            db.get(&(PAGE_CACHE_PREFIX, date, version)).await
+        }
+
+        /// Cache the rendered page of a comic into the database.
+        ///
+        /// # Arguments
+        /// * `page` - The rendered page
+        /// * `date` - The date of the comic
+        /// * `version` - The version of the page, which identifies all inputs to the page
+        pub(super) async fn cache_page(
+            &self,
+            page: &str,
+            date: &NaiveDate,
+            version: &str,
+        ) -> AppResult<()> {
+            let Some(db) = &self.db else {
+                return Ok(());
+            };
+
+            let latest = get_known_latest_date(db, self.source.as_ref()).await?;
+            db.set(
+                &(PAGE_CACHE_PREFIX, date, version),
+                &page,
+                get_cache_ttl(&latest, date),
+            )
+            .await?;
+            info!("Successfully cached page for {date} in cache");
+            Ok(())
+        }
+
+        /// Fetch a comic's image from the source.
+        ///
+        /// The request is throttled, retried on transient failures, and skipped during outages of
+        /// the source, like the other requests to it.
+        #[instrument(skip(self))]
+        pub(super) async fn fetch_image(&self, img_url: &str) -> AppResult<ComicImage> {
+            let img_url = get_absolute_img_url(img_url);
+            let mut resp = self
+                .throttle
+                .send(&self.retry, || self.http_client.get(&img_url).send())
+                .await?;
+            let status = resp.status();
+            if status != StatusCode::OK {
+                return Err(AppError::Scrape(format!(
+                    "Couldn't fetch comic image: {status}"
+                )));
+            }
+
+            let content_type = get_img_content_type(&resp)?;
+            let bytes = resp.body().await?.to_vec();
+            debug!(
+                "Got image of type {content_type} and size: {}B",
+                bytes.len()
+            );
+            Ok(ComicImage {
+                content_type,
+                bytes,
+            })
+        }
+
+        /// Fetch only the metadata of a comic's image from the source, using a HEAD request.
+        ///
+        /// If the source doesn't support HEAD requests, then None is returned. The request is
+        /// throttled like the other requests to the source.
+        #[instrument(skip(self))]
+        pub(super) async fn fetch_image_info(&self, img_url: &str) -> AppResult<Option<ImageInfo>> {
+            let img_url = get_absolute_img_url(img_url);
+            let resp = self
+                .throttle
+                .send(&self.retry, || self.http_client.head(&img_url).send())
+                .await?;
+            match resp.status() {
+                StatusCode::OK => (),
+                StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => return Ok(None),
+                status => {
+                    return Err(AppError::Scrape(format!(
+                        "Couldn't fetch comic image info: {status}"
+                    )))
+                }
+            }
+
+            let content_type = get_img_content_type(&resp)?;
+            let content_length = resp
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            Ok(Some(ImageInfo {
+                content_type,
+                content_length,
+            }))
+        }
+
+        /// Scrape the comic data of the requested date from the source.
+        ///
+        /// The comic's page is located with the CDX API, and if that fails, then with the Memento
+        /// TimeTravel API. The duration of the scrape and the category of its failure (if any) are
+        /// recorded in the metrics and in the `duration_ms` and `failure` fields of the span.
+        #[instrument(skip(self), fields(duration_ms = field::Empty, failure = field::Empty))]
+        pub(super) async fn scrape_data(&self, date: &NaiveDate) -> AppResult<ComicData> {
+            let start = Instant::now();
+            let result = async {
+                let result = fetch_comic_page(
+                    &self.http_client,
+                    self.source.as_ref(),
+                    &self.retry,
+                    &self.throttle,
+                    date,
+                )
+                .await;
+                let (permalink, bytes) = match result {
+                    Err(AppError::Cdx(err)) => {
+                        warn!("Falling back to the Memento API, since the CDX API failed: {err}");
+                        fetch_memento_page(
+                            &self.http_client,
+                            self.source.as_ref(),
+                            &self.retry,
+                            &self.throttle,
+                            date,
+                        )
+                        .await?
+                    }
+                    result => result?,
+                };
+                debug!("Got response body of length: {}B", bytes.len());
+                let content = match std::str::from_utf8(&bytes) {
+                    Ok(text) => text,
+                    Err(_) => return Err(AppError::Scrape("Response is not UTF-8".into())),
+                };
+
+                let comic_data = self.source.parse_comic(content, date, permalink)?;
+                debug!("Scraped comic data: {comic_data:?}");
+
+                #[cfg(debug_assertions)]
+                if let Some(dir) = std::env::var_os(SCRAPE_RECORD_DIR_VAR) {
+                    record_scrape(Path::new(&dir), date, content).await;
+                }
+
+                Ok(comic_data)
+            }
+            .await;
+
+            let duration = start.elapsed();
+            let failure = result.as_ref().err().map(ScrapeFailure::from);
+            METRICS.record_scrape(duration, failure);
+            let span = Span::current();
+            span.record("duration_ms", duration.as_millis() as u64);
+            if let Some(failure) = failure {
+                span.record("failure", failure.as_str());
+            }
+            result
+        }
+    }
+}
+
+mod scraper {
+    #[mockall_double::double]
+    use super::inner::InnerComicScraper;
+    use super::*;
+
+    /// Result of retrieving the data for a comic
+    type ComicResult = AppResult<Option<ComicData>>;
+
+    /// Result of retrieving the data for a comic, shared with concurrent requests for it
+    type SharedResult = Result<Option<ComicData>, Rc<AppError>>;
+
+    /// Struct for a comic scraper
+    ///
+    /// This scraper takes a date as input and returns the info about the comic.
+    pub struct ComicScraper<T: CacheBackend + 'static> {
+        /// The scraper that does the actual scraping/caching
+        ///
+        /// This is shared with the background refreshes of stale comic data.
+        pub(super) inner: Rc<InnerComicScraper<T>>,
+        /// Coalescer for concurrent retrievals of the same comic
+        in_flight: SingleFlight<NaiveDate, SharedResult>,
+        /// Dates of the comics whose stale data is being refreshed in the background
+        pub(super) refreshing: Rc<RefCell<HashSet<NaiveDate>>>,
+        /// Whether to lock each scrape in the cache, for instances of the app sharing it
+        pub(super) scrape_lock: bool,
+    }
+
+    impl<T: CacheBackend + 'static> ComicScraper<T> {
+        /// Initialize a comics scraper given the inner scraper.
+        pub(super) fn from_inner(inner: InnerComicScraper<T>) -> Self {
+            Self {
+                inner: Rc::new(inner),
+                in_flight: SingleFlight::default(),
+                refreshing: Rc::default(),
+                scrape_lock: false,
+            }
+        }
+
+        /// Refresh the stale cached data of a comic in the background.
+        ///
+        /// This runs on the current worker, and is skipped if the comic is already being
+        /// refreshed. If the refresh fails, then the stale data is left as it is.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        fn refresh_in_background(&self, date: NaiveDate) {
+            if !self.refreshing.borrow_mut().insert(date) {
+                debug!("Stale data for {date} is already being refreshed");
+                return;
+            }
+            let inner = self.inner.clone();
+            let refreshing = self.refreshing.clone();
+            let refresh = async move {
+                match inner.scrape_data(&date).await {
+                    Ok(comic_data) if !comic_data.is_complete() => {
+                        warn!("Refreshed data is incomplete, so not caching it: {comic_data:?}");
+                    }
+                    Ok(comic_data) => match inner.cache_data(&comic_data, &date).await {
+                        Ok(()) => info!("Refreshed stale data in cache"),
+                        Err(err) => error!("Error caching refreshed data: {err}"),
+                    },
+                    Err(err) => {
+                        // The refresh isn't part of any response, so report the error here.
+                        report_error(&err, Some(&date));
+                        error!("Refreshing stale data failed with error: {err}");
+                    }
+                }
+                refreshing.borrow_mut().remove(&date);
+            };
+            spawn(refresh.instrument(info_span!("refresh", %date)));
+        }
+
+        /// Retrieve the data for the requested comic, without coalescing.
+        async fn retrieve_comic_data(&self, date: &NaiveDate) -> ComicResult {
+            match self.inner.get_cached_data(date).await {
+                Ok(Some((comic_data, true))) => {
+                    info!("Successful retrieval from cache");
+                    return Ok(Some(comic_data));
+                }
+                Ok(Some((comic_data, false))) => {
+                    // Serve the stale data now, instead of making the request wait on the source.
+                    info!("Retrieved stale data from cache; refreshing it in the background");
+                    self.refresh_in_background(*date);
+                    return Ok(Some(comic_data));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    // Better to re-scrape now than crash unexpectedly, so simply log the error.
+                    error!("Error retrieving from cache: {err}");
+                }
+            };
+
+            info!("Couldn't fetch data from cache; trying to scrape");
+            let result = if self.scrape_lock {
+                let token = uuid::Uuid::new_v4().to_string();
+                match self.inner.lock_scrape(date, &token).await {
+                    Ok(true) => {
+                        let result = self.scrape_and_cache(date).await;
+                        if let Err(err) = self.inner.unlock_scrape(date, &token).await {
+                            // The lock expires on its own, so simply log the error.
+                            error!("Error unlocking scrape: {err}");
+                        }
+                        result
+                    }
+                    Ok(false) => {
+                        info!("Another instance is scraping this comic; waiting for it");
+                        if let Some(result) = self.wait_for_scrape(date).await {
+                            return result;
+                        }
+                        info!("Other instance didn't cache the comic; trying to scrape");
+                        self.scrape_and_cache(date).await
+                    }
+                    Err(err) => {
+                        // Better to scrape without the lock than crash unexpectedly.
+                        error!("Error locking scrape: {err}");
+                        self.scrape_and_cache(date).await
+                    }
+                }
+            } else {
+                self.scrape_and_cache(date).await
+            };
+
+            // This is done after releasing the lock, so that other instances don't wait on it.
+            if let Ok(Some(comic_data)) = &result {
+                if comic_data.is_complete() {
+                    self.prefetch_in_background(comic_data.img_url.clone(), *date);
+                }
+            }
+            result
+        }
+
+        /// Wait for another instance of the app to finish scraping a comic, and get its result.
+        ///
+        /// If the comic isn't cached (or recorded as missing) once the lock is released or
+        /// expires, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        async fn wait_for_scrape(&self, date: &NaiveDate) -> Option<ComicResult> {
+            let deadline = Instant::now() + Duration::from_secs(SCRAPE_LOCK_TTL);
+            loop {
+                sleep(Duration::from_millis(SCRAPE_LOCK_POLL)).await;
+                match self.inner.is_scrape_locked(date).await {
+                    Ok(true) if Instant::now() < deadline => continue,
+                    Ok(_) => break,
+                    Err(err) => {
+                        error!("Error checking scrape lock: {err}");
+                        return None;
+                    }
+                }
+            }
+
+            match self.inner.get_cached_data(date).await {
+                Ok(Some((comic_data, _))) => return Some(Ok(Some(comic_data))),
+                Ok(None) => {}
+                Err(err) => {
+                    error!("Error retrieving from cache: {err}");
+                    return None;
+                }
+            }
+            match self.inner.get_missing_dates().await {
+                Ok(dates) if dates.contains(date) => Some(Ok(None)),
+                Ok(_) => None,
+                Err(err) => {
+                    error!("Error retrieving missing comics from cache: {err}");
+                    None
+                }
+            }
+        }
+
+        /// Scrape the data for the requested comic, and cache it.
+        ///
+        /// If the comic doesn't exist, then it's recorded as missing, and None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        async fn scrape_and_cache(&self, date: &NaiveDate) -> ComicResult {
+            let err = match self.inner.scrape_data(date).await {
+                Ok(comic_data) => {
+                    info!("Scraped data from source");
+                    if !comic_data.is_complete() {
+                        // Serve it for now, but don't let it poison the cache.
+                        warn!("Scraped data is incomplete, so not caching it: {comic_data:?}");
+                    } else if let Err(err) = self.inner.cache_data(&comic_data, date).await {
+                        error!("Error caching data: {err}");
+                    } else {
+                        info!("Cached scraped data");
+                    }
+                    return Ok(Some(comic_data));
+                }
+                Err(err) => err,
+            };
+
+            error!("Scraping failed with error: {err}");
+            match err {
+                AppError::NotFound(_) => {
+                    // This lets navigation skip over the comic.
+                    if let Err(err) = self.inner.cache_missing(date).await {
+                        error!("Error recording missing comic: {err}");
+                    }
+                    Ok(None)
+                }
+                _ => Err(err),
+            }
+        }
+    }
+
+    impl<T: CacheBackend + 'static> ComicScraper<T> {
+        /// Get the cached image of a comic, treating cache errors as cache misses.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `prefix` - The prefix for the kind of image, such as the original or a transcoded one
+        async fn get_cached_image(
+            &self,
+            date: &NaiveDate,
+            prefix: &'static str,
+        ) -> Option<ComicImage> {
+            match self.inner.get_cached_image(date, prefix).await {
+                Ok(Some(image)) => {
+                    info!("Successful retrieval of image from cache");
+                    Some(image)
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    // Better to re-fetch now than crash unexpectedly, so simply log the error.
+                    error!("Error retrieving image from cache: {err}");
+                    None
+                }
+            }
+        }
+
+        /// Get the URL to the image of the requested comic, if the comic exists.
+        async fn get_img_url(&self, date: &NaiveDate) -> AppResult<Option<String>> {
+            Ok(self
+                .get_comic_data(date)
+                .await?
+                .filter(ComicData::is_complete)
+                .map(|comic_data| comic_data.img_url))
+        }
+
+        /// Fetch the image of a comic from the source, and cache it.
+        async fn fetch_and_cache_image(
+            &self,
+            img_url: &str,
+            date: &NaiveDate,
+        ) -> AppResult<ComicImage> {
+            let image = self.inner.fetch_image(img_url).await?;
+            info!("Fetched image from source");
+            if let Err(err) = self.inner.cache_image(&image, date, IMG_CACHE_PREFIX).await {
+                error!("Error caching image: {err}");
+            }
+            Ok(image)
+        }
+
+        /// Prefetch the image of a scraped comic into the cache in the background, if it isn't
+        /// already cached.
+        ///
+        /// This way, requests for the image don't need the image's host to be up, and the request
+        /// for the comic doesn't wait on the image.
+        ///
+        /// # Arguments
+        /// * `img_url` - The URL to the image
+        /// * `date` - The date of the comic
+        fn prefetch_in_background(&self, img_url: String, date: NaiveDate) {
+            let inner = self.inner.clone();
+            let prefetch = async move {
+                match inner.get_cached_image(&date, IMG_CACHE_PREFIX).await {
+                    Ok(Some(_)) => return,
+                    Ok(None) => {}
+                    // Better to fetch the image again than crash unexpectedly, so simply log it.
+                    Err(err) => error!("Error retrieving image from cache: {err}"),
+                }
+                let image = match inner.fetch_image(&img_url).await {
+                    Ok(image) => image,
+                    Err(err) => {
+                        // The image can be fetched again when it's requested, so simply log it.
+                        error!("Error prefetching image: {err}");
+                        return;
+                    }
+                };
+                match inner.cache_image(&image, &date, IMG_CACHE_PREFIX).await {
+                    Ok(()) => info!("Prefetched image into cache"),
+                    Err(err) => error!("Error caching prefetched image: {err}"),
+                }
+            };
+            spawn(prefetch.instrument(info_span!("prefetch", %date)));
+        }
+
+        /// Get the most recent cached comics out of the given ones that pass a filter.
+        ///
+        /// Since the indices of comics can be outdated, the filter should re-check the comics.
+        ///
+        /// # Arguments
+        /// * `dates` - The dates of the comics, in order
+        /// * `filter` - The filter for the comics
+        async fn get_cached_comics(
+            &self,
+            dates: Vec<NaiveDate>,
+            filter: impl Fn(&ComicData) -> bool,
+        ) -> AppResult<Vec<(NaiveDate, ComicData)>> {
+            let max_len = MAX_STRIP_LEN as usize;
+            let mut comics = Vec::new();
+            let dates: Vec<_> = dates.into_iter().rev().collect();
+            // Fetch only as many comics at once as could be returned, to avoid fetching too many.
+            for chunk in dates.chunks(max_len) {
+                // Stale entries are fine, since they'd only be missing recent changes.
+                let results = self.inner.get_cached_data_many(chunk).await?;
+                for (date, comic_data) in chunk.iter().zip(results) {
+                    if let Some(comic_data) = comic_data.filter(&filter) {
+                        comics.push((*date, comic_data));
+                        if comics.len() >= max_len {
+                            return Ok(comics);
+                        }
+                    }
+                }
+            }
+            Ok(comics)
+        }
+    }
+
+    #[cfg_attr(test, automock)]
+    impl<T: CacheBackend + 'static> ComicScraper<T> {
+        /// Initialize a comics scraper.
+        #[cfg_attr(test, allow(dead_code))]
+        pub fn new(
+            db: Option<T>,
+            source: Arc<dyn ComicSource>,
+            http_client: Client,
+            retry: RetryPolicy,
+            throttle: Arc<Throttle>,
+            scrape_lock: bool,
+        ) -> Self {
+            let mut scraper = Self::from_inner(InnerComicScraper::new(
+                db,
+                source,
+                http_client,
+                retry,
+                throttle,
+            ));
+            scraper.scrape_lock = scrape_lock;
+            scraper
+        }
+
+        /// Retrieve the data for the requested comic.
+        ///
+        /// Concurrent requests for the same comic share a single retrieval. If it fails, then the
+        /// other requests get the same error, marked as shared so that it's only reported once.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_data(&self, date: &NaiveDate) -> AppResult<Option<ComicData>> {
+            let leader = Cell::new(false);
+            let retrieve = || async {
+                leader.set(true);
+                self.retrieve_comic_data(date).await.map_err(Rc::new)
+            };
+            let result = self.in_flight.run(*date, retrieve, Clone::clone).await;
+            result.map_err(|err| {
+                if !leader.get() {
+                    return AppError::Shared(err, false);
+                }
+                // The error only needs marking if it was shared with another request.
+                Rc::try_unwrap(err).unwrap_or_else(|err| AppError::Shared(err, true))
+            })
+        }
+
+        /// Get the data for the requested comic from the cache only, without scraping it.
+        ///
+        /// If the comic isn't cached, then None is returned. Stale entries are returned as-is.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_cached_comic_data(
+            &self,
+            date: &NaiveDate,
+        ) -> AppResult<Option<ComicData>> {
+            let cached = self.inner.get_cached_data(date).await?;
+            Ok(cached.map(|(comic_data, _)| comic_data))
+        }
+
+        /// Get the data for many comics from the cache only, in one round trip to the cache.
+        ///
+        /// Comics that aren't cached are None. Stale entries are returned as-is.
+        ///
+        /// # Arguments
+        /// * `dates` - The dates of the comics
+        #[instrument(skip(self))]
+        pub async fn get_cached_comics_data(
+            &self,
+            dates: &[NaiveDate],
+        ) -> AppResult<Vec<Option<ComicData>>> {
+            self.inner.get_cached_data_many(dates).await
+        }
+
+        /// Get the image of the requested comic from the cache only, without fetching it.
+        ///
+        /// If the image isn't cached, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_cached_comic_image(
+            &self,
+            date: &NaiveDate,
+        ) -> AppResult<Option<ComicImage>> {
+            self.inner.get_cached_image(date, IMG_CACHE_PREFIX).await
+        }
+
+        /// Retrieve the image of the requested comic.
+        ///
+        /// If the comic doesn't exist, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_image(&self, date: &NaiveDate) -> AppResult<Option<ComicImage>> {
+            if let Some(image) = self.get_cached_image(date, IMG_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            let Some(img_url) = self.get_img_url(date).await? else {
+                return Ok(None);
+            };
+            // The image may have been prefetched while scraping the comic.
+            if let Some(image) = self.get_cached_image(date, IMG_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            Ok(Some(self.fetch_and_cache_image(&img_url, date).await?))
+        }
+
+        /// Retrieve another resolution of the image of the requested comic.
+        ///
+        /// The variants are cached separately from the original, keyed by their widths. Unlike the
+        /// original, they aren't prefetched, since browsers only request the one that fits them
+        /// best. If the comic or the variant doesn't exist, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        /// * `width` - The width of the requested variant
+        #[instrument(skip(self))]
+        pub async fn get_comic_image_variant(
+            &self,
+            date: &NaiveDate,
+            width: i32,
+        ) -> AppResult<Option<ComicImage>> {
+            match self.inner.get_cached_variant(date, width).await {
+                Ok(Some(image)) => {
+                    info!("Successful retrieval of image variant from cache");
+                    return Ok(Some(image));
+                }
+                Ok(None) => (),
+                // Better to re-fetch now than crash unexpectedly, so simply log the error.
+                Err(err) => error!("Error retrieving image variant from cache: {err}"),
+            }
+
+            let Some(comic_data) = self.get_comic_data(date).await? else {
+                return Ok(None);
+            };
+            let Some(variant) = comic_data
+                .img_variants
+                .into_iter()
+                .find(|variant| variant.width == width)
+            else {
+                return Ok(None);
+            };
+            let image = self.inner.fetch_image(&variant.url).await?;
+            info!("Fetched image variant from source");
+            if let Err(err) = self.inner.cache_variant(&image, date, width).await {
+                error!("Error caching image variant: {err}");
+            }
+            Ok(Some(image))
+        }
+
+        /// Retrieve the image of the requested comic as WebP, if that's smaller.
+        ///
+        /// The transcoded image is cached separately from the original. If the comic doesn't
+        /// exist, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_image_webp(
+            &self,
+            date: &NaiveDate,
+        ) -> AppResult<Option<ComicImage>> {
+            if let Some(image) = self.get_cached_image(date, WEBP_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            let Some(image) = self.get_comic_image(date).await? else {
+                return Ok(None);
+            };
+            if image.content_type == WEBP_MIME_TYPE {
+                return Ok(Some(image));
+            }
+
+            let original = image.clone();
+            // Errors aren't thread-safe, so only their messages are sent back.
+            let result =
+                spawn_blocking(move || transcode_to_webp(&original).map_err(|err| err.to_string()))
+                    .await;
+            let image = match result {
+                Ok(Ok(webp)) if webp.bytes.len() < image.bytes.len() => webp,
+                Ok(Ok(_)) => {
+                    // The original is cached as the "transcoded" one, so that this isn't retried.
+                    info!("WebP image is larger than the original, so using the original");
+                    image
+                }
+                Ok(Err(err)) => {
+                    error!("Error transcoding image to WebP: {err}");
+                    return Ok(Some(image));
+                }
+                Err(err) => {
+                    error!("Transcoding image to WebP crashed: {err}");
+                    return Ok(Some(image));
+                }
+            };
+            if let Err(err) = self
+                .inner
+                .cache_image(&image, date, WEBP_CACHE_PREFIX)
+                .await
+            {
+                error!("Error caching WebP image: {err}");
+            }
+            Ok(Some(image))
+        }
+
+        /// Retrieve the social-share preview image of the requested comic.
+        ///
+        /// The rendered preview is cached separately from the original image. If rendering fails,
+        /// then the original image is returned instead. If the comic doesn't exist, then None is
+        /// returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_preview(&self, date: &NaiveDate) -> AppResult<Option<ComicImage>> {
+            if let Some(image) = self.get_cached_image(date, PREVIEW_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            let Some(comic_data) = self.get_comic_data(date).await? else {
+                return Ok(None);
+            };
+            let Some(image) = self.get_comic_image(date).await? else {
+                return Ok(None);
+            };
+
+            let original = image.clone();
+            let date_disp = date.format(DISP_DATE_FMT).to_string();
+            // Errors aren't thread-safe, so only their messages are sent back.
+            let result = spawn_blocking(move || {
+                render_preview(&original, &comic_data.title, &date_disp)
+                    .map_err(|err| err.to_string())
+            })
+            .await;
+            // The original image is still a usable preview, so fall back to it.
+            let preview = match result {
+                Ok(Ok(preview)) => preview,
+                Ok(Err(err)) => {
+                    error!("Error rendering preview image: {err}");
+                    return Ok(Some(image));
+                }
+                Err(err) => {
+                    error!("Rendering preview image crashed: {err}");
+                    return Ok(Some(image));
+                }
+            };
+            if let Err(err) = self
+                .inner
+                .cache_image(&preview, date, PREVIEW_CACHE_PREFIX)
+                .await
+            {
+                error!("Error caching preview image: {err}");
+            }
+            Ok(Some(preview))
+        }
+
+        /// Retrieve the image of the requested comic with an attribution overlaid on it.
+        ///
+        /// The overlaid image is cached separately from the original image. If rendering fails,
+        /// then the original image is returned instead. If the comic doesn't exist, then None is
+        /// returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        /// * `attribution` - The attribution to overlay
+        #[instrument(skip(self, attribution))]
+        pub async fn get_comic_image_overlaid(
+            &self,
+            date: &NaiveDate,
+            attribution: &Attribution,
+        ) -> AppResult<Option<ComicImage>> {
+            if let Some(image) = self.get_cached_image(date, OVERLAY_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            let Some(comic_data) = self.get_comic_data(date).await? else {
+                return Ok(None);
+            };
+            let Some(image) = self.get_comic_image(date).await? else {
+                return Ok(None);
+            };
+
+            let original = image.clone();
+            let attribution = attribution.clone();
+            // Errors aren't thread-safe, so only their messages are sent back.
+            let result = spawn_blocking(move || {
+                render_overlay(&original, &attribution, &comic_data.permalink)
+                    .map_err(|err| err.to_string())
+            })
+            .await;
+            // Better to serve the comic without the attribution than not at all, so fall back to it.
+            let overlaid = match result {
+                Ok(Ok(overlaid)) => overlaid,
+                Ok(Err(err)) => {
+                    error!("Error rendering attribution overlay: {err}");
+                    return Ok(Some(image));
+                }
+                Err(err) => {
+                    error!("Rendering attribution overlay crashed: {err}");
+                    return Ok(Some(image));
+                }
+            };
+            if let Err(err) = self
+                .inner
+                .cache_image(&overlaid, date, OVERLAY_CACHE_PREFIX)
+                .await
+            {
+                error!("Error caching overlaid image: {err}");
+            }
+            Ok(Some(overlaid))
+        }
+
+        /// Retrieve the downscaled thumbnail of the requested comic.
+        ///
+        /// The thumbnail is rendered on the first request, and cached separately from the original
+        /// image. If rendering fails, then the original image is returned instead. If the comic
+        /// doesn't exist, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_thumbnail(&self, date: &NaiveDate) -> AppResult<Option<ComicImage>> {
+            if let Some(image) = self.get_cached_image(date, THUMBNAIL_CACHE_PREFIX).await {
+                return Ok(Some(image));
+            }
+            let Some(image) = self.get_comic_image(date).await? else {
+                return Ok(None);
+            };
+
+            let original = image.clone();
+            // Errors aren't thread-safe, so only their messages are sent back.
+            let result =
+                spawn_blocking(move || render_thumbnail(&original).map_err(|err| err.to_string()))
+                    .await;
+            // The original image can still be shown in place of its thumbnail, so fall back to it.
+            let thumbnail = match result {
+                Ok(Ok(thumbnail)) => thumbnail,
+                Ok(Err(err)) => {
+                    error!("Error rendering thumbnail: {err}");
+                    return Ok(Some(image));
+                }
+                Err(err) => {
+                    error!("Rendering thumbnail crashed: {err}");
+                    return Ok(Some(image));
+                }
+            };
+            if let Err(err) = self
+                .inner
+                .cache_image(&thumbnail, date, THUMBNAIL_CACHE_PREFIX)
+                .await
+            {
+                error!("Error caching thumbnail: {err}");
+            }
+            Ok(Some(thumbnail))
+        }
+
+        /// Retrieve the metadata of the requested comic's image.
+        ///
+        /// This avoids downloading the image, unless the source doesn't support HEAD requests. If
+        /// the comic doesn't exist, then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the requested comic
+        #[instrument(skip(self))]
+        pub async fn get_comic_image_info(&self, date: &NaiveDate) -> AppResult<Option<ImageInfo>> {
+            if let Some(image) = self.get_cached_image(date, IMG_CACHE_PREFIX).await {
+                return Ok(Some(ImageInfo::from(&image)));
+            }
+            let Some(img_url) = self.get_img_url(date).await? else {
+                return Ok(None);
+            };
+            // The image may have been prefetched while scraping the comic.
+            if let Some(image) = self.get_cached_image(date, IMG_CACHE_PREFIX).await {
+                return Ok(Some(ImageInfo::from(&image)));
+            }
+
+            if let Some(info) = self.inner.fetch_image_info(&img_url).await? {
+                return Ok(Some(info));
+            }
+            // Since the image is downloaded anyway, cache it for later.
+            warn!("Source doesn't support HEAD requests for images; fetching the whole image");
+            let image = self.fetch_and_cache_image(&img_url, date).await?;
+            Ok(Some(ImageInfo::from(&image)))
+        }
+
+        /// Retrieve the cached rendered page of a comic.
+        ///
+        /// If the page isn't in the cache (or the cache fails), then None is returned.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `version` - The version of the page, which identifies all inputs to the page
+        #[instrument(skip(self))]
+        pub async fn get_cached_page(&self, date: &NaiveDate, version: &str) -> Option<String> {
+            match self.inner.get_cached_page(date, version).await {
+                Ok(Some(page)) => {
+                    info!("Successful retrieval of page from cache");
+                    Some(page)
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    // Better to re-render now than crash unexpectedly, so simply log the error.
+                    error!("Error retrieving page from cache: {err}");
+                    None
+                }
+            }
+        }
+
+        /// Cache the rendered page of a comic, logging any errors.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        /// * `version` - The version of the page, which identifies all inputs to the page
+        /// * `page` - The rendered page
+        #[instrument(skip(self, page))]
+        pub async fn cache_page(&self, date: &NaiveDate, version: &str, page: &str) {
+            if let Err(err) = self.inner.cache_page(page, date, version).await {
+                error!("Error caching page: {err}");
+            }
+        }
+
+        /// Retrieve the dates of the comics known to be missing from the source, in order.
+        ///
+        /// Only comics that have been requested are known, since the source has no listing of
+        /// missing comics.
+        #[instrument(skip(self))]
+        pub async fn get_missing_dates(&self) -> AppResult<Vec<NaiveDate>> {
+            self.inner.get_missing_dates().await
+        }
+
+        /// Remove the requested comic from the cache, so that it's scraped again when requested.
+        ///
+        /// # Arguments
+        /// * `date` - The date of the comic
+        #[instrument(skip(self))]
+        pub async fn purge(&self, date: &NaiveDate) -> AppResult<()> {
+            self.inner.purge(date).await
+        }
+
+        /// Remove everything from the cache.
+        #[instrument(skip(self))]
+        pub async fn purge_all(&self) -> AppResult<()> {
+            self.inner.purge_all().await
+        }
+
+        /// Retrieve the most recent cached comics with a tag, newest first.
+        ///
+        /// Only cached comics are returned, since the source has no listing of comics by tag.
+        ///
+        /// # Arguments
+        /// * `tag` - The tag, which is normalized before lookup
+        #[instrument(skip(self))]
+        pub async fn get_tagged_comics(&self, tag: &str) -> AppResult<Vec<(NaiveDate, ComicData)>> {
+            let tag = normalize_tag(tag);
+            let dates = self.inner.get_indexed_dates(TAG_CACHE_PREFIX, &tag).await?;
+            self.get_cached_comics(dates, |comic_data| comic_data.has_tag(&tag))
+                .await
+        }
+
+        /// Search the most recent cached comics, newest first.
+        ///
+        /// Comics match if their title or transcript has every word in the query. Only cached
+        /// comics are searched, since the source can't be searched.
+        ///
+        /// # Arguments
+        /// * `query` - The search query
+        #[instrument(skip(self))]
+        pub async fn search_comics(&self, query: &str) -> AppResult<Vec<(NaiveDate, ComicData)>> {
+            let words = get_search_words(query);
+            let mut matches: Option<Vec<NaiveDate>> = None;
+            for word in &words {
+                let dates = self
+                    .inner
+                    .get_indexed_dates(SEARCH_CACHE_PREFIX, word)
+                    .await?;
+                matches = Some(match matches {
+                    // Both are sorted, so keep only the common dates.
+                    Some(prev) => prev
+                        .into_iter()
+                        .filter(|date| dates.binary_search(date).is_ok())
+                        .collect(),
+                    None => dates,
+                });
+            }
+
+            let dates = matches.unwrap_or_default();
+            self.get_cached_comics(dates, |comic_data| {
+                let comic_words = comic_data.search_words();
+                words.iter().all(|word| comic_words.contains(word))
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inner::*;
+    use super::*;
+
+    use std::io::Cursor;
+    use std::num::NonZeroUsize;
+    use std::sync::Mutex;
+
+    use actix_web::http::{Method, StatusCode};
+    use futures::future::join_all;
+    use image::{ImageFormat, RgbImage};
+    use mockall::Sequence;
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::{
+        LAST_COMIC, MEM_CACHE_SIZE, OVERLAY_MIME_TYPE, PREVIEW_HEIGHT, PREVIEW_MIME_TYPE,
+        PREVIEW_WIDTH, SRC_BASE_URL, SRC_COMIC_PREFIX, THUMBNAIL_MIME_TYPE, THUMBNAIL_WIDTH,
+    };
+    use crate::datetime::str_to_date;
+    use crate::db::mock::MockPool;
+    use crate::errors::AppError;
+    use crate::memory_db::MemoryPool;
+    use crate::scrapers::DilbertSource;
+
+    /// Path to the directory where test scraping files are stored
+    const SCRAPING_TEST_CASE_PATH: &str = "testdata/scraping";
+    /// Time (in milliseconds) taken by the mock source to respond to throttled requests
+    const THROTTLE_TEST_DELAY: u64 = 100;
+
+    /// Enum for the state of the mock struct during cache retrieval.
+    pub enum GetCacheState {
+        /// Retrieve a fresh value.
+        Fresh,
+        /// Retrieve a stale value.
+        Stale,
+        /// Value not found in cache.
+        NotFound,
+        /// Retrieval crashes.
+        Fail,
+    }
+
+    #[test_case((2000, 1, 1), OLD_CACHE_TTL; "old comic")]
+    #[test_case((2023, 3, 5), OLD_CACHE_TTL; "just before recent comics")]
+    #[test_case((2023, 3, 6), RECENT_CACHE_TTL; "recent comic")]
+    #[test_case((2023, 3, 12), RECENT_CACHE_TTL; "last comic")]
+    /// Test whether recent comics are cached for a shorter time than older ones.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - A tuple containing the year, month and day of the comic
+    /// * `expected` - The expected time (in seconds) for which the comic is cached
+    fn test_cache_ttl(date_ymd: (i32, u32, u32), expected: u64) {
+        let date = NaiveDate::from_ymd_opt(date_ymd.0, date_ymd.1, date_ymd.2)
+            .expect("Invalid test parameters");
+        assert_eq!(
+            get_cache_ttl(&str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap(), &date),
+            expected,
+            "Wrong cache TTL"
+        );
+    }
+
+    #[test_case("https://example.com/image", "https://example.com/image"; "absolute")]
+    #[test_case("//example.com/image", "https://example.com/image"; "protocol-relative")]
+    /// Test whether scraped image URLs are made absolute.
+    ///
+    /// # Arguments
+    /// * `img_url` - The scraped image URL
+    /// * `expected` - The expected absolute URL
+    fn test_absolute_img_url(img_url: &str, expected: &str) {
+        assert_eq!(get_absolute_img_url(img_url), expected, "Wrong image URL");
+    }
+
+    #[test_case("Dilbert: I'm done, Dilbert.", &["dilbert", "done"]; "text")]
+    #[test_case("A b", &[]; "short words")]
+    #[test_case("", &[]; "empty")]
+    /// Test whether the words for search are extracted from text.
+    ///
+    /// # Arguments
+    /// * `text` - The text from which to extract words
+    /// * `expected` - The expected words
+    fn test_search_words(text: &str, expected: &[&str]) {
+        assert_eq!(get_search_words(text), expected, "Wrong search words");
+    }
+
+    #[test_case("image/gif", true; "image")]
+    #[test_case("text/html", false; "not an image")]
+    #[actix_web::test]
+    /// Test fetching of comic images from the source.
+    ///
+    /// # Arguments
+    /// * `content_type` - The content type of the source's response
+    /// * `is_image` - Whether the response is expected to be accepted as an image
+    async fn test_fetch_image(content_type: &str, is_image: bool) {
+        let mock_server = MockServer::start().await;
+        let bytes = b"GIF89a".to_vec();
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/image"))
+            .respond_with(
+                ResponseTemplate::new(StatusCode::OK.as_u16())
+                    .set_body_raw(bytes.clone(), content_type),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // The DB shouldn't be used, so use a pool with no connections.
+        let scraper = InnerComicScraper::new(
+            Some(MockPool::new(0)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        let result = scraper
+            .fetch_image(&format!("{}/image", mock_server.uri()))
+            .await;
+
+        if is_image {
+            let expected = ComicImage {
+                content_type: content_type.into(),
+                bytes,
+            };
+            assert_eq!(
+                result.expect("Failed to fetch image"),
+                expected,
+                "Fetched the wrong image"
+            );
+        } else {
+            assert!(result.is_err(), "Non-image response accepted as image");
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether fetches of comic images take permits from the throttle for the source.
+    async fn test_fetch_image_throttled() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/image"))
+            .respond_with(
+                ResponseTemplate::new(StatusCode::OK.as_u16())
+                    .set_body_raw(b"GIF89a".to_vec(), "image/gif")
+                    .set_delay(Duration::from_millis(THROTTLE_TEST_DELAY)),
+            )
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // With only one permit, the fetches can't overlap.
+        let throttle = Throttle::new(Some(NonZeroUsize::MIN), None);
+        let scraper = InnerComicScraper::new(
+            Some(MockPool::new(0)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::new(throttle),
+        );
+        let img_url = format!("{}/image", mock_server.uri());
+        let start = Instant::now();
+        let results = join_all((0..2).map(|_| scraper.fetch_image(&img_url))).await;
+
+        assert!(results.iter().all(Result::is_ok), "Failed to fetch images");
+        assert!(
+            start.elapsed() >= Duration::from_millis(2 * THROTTLE_TEST_DELAY),
+            "Image fetches weren't throttled"
+        );
+        mock_server.verify().await;
+    }
+
+    #[test_case(StatusCode::OK, true; "HEAD supported")]
+    #[test_case(StatusCode::METHOD_NOT_ALLOWED, false; "HEAD not supported")]
+    #[actix_web::test]
+    /// Test fetching of only the metadata of comic images from the source.
+    ///
+    /// # Arguments
+    /// * `status` - The HTTP status code of the source's response to HEAD requests
+    /// * `supported` - Whether the source is expected to support HEAD requests
+    async fn test_fetch_image_info(status: StatusCode, supported: bool) {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::HEAD.as_str()))
+            .and(path("/image"))
+            .respond_with(
+                ResponseTemplate::new(status.as_u16())
+                    .insert_header(CONTENT_TYPE.as_str(), "image/gif")
+                    .insert_header(CONTENT_LENGTH.as_str(), "6"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // The DB shouldn't be used, so use a pool with no connections.
+        let scraper = InnerComicScraper::new(
+            Some(MockPool::new(0)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        let result = scraper
+            .fetch_image_info(&format!("{}/image", mock_server.uri()))
+            .await
+            .expect("Failed to fetch image info");
+
+        let expected = supported.then(|| ImageInfo {
+            content_type: "image/gif".into(),
+            content_length: Some(6),
+        });
+        assert_eq!(result, expected, "Fetched the wrong image info");
+    }
+
+    #[test_case(GetCacheState::Fresh; "comic in cache")]
+    #[test_case(GetCacheState::Stale; "stale comic in cache")]
+    #[test_case(GetCacheState::NotFound; "empty cache")]
+    #[actix_web::test]
+    /// Test cache retrieval of a comic.
+    ///
+    /// # Arguments
+    /// * `status` - Status for the cache retrieval
+    async fn test_comic_cache_retrieval(status: GetCacheState) {
+        // Set up the expected return values, and the entry to store in the mock cache.
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData::default();
+        let expected = match status {
+            GetCacheState::Fresh => Some((comic_data, true)),
+            GetCacheState::Stale => Some((comic_data, false)),
+            GetCacheState::NotFound => None,
+            GetCacheState::Fail => panic!("Invalid test parameter"),
+        };
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let source = DilbertSource::default();
+        if let Some((ref comic_data, fresh)) = expected {
+            if fresh {
+                cache_comic_data(&db, &source, &date, comic_data)
+                    .await
+                    .expect("Couldn't set mock cache entry");
+            } else {
+                // Stale data has no marker of being fresh.
+                db.set(&date, comic_data, OLD_CACHE_TTL)
+                    .await
+                    .expect("Couldn't set mock cache entry");
+            }
+        }
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(db),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        let result = scraper
+            .get_cached_data(&date)
+            .await
+            .expect("Failed to get comic data from cache");
+        assert_eq!(
+            result, expected,
+            "Retrieved the wrong comic data from cache"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test cache storage of a comic.
+    async fn test_comic_cache_storage() {
+        // Set up the entry to store in the mock cache.
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            transcript: Some("The boss is in a meeting.".into()),
+            tags: vec!["Boss".into()],
+            ..Default::default()
+        };
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(db.clone()),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        scraper
+            .cache_data(&comic_data, &date)
+            .await
+            .expect("Failed to set comic data in cache");
+
+        let cached: Option<ComicData> = db.get(&date).await.expect("Couldn't get cache entry");
+        assert_eq!(
+            cached,
+            Some(comic_data),
+            "Stored the wrong comic data in cache"
+        );
+
+        let tagged = scraper
+            .get_indexed_dates(TAG_CACHE_PREFIX, "boss")
+            .await
+            .expect("Couldn't get tagged dates");
+        assert_eq!(tagged, [date], "Tag wasn't indexed");
+        let matched = scraper
+            .get_indexed_dates(SEARCH_CACHE_PREFIX, "meeting")
+            .await
+            .expect("Couldn't get dates for search word");
+        assert_eq!(matched, [date], "Transcript wasn't indexed");
+    }
+
+    #[actix_web::test]
+    /// Test whether comics with a tag that are cached concurrently are all indexed.
+    async fn test_concurrent_tag_indexing() {
+        let dates: Vec<_> = (1..=8)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+        let comic_data = ComicData {
+            tags: vec!["Boss".into()],
+            ..Default::default()
+        };
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let source = DilbertSource::default();
+        join_all(
+            dates
+                .iter()
+                .map(|date| cache_comic_data(&db, &source, date, &comic_data)),
+        )
+        .await
+        .into_iter()
+        .collect::<AppResult<()>>()
+        .expect("Failed to set comic data in cache");
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(db),
+            Arc::new(source),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        let tagged = scraper
+            .get_indexed_dates(TAG_CACHE_PREFIX, "boss")
+            .await
+            .expect("Couldn't get tagged dates");
+        assert_eq!(tagged, dates, "Tagged comics are missing from the index");
+    }
+
+    #[actix_web::test]
+    /// Test cache storage of a rendered page, for only its version.
+    async fn test_page_cache_storage() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(MemoryPool::new(MEM_CACHE_SIZE)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        scraper
+            .cache_page("<html></html>", &date, "v1")
+            .await
+            .expect("Failed to set page in cache");
+
+        let cached = scraper
+            .get_cached_page(&date, "v1")
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(
+            cached.as_deref(),
+            Some("<html></html>"),
+            "Stored the wrong page"
+        );
+        let other = scraper
+            .get_cached_page(&date, "v2")
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(other, None, "Page was retrieved for another version");
+    }
+
+    #[test_case(true; "cache works")]
+    #[test_case(false; "cache fails")]
+    #[actix_web::test]
+    /// Test whether cache errors for pages are treated as cache misses.
+    ///
+    /// # Arguments
+    /// * `cache_works` - Whether the cache works
+    async fn test_cached_page(cache_works: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+        mock_scraper
+            .expect_get_cached_page()
+            .return_once(move |_, _| {
+                if cache_works {
+                    Ok(Some("<html></html>".into()))
+                } else {
+                    Err(AppError::Scrape("Manual error".into()))
+                }
+            });
+        mock_scraper
+            .expect_cache_page()
+            .times(1)
+            .return_once(move |_, _, _| {
+                if cache_works {
+                    Ok(())
+                } else {
+                    Err(AppError::Scrape("Manual error".into()))
+                }
+            });
+
+        let scraper = ComicScraper::from_inner(mock_scraper);
+        let page = scraper.get_cached_page(&date, "v1").await;
+        assert_eq!(
+            page.is_some(),
+            cache_works,
+            "Cache errors aren't cache misses"
+        );
+        // This shouldn't panic on cache errors.
+        scraper.cache_page(&date, "v1", "<html></html>").await;
+    }
+
+    #[actix_web::test]
+    /// Test whether missing comics are recorded in order, without duplicates.
+    async fn test_missing_cache_storage() {
+        let dates = [(2000, 1, 2), (2000, 1, 1), (2000, 1, 2)]
+            .map(|(year, month, day)| NaiveDate::from_ymd_opt(year, month, day).unwrap());
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(MemoryPool::new(MEM_CACHE_SIZE)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        for date in &dates {
+            scraper
+                .cache_missing(date)
+                .await
+                .expect("Failed to record missing comic");
+        }
+
+        let missing = scraper
+            .get_missing_dates()
+            .await
+            .expect("Couldn't get missing dates");
+        assert_eq!(missing, [dates[1], dates[0]], "Missing comics are wrong");
+    }
+
+    #[actix_web::test]
+    /// Test whether missing comics that are recorded concurrently are all recorded.
+    async fn test_concurrent_missing_cache_storage() {
+        let dates: Vec<_> = (1..=8)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(MemoryPool::new(MEM_CACHE_SIZE)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        join_all(dates.iter().map(|date| scraper.cache_missing(date)))
+            .await
+            .into_iter()
+            .collect::<AppResult<()>>()
+            .expect("Failed to record missing comic");
+
+        let missing = scraper
+            .get_missing_dates()
+            .await
+            .expect("Couldn't get missing dates");
+        assert_eq!(missing, dates, "Missing comics weren't all recorded");
+    }
+
+    #[actix_web::test]
+    /// Test whether the data of many comics is retrieved at once, whether cached or not.
+    async fn test_cached_data_many() {
+        let dates = [(2000, 1, 1), (2000, 1, 2), (2000, 1, 3)]
+            .map(|(year, month, day)| NaiveDate::from_ymd_opt(year, month, day).unwrap());
+        let comic_data = ComicData {
+            title: "Title".into(),
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(MemoryPool::new(MEM_CACHE_SIZE)),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        for date in [&dates[0], &dates[2]] {
+            scraper
+                .cache_data(&comic_data, date)
+                .await
+                .expect("Couldn't cache comic data");
+        }
+
+        let cached = scraper
+            .get_cached_data_many(&dates)
+            .await
+            .expect("Couldn't get cached comic data");
+        assert_eq!(
+            cached,
+            [Some(comic_data.clone()), None, Some(comic_data)],
+            "Wrong cached comic data"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether purging a comic removes its data, its images and its record as missing.
+    async fn test_purge() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let other_date = NaiveDate::from_ymd_opt(2000, 1, 2).unwrap();
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"image".to_vec(),
+        };
+
+        let scraper = InnerComicScraper::new(
+            Some(db.clone()),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        db.set(&date, &"data", OLD_CACHE_TTL)
+            .await
+            .expect("Couldn't set mock cache entry");
+        scraper
+            .cache_image(&image, &date, IMG_CACHE_PREFIX)
+            .await
+            .expect("Couldn't cache image");
+        for width in [450, 600] {
+            scraper
+                .cache_variant(&image, &date, width)
+                .await
+                .expect("Couldn't cache image variant");
+        }
+        for date in [&date, &other_date] {
+            scraper
+                .cache_missing(date)
+                .await
+                .expect("Failed to record missing comic");
+        }
+
+        scraper.purge(&date).await.expect("Couldn't purge comic");
+
+        let data: Option<String> = db.get(&date).await.expect("Couldn't get cache entry");
+        assert_eq!(data, None, "Comic data wasn't purged");
+        let cached_image = scraper
+            .get_cached_image(&date, IMG_CACHE_PREFIX)
+            .await
+            .expect("Couldn't get cached image");
+        assert_eq!(cached_image, None, "Comic image wasn't purged");
+        for width in [450, 600] {
+            let cached_variant = scraper
+                .get_cached_variant(&date, width)
+                .await
+                .expect("Couldn't get cached image variant");
+            assert_eq!(cached_variant, None, "Comic image variant wasn't purged");
+        }
+        let missing = scraper
+            .get_missing_dates()
+            .await
+            .expect("Couldn't get missing dates");
+        assert_eq!(missing, [other_date], "Wrong comics recorded as missing");
+
+        scraper.purge_all().await.expect("Couldn't purge cache");
+        let missing = scraper
+            .get_missing_dates()
+            .await
+            .expect("Couldn't get missing dates");
+        assert!(missing.is_empty(), "Cache wasn't purged");
+    }
+
+    #[actix_web::test]
+    /// Test whether purges are passed on to the inner scraper.
+    async fn test_purge_passthrough() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+        mock_scraper
+            .expect_purge()
+            .withf(move |purged| *purged == date)
+            .times(1)
+            .returning(|_| Ok(()));
+        mock_scraper
+            .expect_purge_all()
+            .times(1)
+            .returning(|| Ok(()));
+
+        let scraper = ComicScraper::from_inner(mock_scraper);
+        scraper.purge(&date).await.expect("Couldn't purge comic");
+        scraper.purge_all().await.expect("Couldn't purge cache");
+    }
+
+    #[test_case(true; "image in cache")]
+    #[test_case(false; "empty cache")]
+    #[actix_web::test]
+    /// Test cache retrieval of a comic's image.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the image is in the cache
+    async fn test_image_cache_retrieval(cached: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let expected = cached.then(|| ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        });
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        if let Some(ref image) = expected {
+            db.set(&(IMG_CACHE_PREFIX, &date), image, OLD_CACHE_TTL)
+                .await
+                .expect("Couldn't set mock cache entry");
+        }
+
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(db),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        let result = scraper
+            .get_cached_image(&date, IMG_CACHE_PREFIX)
+            .await
+            .expect("Failed to get image from cache");
+        assert_eq!(result, expected, "Retrieved the wrong image from cache");
+    }
+
+    #[actix_web::test]
+    /// Test cache storage of a comic's image.
+    async fn test_image_cache_storage() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        };
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        // The HTTP client shouldn't be used, so make the URLs empty.
+        let scraper = InnerComicScraper::new(
+            Some(db.clone()),
+            Arc::new(DilbertSource::default()),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+        scraper
+            .cache_image(&image, &date, IMG_CACHE_PREFIX)
+            .await
+            .expect("Failed to set image in cache");
+
+        let cached: Option<ComicImage> = db
+            .get(&(IMG_CACHE_PREFIX, &date))
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(cached, Some(image), "Stored the wrong image in cache");
+    }
+
+    #[test_case((2000, 1, 1), false, ("", "https://web.archive.org/web/20150226185430im_/http://assets.amuniversal.com/bdc8a4d06d6401301d80001dd8b71c47", 900, 266); "without title")]
+    #[test_case((2020, 1, 1), false, ("Rfp Process", "//web.archive.org/web/20200101060221im_/https://assets.amuniversal.com/7c2789d004020138d860005056a9545d", 900, 280); "with title")]
+    #[test_case((2000, 1, 1), true, ("", "", 0, 0); "missing")]
+    #[actix_web::test]
+    /// Test comic scraping.
+    ///
+    /// # Arguments
+    /// * `date_ymd` - A tuple containing the year, month and day for the comic
+    /// * `missing` - Whether the comic is to be indicated as missing
+    /// * `comic_data` - The tuple for the comic data containing the title, image URL, image width
+    ///   and image height
+    async fn test_comic_scraping(
+        date_ymd: (i32, u32, u32),
+        missing: bool,
+        comic_data: (&str, &str, i32, i32),
+    ) {
+        let mock_server = MockServer::start().await;
+        let date = NaiveDate::from_ymd_opt(date_ymd.0, date_ymd.1, date_ymd.2)
+            .expect("Invalid test parameters");
+
+        // The DB shouldn't be used, so use a pool with no connections.
+        let db = Some(MockPool::new(0));
+        let scraper = InnerComicScraper::new(
+            db,
+            Arc::new(DilbertSource::new(
+                mock_server.uri(),
+                format!("{}/cdx", mock_server.uri()),
+                format!("{}/memento", mock_server.uri()),
+            )),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+
+        let expected = ComicData {
+            title: comic_data.0.into(),
+            img_url: comic_data.1.into(),
+            img_width: comic_data.2,
+            img_height: comic_data.3,
+            permalink: format!(
+                "{}/{SRC_COMIC_PREFIX}{}",
+                mock_server.uri(),
+                date.format(SRC_DATE_FMT)
+            ),
+            // Neither of the test pages has a transcript.
+            transcript: None,
+            ..Default::default()
+        };
+
+        let date_str = date.format(SRC_DATE_FMT).to_string();
+        let response = if missing {
+            // "dilbert.com" uses 302 FOUND to inform that the comic is missing.
+            // Response body shouldn't matter, so keep it empty.
+            ResponseTemplate::new(StatusCode::FOUND.as_u16())
+        } else {
+            let html =
+                tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+                    .await
+                    .expect("Couldn't read test page for scraping");
+            ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html)
+        };
+
+        // Set up the mock server to return the pre-fetched "dilbert.com" response for the given date.
+        Mock::given(method(Method::GET.as_str()))
+            .and(path(format!("/{SRC_COMIC_PREFIX}{date_str}")))
+            .respond_with(response)
+            .mount(&mock_server)
+            .await;
+
+        // Set up the mock server to return a bogus timestamp for the base URL, because this is
+        // what the CDX URL is.
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+            .mount(&mock_server)
+            .await;
+
+        // The scraping should fail if and only if the server redirects.
+        match scraper.scrape_data(&date).await {
+            Ok(result) => {
+                if missing {
+                    panic!("Somehow scraped a missing comic");
+                } else {
+                    assert_eq!(result, expected, "Scraped the wrong comic data");
+                }
+            }
+            Err(err) => {
+                if !missing {
+                    panic!("Failed to scrape comic data: {err}")
+                }
+            }
+        };
+    }
+
+    #[actix_web::test]
+    /// Test whether comics are scraped using the Memento TimeTravel API when the CDX API fails.
+    async fn test_memento_fallback() {
+        let mock_server = MockServer::start().await;
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+
+        // The DB shouldn't be used, so use a pool with no connections.
+        let db = Some(MockPool::new(0));
+        let scraper = InnerComicScraper::new(
+            db,
+            Arc::new(DilbertSource::new(
+                mock_server.uri(),
+                format!("{}/cdx", mock_server.uri()),
+                format!("{}/memento", mock_server.uri()),
+            )),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+        );
+
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(StatusCode::BAD_REQUEST.as_u16()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let permalink = format!("{}/memento/20200101/page", mock_server.uri());
+        let body = format!(r#"{{"mementos": {{"closest": {{"uri": ["{permalink}"]}}}}}}"#);
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/memento"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let html = tokio::fs::read_to_string(format!(
+            "{SCRAPING_TEST_CASE_PATH}/{}.html",
+            date.format(SRC_DATE_FMT)
+        ))
+        .await
+        .expect("Couldn't read test page for scraping");
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/memento/20200101/page"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let comic_data = scraper
+            .scrape_data(&date)
+            .await
+            .expect("Failed to scrape comic data");
+        assert_eq!(comic_data.title, "Rfp Process", "Scraped the wrong comic");
+        assert_eq!(comic_data.permalink, permalink, "Wrong permalink");
+        mock_server.verify().await;
+    }
+
+    #[cfg(debug_assertions)]
+    #[actix_web::test]
+    /// Test recording of scraped pages to disk.
+    async fn test_scrape_recording() {
+        let dir = std::env::temp_dir().join(format!("dilbert-viewer-{}", uuid::Uuid::new_v4()));
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let content = "<html></html>";
+
+        record_scrape(&dir, &date, content).await;
+        let recorded = tokio::fs::read_to_string(dir.join("2000-01-01.html"))
+            .await
+            .expect("Scraped page wasn't recorded");
+        tokio::fs::remove_dir_all(&dir)
+            .await
+            .expect("Couldn't clean up recorded pages");
+        assert_eq!(recorded, content, "Recorded the wrong page");
+    }
+
+    #[test_case(GetCacheState::Fresh, true, true; "fresh retrieval")]
+    #[test_case(GetCacheState::Stale, true, true; "stale retrieval, scrape works, storage works")]
+    #[test_case(GetCacheState::Stale, true, false; "stale retrieval, scrape works, storage fails")]
+    #[test_case(GetCacheState::Stale, false, true; "stale retrieval, scrape fails")]
+    #[test_case(GetCacheState::NotFound, true, true; "empty cache, storage works")]
+    #[test_case(GetCacheState::NotFound, true, false; "empty cache, storage fails")]
+    #[test_case(GetCacheState::Fail, true, true; "cache retrieval fails, storage works")]
+    #[test_case(GetCacheState::Fail, true, false; "cache retrieval fails, storage fails")]
+    #[actix_web::test]
+    /// Test multiple scenarios of data requested from the scraper.
+    ///
+    /// # Arguments
+    /// * `retrieve_status` - Status for the cache retrieval
+    /// * `scrape_works` - Whether scraping works
+    /// * `storage_works` - Whether cache storage works
+    async fn test_get_comic_data(
+        retrieve_status: GetCacheState,
+        scrape_works: bool,
+        storage_works: bool,
+    ) {
+        // Set up the expected return values, and the entry to store in the mock cache.
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            ..Default::default()
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        // Mock cache retrieval.
+        mock_scraper.expect_get_cached_data().return_once({
+            let comic_data = comic_data.clone();
+            move |_| match retrieve_status {
+                GetCacheState::Fresh => Ok(Some((comic_data, true))),
+                GetCacheState::Stale => Ok(Some((comic_data, false))),
+                GetCacheState::NotFound => Ok(None),
+                GetCacheState::Fail => Err(AppError::Scrape("Manual error".into())),
+            }
+        });
+
+        // Mock cache storage.
+        mock_scraper.expect_cache_data().return_once(move |_, _| {
+            if storage_works {
+                Ok(())
+            } else {
+                Err(AppError::Scrape("Manual error".into()))
+            }
+        });
+
+        // Mock scraping.
+        mock_scraper.expect_scrape_data().return_once({
+            let comic_data = comic_data.clone();
+            move |_| {
+                if scrape_works {
+                    Ok(comic_data)
+                } else {
+                    Err(AppError::Scrape("Manual error".into()))
+                }
+            }
+        });
+
+        // Mock the image as already prefetched.
+        mock_scraper.expect_get_cached_image().returning(|_, _| {
+            Ok(Some(ComicImage {
+                content_type: "image/gif".into(),
+                bytes: b"GIF89a".to_vec(),
+            }))
+        });
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        assert_eq!(result, Some(comic_data), "Scraper returned the wrong data");
+    }
+
+    #[actix_web::test]
+    /// Test whether the lock for scraping a comic is held by only one scraper until released.
+    async fn test_scrape_lock() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let scrapers: Vec<_> = (0..2)
+            .map(|_| {
+                InnerComicScraper::new(
+                    Some(db.clone()),
+                    Arc::new(DilbertSource::default()),
+                    Client::default(),
+                    RetryPolicy::default(),
+                    Arc::default(),
+                )
+            })
+            .collect();
+
+        let tokens = ["first", "second"];
+        let lock = |i: usize| scrapers[i].lock_scrape(&date, tokens[i]);
+        assert!(lock(0).await.expect("Couldn't lock"), "Lock wasn't taken");
+        assert!(
+            !lock(1).await.expect("Couldn't lock"),
+            "Lock was taken twice"
+        );
+        assert!(
+            scrapers[1]
+                .is_scrape_locked(&date)
+                .await
+                .expect("Couldn't check lock"),
+            "Lock isn't held"
+        );
+
+        // Only the holder of the lock should be able to release it.
+        scrapers[1]
+            .unlock_scrape(&date, tokens[1])
+            .await
+            .expect("Couldn't unlock");
+        assert!(
+            scrapers[1]
+                .is_scrape_locked(&date)
+                .await
+                .expect("Couldn't check lock"),
+            "Lock was released by another scraper"
+        );
+
+        scrapers[0]
+            .unlock_scrape(&date, tokens[0])
+            .await
+            .expect("Couldn't unlock");
+        assert!(
+            !scrapers[1]
+                .is_scrape_locked(&date)
+                .await
+                .expect("Couldn't check lock"),
+            "Lock is still held"
+        );
+        assert!(
+            lock(1).await.expect("Couldn't lock"),
+            "Lock wasn't released"
+        );
+    }
+
+    #[test_case(true; "lock taken")]
+    #[test_case(false; "lock fails")]
+    #[actix_web::test]
+    /// Test whether comics are scraped when the scrape lock is taken, or can't be used.
+    ///
+    /// The lock should be released with the token it was taken with, before the image is
+    /// prefetched.
+    ///
+    /// # Arguments
+    /// * `lock_works` - Whether taking the lock works
+    async fn test_scrape_lock_taken(lock_works: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            ..Default::default()
+        };
+        let token = Arc::new(Mutex::new(String::new()));
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+        let mut seq = Sequence::new();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(|_| Ok(None));
+        mock_scraper
+            .expect_lock_scrape()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once({
+                let token = token.clone();
+                move |_, new_token| {
+                    *token.lock().unwrap() = new_token.into();
+                    if lock_works {
+                        Ok(true)
+                    } else {
+                        Err(AppError::Scrape("Manual error".into()))
+                    }
+                }
+            });
+        mock_scraper
+            .expect_scrape_data()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once({
+                let comic_data = comic_data.clone();
+                move |_| Ok(comic_data)
+            });
+        mock_scraper
+            .expect_cache_data()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _| Ok(()));
+        // The lock should only be released by its holder.
+        if lock_works {
+            mock_scraper
+                .expect_unlock_scrape()
+                .withf({
+                    let token = token.clone();
+                    move |_, other| !other.is_empty() && *token.lock().unwrap() == other
+                })
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_, _| Ok(()));
+        } else {
+            mock_scraper.expect_unlock_scrape().never();
+        }
+        // Mock the image as already prefetched.
+        mock_scraper
+            .expect_get_cached_image()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _| {
+                Ok(Some(ComicImage {
+                    content_type: "image/gif".into(),
+                    bytes: b"GIF89a".to_vec(),
+                }))
+            });
+
+        let mut scraper = ComicScraper::from_inner(mock_scraper);
+        scraper.scrape_lock = true;
+        let result = scraper
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        assert_eq!(result, Some(comic_data), "Scraper returned the wrong data");
+    }
+
+    #[test_case(false; "cached")]
+    #[test_case(true; "missing")]
+    #[actix_web::test]
+    /// Test whether comics scraped by another instance are taken from the cache.
+    ///
+    /// # Arguments
+    /// * `missing` - Whether the other instance found the comic to be missing
+    async fn test_scrape_lock_wait(missing: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData::default();
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+        let mut seq = Sequence::new();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Ok(None));
+        mock_scraper
+            .expect_lock_scrape()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_, _| Ok(false));
+        // The other instance finishes after the first check.
+        mock_scraper
+            .expect_is_scrape_locked()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Ok(true));
+        mock_scraper
+            .expect_is_scrape_locked()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| Ok(false));
+        mock_scraper
+            .expect_get_cached_data()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once({
+                let comic_data = comic_data.clone();
+                move |_| Ok((!missing).then_some((comic_data, true)))
+            });
+        if missing {
+            mock_scraper
+                .expect_get_missing_dates()
+                .times(1)
+                .in_sequence(&mut seq)
+                .return_once(move || Ok(vec![date]));
+        }
+        mock_scraper.expect_scrape_data().never();
+
+        let mut scraper = ComicScraper::from_inner(mock_scraper);
+        scraper.scrape_lock = true;
+        let result = scraper
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        let expected = (!missing).then_some(comic_data);
+        assert_eq!(result, expected, "Scraper returned the wrong data");
+    }
+
+    #[test_case(true; "fetch works")]
+    #[test_case(false; "fetch fails")]
+    #[actix_web::test]
+    /// Test whether the image of a scraped comic is prefetched into the cache in the background.
+    ///
+    /// # Arguments
+    /// * `fetch_works` - Whether fetching the image works
+    async fn test_image_prefetch(fetch_works: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            ..Default::default()
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(|_| Ok(None));
+        mock_scraper.expect_scrape_data().return_once({
+            let comic_data = comic_data.clone();
+            move |_| Ok(comic_data)
+        });
+        mock_scraper.expect_cache_data().return_once(|_, _| Ok(()));
+        mock_scraper
+            .expect_get_cached_image()
+            .return_once(|_, _| Ok(None));
+        mock_scraper
+            .expect_fetch_image()
+            .withf(|img_url| img_url == SRC_BASE_URL)
+            .times(1)
+            .return_once(move |_| {
+                if fetch_works {
+                    Ok(ComicImage {
+                        content_type: "image/gif".into(),
+                        bytes: b"GIF89a".to_vec(),
+                    })
+                } else {
+                    Err(AppError::Scrape("Manual error".into()))
+                }
+            });
+        mock_scraper
+            .expect_cache_image()
+            .withf(|_, _, prefix| prefix == IMG_CACHE_PREFIX)
+            .times(if fetch_works { 1 } else { 0 })
+            .return_once(|_, _, _| Ok(()));
+
+        let scraper = ComicScraper::from_inner(mock_scraper);
+        let result = scraper
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        assert_eq!(result, Some(comic_data), "Scraper returned the wrong data");
+        assert!(
+            Rc::strong_count(&scraper.inner) > 1,
+            "Data retrieval waited for the prefetch"
+        );
+
+        // Let the background prefetch run, which holds the inner scraper until it's done.
+        while Rc::strong_count(&scraper.inner) > 1 {
+            actix_web::rt::task::yield_now().await;
+        }
+    }
+
+    #[actix_web::test]
+    /// Test that comics missing from the source are recorded, and retrieved, as missing.
+    async fn test_missing_comic_recorded() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(|_| Ok(None));
+        mock_scraper
+            .expect_scrape_data()
+            .return_once(|_| Err(AppError::NotFound("Manual error".into())));
+        mock_scraper
+            .expect_cache_missing()
+            .withf(move |other| other == &date)
+            .times(1)
+            .return_once(|_| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        assert_eq!(result, None, "Scraper returned data for a missing comic");
+
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+        mock_scraper
+            .expect_get_missing_dates()
+            .return_once(move || Ok(vec![date]));
+        let missing = ComicScraper::from_inner(mock_scraper)
+            .get_missing_dates()
+            .await
+            .expect("Failed to get missing dates");
+        assert_eq!(missing, [date], "Scraper returned the wrong missing dates");
+    }
+
+    #[actix_web::test]
+    /// Test that outages of the comic source are returned as errors, without recording the comic
+    /// as missing.
+    async fn test_source_unavailable() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(|_| Ok(None));
+        mock_scraper.expect_scrape_data().return_once(|_| {
+            Err(AppError::SourceUnavailable(
+                "Manual error".into(),
+                Duration::from_secs(1),
+            ))
+        });
+        mock_scraper.expect_cache_missing().never();
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_data(&date)
+            .await;
+        assert!(
+            matches!(result, Err(AppError::SourceUnavailable(..))),
+            "Outage wasn't returned as an error"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test that scraped data without an image is served, but not cached.
+    async fn test_incomplete_scrape_not_cached() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            title: "Title".into(),
+            ..Default::default()
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(|_| Ok(None));
+        mock_scraper.expect_scrape_data().return_once({
+            let comic_data = comic_data.clone();
+            move |_| Ok(comic_data)
+        });
+        mock_scraper.expect_cache_data().never();
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_data(&date)
+            .await
+            .expect("Data retrieval from scraper crashed");
+        assert_eq!(result, Some(comic_data), "Scraper returned the wrong data");
+    }
+
+    #[test_case(true; "image in cache")]
+    #[test_case(false; "empty cache")]
+    #[actix_web::test]
+    /// Test whether images are fetched and cached only on a cache miss.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the image is in the cache
+    async fn test_get_comic_image(cached: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            ..Default::default()
+        };
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper.expect_get_cached_image().returning({
+            let image = image.clone();
+            move |_, _| Ok(cached.then(|| image.clone()))
+        });
+        let times = if cached { 0 } else { 1 };
+        mock_scraper
+            .expect_get_cached_data()
+            .times(times)
+            .return_once(move |_| Ok(Some((comic_data, true))));
+        mock_scraper.expect_fetch_image().times(times).return_once({
+            let image = image.clone();
+            move |_| Ok(image)
+        });
+        mock_scraper
+            .expect_cache_image()
+            .times(times)
+            .return_once(|_, _, _| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_image(&date)
+            .await
+            .expect("Image retrieval from scraper crashed");
+        assert_eq!(result, Some(image), "Scraper returned the wrong image");
+    }
+
+    #[test_case(450, true, false; "available")]
+    #[test_case(450, true, true; "cached")]
+    #[test_case(600, false, false; "unavailable")]
+    #[actix_web::test]
+    /// Test whether only the available variants of a comic's image are fetched, and then cached.
+    ///
+    /// # Arguments
+    /// * `width` - The width of the requested variant
+    /// * `available` - Whether the comic has a variant with that width
+    /// * `cached` - Whether the variant is in the cache
+    async fn test_get_comic_image_variant(width: i32, available: bool, cached: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            img_width: 900,
+            img_variants: vec![ImageVariant {
+                url: "https://example.com/small".into(),
+                width: 450,
+            }],
+            ..Default::default()
+        };
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_variant()
+            .withf(move |_, cached_width| *cached_width == width)
+            .return_once({
+                let image = image.clone();
+                move |_, _| Ok(cached.then_some(image))
+            });
+        mock_scraper
+            .expect_get_cached_data()
+            .times(usize::from(!cached))
+            .return_once(move |_| Ok(Some((comic_data, true))));
+        let fetched = available && !cached;
+        mock_scraper
+            .expect_fetch_image()
+            .withf(|img_url| img_url == "https://example.com/small")
+            .times(usize::from(fetched))
+            .return_once({
+                let image = image.clone();
+                move |_| Ok(image)
+            });
+        mock_scraper
+            .expect_cache_variant()
+            .withf(move |_, _, cached_width| *cached_width == width)
+            .times(usize::from(fetched))
+            .returning(|_, _, _| Ok(()));
+        mock_scraper.expect_cache_image().never();
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_image_variant(&date, width)
+            .await
+            .expect("Image variant retrieval from scraper crashed");
+        assert_eq!(
+            result,
+            available.then_some(image),
+            "Scraper returned the wrong image"
+        );
+    }
+
+    #[test_case(true, true; "cached")]
+    #[test_case(false, true; "transcoded")]
+    #[test_case(false, false; "not transcodable")]
+    #[actix_web::test]
+    /// Test retrieval of a comic's image transcoded into WebP.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the WebP image is in the cache
+    /// * `valid` - Whether the original image can be decoded
+    async fn test_get_comic_image_webp(cached: bool, valid: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut bytes = Vec::new();
+        if valid {
+            RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 0]))
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .expect("Couldn't encode test image");
+        } else {
+            bytes.extend_from_slice(b"GIF89a");
+        }
+        let original = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+        let webp = ComicImage {
+            content_type: WEBP_MIME_TYPE.into(),
+            bytes: b"RIFF".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == WEBP_CACHE_PREFIX)
+            .return_once({
+                let webp = webp.clone();
+                move |_, _| Ok(cached.then_some(webp))
+            });
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == IMG_CACHE_PREFIX)
+            .times(if cached { 0 } else { 1 })
+            .return_once({
+                let original = original.clone();
+                move |_, _| Ok(Some(original))
+            });
+        mock_scraper
+            .expect_cache_image()
+            .withf(|_, _, prefix| prefix == WEBP_CACHE_PREFIX)
+            .times(if !cached && valid { 1 } else { 0 })
+            .return_once(|_, _, _| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_image_webp(&date)
+            .await
+            .expect("WebP image retrieval from scraper crashed")
+            .expect("Scraper returned no image");
+        if cached {
+            assert_eq!(result, webp, "Didn't use the cached WebP image");
+        } else if valid {
+            let decoded = image::load_from_memory(&result.bytes).expect("Invalid image returned");
+            assert_eq!(decoded.width(), 64, "Wrong image returned");
+        } else {
+            assert_eq!(result, original, "Didn't fall back to the original image");
+        }
+    }
+
+    #[test_case(true, true; "cached")]
+    #[test_case(false, true; "rendered")]
+    #[test_case(false, false; "not renderable")]
+    #[actix_web::test]
+    /// Test retrieval of a comic's image with an attribution overlaid on it.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the overlaid image is in the cache
+    /// * `valid` - Whether the original image can be decoded
+    async fn test_get_comic_image_overlaid(cached: bool, valid: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            img_width: 64,
+            img_height: 64,
+            permalink: SRC_BASE_URL.into(),
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        if valid {
+            RgbImage::from_pixel(64, 64, image::Rgb([255, 255, 255]))
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .expect("Couldn't encode test image");
+        } else {
+            bytes.extend_from_slice(b"GIF89a");
+        }
+        let original = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+        let overlaid = ComicImage {
+            content_type: OVERLAY_MIME_TYPE.into(),
+            bytes: b"PNG".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == OVERLAY_CACHE_PREFIX)
+            .return_once({
+                let overlaid = overlaid.clone();
+                move |_, _| Ok(cached.then_some(overlaid))
+            });
+        let times = if cached { 0 } else { 1 };
+        mock_scraper
+            .expect_get_cached_data()
+            .times(times)
+            .return_once(move |_| Ok(Some((comic_data, true))));
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == IMG_CACHE_PREFIX)
+            .times(times)
+            .return_once({
+                let original = original.clone();
+                move |_, _| Ok(Some(original))
+            });
+        mock_scraper
+            .expect_cache_image()
+            .withf(|_, _, prefix| prefix == OVERLAY_CACHE_PREFIX)
+            .times(if !cached && valid { 1 } else { 0 })
+            .return_once(|_, _, _| Ok(()));
+
+        let attribution = Attribution::load("Attribution".into(), None).expect("Invalid font");
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_image_overlaid(&date, &attribution)
+            .await
+            .expect("Overlaid image retrieval from scraper crashed")
+            .expect("Scraper returned no image");
+        if cached {
+            assert_eq!(result, overlaid, "Didn't use the cached overlaid image");
+        } else if valid {
+            assert_eq!(
+                result.content_type, OVERLAY_MIME_TYPE,
+                "Image wasn't overlaid"
+            );
+            assert_ne!(result, original, "Image wasn't overlaid");
+        } else {
+            assert_eq!(result, original, "Didn't fall back to the original image");
+        }
+    }
+
+    #[test_case(true, true; "cached")]
+    #[test_case(false, true; "rendered")]
+    #[test_case(false, false; "not renderable")]
+    #[actix_web::test]
+    /// Test retrieval of a comic's preview image.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the preview image is in the cache
+    /// * `valid` - Whether the original image can be decoded
+    async fn test_get_comic_preview(cached: bool, valid: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            title: "Title".into(),
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            img_width: 64,
+            img_height: 64,
+            ..Default::default()
+        };
+        let mut bytes = Vec::new();
+        if valid {
+            RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 0]))
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .expect("Couldn't encode test image");
+        } else {
+            bytes.extend_from_slice(b"GIF89a");
+        }
+        let original = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+        let preview = ComicImage {
+            content_type: PREVIEW_MIME_TYPE.into(),
+            bytes: b"PNG".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == PREVIEW_CACHE_PREFIX)
+            .return_once({
+                let preview = preview.clone();
+                move |_, _| Ok(cached.then_some(preview))
+            });
+        let times = if cached { 0 } else { 1 };
+        mock_scraper
+            .expect_get_cached_data()
+            .times(times)
+            .return_once(move |_| Ok(Some((comic_data, true))));
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == IMG_CACHE_PREFIX)
+            .times(times)
+            .return_once({
+                let original = original.clone();
+                move |_, _| Ok(Some(original))
+            });
+        mock_scraper
+            .expect_cache_image()
+            .withf(|_, _, prefix| prefix == PREVIEW_CACHE_PREFIX)
+            .times(if !cached && valid { 1 } else { 0 })
+            .return_once(|_, _, _| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_preview(&date)
+            .await
+            .expect("Preview image retrieval from scraper crashed")
+            .expect("Scraper returned no image");
+        if cached {
+            assert_eq!(result, preview, "Didn't use the cached preview image");
+        } else if valid {
+            let decoded = image::load_from_memory(&result.bytes).expect("Invalid image returned");
+            assert_eq!(
+                (decoded.width(), decoded.height()),
+                (PREVIEW_WIDTH, PREVIEW_HEIGHT),
+                "Wrong image returned"
+            );
+        } else {
+            assert_eq!(result, original, "Didn't fall back to the original image");
+        }
+    }
+
+    #[test_case(true, true; "cached")]
+    #[test_case(false, true; "rendered")]
+    #[test_case(false, false; "not renderable")]
+    #[actix_web::test]
+    /// Test retrieval of a comic's thumbnail.
+    ///
+    /// # Arguments
+    /// * `cached` - Whether the thumbnail is in the cache
+    /// * `valid` - Whether the original image can be decoded
+    async fn test_get_comic_thumbnail(cached: bool, valid: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let mut bytes = Vec::new();
+        if valid {
+            RgbImage::from_fn(900, 280, |x, y| image::Rgb([x as u8, y as u8, 0]))
+                .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                .expect("Couldn't encode test image");
+        } else {
+            bytes.extend_from_slice(b"GIF89a");
+        }
+        let original = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+        let thumbnail = ComicImage {
+            content_type: THUMBNAIL_MIME_TYPE.into(),
+            bytes: b"PNG".to_vec(),
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == THUMBNAIL_CACHE_PREFIX)
+            .return_once({
+                let thumbnail = thumbnail.clone();
+                move |_, _| Ok(cached.then_some(thumbnail))
+            });
+        mock_scraper
+            .expect_get_cached_image()
+            .withf(|_, prefix| prefix == IMG_CACHE_PREFIX)
+            .times(if cached { 0 } else { 1 })
+            .return_once({
+                let original = original.clone();
+                move |_, _| Ok(Some(original))
+            });
+        mock_scraper
+            .expect_cache_image()
+            .withf(|_, _, prefix| prefix == THUMBNAIL_CACHE_PREFIX)
+            .times(if !cached && valid { 1 } else { 0 })
+            .return_once(|_, _, _| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_thumbnail(&date)
+            .await
+            .expect("Thumbnail retrieval from scraper crashed")
+            .expect("Scraper returned no image");
+        if cached {
+            assert_eq!(result, thumbnail, "Didn't use the cached thumbnail");
+        } else if valid {
+            let decoded = image::load_from_memory(&result.bytes).expect("Invalid image returned");
+            assert_eq!(decoded.width(), THUMBNAIL_WIDTH, "Wrong image returned");
+        } else {
+            assert_eq!(result, original, "Didn't fall back to the original image");
+        }
+    }
+
+    #[test_case(true; "scrape works")]
+    #[test_case(false; "scrape fails")]
+    #[actix_web::test]
+    /// Test whether stale data is served immediately, and refreshed once in the background.
+    ///
+    /// # Arguments
+    /// * `scrape_works` - Whether scraping works
+    async fn test_stale_refresh(scrape_works: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let get_comic_data = |title: &str| ComicData {
+            title: title.into(),
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            img_width: 1,
+            img_height: 1,
+            ..Default::default()
+        };
+        let stale = get_comic_data("Stale");
+        let refreshed = get_comic_data("Refreshed");
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper.expect_get_cached_data().times(2).returning({
+            let stale = stale.clone();
+            move |_| Ok(Some((stale.clone(), false)))
+        });
+        mock_scraper.expect_scrape_data().times(1).return_once({
+            let refreshed = refreshed.clone();
+            move |_| {
+                if scrape_works {
+                    Ok(refreshed)
+                } else {
+                    Err(AppError::Scrape("Manual error".into()))
+                }
+            }
+        });
+        mock_scraper
+            .expect_cache_data()
+            .withf(move |comic_data, _| comic_data == &refreshed)
+            .times(usize::from(scrape_works))
+            .return_once(|_, _| Ok(()));
+
+        let scraper = ComicScraper::from_inner(mock_scraper);
+        // The second request comes in while the first one's refresh is still pending.
+        for _ in 0..2 {
+            let result = scraper
+                .get_comic_data(&date)
+                .await
+                .expect("Data retrieval from scraper crashed");
+            assert_eq!(result, Some(stale.clone()), "Didn't serve the stale data");
+        }
+
+        // Let the background refresh run.
+        while !scraper.refreshing.borrow().is_empty() {
+            actix_web::rt::task::yield_now().await;
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether only cached comics that still have a tag are retrieved for it, newest first.
+    async fn test_get_tagged_comics() {
+        let dates: Vec<_> = (1..=3)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+        let get_comic_data = |tag: &str| ComicData {
+            tags: vec![tag.into()],
+            ..Default::default()
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper.expect_get_indexed_dates().return_once({
+            let dates = dates.clone();
+            move |_, _| Ok(dates)
+        });
+        mock_scraper.expect_get_cached_data_many().returning({
+            let dates = dates.clone();
+            move |requested| {
+                // The second comic isn't cached, and the third one's tags have changed.
+                Ok(requested
+                    .iter()
+                    .map(|date| {
+                        if *date == dates[0] {
+                            Some(get_comic_data("Boss"))
+                        } else if *date == dates[2] {
+                            Some(get_comic_data("Wally"))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect())
+            }
+        });
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_tagged_comics("#boss")
+            .await
+            .expect("Tagged comic retrieval from scraper crashed");
+        assert_eq!(
+            result,
+            [(dates[0], get_comic_data("Boss"))],
+            "Scraper returned the wrong comics"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether only cached comics that still have every searched word are found.
+    async fn test_search_comics() {
+        let dates: Vec<_> = (1..=3)
+            .map(|day| NaiveDate::from_ymd_opt(2000, 1, day).unwrap())
+            .collect();
+        let get_comic_data = |transcript: &str| ComicData {
+            transcript: Some(transcript.into()),
+            ..Default::default()
+        };
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper.expect_get_indexed_dates().returning({
+            let dates = dates.clone();
+            move |_, word| {
+                // Only the first and third comics have both words.
+                Ok(match word {
+                    "boss" => dates.clone(),
+                    _ => vec![dates[0], dates[2]],
+                })
+            }
+        });
+        mock_scraper.expect_get_cached_data_many().returning({
+            let dates = dates.clone();
+            move |requested| {
+                // The third comic's transcript has changed.
+                Ok(requested
+                    .iter()
+                    .map(|date| {
+                        Some(if *date == dates[0] {
+                            get_comic_data("The boss is in a meeting.")
+                        } else {
+                            get_comic_data("The boss is out.")
+                        })
+                    })
+                    .collect())
+            }
+        });
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .search_comics("Boss meeting")
+            .await
+            .expect("Comic search from scraper crashed");
+        assert_eq!(
+            result,
+            [(dates[0], get_comic_data("The boss is in a meeting."))],
+            "Scraper returned the wrong comics"
+        );
+    }
+
+    #[test_case(true; "HEAD supported")]
+    #[test_case(false; "HEAD not supported")]
+    #[actix_web::test]
+    /// Test whether image metadata falls back to fetching the whole image if needed.
+    ///
+    /// # Arguments
+    /// * `head_supported` - Whether the source supports HEAD requests
+    async fn test_get_comic_image_info(head_supported: bool) {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = ComicData {
+            img_url: SRC_BASE_URL.into(), // Any URL should technically work.
+            ..Default::default()
+        };
+        let image = ComicImage {
+            content_type: "image/gif".into(),
+            bytes: b"GIF89a".to_vec(),
+        };
+        let expected = ImageInfo::from(&image);
+        let mut mock_scraper = MockInnerComicScraper::<MockPool>::default();
+
+        mock_scraper
+            .expect_get_cached_image()
+            .returning(|_, _| Ok(None));
+        mock_scraper
+            .expect_get_cached_data()
+            .return_once(move |_| Ok(Some((comic_data, true))));
+        mock_scraper.expect_fetch_image_info().return_once({
+            let expected = expected.clone();
+            move |_| Ok(head_supported.then_some(expected))
+        });
+        let times = if head_supported { 0 } else { 1 };
+        mock_scraper
+            .expect_fetch_image()
+            .times(times)
+            .return_once(move |_| Ok(image));
+        mock_scraper
+            .expect_cache_image()
+            .times(times)
+            .return_once(|_, _, _| Ok(()));
+
+        let result = ComicScraper::from_inner(mock_scraper)
+            .get_comic_image_info(&date)
+            .await
+            .expect("Image info retrieval from scraper crashed");
+        assert_eq!(
+            result,
+            Some(expected),
+            "Scraper returned the wrong image info"
+        );
+    }
+}
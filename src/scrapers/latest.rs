@@ -0,0 +1,353 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Scraper to get the date of the latest comic
+use std::sync::Arc;
+
+use awc::Client;
+use chrono::{Duration, NaiveDate};
+use tracing::{debug, error, info, instrument};
+
+use crate::constants::{LATEST_DATE_KEY, LATEST_DATE_LOOKBACK, LATEST_DATE_REFRESH};
+use crate::datetime::curr_date;
+use crate::db::CacheBackend;
+use crate::errors::{AppError, AppResult};
+use crate::retry::RetryPolicy;
+use crate::singleflight::SingleFlight;
+use crate::throttle::Throttle;
+
+use super::{fetch_comic_page, ComicSource};
+
+/// Get the date of the latest comic, as last detected by the latest date scrapers sharing the cache.
+///
+/// This doesn't make any request to the source. If the date hasn't been detected, such as when it's
+/// pinned, then this is the source's last comic.
+///
+/// # Arguments
+/// * `db` - The cache for the latest date
+/// * `source` - The site of the comics
+pub async fn get_known_latest_date<T: CacheBackend>(
+    db: &T,
+    source: &dyn ComicSource,
+) -> AppResult<NaiveDate> {
+    match db.get(&LATEST_DATE_KEY).await? {
+        Some(date) => Ok(date),
+        None => Ok(*source.date_range()?.end()),
+    }
+}
+
+/// Struct for a scraper of the latest comic's date
+///
+/// Since the Dilbert comic has ended, the latest date is pinned to the source's last comic by
+/// default. For sources that still publish new comics, it can instead be detected from the source.
+pub struct LatestDateScraper<T: CacheBackend + 'static> {
+    db: Option<T>,
+    source: Arc<dyn ComicSource>,
+    http_client: Client,
+    retry: RetryPolicy,
+    throttle: Arc<Throttle>,
+    /// Whether to detect the latest date from the source, instead of pinning it
+    live: bool,
+    /// Coalescer for concurrent detections of the latest date
+    in_flight: SingleFlight<(), NaiveDate>,
+}
+
+impl<T: CacheBackend + 'static> LatestDateScraper<T> {
+    /// Initialize a latest date scraper.
+    ///
+    /// # Arguments
+    /// * `db` - The optional cache for the latest date
+    /// * `source` - The site of the comics
+    /// * `http_client` - The HTTP client for requests to the comic source
+    /// * `retry` - The policy for retrying requests to the comic source
+    /// * `throttle` - The throttle for requests to the comic source, shared with other scrapers
+    /// * `live` - Whether to detect the latest date from the source, instead of pinning it
+    pub fn new(
+        db: Option<T>,
+        source: Arc<dyn ComicSource>,
+        http_client: Client,
+        retry: RetryPolicy,
+        throttle: Arc<Throttle>,
+        live: bool,
+    ) -> Self {
+        Self {
+            db,
+            source,
+            http_client,
+            retry,
+            throttle,
+            live,
+            in_flight: SingleFlight::default(),
+        }
+    }
+
+    /// Get the date of the latest comic.
+    ///
+    /// If pinned, then this is the source's last comic, without any request to the source.
+    /// Otherwise, the detected date is cached for a while. If detection fails, then this falls
+    /// back to the source's last comic, which is cached for just as long.
+    #[instrument(skip(self))]
+    pub async fn get_latest_date(&self) -> AppResult<NaiveDate> {
+        let last_comic = *self.source.date_range()?.end();
+        if !self.live {
+            return Ok(last_comic);
+        }
+        Ok(self
+            .in_flight
+            .run((), || self.retrieve_latest_date(last_comic), Clone::clone)
+            .await)
+    }
+
+    /// Retrieve the date of the latest comic from the cache, or else from the source.
+    ///
+    /// # Arguments
+    /// * `fallback` - The date to use if it can't be retrieved
+    async fn retrieve_latest_date(&self, fallback: NaiveDate) -> NaiveDate {
+        match self.get_cached_date().await {
+            Ok(Some(date)) => {
+                info!("Successful retrieval of latest date from cache");
+                return date;
+            }
+            Ok(None) => (),
+            // Better to re-scrape now than crash unexpectedly, so simply log the error.
+            Err(err) => error!("Error retrieving latest date from cache: {err}"),
+        }
+
+        let date = match self.scrape_latest_date().await {
+            Ok(date) => {
+                info!("Scraped latest date from source: {date}");
+                date
+            }
+            Err(err) => {
+                error!("Scraping latest date failed with error: {err}. Using {fallback}.");
+                fallback
+            }
+        };
+        // The fallback is cached too, so that a failing source isn't probed on every request.
+        if let Err(err) = self.cache_date(&date).await {
+            error!("Error caching latest date: {err}");
+        }
+        date
+    }
+
+    /// Get the cached date of the latest comic from the database.
+    async fn get_cached_date(&self) -> AppResult<Option<NaiveDate>> {
+        let Some(db) = &self.db else {
+            return Ok(None);
+        };
+        db.get(&LATEST_DATE_KEY).await
+    }
+
+    /// Cache the date of the latest comic into the database.
+    async fn cache_date(&self, date: &NaiveDate) -> AppResult<()> {
+        let Some(db) = &self.db else {
+            return Ok(());
+        };
+        db.set(&LATEST_DATE_KEY, date, LATEST_DATE_REFRESH).await
+    }
+
+    /// Scrape the date of the latest comic from the source.
+    ///
+    /// This checks every day backwards from today, until a comic is found.
+    async fn scrape_latest_date(&self) -> AppResult<NaiveDate> {
+        let today = curr_date();
+        for days in 0..=LATEST_DATE_LOOKBACK {
+            let date = today - Duration::days(days);
+            let result = fetch_comic_page(
+                &self.http_client,
+                self.source.as_ref(),
+                &self.retry,
+                &self.throttle,
+                &date,
+            )
+            .await;
+            match result {
+                Ok(_) => return Ok(date),
+                Err(AppError::NotFound(_)) => debug!("No comic found for {date}"),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(AppError::NotFound(format!(
+            "No comic found in the last {LATEST_DATE_LOOKBACK} days"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::http::{Method, StatusCode};
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::{LAST_COMIC, MEM_CACHE_SIZE, SRC_COMIC_PREFIX, SRC_DATE_FMT};
+    use crate::datetime::str_to_date;
+    use crate::memory_db::MemoryPool;
+    use crate::scrapers::DilbertSource;
+
+    /// Get a latest date scraper for the given mock server.
+    ///
+    /// # Arguments
+    /// * `mock_server` - The mock server for the comic source
+    /// * `db` - The cache for the latest date
+    /// * `live` - Whether to detect the latest date from the source
+    fn get_scraper(
+        mock_server: &MockServer,
+        db: MemoryPool,
+        live: bool,
+    ) -> LatestDateScraper<MemoryPool> {
+        LatestDateScraper::new(
+            Some(db),
+            Arc::new(DilbertSource::new(
+                mock_server.uri(),
+                format!("{}/cdx", mock_server.uri()),
+                format!("{}/memento", mock_server.uri()),
+            )),
+            Client::default(),
+            RetryPolicy::default(),
+            Arc::default(),
+            live,
+        )
+    }
+
+    #[actix_web::test]
+    /// Test whether the pinned latest date is the last comic, without requests to the source.
+    async fn test_pinned() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let scraper = get_scraper(&mock_server, MemoryPool::new(MEM_CACHE_SIZE), false);
+        let result = scraper
+            .get_latest_date()
+            .await
+            .expect("Failed to get latest date");
+
+        assert_eq!(
+            result,
+            str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap(),
+            "Latest date isn't pinned to the last comic"
+        );
+        mock_server.verify().await;
+    }
+
+    #[test_case(Some(0); "published today")]
+    #[test_case(Some(2); "published days ago")]
+    #[test_case(None; "not published recently")]
+    #[actix_web::test]
+    /// Test detection of the latest date from the source, and the caching of it or its fallback.
+    ///
+    /// # Arguments
+    /// * `days_ago` - The number of days before today of the latest comic, if recent
+    async fn test_live(days_ago: Option<i64>) {
+        let mock_server = MockServer::start().await;
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let latest = days_ago.map(|days| curr_date() - Duration::days(days));
+
+        if let Some(latest) = latest {
+            Mock::given(method(Method::GET.as_str()))
+                .and(path(format!(
+                    "/{SRC_COMIC_PREFIX}{}",
+                    latest.format(SRC_DATE_FMT)
+                )))
+                .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+                .mount(&mock_server)
+                .await;
+        }
+        // "dilbert.com" uses 302 FOUND to inform that the comic is missing.
+        Mock::given(method(Method::GET.as_str()))
+            .and(path_regex(format!("^/{SRC_COMIC_PREFIX}")))
+            .respond_with(ResponseTemplate::new(StatusCode::FOUND.as_u16()))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+            .mount(&mock_server)
+            .await;
+
+        let scraper = get_scraper(&mock_server, db.clone(), true);
+        let result = scraper
+            .get_latest_date()
+            .await
+            .expect("Failed to get latest date");
+
+        let last_comic = str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap();
+        assert_eq!(
+            result,
+            latest.unwrap_or(last_comic),
+            "Detected the wrong latest date"
+        );
+        let cached: Option<NaiveDate> = db
+            .get(&LATEST_DATE_KEY)
+            .await
+            .expect("Couldn't get cache entry");
+        assert_eq!(
+            cached,
+            Some(latest.unwrap_or(last_comic)),
+            "Cached the wrong latest date"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether a cached latest date is used without requests to the source.
+    async fn test_live_cached() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let expected = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        db.set(&LATEST_DATE_KEY, &expected, LATEST_DATE_REFRESH)
+            .await
+            .expect("Couldn't set mock cache entry");
+
+        let scraper = get_scraper(&mock_server, db, true);
+        let result = scraper
+            .get_latest_date()
+            .await
+            .expect("Failed to get latest date");
+
+        assert_eq!(result, expected, "Didn't use the cached latest date");
+        mock_server.verify().await;
+    }
+
+    #[test_case(Some((2024, 1, 1)); "detected")]
+    #[test_case(None; "not detected")]
+    #[actix_web::test]
+    /// Test whether the known latest date is the detected one, or else the source's last comic.
+    ///
+    /// # Arguments
+    /// * `detected_ymd` - A tuple containing the year, month and day of the detected date, if any
+    async fn test_known_latest_date(detected_ymd: Option<(i32, u32, u32)>) {
+        let db = MemoryPool::new(MEM_CACHE_SIZE);
+        let detected = detected_ymd.map(|(year, month, day)| {
+            NaiveDate::from_ymd_opt(year, month, day).expect("Invalid test parameters")
+        });
+        if let Some(detected) = &detected {
+            db.set(&LATEST_DATE_KEY, detected, LATEST_DATE_REFRESH)
+                .await
+                .expect("Couldn't set mock cache entry");
+        }
+
+        let result = get_known_latest_date(&db, &DilbertSource::default())
+            .await
+            .expect("Failed to get latest date");
+        assert_eq!(
+            result,
+            detected.unwrap_or(str_to_date(LAST_COMIC, SRC_DATE_FMT).unwrap()),
+            "Wrong latest date"
+        );
+    }
+}
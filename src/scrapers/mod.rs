@@ -0,0 +1,535 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Scrapers to get info about comics from the source
+mod comic;
+mod latest;
+mod source;
+
+use actix_web::web::Bytes;
+use awc::{error::PayloadError, http::StatusCode, Client, ClientResponse};
+use chrono::NaiveDate;
+use futures::Stream;
+use serde::Deserialize;
+use tracing::{debug, error, instrument, warn};
+
+use crate::constants::CDX_MAX_SNAPSHOTS;
+use crate::errors::{AppError, AppResult};
+use crate::retry::RetryPolicy;
+use crate::throttle::Throttle;
+
+pub use comic::*;
+pub use latest::*;
+pub use source::*;
+
+/// An archived version of a page, as listed by the CDX API
+#[derive(Debug, PartialEq, Eq)]
+struct Snapshot {
+    /// The timestamp of the capture
+    timestamp: String,
+    /// The HTTP status of the captured response, if known
+    status: Option<StatusCode>,
+}
+
+/// Parse the snapshots listed in a CDX API response, the most preferred first.
+///
+/// Each line has a snapshot's timestamp, optionally followed by its status. Captures of successful
+/// responses are preferred, followed by the rest, and the most recent are preferred within each.
+/// If there are no snapshots, then a single one with an empty timestamp is returned, which the
+/// Wayback Machine resolves to its latest capture.
+///
+/// # Arguments
+/// * `text` - The body of the CDX API response
+fn parse_snapshots(text: &str) -> Vec<Snapshot> {
+    let mut snapshots: Vec<_> = text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let timestamp = fields.next()?;
+            if !timestamp.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            // Statuses of some captures (such as revisits) are unknown, which the API gives as "-".
+            let status = fields
+                .next()
+                .and_then(|status| status.parse().ok())
+                .and_then(|status| StatusCode::from_u16(status).ok());
+            Some(Snapshot {
+                timestamp: timestamp.into(),
+                status,
+            })
+        })
+        .collect();
+
+    if snapshots.is_empty() {
+        snapshots.push(Snapshot {
+            timestamp: String::new(),
+            status: None,
+        });
+    }
+    // The API lists snapshots oldest first, and the sort is stable.
+    snapshots.reverse();
+    snapshots.sort_by_key(|snapshot| snapshot.status != Some(StatusCode::OK));
+    snapshots
+}
+
+/// A response of the Memento TimeTravel API
+#[derive(Deserialize)]
+struct MementoResponse {
+    /// The archived versions of the requested page
+    mementos: Mementos,
+}
+
+/// The archived versions of a page, as given by the Memento TimeTravel API
+#[derive(Deserialize)]
+struct Mementos {
+    /// The archived version closest to the requested time
+    closest: Memento,
+}
+
+/// An archived version of a page, as given by the Memento TimeTravel API
+#[derive(Deserialize)]
+struct Memento {
+    /// The URLs of the archived version, in different archives
+    uri: Vec<String>,
+}
+
+/// Look up the archived versions of a comic's page using the CDX API, the most preferred first.
+///
+/// Failures of the lookup are given as CDX API errors, so that the page can be located in other
+/// ways. Requests skipped during an outage of the source are given as-is.
+///
+/// # Arguments
+/// * `http_client` - The HTTP client for the request
+/// * `source` - The site of the comic
+/// * `retry` - The policy for retrying the request
+/// * `throttle` - The throttle for the request
+/// * `date` - The date of the comic
+async fn lookup_snapshots(
+    http_client: &Client,
+    source: &dyn ComicSource,
+    retry: &RetryPolicy,
+    throttle: &Throttle,
+    date: &NaiveDate,
+) -> AppResult<Vec<Snapshot>> {
+    let cdx_query = source.cdx_query(date);
+    let mut resp = match throttle
+        .send(retry, || http_client.get(&cdx_query).send())
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err @ AppError::SourceUnavailable(..)) => return Err(err),
+        Err(err) => return Err(AppError::Cdx(err.to_string())),
+    };
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(AppError::Cdx(format!(
+            "Unexpected response status: {status}"
+        )));
+    }
+
+    let bytes = resp
+        .body()
+        .await
+        .map_err(|err| AppError::Cdx(err.to_string()))?;
+    debug!("Got CDX API response body of length: {}B", bytes.len());
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => Ok(parse_snapshots(text)),
+        Err(_) => Err(AppError::Cdx("CDX API response is not UTF-8".into())),
+    }
+}
+
+/// Read the page of a comic from the response for an archived version of it.
+///
+/// If the source indicates that there's no comic for this date, then a "not found" error is
+/// returned.
+///
+/// # Arguments
+/// * `source` - The site of the comic
+/// * `date` - The date of the comic
+/// * `resp` - The response for the archived version of the page
+async fn read_comic_page<S>(
+    source: &dyn ComicSource,
+    date: &NaiveDate,
+    mut resp: ClientResponse<S>,
+) -> AppResult<Bytes>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+{
+    let status = resp.status();
+    if source.is_missing(status) {
+        return Err(AppError::NotFound(format!("Comic for {date} not found")));
+    }
+
+    match status {
+        StatusCode::OK => Ok(resp.body().await?),
+        _ => {
+            error!("Unexpected response status: {status}");
+            Err(AppError::Scrape(format!(
+                "Couldn't scrape comic: {:#?}",
+                resp.body().await?
+            )))
+        }
+    }
+}
+
+/// Fetch the page of a comic from the source.
+///
+/// The archived versions of the page are looked up using the CDX API, and tried in order of
+/// preference. If a capture of a successful response isn't replayed as one (i.e. it's not found or
+/// redirects), or if a capture is not found, then the next one is tried. If the source indicates
+/// that there's no comic for this date, then a "not found" error is returned. If the lookup fails,
+/// then a CDX API error is returned. All requests are throttled, retried on transient failures,
+/// and skipped during an outage of the source.
+///
+/// # Arguments
+/// * `http_client` - The HTTP client for the requests
+/// * `source` - The site of the comic
+/// * `retry` - The policy for retrying the requests
+/// * `throttle` - The throttle for the requests
+/// * `date` - The date of the comic
+///
+/// # Returns
+/// * The permalink to the comic
+/// * The raw HTML of the page
+#[instrument(skip(http_client, source, retry, throttle))]
+async fn fetch_comic_page(
+    http_client: &Client,
+    source: &dyn ComicSource,
+    retry: &RetryPolicy,
+    throttle: &Throttle,
+    date: &NaiveDate,
+) -> AppResult<(String, Bytes)> {
+    let snapshots = lookup_snapshots(http_client, source, retry, throttle, date).await?;
+    let mut last_err = None;
+    for snapshot in snapshots.iter().take(CDX_MAX_SNAPSHOTS) {
+        let permalink = source.comic_url(&snapshot.timestamp, date);
+        debug!("CDX API snapshot: {snapshot:?}, permalink: {permalink}");
+        let resp = throttle
+            .send(retry, || http_client.get(&permalink).send())
+            .await?;
+        let status = resp.status();
+
+        // These are problems with the capture, instead of signs that the comic is missing.
+        let unexpected = status == StatusCode::NOT_FOUND
+            || (snapshot.status == Some(StatusCode::OK) && status.is_redirection());
+        if unexpected {
+            warn!(
+                "Skipping snapshot {} with status: {status}",
+                snapshot.timestamp
+            );
+            last_err = Some(AppError::Scrape(format!(
+                "Snapshot {} of comic for {date} has status: {status}",
+                snapshot.timestamp
+            )));
+            continue;
+        }
+        let bytes = read_comic_page(source, date, resp).await?;
+        return Ok((permalink, bytes));
+    }
+
+    // There's always at least one snapshot, so there's always an error if none were usable.
+    Err(last_err.unwrap_or_else(|| AppError::Scrape(format!("No snapshots of comic for {date}"))))
+}
+
+/// Fetch the page of a comic from the source, locating it with the Memento TimeTravel API.
+///
+/// This is for when the CDX API fails. The archived version closest to the time in the query is
+/// fetched. A page that can't be located may still exist, so failures of locating it are given as
+/// Memento API errors, instead of "not found" errors. If the source indicates that there's no
+/// comic for this date, then a "not found" error is returned. All requests are throttled, retried
+/// on transient failures, and skipped during an outage of the source.
+///
+/// # Arguments
+/// * `http_client` - The HTTP client for the requests
+/// * `source` - The site of the comic
+/// * `retry` - The policy for retrying the requests
+/// * `throttle` - The throttle for the requests
+/// * `date` - The date of the comic
+///
+/// # Returns
+/// * The permalink to the comic
+/// * The raw HTML of the page
+#[instrument(skip(http_client, source, retry, throttle))]
+async fn fetch_memento_page(
+    http_client: &Client,
+    source: &dyn ComicSource,
+    retry: &RetryPolicy,
+    throttle: &Throttle,
+    date: &NaiveDate,
+) -> AppResult<(String, Bytes)> {
+    let memento_query = source.memento_query(date);
+    let mut resp = match throttle
+        .send(retry, || http_client.get(&memento_query).send())
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err @ AppError::SourceUnavailable(..)) => return Err(err),
+        Err(err) => return Err(AppError::Memento(err.to_string())),
+    };
+    let status = resp.status();
+    // The API responds with 404 Not Found if the page isn't in any archive.
+    if status == StatusCode::NOT_FOUND {
+        return Err(AppError::Memento(format!(
+            "No archived version of comic for {date}"
+        )));
+    } else if !status.is_success() {
+        return Err(AppError::Memento(format!(
+            "Unexpected response status: {status}"
+        )));
+    }
+
+    let bytes = resp
+        .body()
+        .await
+        .map_err(|err| AppError::Memento(err.to_string()))?;
+    let response: MementoResponse = serde_json::from_slice(&bytes)
+        .map_err(|err| AppError::Memento(format!("Invalid response: {err}")))?;
+    let Some(permalink) = response.mementos.closest.uri.into_iter().next() else {
+        return Err(AppError::Memento(format!(
+            "No URL to archived version of comic for {date}"
+        )));
+    };
+
+    debug!("Memento API permalink: {permalink}");
+    let resp = throttle
+        .send(retry, || http_client.get(&permalink).send())
+        .await?;
+    let bytes = read_comic_page(source, date, resp).await?;
+    Ok((permalink, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use awc::http::Method;
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::constants::{SRC_COMIC_PREFIX, SRC_DATE_FMT};
+
+    #[test_case("20000101 200\n20010101 302\n20020101 200\n", &[("20020101", Some(200)), ("20000101", Some(200)), ("20010101", Some(302))]; "prefers successful")]
+    #[test_case("20000101\n20010101\n", &[("20010101", None), ("20000101", None)]; "timestamps only")]
+    #[test_case("20000101 -\ninvalid 200\n", &[("20000101", None)]; "unknown and invalid")]
+    #[test_case("", &[("", None)]; "empty")]
+    /// Test whether snapshots in CDX API responses are parsed in order of preference.
+    ///
+    /// # Arguments
+    /// * `text` - The body of the CDX API response
+    /// * `expected` - The expected timestamps and statuses of the snapshots, in order
+    fn test_parse_snapshots(text: &str, expected: &[(&str, Option<u16>)]) {
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(timestamp, status)| Snapshot {
+                timestamp: timestamp.to_string(),
+                status: status.and_then(|status| StatusCode::from_u16(status).ok()),
+            })
+            .collect();
+        assert_eq!(parse_snapshots(text), expected, "Wrong snapshots");
+    }
+
+    #[test_case(StatusCode::FOUND, Some("20000101"); "redirected capture")]
+    #[test_case(StatusCode::NOT_FOUND, Some("20000101"); "missing capture")]
+    #[test_case(StatusCode::FOUND, None; "missing comic")]
+    #[actix_web::test]
+    /// Test whether the next snapshot is tried if a capture of a successful response fails.
+    ///
+    /// # Arguments
+    /// * `failure` - The status of the failing capture of a successful response
+    /// * `fallback` - The timestamp of another capture of a successful response, if any
+    async fn test_snapshot_fallback(failure: StatusCode, fallback: Option<&str>) {
+        let mock_server = MockServer::start().await;
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_path = format!("{SRC_COMIC_PREFIX}{}", date.format(SRC_DATE_FMT));
+
+        // The API lists snapshots oldest first. The capture of a redirect marks the comic as
+        // missing, which is tried last.
+        let mut cdx = String::from("19990101 302\n");
+        if let Some(fallback) = fallback {
+            cdx.push_str(&format!("{fallback} 200\n"));
+            Mock::given(method(Method::GET.as_str()))
+                .and(path(format!("/{fallback}/{comic_path}")))
+                .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("ok"))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+        cdx.push_str("20100101 200\n");
+        Mock::given(method(Method::GET.as_str()))
+            .and(path(format!("/20100101/{comic_path}")))
+            .respond_with(ResponseTemplate::new(failure.as_u16()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // "dilbert.com" uses 302 FOUND to inform that the comic is missing.
+        Mock::given(method(Method::GET.as_str()))
+            .and(path(format!("/19990101/{comic_path}")))
+            .respond_with(ResponseTemplate::new(StatusCode::FOUND.as_u16()))
+            .expect(if fallback.is_some() { 0 } else { 1 })
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(cdx))
+            .mount(&mock_server)
+            .await;
+
+        let source = DilbertSource::new(
+            format!("{}/{{}}", mock_server.uri()),
+            format!("{}/cdx", mock_server.uri()),
+            format!("{}/memento", mock_server.uri()),
+        );
+        let result = fetch_comic_page(
+            &Client::default(),
+            &source,
+            &RetryPolicy::default(),
+            &Throttle::default(),
+            &date,
+        )
+        .await;
+
+        match fallback {
+            Some(fallback) => {
+                let (permalink, bytes) = result.expect("Failed to fetch the fallback snapshot");
+                assert!(permalink.contains(fallback), "Used the wrong snapshot");
+                assert_eq!(bytes, "ok", "Got the wrong page");
+            }
+            None => assert!(
+                matches!(result, Err(AppError::NotFound(_))),
+                "Comic isn't missing: {result:?}"
+            ),
+        }
+        mock_server.verify().await;
+    }
+
+    #[actix_web::test]
+    /// Test whether failures of the CDX API are given as CDX API errors.
+    async fn test_cdx_failure() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/cdx"))
+            .respond_with(ResponseTemplate::new(StatusCode::BAD_REQUEST.as_u16()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let source = DilbertSource::new(
+            format!("{}/{{}}", mock_server.uri()),
+            format!("{}/cdx", mock_server.uri()),
+            format!("{}/memento", mock_server.uri()),
+        );
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let result = fetch_comic_page(
+            &Client::default(),
+            &source,
+            &RetryPolicy::default(),
+            &Throttle::default(),
+            &date,
+        )
+        .await;
+
+        assert!(
+            matches!(result, Err(AppError::Cdx(_))),
+            "Not a CDX API error: {result:?}"
+        );
+        mock_server.verify().await;
+    }
+
+    #[test_case(StatusCode::OK, StatusCode::OK; "found")]
+    #[test_case(StatusCode::NOT_FOUND, StatusCode::OK; "no memento")]
+    #[test_case(StatusCode::OK, StatusCode::FOUND; "missing comic")]
+    #[actix_web::test]
+    /// Test whether comic pages are located with the Memento TimeTravel API.
+    ///
+    /// # Arguments
+    /// * `memento_status` - The status of the Memento API response
+    /// * `page_status` - The status of the archived version of the page
+    async fn test_memento_page(memento_status: StatusCode, page_status: StatusCode) {
+        let mock_server = MockServer::start().await;
+        let permalink = format!("{}/20200101/page", mock_server.uri());
+        let body = format!(r#"{{"mementos": {{"closest": {{"uri": ["{permalink}"]}}}}}}"#);
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/memento"))
+            .respond_with(ResponseTemplate::new(memento_status.as_u16()).set_body_string(body))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/20200101/page"))
+            .respond_with(ResponseTemplate::new(page_status.as_u16()).set_body_string("ok"))
+            .expect(if memento_status.is_success() { 1 } else { 0 })
+            .mount(&mock_server)
+            .await;
+
+        let source = DilbertSource::new(
+            format!("{}/{{}}", mock_server.uri()),
+            format!("{}/cdx", mock_server.uri()),
+            format!("{}/memento", mock_server.uri()),
+        );
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let result = fetch_memento_page(
+            &Client::default(),
+            &source,
+            &RetryPolicy::default(),
+            &Throttle::default(),
+            &date,
+        )
+        .await;
+
+        if !memento_status.is_success() {
+            // A page that can't be located isn't necessarily missing.
+            assert!(
+                matches!(result, Err(AppError::Memento(_))),
+                "Not a Memento API error: {result:?}"
+            );
+        } else if page_status == StatusCode::FOUND {
+            assert!(
+                matches!(result, Err(AppError::NotFound(_))),
+                "Comic isn't missing: {result:?}"
+            );
+        } else {
+            let (url, bytes) = result.expect("Failed to fetch the page");
+            assert_eq!(url, permalink, "Wrong permalink");
+            assert_eq!(bytes, "ok", "Got the wrong page");
+        }
+        mock_server.verify().await;
+    }
+
+    #[actix_web::test]
+    /// Test whether invalid Memento TimeTravel API responses are given as Memento API errors.
+    async fn test_memento_invalid() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/memento"))
+            .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("{}"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let source = DilbertSource::new(
+            format!("{}/{{}}", mock_server.uri()),
+            format!("{}/cdx", mock_server.uri()),
+            format!("{}/memento", mock_server.uri()),
+        );
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let result = fetch_memento_page(
+            &Client::default(),
+            &source,
+            &RetryPolicy::default(),
+            &Throttle::default(),
+            &date,
+        )
+        .await;
+
+        assert!(
+            matches!(result, Err(AppError::Memento(_))),
+            "Not a Memento API error: {result:?}"
+        );
+        mock_server.verify().await;
+    }
+}
@@ -0,0 +1,429 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Sites from which comics are scraped
+use std::ops::RangeInclusive;
+
+use awc::http::StatusCode;
+use chrono::NaiveDate;
+use html_escape::decode_html_entities;
+use tl::{parse as parse_html, Bytes, Node, ParserOptions, VDom};
+use tracing::debug;
+
+use crate::constants::{
+    ARC_BASE_URL, CDX_URL, FIRST_COMIC, LAST_COMIC, MEMENTO_URL, SRC_BASE_URL, SRC_COMIC_PREFIX,
+    SRC_DATE_FMT,
+};
+use crate::datetime::str_to_date;
+use crate::errors::{AppError, AppResult};
+
+use super::{ComicData, ImageVariant};
+
+/// A site with a comic for each date, which is scraped through its archive on the Wayback Machine
+///
+/// This covers everything specific to a site, so that the scrapers work for any such site.
+pub trait ComicSource: Send + Sync {
+    /// Get the inclusive range of dates of the comics on the site.
+    fn date_range(&self) -> AppResult<RangeInclusive<NaiveDate>>;
+
+    /// Get the URL to the CDX API for looking up the archived versions of a comic's page.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    fn cdx_query(&self, date: &NaiveDate) -> String;
+
+    /// Get the URL to the Memento TimeTravel API for locating an archived version of a comic's
+    /// page.
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    fn memento_query(&self, date: &NaiveDate) -> String;
+
+    /// Get the URL to an archived version of a comic's page.
+    ///
+    /// # Arguments
+    /// * `timestamp` - The timestamp of the archived version, as given by the CDX API
+    /// * `date` - The date of the comic
+    fn comic_url(&self, timestamp: &str, date: &NaiveDate) -> String;
+
+    /// Check whether the response status for a comic's page means that the comic is missing.
+    ///
+    /// # Arguments
+    /// * `status` - The response status
+    fn is_missing(&self, status: StatusCode) -> bool;
+
+    /// Parse the data of a comic from its page.
+    ///
+    /// # Arguments
+    /// * `content` - The raw HTML of the comic's page
+    /// * `date` - The date of the comic
+    /// * `permalink` - The permalink to the comic's page
+    fn parse_comic(
+        &self,
+        content: &str,
+        date: &NaiveDate,
+        permalink: String,
+    ) -> AppResult<ComicData>;
+}
+
+/// The source for Dilbert comics from "dilbert.com"
+pub struct DilbertSource {
+    /// The base URL of the comic source
+    base_url: String,
+    /// The URL to the CDX API for looking up archived comics
+    cdx_url: String,
+    /// The URL to the Memento TimeTravel API for locating archived comics
+    memento_url: String,
+}
+
+impl DilbertSource {
+    /// Initialize the source for Dilbert comics.
+    ///
+    /// # Arguments
+    /// * `base_url` - The base URL of the comic source
+    /// * `cdx_url` - The URL to the CDX API for looking up archived comics
+    /// * `memento_url` - The URL to the Memento TimeTravel API for locating archived comics
+    pub fn new(base_url: String, cdx_url: String, memento_url: String) -> Self {
+        Self {
+            base_url,
+            cdx_url,
+            memento_url,
+        }
+    }
+
+    /// Get the URL to a comic's page on "dilbert.com".
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    fn source_url(date: &NaiveDate) -> String {
+        format!("{SRC_BASE_URL}{}", Self::comic_path(date))
+    }
+
+    /// Get the URL path of a comic's page on "dilbert.com".
+    ///
+    /// # Arguments
+    /// * `date` - The date of the comic
+    fn comic_path(date: &NaiveDate) -> String {
+        format!("{SRC_COMIC_PREFIX}{}", date.format(SRC_DATE_FMT))
+    }
+}
+
+impl Default for DilbertSource {
+    fn default() -> Self {
+        Self::new(ARC_BASE_URL.into(), CDX_URL.into(), MEMENTO_URL.into())
+    }
+}
+
+/// Get the transcript of a comic from its page, if the page has a non-empty one.
+///
+/// # Arguments
+/// * `dom` - The parsed HTML of the comic's page
+/// * `date` - The date of the comic
+fn get_transcript(dom: &VDom, date: &NaiveDate) -> Option<String> {
+    let parser = dom.parser();
+    // The transcript is the paragraph inside the element with this ID.
+    let container = dom
+        .get_element_by_id(format!("js-toggle-transcript-{}", date.format(SRC_DATE_FMT)).as_str())?
+        .get(parser)?
+        .as_tag()?;
+    let paragraph = container.query_selector(parser, "p")?.next()?.get(parser)?;
+    let transcript = decode_html_entities(&paragraph.inner_text(parser))
+        .trim()
+        .to_string();
+    (!transcript.is_empty()).then_some(transcript)
+}
+
+/// Get the tags of a comic from its page.
+///
+/// # Arguments
+/// * `dom` - The parsed HTML of the comic's page
+fn get_tags(dom: &VDom) -> Vec<String> {
+    let parser = dom.parser();
+    let mut tags = Vec::new();
+    // The tags are links inside the elements with the class "comic-tags".
+    for container in dom
+        .get_elements_by_class_name("comic-tags")
+        .filter_map(|handle| handle.get(parser)?.as_tag())
+    {
+        let Some(links) = container.query_selector(parser, "a") else {
+            continue;
+        };
+        for link in links.filter_map(|handle| handle.get(parser)) {
+            let text = decode_html_entities(&link.inner_text(parser)).into_owned();
+            let tag = text.trim().trim_start_matches('#').trim();
+            if !tag.is_empty() && !tags.iter().any(|other| other == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+    tags
+}
+
+/// Get the other resolutions of a comic's image from the "srcset" attribute of its element.
+///
+/// Candidates with a density descriptor (such as "2x") are scaled from the image's width, and
+/// those with the same width as the image itself are left out. The variants are sorted by width.
+///
+/// # Arguments
+/// * `srcset` - The value of the "srcset" attribute
+/// * `img_width` - The width of the image given by the "src" attribute
+fn get_img_variants(srcset: &str, img_width: i32) -> Vec<ImageVariant> {
+    let mut variants: Vec<_> = srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = decode_html_entities(parts.next()?).into_owned();
+            let width = match parts.next() {
+                Some(desc) if desc.ends_with('w') => desc.trim_end_matches('w').parse().ok()?,
+                Some(desc) if desc.ends_with('x') => {
+                    let density: f64 = desc.trim_end_matches('x').parse().ok()?;
+                    (f64::from(img_width) * density).round() as i32
+                }
+                Some(_) => return None,
+                None => img_width,
+            };
+            (width > 0 && width != img_width).then_some(ImageVariant { url, width })
+        })
+        .collect();
+    variants.sort_by_key(|variant| variant.width);
+    variants.dedup_by_key(|variant| variant.width);
+    variants
+}
+
+impl ComicSource for DilbertSource {
+    fn date_range(&self) -> AppResult<RangeInclusive<NaiveDate>> {
+        Ok(str_to_date(FIRST_COMIC, SRC_DATE_FMT)?..=str_to_date(LAST_COMIC, SRC_DATE_FMT)?)
+    }
+
+    fn cdx_query(&self, date: &NaiveDate) -> String {
+        self.cdx_url.replace("{}", &Self::source_url(date))
+    }
+
+    fn memento_query(&self, date: &NaiveDate) -> String {
+        self.memento_url.replace("{}", &Self::source_url(date))
+    }
+
+    fn comic_url(&self, timestamp: &str, date: &NaiveDate) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.replace("{}", timestamp),
+            Self::comic_path(date)
+        )
+    }
+
+    fn is_missing(&self, status: StatusCode) -> bool {
+        // "dilbert.com" redirects to the homepage if there's no comic for a date.
+        status == StatusCode::FOUND
+    }
+
+    fn parse_comic(
+        &self,
+        content: &str,
+        date: &NaiveDate,
+        permalink: String,
+    ) -> AppResult<ComicData> {
+        let dom = parse_html(content, ParserOptions::default())?;
+        let parser = dom.parser();
+        let get_first_node_by_class = |class| {
+            dom.get_elements_by_class_name(class)
+                .next()
+                .and_then(|handle| handle.get(parser))
+        };
+
+        // The title element is the only tag with the class "comic-title-name"
+        let title = if let Some(node) = get_first_node_by_class("comic-title-name") {
+            decode_html_entities(&node.inner_text(parser)).into_owned()
+        } else {
+            // Some comics don't have a title. This is mostly for older comics.
+            debug!("No title found for comic on: {date}");
+            String::new()
+        };
+
+        // The image element is the only tag with the class "img-comic"
+        let img_attrs =
+            if let Some(tag) = get_first_node_by_class("img-comic").and_then(Node::as_tag) {
+                tag.attributes()
+            } else {
+                return Err(AppError::Scrape(
+                    "Error in scraping the image's details".into(),
+                ));
+            };
+        let get_i32_img_attr = |attr| -> Option<i32> {
+            img_attrs
+                .get(attr)
+                .flatten()
+                .and_then(Bytes::try_as_utf8_str)
+                .and_then(|attr_str| attr_str.parse().ok())
+        };
+
+        // The image width is the "width" attribute of the image element
+        let img_width = if let Some(width) = get_i32_img_attr("width") {
+            width
+        } else {
+            return Err(AppError::Scrape(
+                "Error in scraping the image's width".into(),
+            ));
+        };
+
+        // The image height is the "height" attribute of the image element
+        let img_height = if let Some(height) = get_i32_img_attr("height") {
+            height
+        } else {
+            return Err(AppError::Scrape(
+                "Error in scraping the image's height".into(),
+            ));
+        };
+
+        // The image URL is the "src" attribute of the image element, or its "data-image" attribute
+        // if the image is lazy-loaded.
+        let get_str_img_attr = |attr| {
+            img_attrs
+                .get(attr)
+                .flatten()
+                .and_then(Bytes::try_as_utf8_str)
+                .filter(|attr_str| !attr_str.is_empty())
+        };
+        let img_url = if let Some(url) = get_str_img_attr("src").or(get_str_img_attr("data-image"))
+        {
+            String::from(url)
+        } else {
+            return Err(AppError::Scrape("Error in scraping the image's URL".into()));
+        };
+
+        // Other resolutions of the image are in the "srcset" attribute, if the source offers them.
+        let img_variants = get_str_img_attr("srcset")
+            .map(|srcset| get_img_variants(srcset, img_width))
+            .unwrap_or_default();
+
+        let transcript = get_transcript(&dom, date);
+        if transcript.is_none() {
+            debug!("No transcript found for comic on: {date}");
+        }
+
+        Ok(ComicData {
+            title,
+            img_url,
+            img_width,
+            img_height,
+            img_variants,
+            permalink,
+            transcript,
+            tags: get_tags(&dom),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case("<a>#Boss</a>, <a> #Wally </a>, <a>#Boss</a>", &["Boss", "Wally"]; "present")]
+    #[test_case("<a>#</a>", &[]; "empty")]
+    #[test_case("", &[]; "missing")]
+    /// Test whether the tags are scraped from the tag links in the comic's page.
+    ///
+    /// # Arguments
+    /// * `links` - The contents of the tags block
+    /// * `expected` - The expected tags
+    fn test_tags(links: &str, expected: &[&str]) {
+        let html = format!("<p class=\"comic-tags\"><span>Tags</span>{links}</p>");
+        let dom = parse_html(&html, ParserOptions::default()).expect("Couldn't parse test page");
+        assert_eq!(get_tags(&dom), expected, "Wrong tags");
+    }
+
+    #[test_case("<p>Dilbert: I&#39;m done.</p>", Some("Dilbert: I'm done."); "present")]
+    #[test_case("<p>  </p>", None; "empty")]
+    #[test_case("", None; "missing")]
+    /// Test whether the transcript is scraped from its block in the comic's page.
+    ///
+    /// # Arguments
+    /// * `block` - The contents of the transcript block
+    /// * `expected` - The expected transcript
+    fn test_transcript(block: &str, expected: Option<&str>) {
+        let html =
+            format!("<div id=\"js-toggle-transcript-2000-01-01\"><h4>Transcript</h4>{block}</div>");
+        let dom = parse_html(&html, ParserOptions::default()).expect("Couldn't parse test page");
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        assert_eq!(
+            get_transcript(&dom, &date).as_deref(),
+            expected,
+            "Wrong transcript"
+        );
+    }
+
+    #[test_case("small 450w, large 1800w", &[("small", 450), ("large", 1800)]; "widths")]
+    #[test_case("large 2x, small 0.5x", &[("small", 450), ("large", 1800)]; "densities")]
+    #[test_case("same, same 900w, same 1x", &[]; "same width")]
+    #[test_case("small 450w, other 450w", &[("small", 450)]; "duplicate width")]
+    #[test_case("bad 450h, , zero 0w", &[]; "invalid")]
+    /// Test whether the other resolutions of the image are scraped from its "srcset" attribute.
+    ///
+    /// # Arguments
+    /// * `srcset` - The value of the "srcset" attribute, for an image 900 pixels wide
+    /// * `expected` - The expected URLs and widths of the variants
+    fn test_img_variants(srcset: &str, expected: &[(&str, i32)]) {
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(url, width)| ImageVariant {
+                url: url.into(),
+                width,
+            })
+            .collect();
+        assert_eq!(get_img_variants(srcset, 900), expected, "Wrong variants");
+    }
+
+    #[test_case("src=\"image\"", "image"; "eager")]
+    #[test_case("src=\"\" data-image=\"image\"", "image"; "lazy")]
+    /// Test whether the image URL is scraped from lazy-loaded images too.
+    ///
+    /// # Arguments
+    /// * `attrs` - The attributes of the image element for its URL
+    /// * `expected` - The expected image URL
+    fn test_lazy_img_url(attrs: &str, expected: &str) {
+        let html = format!(
+            "<img class=\"img-comic\" width=\"900\" height=\"280\" {attrs} srcset=\"large 2x\" />"
+        );
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let comic_data = DilbertSource::default()
+            .parse_comic(&html, &date, String::new())
+            .expect("Couldn't parse test page");
+        assert_eq!(comic_data.img_url, expected, "Wrong image URL");
+        assert_eq!(
+            comic_data.img_variants,
+            [ImageVariant {
+                url: "large".into(),
+                width: 1800,
+            }],
+            "Wrong variants"
+        );
+    }
+
+    #[test]
+    /// Test whether the URLs for looking up and fetching archived Dilbert comics are correct.
+    fn test_dilbert_urls() {
+        let source = DilbertSource::new(
+            "https://archive.example/{}/dilbert".into(),
+            "https://cdx.example?url={}".into(),
+            "https://memento.example/{}".into(),
+        );
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        assert_eq!(
+            source.cdx_query(&date),
+            "https://cdx.example?url=https://dilbert.com/strip/2000-01-01",
+            "Wrong CDX API query"
+        );
+        assert_eq!(
+            source.memento_query(&date),
+            "https://memento.example/https://dilbert.com/strip/2000-01-01",
+            "Wrong Memento API query"
+        );
+        assert_eq!(
+            source.comic_url("20000101", &date),
+            "https://archive.example/20000101/dilbert/strip/2000-01-01",
+            "Wrong URL to the archived comic"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Coalescing of concurrent identical operations
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use futures::channel::oneshot;
+use tracing::{debug, warn};
+
+/// Coalescer for concurrent operations with the same key
+///
+/// Only one operation per key is in flight at a time. Every other concurrent request for that key
+/// waits for the in-flight operation and gets a copy of its result.
+pub struct SingleFlight<K, V> {
+    /// Waiters for each in-flight operation
+    in_flight: Mutex<HashMap<K, Vec<oneshot::Sender<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Guard that removes an in-flight operation when it's done or cancelled
+///
+/// If the operation is cancelled, then its waiters are dropped, which notifies them.
+struct InFlightGuard<'a, K: Eq + Hash, V> {
+    single_flight: &'a SingleFlight<K, V>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> InFlightGuard<'_, K, V> {
+    /// Remove the in-flight operation, and return its waiters.
+    fn take_waiters(&self) -> Vec<oneshot::Sender<V>> {
+        match self.single_flight.in_flight.lock() {
+            Ok(mut in_flight) => in_flight.remove(&self.key).unwrap_or_default(),
+            Err(err) => {
+                warn!("Lock for in-flight operations is poisoned: {err}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> Drop for InFlightGuard<'_, K, V> {
+    fn drop(&mut self) {
+        self.take_waiters();
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> SingleFlight<K, V> {
+    /// Run an operation, unless one with the same key is in flight.
+    ///
+    /// If one is in flight, then wait for its result instead. If the in-flight operation is
+    /// cancelled midway, then the given operation is run without coalescing.
+    ///
+    /// # Arguments
+    /// * `key` - The key identifying the operation
+    /// * `op` - The operation to run
+    /// * `share` - Function to copy the in-flight operation's result for each waiter
+    pub async fn run<F, Fut, S>(&self, key: K, op: F, share: S) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+        S: Fn(&V) -> V,
+    {
+        // The lock must not be held across any `.await`, so keep it in a separate scope.
+        let receiver = match self.in_flight.lock() {
+            Ok(mut in_flight) => {
+                if let Some(waiters) = in_flight.get_mut(&key) {
+                    let (sender, receiver) = oneshot::channel();
+                    waiters.push(sender);
+                    Some(receiver)
+                } else {
+                    in_flight.insert(key.clone(), Vec::new());
+                    None
+                }
+            }
+            Err(err) => {
+                warn!("Lock for in-flight operations is poisoned: {err}");
+                return op().await;
+            }
+        };
+
+        if let Some(receiver) = receiver {
+            debug!("Waiting for identical in-flight operation");
+            return match receiver.await {
+                Ok(result) => result,
+                Err(_) => {
+                    warn!("Identical in-flight operation was cancelled; running it again");
+                    op().await
+                }
+            };
+        }
+
+        let guard = InFlightGuard {
+            single_flight: self,
+            key,
+        };
+        let result = op().await;
+        for waiter in guard.take_waiters() {
+            // The waiter may have been cancelled, which is fine.
+            let _ = waiter.send(share(&result));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use actix_web::rt::time::sleep;
+    use futures::future::join_all;
+    use test_case::test_case;
+
+    /// Number of concurrent operations to run
+    const CONCURRENCY: usize = 5;
+
+    #[test_case(Ok(1); "success")]
+    #[test_case(Err("Manual error".into()); "failure")]
+    #[actix_web::test]
+    /// Test whether concurrent identical operations are run only once, and share the result.
+    ///
+    /// # Arguments
+    /// * `expected` - The result of the operation
+    async fn test_coalescing(expected: Result<u32, String>) {
+        let single_flight = SingleFlight::default();
+        let runs = Cell::new(0);
+
+        let results = join_all((0..CONCURRENCY).map(|_| {
+            single_flight.run(
+                "key",
+                || async {
+                    runs.set(runs.get() + 1);
+                    // Yield, so that the other operations get a chance to start.
+                    sleep(Duration::from_millis(10)).await;
+                    expected.clone()
+                },
+                Clone::clone,
+            )
+        }))
+        .await;
+
+        assert_eq!(runs.get(), 1, "Operation ran more than once");
+        assert!(
+            results.iter().all(|result| result == &expected),
+            "Not all requests got the same result"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether operations with different keys are not coalesced.
+    async fn test_different_keys() {
+        let single_flight = SingleFlight::default();
+        let runs = Cell::new(0);
+
+        let results = join_all((0..CONCURRENCY).map(|key| {
+            let runs = &runs;
+            single_flight.run(
+                key,
+                move || async move {
+                    runs.set(runs.get() + 1);
+                    sleep(Duration::from_millis(10)).await;
+                    key
+                },
+                Clone::clone,
+            )
+        }))
+        .await;
+
+        assert_eq!(
+            runs.get(),
+            CONCURRENCY,
+            "Different operations were coalesced"
+        );
+        assert_eq!(
+            results,
+            (0..CONCURRENCY).collect::<Vec<_>>(),
+            "Wrong results for different operations"
+        );
+    }
+}
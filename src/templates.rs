@@ -5,7 +5,20 @@
 //! Contains structs for HTML templates
 use askama::Template;
 
-use crate::scraper::ComicData;
+use crate::assets::asset_url;
+use crate::i18n::Strings;
+use crate::scrapers::ComicData;
+use crate::theme::Theme;
+
+/// Custom filters for the templates
+mod filters {
+    use super::*;
+
+    /// Get the URL of a static file, which is fingerprinted to be cached forever.
+    pub fn asset<T: std::fmt::Display>(name: T) -> askama::Result<String> {
+        Ok(asset_url(&name.to_string()))
+    }
+}
 
 /// The main template for a comic
 #[derive(Template, Debug)]
@@ -15,6 +28,8 @@ pub struct ComicTemplate<'a> {
     pub data: &'a ComicData,
     /// The date of the comic, formatted for display
     pub date_disp: &'a str,
+    /// The sequence number of the comic, starting from 1 for the first comic
+    pub number: Option<u32>,
 
     // All date formats should conform to the format given by `crate::constants::SRC_DATE_FMT`.
     /// The date of the comic
@@ -30,10 +45,218 @@ pub struct ComicTemplate<'a> {
     pub disable_left_nav: bool,
     /// Whether to disable navigation to next comics
     pub disable_right_nav: bool,
+    /// A notice to show above the comic, if any
+    pub notice: Option<&'a str>,
     /// Link to the original source comic
     pub permalink: &'a str,
+    /// The JSON-LD structured data of the comic, escaped for embedding in a `<script>` element
+    pub json_ld: &'a str,
+    /// The minified CSS to inline into the page instead of linking it, if any
+    pub inline_css: Option<&'a str>,
+    /// The CSP nonce for inline content in the page
+    pub nonce: &'a str,
+    /// The colour theme chosen by the client
+    pub theme: Theme,
+    /// Whether the client has marked the comic as a favorite
+    pub favorite: bool,
+    /// The code of the page's language
+    pub lang: &'a str,
+    /// The UI strings in the page's language
+    pub text: &'a Strings,
+    /// Link to the app where this code is deployed
+    pub app_url: &'a str,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+    /// Width (in pixels) of the comic's preview image
+    pub preview_width: u32,
+    /// Height (in pixels) of the comic's preview image
+    pub preview_height: u32,
+}
+
+/// A single comic in a page of multiple comics
+#[derive(Debug)]
+pub struct StripEntry {
+    /// The date of the comic, in the format given by `crate::constants::SRC_DATE_FMT`
+    pub date: String,
+    /// The date of the comic, formatted for display
+    pub date_disp: String,
+    /// The scraped comic data
+    pub data: ComicData,
+}
+
+/// A single day in a calendar of a month
+#[derive(Debug, PartialEq, Eq)]
+pub struct CalendarDay {
+    /// The day of the month
+    pub day: u32,
+    /// The date, in the format given by `crate::constants::SRC_DATE_FMT`
+    pub date: String,
+    /// Whether a comic can be available on this date
+    pub available: bool,
+}
+
+/// The template for a calendar of the comics in a month
+#[derive(Template, Debug)]
+#[template(path = "archive.html")]
+pub struct ArchiveTemplate<'a> {
+    /// The title of the page, which is the month and year
+    pub title: &'a str,
+    /// The year of the month
+    pub year: i32,
+    /// The weeks of the month, starting on Sundays, with None for days outside the month
+    pub weeks: &'a [[Option<CalendarDay>; 7]],
+    /// The URL path of the previous month after "/archive/", if it has comics
+    pub previous_month: Option<&'a str>,
+    /// The URL path of the next month after "/archive/", if it has comics
+    pub next_month: Option<&'a str>,
+    /// The URL path of the month's thumbnail grid after "/grid/"
+    pub grid_month: &'a str,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// A single comic in a grid of thumbnails
+#[derive(Debug, PartialEq, Eq)]
+pub struct GridEntry {
+    /// The date of the comic, in the format given by `crate::constants::SRC_DATE_FMT`
+    pub date: String,
+    /// The date of the comic, formatted for display
+    pub date_disp: String,
+}
+
+/// The template for a grid of thumbnails of the comics in a month
+#[derive(Template, Debug)]
+#[template(path = "grid.html")]
+pub struct GridTemplate<'a> {
+    /// The title of the page, which is the month and year
+    pub title: &'a str,
+    /// The comics in the month, in order
+    pub comics: &'a [GridEntry],
+    /// The URL path of the month's calendar after "/archive/"
+    pub archive_month: &'a str,
+    /// The URL path of the previous month after "/grid/", if it has comics
+    pub previous_month: Option<&'a str>,
+    /// The URL path of the next month after "/grid/", if it has comics
+    pub next_month: Option<&'a str>,
+    /// Width (in pixels) of the thumbnails
+    pub thumbnail_width: u32,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// A single period in an index of the archive
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// The URL path of the period's page after "/archive/"
+    pub path: String,
+    /// The period, formatted for display
+    pub label: String,
+}
+
+/// The template for an index of the years or months in the archive
+#[derive(Template, Debug)]
+#[template(path = "archive_index.html")]
+pub struct ArchiveIndexTemplate<'a> {
+    /// The title of the page
+    pub title: &'a str,
+    /// The periods in the index, in order
+    pub entries: &'a [ArchiveEntry],
+    /// Whether this is the index of the months in a year
+    pub is_year: bool,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// The template for a page of multiple comics
+#[derive(Template, Debug)]
+#[template(path = "strip.html")]
+pub struct StripTemplate<'a> {
+    /// The title of the page
+    pub title: &'a str,
+    /// The comics to show, in order
+    pub comics: &'a [StripEntry],
+    /// The message to show if there are no comics
+    pub empty_msg: &'a str,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// The template for the comics in a week
+#[derive(Template, Debug)]
+#[template(path = "week.html")]
+pub struct WeekTemplate<'a> {
+    /// The title of the page, which has the first day of the week
+    pub title: &'a str,
+    /// The comics in the week, in order
+    pub comics: &'a [StripEntry],
+    /// The first day of the previous week after "/week/", if it has comics
+    pub previous_week: Option<&'a str>,
+    /// The first day of the next week after "/week/", if it has comics
+    pub next_week: Option<&'a str>,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// A single comic in a list of comics
+#[derive(Debug)]
+pub struct ComicListEntry {
+    /// The date of the comic, in the format given by `crate::constants::SRC_DATE_FMT`
+    pub date: String,
+    /// The date of the comic, formatted for display
+    pub date_disp: String,
+    /// The title of the comic, which is empty if it has none or it's unknown
+    pub title: String,
+}
+
+/// The template for a list of comics, which links to their pages
+#[derive(Template, Debug)]
+#[template(path = "comic_list.html")]
+pub struct ComicListTemplate<'a> {
+    /// The title of the page
+    pub title: &'a str,
+    /// The comics to list, in order
+    pub comics: &'a [ComicListEntry],
+    /// The message to show if there are no comics
+    pub empty_msg: &'a str,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// The template for the text-only view of a comic, for screen readers and text browsers
+#[derive(Template, Debug)]
+#[template(path = "text.html")]
+pub struct TextTemplate<'a> {
+    /// The date of the comic
+    pub date: &'a str,
+    /// The date of the comic in the format used for displaying
+    pub date_disp: &'a str,
+    /// The title of the comic, which is empty if it has none
+    pub title: &'a str,
+    /// The transcript of the comic, if it has been scraped
+    pub transcript: Option<&'a str>,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// The template for an Atom feed of recent comics
+#[derive(Template, Debug)]
+#[template(path = "feed.xml")]
+pub struct FeedTemplate<'a> {
+    /// The date of the latest comic in the feed, in the format given by
+    /// `crate::constants::SRC_DATE_FMT`
+    pub updated: &'a str,
+    /// The comics to include, newest first
+    pub comics: &'a [StripEntry],
     /// Link to the app where this code is deployed
     pub app_url: &'a str,
+}
+
+/// The template for a 400 bad request page
+#[derive(Template, Debug)]
+#[template(path = "bad_request.html")]
+pub struct BadRequestTemplate<'a> {
+    /// The reason why the request is invalid
+    pub message: &'a str,
     /// Link to the repo where this code is hosted
     pub repo_url: &'a str,
 }
@@ -44,6 +267,18 @@ pub struct ComicTemplate<'a> {
 pub struct NotFoundTemplate<'a> {
     /// The date of the requested comic, if available
     pub date: Option<&'a str>,
+    /// The colour theme chosen by the client
+    pub theme: Theme,
+    /// Link to the repo where this code is hosted
+    pub repo_url: &'a str,
+}
+
+/// The template for a 429 too many requests page
+#[derive(Template, Debug)]
+#[template(path = "too_many_requests.html")]
+pub struct TooManyRequestsTemplate<'a> {
+    /// The time (in seconds) after which the client can retry
+    pub retry_after: u64,
     /// Link to the repo where this code is hosted
     pub repo_url: &'a str,
 }
@@ -54,6 +289,8 @@ pub struct NotFoundTemplate<'a> {
 pub struct ErrorTemplate<'a> {
     /// The error message of the interval server error
     pub error: &'a str,
+    /// The colour theme chosen by the client
+    pub theme: Theme,
     /// Link to the repo where this code is hosted
     pub repo_url: &'a str,
 }
@@ -0,0 +1,140 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Colour themes of pages, chosen by clients through a cookie
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    dev::Payload,
+    http::{header::HeaderValue, Uri},
+    FromRequest, HttpRequest,
+};
+use serde::Deserialize;
+
+use crate::constants::{THEME_COOKIE, THEME_COOKIE_MAX_AGE};
+
+/// The colour theme of a page
+///
+/// This is chosen on the server, so that pages are never shown in the wrong theme at first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Get the name of the theme, as stored in the cookie.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    /// Check whether this is the dark theme.
+    pub fn is_dark(self) -> bool {
+        self == Self::Dark
+    }
+
+    /// Get the theme that the toggle switches to from this theme.
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Light => Self::Dark,
+            Self::Dark => Self::Light,
+        }
+    }
+
+    /// Get the cookie that stores this theme as the client's choice.
+    pub fn cookie(self) -> Cookie<'static> {
+        Cookie::build(THEME_COOKIE, self.as_str())
+            .path("/")
+            .max_age(Duration::seconds(THEME_COOKIE_MAX_AGE))
+            .same_site(SameSite::Lax)
+            .http_only(true)
+            .finish()
+    }
+}
+
+impl FromRequest for Theme {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Get the theme chosen by the client, with unknown themes treated as the default.
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let theme = match req.cookie(THEME_COOKIE).as_ref().map(Cookie::value) {
+            Some("dark") => Self::Dark,
+            _ => Self::default(),
+        };
+        ready(Ok(theme))
+    }
+}
+
+/// Get the URL path to return to after choosing a theme.
+///
+/// This is the path of the page on which the theme was chosen, if it's known. Only the path is
+/// used, so that clients are never redirected to another site.
+///
+/// # Arguments
+/// * `referer` - The value of the request's "Referer" header, if any
+pub fn get_return_path(referer: Option<&HeaderValue>) -> String {
+    referer
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| referer.parse::<Uri>().ok())
+        .and_then(|uri| uri.path_and_query().map(|path| path.as_str().to_string()))
+        // Paths starting with "//" would be treated by clients as URLs to other sites.
+        .filter(|path| path.starts_with('/') && !path.starts_with("//"))
+        .unwrap_or_else(|| "/".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_web::test::TestRequest;
+    use test_case::test_case;
+
+    #[test_case(None, Theme::Light; "no cookie")]
+    #[test_case(Some("dark"), Theme::Dark; "dark")]
+    #[test_case(Some("light"), Theme::Light; "light")]
+    #[test_case(Some("unknown"), Theme::Light; "unknown theme")]
+    #[actix_web::test]
+    /// Test whether the theme is read from the cookie.
+    ///
+    /// # Arguments
+    /// * `cookie` - The value of the theme cookie, if any
+    /// * `expected` - The expected theme
+    async fn test_theme_from_cookie(cookie: Option<&str>, expected: Theme) {
+        let mut req = TestRequest::default();
+        if let Some(cookie) = cookie {
+            req = req.cookie(Cookie::new(THEME_COOKIE, cookie));
+        }
+        let theme = Theme::extract(&req.to_http_request())
+            .await
+            .expect("Theme extraction failed");
+        assert_eq!(theme, expected, "Wrong theme");
+    }
+
+    #[test_case(None, "/"; "no referer")]
+    #[test_case(Some("http://localhost/2000-01-01?a=b"), "/2000-01-01?a=b"; "absolute")]
+    #[test_case(Some("/2000-01-01"), "/2000-01-01"; "path")]
+    #[test_case(Some("http://localhost//example.com"), "/"; "other site")]
+    #[test_case(Some("not a URL"), "/"; "invalid")]
+    /// Test whether only paths are returned to after choosing a theme.
+    ///
+    /// # Arguments
+    /// * `referer` - The value of the "Referer" header, if any
+    /// * `expected` - The expected path to return to
+    fn test_return_path(referer: Option<&str>, expected: &str) {
+        let referer = referer.map(|referer| HeaderValue::from_str(referer).unwrap());
+        assert_eq!(
+            get_return_path(referer.as_ref()),
+            expected,
+            "Wrong return path"
+        );
+    }
+}
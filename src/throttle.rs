@@ -0,0 +1,345 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Throttling of requests to the comic source
+use std::future::Future;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time::sleep;
+use awc::{error::SendRequestError, http::StatusCode, ClientResponse};
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+use crate::circuit::CircuitBreaker;
+use crate::errors::{AppError, AppResult};
+use crate::retry::{retry_after, transient_failure, RetryPolicy};
+
+/// Throttle for requests to the comic source
+///
+/// This is shared by all workers, so that the limits apply to the whole app. Requests over the
+/// limits are queued until they're allowed. Requests while the comic source asks to back off, and
+/// requests during an outage of the comic source (if a circuit breaker is set), are failed
+/// immediately.
+#[derive(Default)]
+pub struct Throttle {
+    /// Permits for concurrent requests, if limited
+    permits: Option<Semaphore>,
+    /// Minimum interval between the starts of consecutive requests, if limited
+    interval: Option<Duration>,
+    /// The earliest time at which the next request can start
+    next_start: Mutex<Option<Instant>>,
+    /// Circuit breaker for outages of the comic source, if enabled
+    breaker: Option<CircuitBreaker>,
+    /// The time until which the comic source asked to back off, if it did
+    backoff_until: Mutex<Option<Instant>>,
+}
+
+/// Get how long the comic source asked to back off for, if it did.
+///
+/// This is the case for 429 Too Many Requests responses, and 503 Service Unavailable responses
+/// with a "Retry-After" header.
+///
+/// # Arguments
+/// * `result` - The result of sending the request
+fn requested_backoff<S>(result: &Result<ClientResponse<S>, SendRequestError>) -> Option<Duration> {
+    let resp = result.as_ref().ok()?;
+    let delay = retry_after(resp.headers());
+    match resp.status() {
+        StatusCode::TOO_MANY_REQUESTS => Some(delay.unwrap_or_default()),
+        StatusCode::SERVICE_UNAVAILABLE => delay,
+        _ => None,
+    }
+}
+
+impl Throttle {
+    /// Initialize a throttle.
+    ///
+    /// # Arguments
+    /// * `max_concurrent` - The maximum number of concurrent requests, if limited
+    /// * `per_minute` - The maximum number of requests per minute, if limited
+    pub fn new(max_concurrent: Option<NonZeroUsize>, per_minute: Option<NonZeroU32>) -> Self {
+        Self {
+            permits: max_concurrent.map(|max| Semaphore::new(max.get())),
+            interval: per_minute.map(|rate| Duration::from_secs(60) / rate.get()),
+            next_start: Mutex::new(None),
+            breaker: None,
+            backoff_until: Mutex::new(None),
+        }
+    }
+
+    /// Set a circuit breaker for outages of the comic source.
+    ///
+    /// # Arguments
+    /// * `breaker` - The circuit breaker
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Reserve the next start time for a request, and get how long to wait until then.
+    fn reserve_start(&self) -> Duration {
+        let Some(interval) = self.interval else {
+            return Duration::ZERO;
+        };
+        let mut next_start = match self.next_start.lock() {
+            Ok(next_start) => next_start,
+            Err(err) => {
+                // Better to send the request now than fail it.
+                warn!("Lock for the throttle is poisoned: {err}");
+                return Duration::ZERO;
+            }
+        };
+
+        let now = Instant::now();
+        let start = next_start.map_or(now, |next| next.max(now));
+        *next_start = Some(start + interval);
+        start - now
+    }
+
+    /// Lock the time until which to back off, recovering it if the lock is poisoned.
+    fn backoff_until(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.backoff_until.lock().unwrap_or_else(|err| {
+            // The time is always left consistent, so it's safe to keep using it.
+            warn!("Lock for the backoff is poisoned: {err}");
+            err.into_inner()
+        })
+    }
+
+    /// Run a request once the limits allow it.
+    ///
+    /// # Arguments
+    /// * `request` - The future that sends the request
+    pub async fn run<F: Future>(&self, request: F) -> F::Output {
+        // The semaphore is never closed, so acquiring a permit never fails.
+        let _permit = match &self.permits {
+            Some(permits) => permits.acquire().await.ok(),
+            None => None,
+        };
+
+        let wait = self.reserve_start();
+        if !wait.is_zero() {
+            debug!("Throttling request to the comic source for {wait:?}");
+            sleep(wait).await;
+        }
+        request.await
+    }
+
+    /// Send a request once the limits allow it, retrying it on transient failures.
+    ///
+    /// If the comic source asked to back off, or if the circuit breaker is open, then the request
+    /// isn't sent, and an error is returned immediately. Otherwise, the final outcome after retries
+    /// is recorded in the breaker. If the comic source asks to back off in the final response, then
+    /// later requests are skipped until the delay in its "Retry-After" header is over, and an error
+    /// is returned.
+    ///
+    /// # Arguments
+    /// * `retry` - The policy for retrying the request
+    /// * `send` - Function to send a fresh request for each attempt
+    pub async fn send<F, Fut, S>(
+        &self,
+        retry: &RetryPolicy,
+        mut send: F,
+    ) -> AppResult<ClientResponse<S>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<ClientResponse<S>, SendRequestError>>,
+    {
+        let now = Instant::now();
+        if let Some(until) = self.backoff_until().filter(|until| now < *until) {
+            return Err(AppError::SourceUnavailable(
+                "Comic source asked to back off; skipping request".into(),
+                until - now,
+            ));
+        }
+        if let Some(breaker) = &self.breaker {
+            if !breaker.allow() {
+                return Err(AppError::SourceUnavailable(
+                    "Comic source is unavailable; skipping request".into(),
+                    breaker.retry_after(),
+                ));
+            }
+        }
+
+        let result = retry.send(|| self.run(send())).await;
+        if let Some(breaker) = &self.breaker {
+            if transient_failure(&result).is_some() {
+                breaker.record_failure();
+            } else {
+                breaker.record_success();
+            }
+        }
+        if let Some(delay) = requested_backoff(&result) {
+            warn!("Comic source asked to back off for {delay:?}");
+            let until = Instant::now() + delay;
+            let mut backoff_until = self.backoff_until();
+            *backoff_until = Some(backoff_until.map_or(until, |prev| prev.max(until)));
+            return Err(AppError::SourceUnavailable(
+                format!("Comic source asked to back off for {delay:?}"),
+                delay,
+            ));
+        }
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::http::{header::RETRY_AFTER, Method};
+    use awc::Client;
+    use futures::future::join_all;
+    use test_case::test_case;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    /// Time (in milliseconds) for which each mock request runs
+    const REQUEST_TIME: u64 = 50;
+
+    #[actix_web::test]
+    /// Test whether no more than the maximum number of requests run concurrently.
+    async fn test_max_concurrent() {
+        let throttle = Throttle::new(NonZeroUsize::new(2), None);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        let request = || async {
+            let count = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(count, Ordering::SeqCst);
+            sleep(Duration::from_millis(REQUEST_TIME)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+        };
+        join_all((0..5).map(|_| throttle.run(request()))).await;
+
+        assert_eq!(
+            max_running.load(Ordering::SeqCst),
+            2,
+            "Wrong number of concurrent requests"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether requests are spaced out to stay under the rate limit.
+    async fn test_per_minute() {
+        // This allows one request every 100ms.
+        let throttle = Throttle::new(None, NonZeroU32::new(600));
+        let start = Instant::now();
+        let starts = join_all((0..3).map(|_| throttle.run(async { start.elapsed() }))).await;
+
+        assert!(
+            starts[0] < Duration::from_millis(100),
+            "First request was delayed: {starts:?}"
+        );
+        for (prev, next) in starts.iter().zip(&starts[1..]) {
+            assert!(
+                *next - *prev >= Duration::from_millis(90),
+                "Requests weren't spaced out: {starts:?}"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    /// Test whether requests aren't delayed without limits.
+    async fn test_unlimited() {
+        let throttle = Throttle::default();
+        let start = Instant::now();
+        join_all((0..5).map(|_| throttle.run(sleep(Duration::from_millis(REQUEST_TIME))))).await;
+        assert!(
+            start.elapsed() < Duration::from_millis(5 * REQUEST_TIME),
+            "Requests were throttled without limits"
+        );
+    }
+
+    #[actix_web::test]
+    /// Test whether requests are skipped once the circuit breaker opens.
+    async fn test_breaker() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(ResponseTemplate::new(
+                StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let breaker = CircuitBreaker::new(NonZeroU32::MIN, Duration::from_secs(60));
+        let throttle = Throttle::default().with_breaker(breaker);
+        let retry = RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let client = Client::default();
+        let url = format!("{}/page", mock_server.uri());
+
+        let resp = throttle
+            .send(&retry, || client.get(&url).send())
+            .await
+            .expect("Failed to send request");
+        assert_eq!(
+            resp.status(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Wrong response"
+        );
+        let result = throttle.send(&retry, || client.get(&url).send()).await;
+        let Err(AppError::SourceUnavailable(_, wait)) = result else {
+            panic!("Request wasn't skipped with an open breaker");
+        };
+        assert!(
+            !wait.is_zero() && wait <= Duration::from_secs(60),
+            "Wrong time to retry after: {wait:?}"
+        );
+        mock_server.verify().await;
+    }
+
+    #[test_case(StatusCode::TOO_MANY_REQUESTS, Some("1"), true; "too many requests")]
+    #[test_case(StatusCode::TOO_MANY_REQUESTS, None, false; "too many requests without delay")]
+    #[test_case(StatusCode::SERVICE_UNAVAILABLE, Some("1"), true; "unavailable")]
+    #[actix_web::test]
+    /// Test whether requests are skipped while the comic source asks to back off.
+    ///
+    /// # Arguments
+    /// * `status` - The status of the response asking to back off
+    /// * `delay` - The value of the "Retry-After" header, if any
+    /// * `skipped` - Whether the next request is expected to be skipped
+    async fn test_backoff(status: StatusCode, delay: Option<&str>, skipped: bool) {
+        let mock_server = MockServer::start().await;
+        let mut response = ResponseTemplate::new(status.as_u16());
+        if let Some(delay) = delay {
+            response = response.insert_header(RETRY_AFTER.as_str(), delay);
+        }
+        Mock::given(method(Method::GET.as_str()))
+            .and(path("/page"))
+            .respond_with(response)
+            .expect(if skipped { 1 } else { 2 })
+            .mount(&mock_server)
+            .await;
+
+        let throttle = Throttle::default();
+        let retry = RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let client = Client::default();
+        let url = format!("{}/page", mock_server.uri());
+
+        for _ in 0..2 {
+            let result = throttle.send(&retry, || client.get(&url).send()).await;
+            let Err(AppError::SourceUnavailable(_, wait)) = result else {
+                panic!("Request wasn't failed while backing off");
+            };
+            assert!(
+                wait <= Duration::from_secs(1),
+                "Wrong time to retry after: {wait:?}"
+            );
+        }
+        mock_server.verify().await;
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Downscaled thumbnails of comics, for showing many comics on one page
+use std::io::Cursor;
+
+use image::{imageops::FilterType, ImageFormat};
+use tracing::debug;
+
+use crate::constants::{THUMBNAIL_MAX_HEIGHT, THUMBNAIL_MIME_TYPE, THUMBNAIL_WIDTH};
+use crate::errors::AppResult;
+use crate::scrapers::ComicImage;
+
+/// Render the thumbnail of a comic's image, which fits within the thumbnail size.
+///
+/// The aspect ratio of the comic is kept, and images that already fit aren't upscaled. This is
+/// CPU-intensive, so it should be run on a blocking thread.
+///
+/// # Arguments
+/// * `image` - The comic's image
+pub fn render_thumbnail(image: &ComicImage) -> AppResult<ComicImage> {
+    let decoded = image::load_from_memory(&image.bytes)?;
+    if decoded.width() <= THUMBNAIL_WIDTH && decoded.height() <= THUMBNAIL_MAX_HEIGHT {
+        return Ok(image.clone());
+    }
+
+    let mut bytes = Vec::new();
+    decoded
+        .resize(THUMBNAIL_WIDTH, THUMBNAIL_MAX_HEIGHT, FilterType::Triangle)
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    debug!(
+        "Downscaled image of {} bytes to a thumbnail of {} bytes",
+        image.bytes.len(),
+        bytes.len()
+    );
+    Ok(ComicImage {
+        content_type: THUMBNAIL_MIME_TYPE.into(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgb, RgbImage};
+    use test_case::test_case;
+
+    #[test_case(900, 280, (THUMBNAIL_WIDTH, 93); "daily")]
+    #[test_case(900, 900, (THUMBNAIL_WIDTH, THUMBNAIL_MAX_HEIGHT); "square")]
+    #[test_case(200, 60, (200, 60); "already small")]
+    /// Test whether thumbnails fit within the thumbnail size, keeping the aspect ratio.
+    ///
+    /// # Arguments
+    /// * `width` - The width of the comic's image
+    /// * `height` - The height of the comic's image
+    /// * `expected` - The expected width and height of the thumbnail
+    fn test_render_thumbnail(width: u32, height: u32, expected: (u32, u32)) {
+        let mut bytes = Vec::new();
+        RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 0]))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .expect("Couldn't encode test image");
+        let image = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+
+        let thumbnail = render_thumbnail(&image).expect("Couldn't render thumbnail");
+        assert_eq!(
+            thumbnail.content_type, THUMBNAIL_MIME_TYPE,
+            "Wrong content type"
+        );
+        let decoded = image::load_from_memory(&thumbnail.bytes).expect("Invalid thumbnail");
+        assert_eq!(
+            (decoded.width(), decoded.height()),
+            expected,
+            "Wrong thumbnail size"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! TLS termination for the server
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::{crypto::ring::default_provider, ServerConfig};
+
+use crate::errors::TlsError;
+
+/// Load the TLS config for the server from PEM files.
+///
+/// # Arguments
+/// * `cert_path` - The path to the certificate chain, with the server's certificate first
+/// * `key_path` - The path to the private key for the server's certificate
+pub fn load_tls_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig, TlsError> {
+    let mut cert_reader = BufReader::new(File::open(cert_path)?);
+    let certs = rustls_pemfile::certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
+
+    let mut key_reader = BufReader::new(File::open(key_path)?);
+    let Some(key) = rustls_pemfile::private_key(&mut key_reader)? else {
+        return Err(TlsError::MissingKey);
+    };
+
+    Ok(
+        ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path to the test certificate
+    const CERT_PATH: &str = "testdata/tls/cert.pem";
+    /// Path to the test private key
+    const KEY_PATH: &str = "testdata/tls/key.pem";
+
+    #[test]
+    /// Test whether a valid certificate and key are loaded.
+    fn test_load() {
+        let result = load_tls_config(Path::new(CERT_PATH), Path::new(KEY_PATH));
+        assert!(result.is_ok(), "Couldn't load TLS config: {result:?}");
+    }
+
+    #[test]
+    /// Test whether a key file without a private key is rejected.
+    fn test_missing_key() {
+        let result = load_tls_config(Path::new(CERT_PATH), Path::new(CERT_PATH));
+        assert!(
+            matches!(result, Err(TlsError::MissingKey)),
+            "Key file without a key wasn't rejected: {result:?}"
+        );
+    }
+
+    #[test]
+    /// Test whether a certificate file without certificates is rejected.
+    fn test_missing_cert() {
+        let result = load_tls_config(Path::new(KEY_PATH), Path::new(KEY_PATH));
+        assert!(
+            matches!(result, Err(TlsError::Rustls(_))),
+            "Certificate file without certificates wasn't rejected: {result:?}"
+        );
+    }
+}
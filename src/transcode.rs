@@ -0,0 +1,107 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Transcoding of comic images into smaller formats
+use actix_web::http::header::{Accept, Quality};
+use image::codecs::webp::WebPEncoder;
+use tracing::debug;
+
+use crate::constants::WEBP_MIME_TYPE;
+use crate::errors::AppResult;
+use crate::scrapers::ComicImage;
+
+/// Check whether a client accepts WebP images.
+///
+/// Only an explicit WebP media type counts, since wildcards are also sent by clients that don't
+/// support WebP.
+///
+/// # Arguments
+/// * `accept` - The media types accepted by the client
+pub fn accepts_webp(accept: &Accept) -> bool {
+    accept
+        .iter()
+        .any(|item| item.quality > Quality::ZERO && item.item.essence_str() == WEBP_MIME_TYPE)
+}
+
+/// Transcode an image into a lossless WebP image.
+///
+/// This is CPU-intensive, so it should be run on a blocking thread.
+///
+/// # Arguments
+/// * `image` - The original image
+pub fn transcode_to_webp(image: &ComicImage) -> AppResult<ComicImage> {
+    let decoded = image::load_from_memory(&image.bytes)?;
+    let mut bytes = Vec::new();
+    decoded.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))?;
+    debug!(
+        "Transcoded image from {} bytes to {} bytes of WebP",
+        image.bytes.len(),
+        bytes.len()
+    );
+    Ok(ComicImage {
+        content_type: WEBP_MIME_TYPE.into(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use actix_web::http::header::{q, QualityItem};
+    use image::{ImageFormat, RgbImage};
+    use test_case::test_case;
+
+    #[test_case(&["image/webp"], true; "explicit")]
+    #[test_case(&["image/*", "*/*"], false; "wildcards")]
+    /// Test whether WebP is accepted only if explicitly requested.
+    ///
+    /// # Arguments
+    /// * `types` - The media types accepted by the client
+    /// * `expected` - Whether WebP is expected to be accepted
+    fn test_accepts_webp(types: &[&str], expected: bool) {
+        let accept = Accept(
+            types
+                .iter()
+                .map(|mime| QualityItem::max(mime.parse().unwrap()))
+                .collect(),
+        );
+        assert_eq!(accepts_webp(&accept), expected, "Wrong WebP acceptance");
+    }
+
+    #[test]
+    /// Test whether WebP is rejected with a quality of 0.
+    fn test_rejects_webp() {
+        let accept = Accept(vec![QualityItem::new(
+            WEBP_MIME_TYPE.parse().unwrap(),
+            q(0.0),
+        )]);
+        assert!(!accepts_webp(&accept), "WebP is accepted with quality 0");
+    }
+
+    #[test]
+    /// Test whether a PNG image is transcoded into an identical WebP image.
+    fn test_transcode_to_webp() {
+        let original = RgbImage::from_fn(16, 8, |x, y| image::Rgb([x as u8 * 16, y as u8 * 32, 0]));
+        let mut bytes = Vec::new();
+        original
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .expect("Couldn't encode test image");
+        let image = ComicImage {
+            content_type: "image/png".into(),
+            bytes,
+        };
+
+        let transcoded = transcode_to_webp(&image).expect("Couldn't transcode image");
+        assert_eq!(
+            transcoded.content_type, WEBP_MIME_TYPE,
+            "Wrong content type"
+        );
+        let decoded = image::load_from_memory_with_format(&transcoded.bytes, ImageFormat::WebP)
+            .expect("Transcoded image isn't WebP");
+        assert_eq!(decoded.to_rgb8(), original, "Transcoding isn't lossless");
+    }
+}
@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+// Navigate to the previous/next page with the arrow keys and with swipes, using the links marked
+// with the "data-nav" attribute. Without this script, these links still work as usual.
+
+// Minimum horizontal distance (in pixels) of a touch to count as a swipe
+const MIN_SWIPE = 50;
+
+// Follow the navigation link in the given direction ("prev" or "next"), unless it's disabled.
+function navigate(direction) {
+  const link = document.querySelector("a[data-nav=\"" + direction + "\"]");
+  if (link && !link.classList.contains("disabled")) {
+    window.location.href = link.getAttribute("href");
+  }
+}
+
+document.addEventListener("keyup", (ev) => {
+  // Don't hijack the arrow keys while typing, or when used in shortcuts.
+  if (ev.target.closest("input, textarea, select") || ev.altKey || ev.ctrlKey || ev.metaKey || ev.shiftKey) {
+    return;
+  }
+
+  switch (ev.code) {
+    case "ArrowLeft":
+      navigate("prev");
+      break;
+    case "ArrowRight":
+      navigate("next");
+      break;
+  }
+});
+
+var touchStart = null;
+
+document.addEventListener("touchstart", (ev) => {
+  // Multi-touch gestures (such as zooming into the comic) aren't swipes.
+  touchStart = ev.touches.length === 1 ? ev.touches[0] : null;
+}, { passive: true });
+
+document.addEventListener("touchend", (ev) => {
+  if (!touchStart || ev.changedTouches.length !== 1) {
+    return;
+  }
+  const dx = ev.changedTouches[0].clientX - touchStart.clientX;
+  const dy = ev.changedTouches[0].clientY - touchStart.clientY;
+  touchStart = null;
+
+  // Mostly vertical touches are scrolls, not swipes.
+  if (Math.abs(dx) >= MIN_SWIPE && Math.abs(dx) > 2 * Math.abs(dy)) {
+    // Swiping left brings in the next page from the right, like turning a page.
+    navigate(dx < 0 ? "next" : "prev");
+  }
+}, { passive: true });
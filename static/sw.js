@@ -0,0 +1,59 @@
+// SPDX-FileCopyrightText: 2022 Harish Rajagopal <harish.rajagopals@gmail.com>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+// Service worker that keeps recently viewed pages available offline.
+const CACHE_NAME = "dilbert-viewer-v1";
+// Maximum number of responses (such as comic pages and images) to keep offline
+const MAX_ENTRIES = 100;
+
+self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (ev) => {
+  // Remove caches from older versions of this worker.
+  ev.waitUntil(
+    caches.keys()
+      .then((names) => Promise.all(
+        names.filter((name) => name !== CACHE_NAME).map((name) => caches.delete(name))
+      ))
+      .then(() => self.clients.claim())
+  );
+});
+
+// Keep a response offline, evicting the oldest ones beyond the limit.
+async function keepOffline(request, response) {
+  const cache = await caches.open(CACHE_NAME);
+  // Re-insert the response, so that its key is the newest.
+  await cache.delete(request);
+  await cache.put(request, response);
+
+  const keys = await cache.keys();
+  for (const key of keys.slice(0, Math.max(keys.length - MAX_ENTRIES, 0))) {
+    await cache.delete(key);
+  }
+}
+
+// Fetch from the network first, so that pages are fresh, and fall back to the offline copy.
+async function fetchOrOffline(ev) {
+  try {
+    const response = await fetch(ev.request);
+    if (response.ok) {
+      ev.waitUntil(keepOffline(ev.request, response.clone()));
+    }
+    return response;
+  } catch (err) {
+    const cached = await caches.match(ev.request);
+    if (cached) {
+      return cached;
+    }
+    throw err;
+  }
+}
+
+self.addEventListener("fetch", (ev) => {
+  const url = new URL(ev.request.url);
+  // Let the browser handle everything else, such as the stylesheets from the CDN.
+  if (ev.request.method === "GET" && url.origin === self.location.origin) {
+    ev.respondWith(fetchOrOffline(ev));
+  }
+});
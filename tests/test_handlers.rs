@@ -2,18 +2,29 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use std::net::TcpStream;
+use std::num::NonZeroU32;
 use std::time::Duration;
 
-use actix_web::rt::spawn;
+use actix_web::{
+    dev::ServerHandle,
+    rt::{spawn, task::spawn_blocking},
+};
 use awc::{
     http::{
-        header::{CONTENT_TYPE, LOCATION},
+        header::{
+            ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+            AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+            COOKIE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, ORIGIN,
+            REFERER, RETRY_AFTER, SET_COOKIE,
+        },
         Method, StatusCode,
     },
     Client, ClientResponse,
 };
 use chrono::NaiveDate;
-use dilbert_viewer::run;
+use dilbert_viewer::{Config, Server};
+use futures::future::join_all;
 use portpicker::pick_unused_port;
 use test_case::test_case;
 use wiremock::{
@@ -25,6 +36,10 @@ use wiremock::{
 const HOST: &str = "localhost";
 /// Timeout (in seconds) for getting a response from the server
 const RESP_TIMEOUT: u64 = 5;
+/// Timeout (in seconds) for the server's graceful shutdown
+// This is short, since the HTTP client can keep idle connections open, which would otherwise delay
+// the shutdown of the server.
+const SHUTDOWN_TIMEOUT: u64 = 1;
 /// Date of the first ever Dilbert comic
 const FIRST_COMIC: &str = "1989-04-16";
 /// Date of the last available Dilbert comic
@@ -35,6 +50,12 @@ const SRC_DATE_FMT: &str = "%Y-%m-%d";
 const SCRAPING_TEST_CASE_PATH: &str = "testdata/scraping";
 /// Number of times to run the random comic test
 const RAND_TEST_ITER: usize = 10;
+/// Number of concurrent requests for the request coalescing test
+const COALESCING_REQUESTS: usize = 5;
+/// Delay (in milliseconds) of the mock server's response for the request coalescing test
+const COALESCING_DELAY: u64 = 500;
+/// Header with the ID of each request
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
 /// Get the HTTP client.
 fn get_http_client() -> Client {
@@ -45,6 +66,25 @@ fn get_http_client() -> Client {
         .finish()
 }
 
+/// Start the server in the background.
+///
+/// The server is accepting connections once this returns.
+///
+/// # Arguments
+/// * `config` - The configuration for the server
+async fn start_server(config: Config) -> ServerHandle {
+    let server = Server::builder()
+        .config(config)
+        .shutdown_timeout(SHUTDOWN_TIMEOUT)
+        .build()
+        .run()
+        .await
+        .expect("Couldn't start server");
+    let handle = server.handle();
+    spawn(server);
+    handle
+}
+
 /// Test if an HTTP response is a valid HTML page.
 ///
 /// # Arguments
@@ -92,13 +132,15 @@ async fn test_last_comic(html_file_stem: &str) {
         .await;
 
     // Start the server on a single thread.
-    let handle = spawn(run(
-        host.clone(),
-        None,
-        Some(mock_server.uri()),
-        Some(format!("{}/cdx", mock_server.uri())),
-        Some(1),
-    ));
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
 
     let client = get_http_client();
     let resp = client
@@ -108,7 +150,7 @@ async fn test_last_comic(html_file_stem: &str) {
         .expect("Failed to send request to server");
 
     // Close the server.
-    handle.abort();
+    handle.stop(true).await;
 
     assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK",);
     test_content_type(resp, "text/html").await;
@@ -157,13 +199,15 @@ async fn test_comic(year: i32, month: u32, day: u32) {
         .await;
 
     // Start the server on a single thread.
-    let handle = spawn(run(
-        host.clone(),
-        None,
-        Some(mock_server.uri()),
-        Some(format!("{}/cdx", mock_server.uri())),
-        Some(1),
-    ));
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
 
     let client = get_http_client();
     let resp = client
@@ -171,105 +215,1680 @@ async fn test_comic(year: i32, month: u32, day: u32) {
         .send()
         .await
         .expect("Failed to send request to server");
+    assert_eq!(resp.status(), expected_status, "Unexpected response status",);
+    if expected_status != StatusCode::OK {
+        handle.stop(true).await;
+        return;
+    }
+
+    // The first view is recorded in the client's history, so the page is private to the client.
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        Some("private, no-cache"),
+        "Page with the history cookie isn't private"
+    );
+    let cookie = resp
+        .headers()
+        .get(SET_COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|cookie| cookie.split(';').next())
+        .expect("Missing history cookie")
+        .to_string();
+    assert!(cookie.starts_with("history="), "Wrong cookie: {cookie}");
+
+    // Viewing the comic again doesn't change the history, so the page can be cached.
+    let resp = client
+        .get(format!("http://{host}/{date_str}"))
+        .insert_header((COOKIE, cookie))
+        .send()
+        .await
+        .expect("Failed to send request to server");
 
     // Close the server.
-    handle.abort();
+    handle.stop(true).await;
 
-    assert_eq!(resp.status(), expected_status, "Unexpected response status",);
-    if let StatusCode::OK = expected_status {
-        test_content_type(resp, "text/html").await;
-    }
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    assert!(
+        !resp.headers().contains_key(SET_COOKIE),
+        "Unchanged history was set again"
+    );
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        Some("public, max-age=3600, must-revalidate"),
+        "Wrong Cache-Control header"
+    );
+    test_content_type(resp, "text/html").await;
 }
 
+#[test_case("1", Some("/1989-04-16"); "first comic")]
+#[test_case("3913", Some("/2000-01-01"); "middle comic")]
+#[test_case("0", None; "zero")]
+#[test_case("12385", None; "after last comic")]
+#[test_case("first", None; "not a number")]
 #[actix_web::test]
-/// Test the random comic request.
-async fn test_random_comic() {
+/// Test whether numbered comic URLs are redirected to the comic's URL.
+///
+/// # Arguments
+/// * `number` - The sequence number in the URL
+/// * `expected` - The expected URL path of the comic, if it exists
+async fn test_numbered_comic(number: &str, expected: Option<&str>) {
     let port = pick_unused_port().expect("Couldn't find an available port");
     let host = format!("{HOST}:{port}");
 
     // Start the server on a single thread.
-    // The random comic generator shouldn't make any request to "dilbert.com", so make the URL
-    // empty.
-    let handle = spawn(run(
-        host.clone(),
-        None,
-        Some(String::new()),
-        Some(String::new()),
-        Some(1),
-    ));
+    // The redirection shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
 
     let client = get_http_client();
-    let first_comic = NaiveDate::parse_from_str(FIRST_COMIC, SRC_DATE_FMT).unwrap();
-    let last_comic = NaiveDate::parse_from_str(LAST_COMIC, SRC_DATE_FMT).unwrap();
+    let resp = client
+        .get(format!("http://{host}/n/{number}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
 
-    for _ in 0..RAND_TEST_ITER {
-        let resp = client
-            .get(format!("http://{host}/random"))
-            .send()
-            .await
-            .expect("Failed to send request to server");
+    // Close the server.
+    handle.stop(true).await;
 
+    let Some(expected) = expected else {
         assert_eq!(
             resp.status(),
-            StatusCode::TEMPORARY_REDIRECT,
-            "Response status is not a temporary redirect",
+            StatusCode::NOT_FOUND,
+            "Response status is not NOT FOUND"
         );
+        return;
+    };
+    assert_eq!(
+        resp.status(),
+        StatusCode::MOVED_PERMANENTLY,
+        "Response status is not a permanent redirect"
+    );
+    let location = resp
+        .headers()
+        .get(LOCATION)
+        .expect("Missing Location header")
+        .to_str()
+        .expect("Location header is not ASCII");
+    assert_eq!(location, expected, "Redirected to the wrong URL");
+}
 
-        // Check that the comic it redirects to is valid.
-        let location = resp
-            .headers()
-            .get(LOCATION)
-            .expect("Missing Location header")
-            .to_str()
-            .expect("Location header is not ASCII");
-        let random_date = NaiveDate::parse_from_str(&location[1..], SRC_DATE_FMT)
-            .expect("Redirected to invalid date");
-        assert!(
-            random_date >= first_comic && random_date <= last_comic,
-            "Redirected to invalid date"
-        );
+#[test_case("2000-1-1", "/2000-01-01"; "no leading zeros")]
+#[test_case("2000-01-1", "/2000-01-01"; "no leading zero in day")]
+#[test_case("02000-01-01", "/2000-01-01"; "extra leading zero in year")]
+#[test_case("20000101", "/2000-01-01"; "compact")]
+#[test_case("2000/01/01", "/2000-01-01"; "slashes")]
+#[test_case("2000/1/1", "/2000-01-01"; "slashes without leading zeros")]
+#[actix_web::test]
+/// Test whether non-canonical comic URLs are redirected to the canonical URL.
+///
+/// # Arguments
+/// * `date_str` - The non-canonical date in the URL
+/// * `expected` - The expected canonical URL path
+async fn test_canonical_redirect(date_str: &str, expected: &str) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The redirection shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .get(format!("http://{host}/{date_str}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(
+        resp.status(),
+        StatusCode::MOVED_PERMANENTLY,
+        "Response status is not a permanent redirect"
+    );
+    let location = resp
+        .headers()
+        .get(LOCATION)
+        .expect("Missing Location header")
+        .to_str()
+        .expect("Location header is not ASCII");
+    assert_eq!(location, expected, "Redirected to the wrong URL");
+}
+
+#[test_case("2000-01-01", "2000-01-01", StatusCode::OK; "single day")]
+#[test_case("2000-01-01", "2000-01-03", StatusCode::OK; "multiple days")]
+#[test_case("2000-01-03", "2000-01-01", StatusCode::BAD_REQUEST; "reversed")]
+#[test_case("2000-01-01", "2000-03-01", StatusCode::BAD_REQUEST; "over cap")]
+#[test_case("2000-01-01", "2000-13-01", StatusCode::BAD_REQUEST; "invalid date")]
+#[actix_web::test]
+/// Test a webpage for a range of comics.
+///
+/// # Arguments
+/// * `start` - The date of the first comic in the range
+/// * `end` - The date of the last comic in the range
+/// * `expected_status` - The expected HTTP status code
+async fn test_comic_range(start: &str, end: &str, expected_status: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Set up the mock server along with the HTML content. Only the first comic exists.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/2000-01-01.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/strip/2000-01-01"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    // "dilbert.com" uses 302 FOUND to inform that the comic is missing.
+    for missing in ["2000-01-02", "2000-01-03"] {
+        Mock::given(method(Method::GET.as_str()))
+            .and(path(format!("/strip/{missing}")))
+            .respond_with(ResponseTemplate::new(StatusCode::FOUND.as_u16()))
+            .mount(&mock_server)
+            .await;
     }
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .get(format!("http://{host}/{start}..{end}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
 
     // Close the server.
-    handle.abort();
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    test_content_type(resp, "text/html").await;
 }
 
-#[test_case("styles.css", StatusCode::OK, "text/css"; "css")]
-#[test_case("script.js", StatusCode::OK, "text/javascript"; "js")]
-#[test_case("robots.txt", StatusCode::OK, "text/plain"; "misc")]
-#[test_case("foo", StatusCode::NOT_FOUND, "text/html"; "non-existant")]
-#[test_case("//", StatusCode::NOT_FOUND, "text/html"; "existing directory")]
+#[test_case("https://example.com/2000-01-01", None, StatusCode::OK; "comic page")]
+#[test_case("https://example.com/2000-01-01", Some("xml"), StatusCode::NOT_IMPLEMENTED; "xml")]
+#[test_case("https://example.com/archive", None, StatusCode::NOT_FOUND; "not a comic page")]
+#[test_case("not a URL", None, StatusCode::NOT_FOUND; "invalid URL")]
 #[actix_web::test]
-/// Test the static file service.
+/// Test the oEmbed endpoint for comic pages.
 ///
 /// # Arguments
-/// * `path` - The URL path to the static file
-/// * `status_code` - The expected HTTP status code
-/// * `content_type` - The expected Content-Type header
-async fn test_static(path: &str, status_code: StatusCode, content_type: &str) {
+/// * `url` - The URL of the page to embed
+/// * `format` - The requested format of the response, if any
+/// * `expected_status` - The expected response status
+async fn test_oembed(url: &str, format: Option<&str>, expected_status: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let date_str = "2000-01-01";
+
+    // Set up the mock server along with the HTML content.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path(format!("/strip/{date_str}")))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    let mut query = vec![("url", url)];
+    if let Some(format) = format {
+        query.push(("format", format));
+    }
+    let client = get_http_client();
+    let resp = client
+        .get(format!("http://{host}/oembed"))
+        .query(&query)
+        .expect("Couldn't encode query")
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    if let StatusCode::OK = expected_status {
+        test_content_type(resp, "application/json").await;
+    }
+}
+
+#[test_case("2000-01-01", StatusCode::OK; "valid comic")]
+#[test_case("2000-00-00", StatusCode::NOT_FOUND; "invalid date")]
+#[actix_web::test]
+/// Test the text-only view of a comic.
+///
+/// # Arguments
+/// * `date_str` - The date of the comic
+/// * `expected_status` - The expected response status
+async fn test_comic_text(date_str: &str, expected_status: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Set up the mock server along with the HTML content.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/2000-01-01.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/strip/2000-01-01"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/{date_str}/text"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    if expected_status != StatusCode::OK {
+        return;
+    }
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        Some("public, max-age=3600, must-revalidate"),
+        "Wrong Cache-Control header"
+    );
+    let body = resp.body().await.expect("Couldn't read response body");
+    let html = std::str::from_utf8(&body).expect("Response body not UTF-8");
+    assert!(!html.contains("<img"), "Text view has an image");
+    assert!(
+        html.contains("href=/2000-01-01"),
+        "Missing link to the comic"
+    );
+}
+
+#[test_case(&[], None; "disabled")]
+#[test_case(&["https://example.com"], Some("https://example.com"); "allowed")]
+#[test_case(&["https://example.org"], None; "not allowed")]
+#[test_case(&["*"], Some("https://example.com"); "any origin")]
+#[actix_web::test]
+/// Test whether preflight requests to the API are allowed only from the configured origins.
+///
+/// # Arguments
+/// * `origins` - The origins allowed to make cross-origin requests
+/// * `expected` - The expected Access-Control-Allow-Origin header, if any
+async fn test_api_cors(origins: &[&str], expected: Option<&str>) {
     let port = pick_unused_port().expect("Couldn't find an available port");
     let host = format!("{HOST}:{port}");
 
     // Start the server on a single thread.
-    // The static file service shouldn't make any request to "dilbert.com", so make the URL empty.
-    let handle = spawn(run(
-        host.clone(),
-        None,
-        Some(String::new()),
-        Some(String::new()),
-        Some(1),
-    ));
+    // Preflight requests don't reach the handlers, so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        cors_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+        ..Default::default()
+    })
+    .await;
 
     let client = get_http_client();
     let resp = client
-        .get(format!("http://{host}/{path}"))
+        .request(
+            Method::OPTIONS,
+            format!("http://{host}/api/v1/comic/2000-01-01"),
+        )
+        .insert_header((ORIGIN, "https://example.com"))
+        .insert_header((ACCESS_CONTROL_REQUEST_METHOD, "GET"))
         .send()
         .await
         .expect("Failed to send request to server");
 
     // Close the server.
-    handle.abort();
+    handle.stop(true).await;
 
-    assert_eq!(resp.status(), status_code, "Unexpected response status",);
-    test_content_type(resp, content_type).await;
+    assert_eq!(
+        resp.headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .and_then(|value| value.to_str().ok()),
+        expected,
+        "Wrong allowed origin"
+    );
+}
+
+#[test_case(Method::GET, "query=%7B%20randomComic%20%7B%20date%20%7D%20%7D", "", StatusCode::OK; "GET")]
+#[test_case(Method::POST, "", r#"{"query": "{ randomComic { date } }"}"#, StatusCode::OK; "POST")]
+#[test_case(Method::POST, "", "not JSON", StatusCode::BAD_REQUEST; "invalid")]
+#[actix_web::test]
+/// Test whether GraphQL queries are accepted in both GET and POST requests.
+///
+/// # Arguments
+/// * `method` - The method of the request
+/// * `query` - The query string of the request
+/// * `body` - The body of the request
+/// * `expected_status` - The expected status of the response
+async fn test_graphql(method: Method, query: &str, body: &str, expected_status: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // Failing to get the random comic is still a valid GraphQL response, so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        retry_attempts: 1,
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .request(method, format!("http://{host}/graphql?{query}"))
+        .insert_header((CONTENT_TYPE, "application/json"))
+        .send_body(body.to_string())
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected_status, "Unexpected response status");
+    if expected_status == StatusCode::OK {
+        test_content_type(resp, "application/json").await;
+    }
+}
+
+#[actix_web::test]
+/// Test the Atom feed of recent comics.
+async fn test_comic_feed() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Set up the mock server along with the HTML content. Only the first comic exists.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/2000-01-01.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/strip/2000-01-01"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    // "dilbert.com" uses 302 FOUND to inform that the comic is missing.
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/strip/2000-01-02"))
+        .respond_with(ResponseTemplate::new(StatusCode::FOUND.as_u16()))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        feed_len: 2,
+        feed_end: NaiveDate::from_ymd_opt(2000, 1, 2),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/feed.xml"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .expect("Missing Content-Type header")
+        .to_str()
+        .expect("Content-Type header is not ASCII");
+    assert!(
+        content_type.starts_with("application/atom+xml"),
+        "Content type is not Atom"
+    );
+
+    let feed = std::str::from_utf8(&body).expect("Feed is not UTF-8");
+    assert!(
+        feed.starts_with("<?xml"),
+        "Feed doesn't start with XML declaration"
+    );
+    assert_eq!(
+        feed.matches("<entry>").count(),
+        1,
+        "Feed doesn't have only the available comic"
+    );
+    assert!(
+        feed.contains("/2000-01-01</id>"),
+        "Feed doesn't have the available comic"
+    );
+}
+
+#[actix_web::test]
+/// Test whether concurrent requests for the same comic result in a single upstream scrape.
+async fn test_request_coalescing() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let date_str = "2000-01-01";
+
+    // Set up the mock server to respond slowly, so that all requests arrive during the scrape.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path(format!("/strip/{date_str}")))
+        .respond_with(
+            ResponseTemplate::new(StatusCode::OK.as_u16())
+                .set_body_string(html)
+                .set_delay(Duration::from_millis(COALESCING_DELAY)),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread, without a cache.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let responses = join_all(
+        (0..COALESCING_REQUESTS).map(|_| client.get(format!("http://{host}/{date_str}")).send()),
+    )
+    .await;
+
+    // Close the server.
+    handle.stop(true).await;
+
+    for resp in responses {
+        let resp = resp.expect("Failed to send request to server");
+        assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    }
+    mock_server.verify().await;
+}
+
+#[actix_web::test]
+/// Test whether the server started with the builder API serves requests and stops gracefully.
+async fn test_graceful_shutdown() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let date_str = "2000-01-01";
+
+    // Set up the mock server along with the HTML content.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path(format!("/strip/{date_str}")))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let server = Server::builder()
+        .host(HOST)
+        .port(port)
+        .workers(1)
+        .source(mock_server.uri())
+        .cdx(format!("{}/cdx", mock_server.uri()))
+        .shutdown_timeout(SHUTDOWN_TIMEOUT)
+        .build()
+        .run()
+        .await
+        .expect("Couldn't start server");
+    let handle = server.handle();
+    let server_task = spawn(server);
+
+    let resp = get_http_client()
+        .get(format!("http://{host}/{date_str}"))
+        .send()
+        .await
+        .expect("Failed to get response from server");
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+
+    handle.stop(true).await;
+    server_task
+        .await
+        .expect("Server task panicked")
+        .expect("Server failed while shutting down");
+    assert!(
+        TcpStream::connect(&host).is_err(),
+        "Server still accepts connections after shutdown"
+    );
+}
+
+#[actix_web::test]
+/// Test whether the CSP nonce in the header matches the one in the inlined CSS.
+async fn test_csp_nonce() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let date_str = "2000-01-01";
+
+    // Set up the mock server along with the HTML content.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path(format!("/strip/{date_str}")))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread, with the CSS inlined.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        inline_css: true,
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/{date_str}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    let policy = resp
+        .headers()
+        .get(CONTENT_SECURITY_POLICY)
+        .expect("Missing Content-Security-Policy header")
+        .to_str()
+        .expect("Content-Security-Policy header is not ASCII");
+    let nonce = policy
+        .split(['\'', ' ', ';'])
+        .find_map(|source| source.strip_prefix("nonce-"))
+        .expect("No nonce in the Content-Security-Policy header");
+
+    let body = std::str::from_utf8(&body).expect("Response body is not UTF-8");
+    assert!(
+        body.contains(&format!("<style nonce={nonce}>")),
+        "CSP nonce doesn't match the nonce of the inlined CSS"
+    );
+}
+
+#[test_case("identity"; "uncompressed")]
+#[test_case("gzip"; "compressed")]
+#[actix_web::test]
+/// Test whether comic pages have weak ETags, which are honoured across encodings.
+///
+/// # Arguments
+/// * `encoding` - The encoding accepted by the client for the conditional request
+async fn test_comic_etag(encoding: &str) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let date_str = "2000-01-01";
+
+    // Set up the mock server along with the HTML content.
+    let mock_server = MockServer::start().await;
+    let html = tokio::fs::read_to_string(format!("{SCRAPING_TEST_CASE_PATH}/{date_str}.html"))
+        .await
+        .expect("Couldn't get test page for scraping");
+    Mock::given(method(Method::GET.as_str()))
+        .and(path(format!("/strip/{date_str}")))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    // The first request uses the other encoding, so that the ETag must hold across encodings.
+    let other_encoding = if encoding == "gzip" {
+        "identity"
+    } else {
+        "gzip"
+    };
+    let client = get_http_client();
+    let url = format!("http://{host}/{date_str}");
+    let resp = client
+        .get(&url)
+        .insert_header((ACCEPT_ENCODING, other_encoding))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .expect("Missing ETag header")
+        .clone();
+    assert!(
+        etag.as_bytes().starts_with(b"W/"),
+        "ETag isn't weak: {etag:?}"
+    );
+
+    let resp = client
+        .get(&url)
+        .insert_header((ACCEPT_ENCODING, encoding))
+        .insert_header((IF_NONE_MATCH, etag.clone()))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(
+        resp.status(),
+        StatusCode::NOT_MODIFIED,
+        "Response status is not Not Modified"
+    );
+    assert_eq!(resp.headers().get(ETAG), Some(&etag), "ETag changed");
+    assert!(
+        resp.headers().get(CONTENT_SECURITY_POLICY).is_none(),
+        "Not Modified response has a fresh CSP nonce"
+    );
+}
+
+#[test_case(false; "random")]
+#[test_case(true; "daily")]
+#[actix_web::test]
+/// Test the random comic request.
+///
+/// # Arguments
+/// * `daily` - Whether to request the "comic of the day"
+async fn test_random_comic(daily: bool) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The random comic generator shouldn't make any request to "dilbert.com", so make the URL
+    // empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let first_comic = NaiveDate::parse_from_str(FIRST_COMIC, SRC_DATE_FMT).unwrap();
+    let last_comic = NaiveDate::parse_from_str(LAST_COMIC, SRC_DATE_FMT).unwrap();
+    let mut daily_date = None;
+
+    for _ in 0..RAND_TEST_ITER {
+        let resp = client
+            .get(format!("http://{host}/random?daily={daily}"))
+            .send()
+            .await
+            .expect("Failed to send request to server");
+
+        assert_eq!(
+            resp.status(),
+            StatusCode::TEMPORARY_REDIRECT,
+            "Response status is not a temporary redirect",
+        );
+        assert_eq!(
+            resp.headers()
+                .get(CACHE_CONTROL)
+                .map(|value| value.as_bytes()),
+            Some(b"no-store".as_slice()),
+            "Random comic is cacheable"
+        );
+
+        // Check that the comic it redirects to is valid.
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .expect("Missing Location header")
+            .to_str()
+            .expect("Location header is not ASCII");
+        let random_date = NaiveDate::parse_from_str(&location[1..], SRC_DATE_FMT)
+            .expect("Redirected to invalid date");
+        assert!(
+            random_date >= first_comic && random_date <= last_comic,
+            "Redirected to invalid date"
+        );
+        if daily {
+            assert_eq!(
+                *daily_date.get_or_insert(random_date),
+                random_date,
+                "Comic of the day changed"
+            );
+        }
+    }
+
+    // Close the server.
+    handle.stop(true).await;
+}
+
+#[actix_web::test]
+/// Test choosing the colour theme, and whether pages are then served in it.
+async fn test_set_theme() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // Neither request needs "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .post(format!("http://{host}/theme"))
+        .insert_header((REFERER, format!("http://{host}/2000-01-01")))
+        .send_form(&[("theme", "dark")])
+        .await
+        .expect("Failed to send request to server");
+    assert_eq!(
+        resp.status(),
+        StatusCode::SEE_OTHER,
+        "Response status is not a redirect"
+    );
+    assert_eq!(
+        resp.headers().get(LOCATION).map(|value| value.as_bytes()),
+        Some(&b"/2000-01-01"[..]),
+        "Not redirected back to the page"
+    );
+    let cookie = resp
+        .headers()
+        .get(SET_COOKIE)
+        .expect("Missing Set-Cookie header")
+        .to_str()
+        .expect("Set-Cookie header is not ASCII")
+        .to_string();
+    assert!(cookie.starts_with("theme=dark;"), "Wrong cookie: {cookie}");
+
+    let mut resp = client
+        .get(format!("http://{host}/foo"))
+        .insert_header((COOKIE, "theme=dark"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(
+        resp.status(),
+        StatusCode::NOT_FOUND,
+        "Response status is not Not Found"
+    );
+    let body = std::str::from_utf8(&body).expect("Response body is not UTF-8");
+    assert!(body.contains("/dark."), "Page isn't in the dark theme");
+}
+
+#[actix_web::test]
+/// Test marking a comic as a favorite, and whether the favorites page is private.
+async fn test_favorites() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // Neither request needs "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .post(format!("http://{host}/favorite/2000-01-01"))
+        .insert_header((REFERER, format!("http://{host}/2000-01-01")))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    assert_eq!(
+        resp.status(),
+        StatusCode::SEE_OTHER,
+        "Response status is not a redirect"
+    );
+    assert_eq!(
+        resp.headers().get(LOCATION).map(|value| value.as_bytes()),
+        Some(&b"/2000-01-01"[..]),
+        "Not redirected back to the page"
+    );
+    let cookie = resp
+        .headers()
+        .get(SET_COOKIE)
+        .expect("Missing Set-Cookie header")
+        .to_str()
+        .expect("Set-Cookie header is not ASCII")
+        .to_string();
+    // The signature is prepended to the value.
+    let value = cookie
+        .strip_prefix("favorites=")
+        .and_then(|cookie| cookie.split(';').next())
+        .unwrap_or_else(|| panic!("Wrong cookie: {cookie}"));
+    assert!(value.ends_with("20000101"), "Wrong favorites: {value}");
+
+    // A tampered cookie is ignored, so no comics need to be scraped.
+    let mut resp = client
+        .get(format!("http://{host}/favorites"))
+        .insert_header((COOKIE, "favorites=20000101"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response is not status OK");
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        Some("private, no-cache"),
+        "Favorites page isn't private"
+    );
+    let body = std::str::from_utf8(&body).expect("Response body is not UTF-8");
+    assert!(
+        body.contains("Mark comics as favorites"),
+        "Tampered favorites were shown"
+    );
+}
+
+#[test_case("styles.css", StatusCode::OK, "text/css", Some("public, max-age=3600"); "css")]
+#[test_case("script.js", StatusCode::OK, "text/javascript", Some("public, max-age=3600"); "js")]
+#[test_case("nav.js", StatusCode::OK, "text/javascript", Some("public, max-age=3600"); "navigation js")]
+#[test_case("icon.svg", StatusCode::OK, "image/svg+xml", Some("public, max-age=3600"); "file")]
+#[test_case("styles.0123456789.css", StatusCode::OK, "text/css", Some("public, max-age=3600"); "outdated css fingerprint")]
+#[test_case("script.0123456789.js", StatusCode::OK, "text/javascript", Some("public, max-age=3600"); "outdated js fingerprint")]
+#[test_case("icon.0123456789.svg", StatusCode::OK, "image/svg+xml", Some("public, max-age=3600"); "outdated file fingerprint")]
+#[test_case("missing.0123456789.svg", StatusCode::NOT_FOUND, "text/html", None; "missing fingerprinted file")]
+#[test_case("robots.txt", StatusCode::OK, "text/plain", None; "misc")]
+#[test_case("foo", StatusCode::NOT_FOUND, "text/html", None; "non-existant")]
+#[test_case("//", StatusCode::NOT_FOUND, "text/html", None; "existing directory")]
+#[actix_web::test]
+/// Test the static file service.
+///
+/// # Arguments
+/// * `path` - The URL path to the static file
+/// * `status_code` - The expected HTTP status code
+/// * `content_type` - The expected Content-Type header
+/// * `cache_control` - The expected Cache-Control header, if any
+async fn test_static(
+    path: &str,
+    status_code: StatusCode,
+    content_type: &str,
+    cache_control: Option<&str>,
+) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The static file service shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .get(format!("http://{host}/{path}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), status_code, "Unexpected response status",);
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        cache_control,
+        "Wrong Cache-Control header"
+    );
+    test_content_type(resp, content_type).await;
+}
+
+#[actix_web::test]
+/// Test whether pages link to fingerprinted static files, which are cached forever.
+async fn test_fingerprinted_static() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The static file service shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/foo"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+    let body = std::str::from_utf8(&body).expect("Response body is not UTF-8");
+    // The minified HTML has unquoted attributes.
+    let url = body
+        .split_once("href=/styles.")
+        .and_then(|(_, rest)| rest.split_once(['>', ' ']))
+        .map(|(hash, _)| format!("/styles.{hash}"))
+        .expect("Stylesheet isn't linked");
+
+    let resp = client
+        .get(format!("http://{host}{url}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_ne!(url, "/styles.css", "Stylesheet URL isn't fingerprinted");
+    assert_eq!(resp.status(), StatusCode::OK, "Unexpected response status");
+    assert_eq!(
+        resp.headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok()),
+        Some("public, max-age=31536000, immutable"),
+        "Wrong Cache-Control header"
+    );
+}
+
+#[test_case(None, &["disallow: /random", "disallow: /api/"]; "default")]
+#[test_case(Some(vec!["/foo".into()]), &["disallow: /foo"]; "custom")]
+#[test_case(Some(vec![]), &["disallow:"]; "allow all")]
+#[actix_web::test]
+/// Test whether `robots.txt` has the right disallow rules.
+///
+/// # Arguments
+/// * `disallow` - The URL path prefixes to disallow, if not the default ones
+/// * `expected` - The expected disallow rules
+async fn test_robots_txt(disallow: Option<Vec<String>>, expected: &[&str]) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    let mut config = Config::default();
+    if let Some(disallow) = disallow {
+        config.robots_disallow = disallow;
+    }
+
+    // Start the server on a single thread.
+    // This shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..config
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/robots.txt"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    let body = std::str::from_utf8(&body).expect("robots.txt is not UTF-8");
+    let rules: Vec<_> = body
+        .lines()
+        .filter(|line| line.starts_with("disallow:"))
+        .collect();
+    assert_eq!(rules, expected, "Wrong disallow rules in robots.txt");
+}
+
+#[test_case("styles.css"; "css")]
+#[test_case("robots.txt"; "robots")]
+#[test_case("icon.svg"; "file")]
+#[actix_web::test]
+/// Test whether static files are revalidated with their ETags and modification times.
+///
+/// # Arguments
+/// * `path` - The URL path to the static file
+async fn test_static_revalidation(path: &str) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The static file service shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let url = format!("http://{host}/{path}");
+    let resp = client
+        .get(&url)
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .expect("Missing ETag header")
+        .clone();
+    let last_modified = resp
+        .headers()
+        .get(LAST_MODIFIED)
+        .expect("Missing Last-Modified header")
+        .clone();
+
+    let etag_resp = client
+        .get(&url)
+        .insert_header((IF_NONE_MATCH, etag))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let since_resp = client
+        .get(&url)
+        .insert_header((IF_MODIFIED_SINCE, last_modified))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(
+        etag_resp.status(),
+        StatusCode::NOT_MODIFIED,
+        "Not revalidated with the ETag"
+    );
+    assert_eq!(
+        since_resp.status(),
+        StatusCode::NOT_MODIFIED,
+        "Not revalidated with the modification time"
+    );
+}
+
+#[test_case("healthz"; "liveness")]
+#[test_case("readyz"; "readiness")]
+#[actix_web::test]
+/// Test the health check endpoints.
+///
+/// # Arguments
+/// * `path` - The URL path to the health check endpoint
+async fn test_health(path: &str) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // Without a database or a source check, there are no dependencies to be unavailable.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .get(format!("http://{host}/{path}"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    test_content_type(resp, "text/plain").await;
+}
+
+#[test_case(Some("proxy-id"); "given")]
+#[test_case(None; "generated")]
+#[actix_web::test]
+/// Test whether the request ID is echoed back in the response.
+///
+/// # Arguments
+/// * `request_id` - The request ID given in the request, if any
+async fn test_request_id(request_id: Option<&str>) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut req = client.get(format!("http://{host}/healthz"));
+    if let Some(request_id) = request_id {
+        req = req.insert_header((REQUEST_ID_HEADER, request_id));
+    }
+    let resp = req.send().await.expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    let echoed = resp
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .expect("Missing request ID header")
+        .to_str()
+        .expect("Request ID header is not ASCII");
+    if let Some(request_id) = request_id {
+        assert_eq!(echoed, request_id, "Request ID isn't echoed back");
+    } else {
+        assert!(!echoed.is_empty(), "Request ID is empty");
+    }
+}
+
+#[test_case("cache/2000-01-01", Some("Bearer secret"), StatusCode::NO_CONTENT; "purge comic")]
+#[test_case("cache", Some("Bearer secret"), StatusCode::NO_CONTENT; "purge all")]
+#[test_case("cache/2000-01-01", Some("Bearer wrong"), StatusCode::NOT_FOUND; "wrong token")]
+#[test_case("cache", None, StatusCode::NOT_FOUND; "no token")]
+#[actix_web::test]
+/// Test whether the cache can be purged through the admin routes, only with the admin token.
+///
+/// # Arguments
+/// * `path` - The URL path to the admin route, without the "/admin/" prefix
+/// * `authorization` - The request's `Authorization` header, if any
+/// * `expected` - The expected response status
+async fn test_admin_purge(path: &str, authorization: Option<&str>, expected: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        admin_token: Some("secret".into()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut req = client.delete(format!("http://{host}/admin/{path}"));
+    if let Some(authorization) = authorization {
+        req = req.insert_header((AUTHORIZATION, authorization));
+    }
+    let resp = req.send().await.expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected, "Wrong response status");
+}
+
+#[test_case("from=2000-01-01&to=2000-01-01", StatusCode::ACCEPTED; "valid")]
+#[test_case("from=2000-01-02&to=2000-01-01", StatusCode::BAD_REQUEST; "reversed")]
+#[test_case("from=2000-01-32", StatusCode::BAD_REQUEST; "invalid date")]
+#[actix_web::test]
+/// Test whether warming up the cache through the admin route starts only for valid dates.
+///
+/// # Arguments
+/// * `query` - The URL query with the dates
+/// * `expected` - The expected response status
+async fn test_admin_warm(query: &str, expected: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // The warm-up's scraping would fail without any request to "dilbert.com", so make the URL
+    // empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        admin_token: Some("secret".into()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let resp = client
+        .post(format!("http://{host}/admin/warm?{query}"))
+        .insert_header((AUTHORIZATION, "Bearer secret"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected, "Wrong response status");
+}
+
+#[actix_web::test]
+/// Test whether the metrics are served through the admin route in the Prometheus text format.
+async fn test_admin_metrics() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // This shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        admin_token: Some("secret".into()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .get(format!("http://{host}/admin/metrics"))
+        .insert_header((AUTHORIZATION, "Bearer secret"))
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Wrong response status");
+    let body = std::str::from_utf8(&body).expect("Response body isn't UTF-8");
+    assert!(
+        body.contains("dilbert_viewer_cache_lookups_total"),
+        "Cache metrics are missing"
+    );
+}
+
+#[test_case(Some("Bearer secret"), StatusCode::OK; "with token")]
+#[test_case(None, StatusCode::NOT_FOUND; "without token")]
+#[actix_web::test]
+/// Test whether the metrics can be reset through the admin route, only with the admin token.
+///
+/// # Arguments
+/// * `authorization` - The request's `Authorization` header, if any
+/// * `expected` - The expected response status
+async fn test_admin_reset_stats(authorization: Option<&str>, expected: StatusCode) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread.
+    // This shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        admin_token: Some("secret".into()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut req = client.post(format!("http://{host}/admin/stats/reset"));
+    if let Some(authorization) = authorization {
+        req = req.insert_header((AUTHORIZATION, authorization));
+    }
+    let mut resp = req.send().await.expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), expected, "Wrong response status");
+    if expected == StatusCode::OK {
+        let snapshot: serde_json::Value =
+            serde_json::from_slice(&body).expect("Snapshot isn't JSON");
+        for key in ["cache_lookups", "scrape_count", "scrape_failures"] {
+            assert!(snapshot.get(key).is_some(), "Missing {key:?} in snapshot");
+        }
+    }
+}
+
+#[actix_web::test]
+/// Test whether requests over the rate limit are rejected, except for health checks.
+async fn test_rate_limit() {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+
+    // Start the server on a single thread, allowing only one request per minute.
+    // This shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        rate_limit: NonZeroU32::new(1),
+        rate_limit_burst: 1,
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut statuses = Vec::new();
+    let mut retry_after = None;
+    for path in ["robots.txt", "robots.txt", "healthz"] {
+        let resp = client
+            .get(format!("http://{host}/{path}"))
+            .send()
+            .await
+            .expect("Failed to send request to server");
+        statuses.push(resp.status());
+        retry_after = retry_after.or(resp.headers().get(RETRY_AFTER).cloned());
+    }
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(
+        statuses,
+        [
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::OK
+        ],
+        "Wrong response statuses"
+    );
+    assert_eq!(
+        retry_after.as_ref().map(|value| value.as_bytes()),
+        Some(&b"60"[..]),
+        "Wrong time after which to retry"
+    );
+}
+
+#[test_case(Method::GET; "GET")]
+#[test_case(Method::HEAD; "HEAD")]
+#[actix_web::test]
+/// Test the same-origin proxy for comic images.
+///
+/// # Arguments
+/// * `req_method` - The HTTP method of the request for the image
+async fn test_comic_image(req_method: Method) {
+    let port = pick_unused_port().expect("Couldn't find an available port");
+    let host = format!("{HOST}:{port}");
+    let image = b"GIF89a".to_vec();
+
+    // Set up the mock server with a minimal comic page, whose image is also on the mock server.
+    let mock_server = MockServer::start().await;
+    let html = format!(
+        "<img class=\"img-comic\" src=\"{}/image.gif\" width=\"1\" height=\"1\" />",
+        mock_server.uri()
+    );
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/strip/2000-01-01"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string(html))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(Method::GET.as_str()))
+        .and(path("/cdx"))
+        .respond_with(ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_string("2000"))
+        .mount(&mock_server)
+        .await;
+    Mock::given(path("/image.gif"))
+        .respond_with(
+            ResponseTemplate::new(StatusCode::OK.as_u16()).set_body_raw(image.clone(), "image/gif"),
+        )
+        .mount(&mock_server)
+        .await;
+
+    // Start the server on a single thread.
+    let handle = start_server(Config {
+        host: HOST.into(),
+        port: Some(port),
+        workers: Some(1),
+        source_url: mock_server.uri(),
+        cdx_url: format!("{}/cdx", mock_server.uri()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = get_http_client();
+    let mut resp = client
+        .request(
+            req_method.clone(),
+            format!("http://{host}/2000-01-01/image"),
+        )
+        .send()
+        .await
+        .expect("Failed to send request to server");
+    let body = resp.body().await.expect("Couldn't read response body");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert_eq!(resp.status(), StatusCode::OK, "Response status is not OK");
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    assert_eq!(
+        header(CONTENT_TYPE).as_deref(),
+        Some("image/gif"),
+        "Wrong image content type"
+    );
+    assert_eq!(
+        header(CONTENT_LENGTH),
+        Some(image.len().to_string()),
+        "Wrong image content length"
+    );
+    if req_method == Method::HEAD {
+        assert!(body.is_empty(), "HEAD response has a body");
+    } else {
+        assert_eq!(body, image, "Wrong image in response");
+    }
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+/// Test whether the server can be run on a Unix domain socket.
+async fn test_unix_socket() {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let socket_path =
+        std::env::temp_dir().join(format!("dilbert-viewer-{}.sock", uuid::Uuid::new_v4()));
+
+    // Start the server on a single thread.
+    // This shouldn't make any request to "dilbert.com", so make the URL empty.
+    let handle = start_server(Config {
+        host: format!("unix:{}", socket_path.display()),
+        workers: Some(1),
+        source_url: String::new(),
+        cdx_url: String::new(),
+        ..Default::default()
+    })
+    .await;
+
+    // Use a blocking client in a separate thread, so that it doesn't block the server's runtime.
+    let client_path = socket_path.clone();
+    let resp = spawn_blocking(move || {
+        let mut stream = UnixStream::connect(client_path).expect("Couldn't connect to socket");
+        stream
+            .write_all(b"GET /robots.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .expect("Failed to send request to server");
+        let mut resp = String::new();
+        stream
+            .read_to_string(&mut resp)
+            .expect("Failed to get response from server");
+        resp
+    })
+    .await
+    .expect("Client thread panicked");
+
+    // Close the server.
+    handle.stop(true).await;
+
+    assert!(
+        resp.starts_with("HTTP/1.1 200 OK"),
+        "Response status is not OK: {resp}"
+    );
 }